@@ -24,6 +24,11 @@ pub struct KernelConfig {
 
     /// Interrupts hooked by the application, keyed by IRQ number.
     pub irqs: BTreeMap<u32, InterruptConfig>,
+
+    /// Retained RAM (one of `extern_regions`) where the kernel keeps its log
+    /// of panics and task faults across resets, if enabled.
+    #[serde(default)]
+    pub fault_log: Option<std::ops::Range<u32>>,
 }
 
 /// Configuration for a single hooked interrupt.
@@ -75,8 +80,18 @@ pub struct TaskConfig {
     /// Initial priority of this task.
     pub priority: u8,
 
+    /// Number of kernel ticks this task may run before being rotated out in
+    /// favor of another runnable task of equal priority. If `None`, the task
+    /// runs until it blocks or is preempted by a more important task.
+    #[serde(default)]
+    pub time_slice: Option<u32>,
+
     /// Should this task be started automatically on boot?
     pub start_at_boot: bool,
+
+    /// May this task read the image through the `read_image` kipc?
+    #[serde(default)]
+    pub read_image: bool,
 }
 
 /// An address within an owned region of memory.
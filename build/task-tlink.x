@@ -72,6 +72,14 @@ SECTIONS
     KEEP(*(.task_slot_table));
   }
 
+  /* ## .task_slot_interfaces */
+  /* Idol interfaces used through each task slot, if recorded by the task's
+     build.rs. Checked against the servers' `serves` lists during packaging. */
+  .task_slot_interfaces (INFO) : {
+    . = .;
+    KEEP(*(.task_slot_interfaces));
+  }
+
   /* ## .caboose_pos_table */
   /* Table of CaboosePos instances and their names. Used to record caboose
      position during packaging. */
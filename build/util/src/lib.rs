@@ -62,12 +62,17 @@ pub fn has_feature(s: &str) -> bool {
 ///
 /// This will set one of `cfg(armv6m)`, `cfg(armv7m)`, or `cfg(armv8m)`
 /// depending on the value of the `TARGET` environment variable.
+///
+/// When building for Linux, this instead sets `cfg(hubris_hosted)`, which
+/// selects the hosted (simulated) architecture support in the kernel and
+/// userlib.
 pub fn expose_m_profile() -> Result<()> {
     let target = crate::target();
 
     println!("cargo::rustc-check-cfg=cfg(armv6m)");
     println!("cargo::rustc-check-cfg=cfg(armv7m)");
     println!("cargo::rustc-check-cfg=cfg(armv8m)");
+    println!("cargo::rustc-check-cfg=cfg(hubris_hosted)");
 
     if target.starts_with("thumbv6m") {
         println!("cargo::rustc-cfg=armv6m");
@@ -76,6 +81,8 @@ pub fn expose_m_profile() -> Result<()> {
         println!("cargo::rustc-cfg=armv7m");
     } else if target.starts_with("thumbv8m") {
         println!("cargo::rustc-cfg=armv8m");
+    } else if crate::target_os() == "linux" {
+        println!("cargo::rustc-cfg=hubris_hosted");
     } else {
         bail!("Don't know the target {target}");
     }
@@ -351,3 +358,34 @@ fn write_task_notifications<W: Write>(out: &mut W, t: &[String]) -> Result<()> {
     }
     Ok(())
 }
+
+/// Lets tasks record which of their task slots they use to reach the Idol
+/// interface described by `idol_path`, which is also used to generate the
+/// client stub.
+///
+/// This writes `client_interface.rs` to `OUT_DIR`, which the API crate must
+/// `include!` after its client stub.  It implements
+/// `userlib::task_slot::Interface` for the client type, so that a task can
+/// declare a slot as `task_slot!(FOO, foo, Foo)`; `xtask dist` then checks
+/// that the task bound to the slot `serves` the interface.
+pub fn build_client_interface(idol_path: &str) -> Result<()> {
+    println!("cargo::rerun-if-changed={idol_path}");
+
+    /// The only part of an interface definition that we care about
+    #[derive(serde::Deserialize)]
+    struct Interface {
+        name: String,
+    }
+    let text = std::fs::read_to_string(idol_path)
+        .with_context(|| format!("reading {idol_path}"))?;
+    let Interface { name } =
+        ron::from_str(&text).with_context(|| format!("parsing {idol_path}"))?;
+
+    let dest_path = out_dir().join("client_interface.rs");
+    let mut out = std::fs::File::create(dest_path)?;
+    writeln!(&mut out, "impl userlib::task_slot::Interface for {name} {{")?;
+    writeln!(&mut out, "    const NAME: &'static str = {name:?};")?;
+    writeln!(&mut out, "}}")?;
+
+    Ok(())
+}
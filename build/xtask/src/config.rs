@@ -16,6 +16,10 @@ use crate::auxflash::{build_auxflash, AuxFlash, AuxFlashData};
 /// A `RawConfig` represents an `app.toml` file that has been deserialized,
 /// but may not be ready for use.  In particular, we use the `chip` field
 /// to load a second file containing peripheral register addresses.
+///
+/// Changes here (or to the types it contains) should be reflected in the
+/// schema in `schema.rs`, which `xtask validate` checks against (and which
+/// `schema_matches_fields` below checks against these types).
 #[derive(Clone, Debug, Deserialize)]
 #[serde(rename_all = "kebab-case", deny_unknown_fields)]
 struct RawConfig {
@@ -30,6 +34,8 @@ struct RawConfig {
     version: u32,
     #[serde(default)]
     fwid: bool,
+    #[serde(default)]
+    allocator: Allocator,
     memory: Option<String>,
     #[serde(default)]
     image_names: Vec<String>,
@@ -68,6 +74,7 @@ pub struct Config {
     pub mmio: Option<MmioData>,
     pub version: u32,
     pub fwid: bool,
+    pub allocator: Allocator,
     pub image_names: Vec<String>,
     pub signing: Option<RoTMfgSettings>,
     pub stacksize: Option<u32>,
@@ -96,6 +103,17 @@ impl Config {
     }
 }
 
+/// Strategy used to lay out tasks in memory
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Allocator {
+    /// Place tasks one at a time, picking whichever fits best each time
+    #[default]
+    Greedy,
+    /// Search for the layout that wastes the least space on padding
+    Optimize,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct CabooseConfig {
     /// List of tasks that are allowed to access the caboose
@@ -138,6 +156,15 @@ impl Config {
             }
         }
 
+        if let Some(r) = &toml.kernel.fault_log {
+            if !toml.kernel.extern_regions.contains(r) {
+                bail!(
+                    "kernel fault-log region '{r}' must also be listed in \
+                     the kernel's extern-regions"
+                );
+            }
+        }
+
         // The app.toml must include a `chip` key, which defines the peripheral
         // register map in a separate file.  We load it then accumulate that
         // file in the buildhash.
@@ -246,6 +273,7 @@ impl Config {
             epoch: toml.epoch,
             version: toml.version,
             fwid: toml.fwid,
+            allocator: toml.allocator,
             signing: toml.signing,
             stacksize: toml.stacksize,
             kernel: toml.kernel,
@@ -729,6 +757,9 @@ pub struct Kernel {
     pub no_default_features: bool,
     #[serde(default)]
     pub extern_regions: Vec<String>,
+    /// Name of one of `extern_regions` to use as retained RAM for the kernel's
+    /// fault log
+    pub fault_log: Option<String>,
 }
 
 fn default_name() -> String {
@@ -900,6 +931,31 @@ fn read_and_flatten_toml(
 pub struct BoardConfig {
     /// Info about how to interact with this board using probe-rs.
     pub probe_rs: Option<ProbeRsBoardConfig>,
+    /// Info about how to emulate this board using QEMU.
+    pub qemu: Option<QemuBoardConfig>,
+}
+
+impl BoardConfig {
+    /// Loads the configuration for `board` from the `boards` directory.
+    pub fn from_board(board: &str) -> Result<Self> {
+        let board_config_path =
+            Path::new("boards").join(format!("{board}.toml"));
+
+        let board_config_text = std::fs::read_to_string(&board_config_path)
+            .with_context(|| {
+                format!(
+                    "can't access board config at: {}",
+                    board_config_path.display()
+                )
+            })?;
+
+        toml::from_str(&board_config_text).with_context(|| {
+            format!(
+                "can't parse board config at: {}",
+                board_config_path.display()
+            )
+        })
+    }
 }
 
 #[derive(Clone, Debug, Deserialize)]
@@ -908,3 +964,109 @@ pub struct ProbeRsBoardConfig {
     /// The "chip name" used by probe-rs for flashing.
     pub chip_name: String,
 }
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct QemuBoardConfig {
+    /// The QEMU machine type (`-M`) that emulates this board.
+    pub machine: String,
+    /// Extra arguments to pass to `qemu-system-arm`.
+    #[serde(default)]
+    pub extra_args: Vec<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auxflash::AuxFlashBlob;
+    use serde::de::{self, DeserializeOwned, Visitor};
+    use serde_json::Value;
+
+    /// Deserializer that records the names of the fields that a struct's
+    /// `Deserialize` impl accepts, and then fails.
+    struct FieldNames<'a>(&'a mut &'static [&'static str]);
+
+    impl<'de> de::Deserializer<'de> for FieldNames<'_> {
+        type Error = de::value::Error;
+
+        fn deserialize_any<V: Visitor<'de>>(
+            self,
+            _visitor: V,
+        ) -> std::result::Result<V::Value, Self::Error> {
+            Err(de::Error::custom("expected a struct"))
+        }
+
+        fn deserialize_struct<V: Visitor<'de>>(
+            self,
+            _name: &'static str,
+            fields: &'static [&'static str],
+            _visitor: V,
+        ) -> std::result::Result<V::Value, Self::Error> {
+            *self.0 = fields;
+            Err(de::Error::custom("recorded fields"))
+        }
+
+        serde::forward_to_deserialize_any! {
+            bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str
+            string bytes byte_buf option unit unit_struct newtype_struct seq
+            tuple tuple_struct map enum identifier ignored_any
+        }
+    }
+
+    fn fields<T: DeserializeOwned>() -> &'static [&'static str] {
+        let mut fields: &[&str] = &[];
+        let _ = T::deserialize(FieldNames(&mut fields));
+        assert!(!fields.is_empty());
+        fields
+    }
+
+    /// The schema is written by hand, so check that it has a property for
+    /// every field that each table in an app.toml can have, and no others.
+    #[test]
+    fn schema_matches_fields() {
+        let schema = crate::schema::app_schema();
+        let check = |pointer: &str, fields: &[&str], extra: &[&str]| {
+            let properties: BTreeSet<&str> = schema
+                .pointer(pointer)
+                .and_then(Value::as_object)
+                .unwrap_or_else(|| panic!("no {pointer} in schema"))
+                .keys()
+                .map(String::as_str)
+                .collect();
+            let fields: BTreeSet<&str> =
+                fields.iter().chain(extra).copied().collect();
+            assert_eq!(properties, fields, "schema at {pointer}");
+        };
+
+        // `inherit` is handled before the file is deserialized.
+        check("/properties", fields::<RawConfig>(), &["inherit"]);
+        check("/properties/mmio/properties", fields::<MmioConfig>(), &[]);
+        check(
+            "/properties/signing/properties",
+            fields::<RoTMfgSettings>(),
+            &[],
+        );
+        check("/properties/kernel/properties", fields::<Kernel>(), &[]);
+        check(
+            "/properties/tasks/additionalProperties/properties",
+            fields::<Task>(),
+            &[],
+        );
+        check(
+            "/properties/extratext/additionalProperties/properties",
+            fields::<Peripheral>(),
+            &[],
+        );
+        check("/properties/auxflash/properties", fields::<AuxFlash>(), &[]);
+        check(
+            "/properties/auxflash/properties/blobs/items/properties",
+            fields::<AuxFlashBlob>(),
+            &[],
+        );
+        check(
+            "/properties/caboose/properties",
+            fields::<CabooseConfig>(),
+            &[],
+        );
+    }
+}
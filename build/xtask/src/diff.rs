@@ -0,0 +1,450 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Compares two build archives, as produced by `xtask dist`.
+//!
+//! Each archive is boiled down into several sections of named items (e.g. the
+//! `sizes` section has an item `ping.flash` giving the flash used by the task
+//! `ping`), and we report the items that were added, removed, or changed in
+//! each section.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Read;
+use std::ops::Range;
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Context, Result};
+use indexmap::IndexMap;
+use serde::Serialize;
+use serde_json::Value;
+use tlvc::{TlvcRead, TlvcReadError, TlvcReader};
+
+use crate::config::Output;
+use crate::{elf, task_slot};
+
+/// One difference between two archives.
+#[derive(Debug, Serialize)]
+struct Change {
+    item: String,
+    /// The value in the first archive, if it has this item.
+    before: Option<Value>,
+    /// The value in the second archive, if it has this item.
+    after: Option<Value>,
+}
+
+/// The items of each section of an archive, in the order they're reported.
+type Sections = IndexMap<&'static str, BTreeMap<String, Value>>;
+
+pub fn run(before: &Path, after: &Path, json: bool) -> Result<()> {
+    let before = summarize(before)?;
+    let after = summarize(after)?;
+
+    let mut changes: IndexMap<&'static str, Vec<Change>> = IndexMap::new();
+    for (&section, before_items) in &before {
+        let empty = BTreeMap::new();
+        let after_items = after.get(section).unwrap_or(&empty);
+        changes.insert(section, diff_items(before_items, after_items));
+    }
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&changes)?);
+        return Ok(());
+    }
+
+    let mut total = 0;
+    for (section, changes) in &changes {
+        if changes.is_empty() {
+            continue;
+        }
+        println!("{section}:");
+        for change in changes {
+            match (&change.before, &change.after) {
+                (Some(b), Some(a)) => {
+                    let delta = match (b.as_i64(), a.as_i64()) {
+                        (Some(b), Some(a)) => format!(" ({:+})", a - b),
+                        _ => String::new(),
+                    };
+                    println!(
+                        "  {}: {} -> {}{delta}",
+                        change.item,
+                        show(b),
+                        show(a)
+                    );
+                }
+                (None, Some(a)) => println!("+ {}: {}", change.item, show(a)),
+                (Some(b), None) => println!("- {}: {}", change.item, show(b)),
+                (None, None) => unreachable!(),
+            }
+        }
+        println!();
+        total += changes.len();
+    }
+    if total == 0 {
+        println!("no differences");
+    } else {
+        println!("{total} difference(s)");
+    }
+    Ok(())
+}
+
+/// Formats a value for humans, which is as JSON except that strings aren't
+/// quoted.
+fn show(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        v => v.to_string(),
+    }
+}
+
+fn diff_items(
+    before: &BTreeMap<String, Value>,
+    after: &BTreeMap<String, Value>,
+) -> Vec<Change> {
+    let mut changes = vec![];
+    for (item, b) in before {
+        match after.get(item) {
+            Some(a) if a == b => (),
+            a => changes.push(Change {
+                item: item.clone(),
+                before: Some(b.clone()),
+                after: a.cloned(),
+            }),
+        }
+    }
+    for (item, a) in after {
+        if !before.contains_key(item) {
+            changes.push(Change {
+                item: item.clone(),
+                before: None,
+                after: Some(a.clone()),
+            });
+        }
+    }
+    changes.sort_by(|x, y| x.item.cmp(&y.item));
+    changes
+}
+
+/// Reads every file in the archive at `path`.
+pub(crate) fn read_archive(path: &Path) -> Result<BTreeMap<String, Vec<u8>>> {
+    let file = File::open(path)
+        .with_context(|| format!("opening {}", path.display()))?;
+    let mut zip = zip::ZipArchive::new(file)
+        .with_context(|| format!("reading {}", path.display()))?;
+    let mut files = BTreeMap::new();
+    for i in 0..zip.len() {
+        let mut f = zip.by_index(i)?;
+        if f.is_dir() {
+            continue;
+        }
+        let mut data = vec![];
+        f.read_to_end(&mut data)?;
+        files.insert(f.name().to_string(), data);
+    }
+    Ok(files)
+}
+
+/// Boils the archive at `path` down into the sections we compare.
+fn summarize(path: &Path) -> Result<Sections> {
+    let files = read_archive(path)?;
+    let text = |name: &str| -> Result<&str> {
+        let data = files.get(name).ok_or_else(|| {
+            anyhow!("{} has no {name}; is it a build archive?", path.display())
+        })?;
+        std::str::from_utf8(data)
+            .with_context(|| format!("{name} in {}", path.display()))
+    };
+
+    let app: toml::Table = toml::from_str(text("app.toml")?)
+        .with_context(|| format!("parsing app.toml in {}", path.display()))?;
+    let memory: IndexMap<String, Vec<Output>> =
+        toml::from_str(text("memory.toml")?).with_context(|| {
+            format!("parsing memory.toml in {}", path.display())
+        })?;
+    // Tasks are numbered in the order they appear in app.toml.
+    let task_names: Vec<String> = app
+        .get("tasks")
+        .and_then(toml::Value::as_table)
+        .map(|tasks| tasks.keys().cloned().collect())
+        .unwrap_or_default();
+    let kconfig = files
+        .get("kconfig.ron")
+        .map(|data| -> Result<build_kconfig::KernelConfig> {
+            Ok(ron::de::from_bytes(data)?)
+        })
+        .transpose()
+        .with_context(|| {
+            format!("parsing kconfig.ron in {}", path.display())
+        })?;
+
+    let mut elves = vec![("kernel".to_string(), "elf/kernel".to_string())];
+    for name in &task_names {
+        elves.push((name.clone(), format!("elf/task/{name}")));
+    }
+
+    let mut sections = Sections::new();
+
+    let build = sections.entry("build").or_default();
+    for name in ["git-rev", "image-name"] {
+        if let Ok(t) = text(name) {
+            build.insert(name.to_string(), t.trim().into());
+        }
+    }
+
+    let sizes = sections.entry("sizes").or_default();
+    for (name, file) in &elves {
+        let Some(data) = files.get(file) else {
+            bail!("{} has no {file}", path.display());
+        };
+        let elf = goblin::elf::Elf::parse(data)
+            .with_context(|| format!("parsing {file} in {}", path.display()))?;
+        for (region, range) in load_extents(&elf, &memory)? {
+            sizes.insert(
+                format!("{name}.{region}"),
+                (range.end - range.start).into(),
+            );
+        }
+    }
+
+    let allocations = sections.entry("allocations").or_default();
+    if let Some(kconfig) = &kconfig {
+        for (name, task) in task_names.iter().zip(&kconfig.tasks) {
+            for (region, r) in &task.owned_regions {
+                let size: u32 = r.sizes.iter().sum();
+                allocations.insert(
+                    format!("{name}.{region}"),
+                    format!("{:#010x}..{:#010x}", r.base, r.base + size).into(),
+                );
+            }
+        }
+    }
+
+    let config = sections.entry("config").or_default();
+    flatten("", &serde_json::to_value(&app)?, config);
+
+    let task_slots = sections.entry("task-slots").or_default();
+    for (name, file) in &elves[1..] {
+        let data = &files[file];
+        let elf = goblin::elf::Elf::parse(data)?;
+        // Tasks with no slots have no table at all.
+        if elf::get_section_by_name(&elf, task_slot::TASK_SLOT_TABLE_SECTION)
+            .is_none()
+        {
+            continue;
+        }
+        for entry in task_slot::get_task_slot_table_entries(data, &elf)? {
+            let offset = entry.taskidx_file_offset as usize;
+            let index = data
+                .get(offset..offset + 2)
+                .map(|b| u16::from_le_bytes([b[0], b[1]]))
+                .ok_or_else(|| anyhow!("{file}: bad task slot"))?;
+            let target = task_names
+                .get(usize::from(index))
+                .cloned()
+                .unwrap_or_else(|| format!("#{index}"));
+            task_slots
+                .insert(format!("{name}.{}", entry.slot_name), target.into());
+        }
+    }
+
+    let caboose = sections.entry("caboose").or_default();
+    if let Some(image) = files.get("img/final.bin") {
+        for (tag, value) in read_caboose(image)? {
+            caboose.insert(tag, value.into());
+        }
+    }
+
+    let kernel_config = sections.entry("kernel-config").or_default();
+    if let Some(kconfig) = &kconfig {
+        let mut value = serde_json::to_value(kconfig)?;
+        // Name tasks rather than numbering them, and leave out their regions,
+        // which are under `allocations`.
+        if let Some(tasks) = value.get_mut("tasks").map(Value::take) {
+            let tasks = task_names
+                .iter()
+                .cloned()
+                .zip(tasks.as_array().into_iter().flatten().cloned())
+                .map(|(name, mut task)| {
+                    if let Some(task) = task.as_object_mut() {
+                        task.remove("owned_regions");
+                    }
+                    (name, task)
+                })
+                .collect();
+            value["tasks"] = Value::Object(tasks);
+        }
+        flatten("", &value, kernel_config);
+    }
+
+    let symbols = sections.entry("symbols").or_default();
+    for (name, file) in &elves {
+        let elf = goblin::elf::Elf::parse(&files[file])?;
+        for (symbol, size) in symbol_sizes(&elf) {
+            symbols.insert(format!("{name}:{symbol}"), size.into());
+        }
+    }
+
+    Ok(sections)
+}
+
+/// Flattens `value` into `out`, naming each leaf by its path from the root.
+/// Arrays of scalars are kept whole, since they're usually lists of names
+/// where the position doesn't matter much.
+fn flatten(prefix: &str, value: &Value, out: &mut BTreeMap<String, Value>) {
+    let join = |key: &str| {
+        if prefix.is_empty() {
+            key.to_string()
+        } else {
+            format!("{prefix}.{key}")
+        }
+    };
+    match value {
+        Value::Object(map) => {
+            for (key, v) in map {
+                flatten(&join(key), v, out);
+            }
+        }
+        Value::Array(items)
+            if items.iter().any(|v| v.is_object() || v.is_array()) =>
+        {
+            for (i, v) in items.iter().enumerate() {
+                flatten(&format!("{prefix}[{i}]"), v, out);
+            }
+        }
+        v => {
+            out.insert(prefix.to_string(), v.clone());
+        }
+    }
+}
+
+/// Finds the extent of each memory region used by an ELF file's loadable
+/// segments.
+fn load_extents(
+    elf: &goblin::elf::Elf,
+    memory: &IndexMap<String, Vec<Output>>,
+) -> Result<BTreeMap<String, Range<u64>>> {
+    let region = |addr: u64| {
+        memory.iter().find_map(|(name, outputs)| {
+            outputs
+                .iter()
+                .any(|o| {
+                    addr >= u64::from(o.address)
+                        && addr < u64::from(o.address) + u64::from(o.size)
+                })
+                .then(|| name.clone())
+        })
+    };
+    let mut extents: BTreeMap<String, Range<u64>> = BTreeMap::new();
+    let mut record = |start: u64, size: u64| {
+        if size == 0 {
+            return Ok(());
+        }
+        let Some(region) = region(start) else {
+            bail!("no memory region contains {start:#x}");
+        };
+        let r = extents.entry(region).or_insert(start..start + size);
+        r.start = r.start.min(start);
+        r.end = r.end.max(start + size);
+        Ok(())
+    };
+    for phdr in &elf.program_headers {
+        if phdr.p_type != goblin::elf::program_header::PT_LOAD {
+            continue;
+        }
+        record(phdr.p_vaddr, phdr.p_memsz)?;
+        // Sections relocated into RAM also take up space where they're loaded
+        // from.
+        if phdr.p_vaddr != phdr.p_paddr {
+            record(phdr.p_paddr, phdr.p_filesz)?;
+        }
+    }
+    Ok(extents)
+}
+
+/// Returns the size of each function and object in an ELF file, by demangled
+/// name (without the hash, so that it's stable across builds). Symbols that
+/// end up with the same name are added together.
+fn symbol_sizes(elf: &goblin::elf::Elf) -> BTreeMap<String, u64> {
+    use goblin::elf::sym::{STT_FUNC, STT_OBJECT};
+
+    let mut sizes = BTreeMap::new();
+    for sym in elf.syms.iter() {
+        if !matches!(sym.st_type(), STT_FUNC | STT_OBJECT) || sym.st_size == 0 {
+            continue;
+        }
+        let Some(name) = elf.strtab.get_at(sym.st_name) else {
+            continue;
+        };
+        let name = format!("{:#}", rustc_demangle::demangle(name));
+        *sizes.entry(name).or_default() += sym.st_size;
+    }
+    sizes
+}
+
+/// Reads the caboose from the end of a flat image, returning its tags and
+/// values. An image with no caboose has no tags.
+///
+/// The caboose starts with `abi::CABOOSE_MAGIC` and ends with its own length,
+/// with its contents in TLV-C format in between.
+pub(crate) fn read_caboose(image: &[u8]) -> Result<Vec<(String, String)>> {
+    let word = |offset: usize| {
+        image
+            .get(offset..offset + 4)
+            .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+    };
+    let Some(len) = image.len().checked_sub(4).and_then(word) else {
+        return Ok(vec![]);
+    };
+    let Some(start) = image.len().checked_sub(len as usize) else {
+        return Ok(vec![]);
+    };
+    if word(start) != Some(abi::CABOOSE_MAGIC) {
+        return Ok(vec![]);
+    }
+    // The length counts the magic word at the start and itself at the end.
+    if (len as usize) < 8 {
+        bail!("caboose length {len} is too short for its magic and length");
+    }
+
+    let contents = SliceReader(&image[start + 4..image.len() - 4]);
+    let mut reader = TlvcReader::begin(contents)
+        .map_err(|e| anyhow!("reading caboose: {e:?}"))?;
+    let mut tags = vec![];
+    // The unused end of the caboose is erased, which doesn't parse as a
+    // chunk, so we stop at the first error.
+    while let Ok(Some(chunk)) = reader.next() {
+        let mut data = vec![0; chunk.len() as usize];
+        chunk
+            .read_exact(0, &mut data)
+            .map_err(|e| anyhow!("reading caboose: {e:?}"))?;
+        let tag = String::from_utf8_lossy(&chunk.header().tag).into_owned();
+        let value = match String::from_utf8(data) {
+            Ok(s) => s,
+            Err(e) => hex::encode(e.as_bytes()),
+        };
+        tags.push((tag, value));
+    }
+    Ok(tags)
+}
+
+/// Adapter to read TLV-C from a byte slice.
+#[derive(Copy, Clone)]
+struct SliceReader<'a>(&'a [u8]);
+
+impl TlvcRead for SliceReader<'_> {
+    type Error = std::convert::Infallible;
+
+    fn extent(&self) -> Result<u64, TlvcReadError<Self::Error>> {
+        Ok(self.0.len() as u64)
+    }
+
+    fn read_exact(
+        &self,
+        offset: u64,
+        dest: &mut [u8],
+    ) -> Result<(), TlvcReadError<Self::Error>> {
+        dest.copy_from_slice(&self.0[offset as usize..][..dest.len()]);
+        Ok(())
+    }
+}
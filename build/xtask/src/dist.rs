@@ -2,15 +2,17 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
 use std::ffi::OsStr;
 use std::fmt::Write as _;
 use std::fs::{self, File};
 use std::hash::{Hash, Hasher};
-use std::io::{Read, Write};
+use std::io::{BufRead, BufReader, Write};
 use std::ops::Range;
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
 
 use anyhow::{anyhow, bail, Context, Result};
 use atty::Stream;
@@ -18,12 +20,13 @@ use indexmap::IndexMap;
 use lpc55_rom_data::FLASH_PAGE_SIZE as LPC55_FLASH_PAGE_SIZE;
 use multimap::MultiMap;
 use path_slash::{PathBufExt, PathExt};
+use serde::{Deserialize, Serialize};
 use sha3::{Digest, Sha3_256};
 use zerocopy::IntoBytes;
 
 use crate::{
     caboose_pos,
-    config::{BuildConfig, CabooseConfig, Config},
+    config::{Allocator, BuildConfig, CabooseConfig, Config},
     elf,
     sizes::load_task_size,
     task_slot,
@@ -75,6 +78,12 @@ pub struct PackageConfig {
     /// `target/$NAME/dist`.
     dist_dir: PathBuf,
 
+    /// Cargo target directory for the kernel and tasks, normally `target`
+    ///
+    /// Linker scripts for tasks and the build's outputs still live in
+    /// `target` and `dist_dir` regardless.
+    target_dir: PathBuf,
+
     /// Sysroot of the relevant toolchain
     sysroot: PathBuf,
 
@@ -144,6 +153,7 @@ impl PackageConfig {
             verbose,
             edges,
             dist_dir,
+            target_dir: PathBuf::from("target"),
             sysroot,
             host_triple,
             remap_paths: Self::remap_paths()?,
@@ -151,6 +161,20 @@ impl PackageConfig {
         })
     }
 
+    /// Builds the kernel and tasks in `dir` instead of `target`.
+    ///
+    /// Paths within `dir` are remapped as if they were in `target`, so that
+    /// the choice of directory doesn't leak into the build's outputs.
+    fn set_target_dir(&mut self, dir: &Path) -> Result<()> {
+        std::fs::create_dir_all(dir)?;
+        // Later `--remap-path-prefix` flags take priority, and this sorts
+        // after the Hubris directory that it's (probably) within.
+        self.remap_paths
+            .insert(dunce::canonicalize(dir)?, "/hubris/target");
+        self.target_dir = dir.to_path_buf();
+        Ok(())
+    }
+
     fn img_dir(&self, img_name: &str) -> PathBuf {
         self.dist_dir.join(img_name)
     }
@@ -239,7 +263,8 @@ mod checked_types {
     /// Simple data structure to store a set of guaranteed-contiguous ranges
     ///
     /// This will panic if you violate that constraint!
-    #[derive(Debug, Clone, Default, Hash)]
+    #[derive(Debug, Clone, Default, Hash, Serialize, Deserialize)]
+    #[serde(try_from = "Vec<Range<u32>>", into = "Vec<Range<u32>>")]
     pub struct ContiguousRanges(Vec<Range<u32>>);
     impl ContiguousRanges {
         pub fn new(r: Range<u32>) -> Self {
@@ -265,6 +290,25 @@ mod checked_types {
         }
     }
 
+    impl TryFrom<Vec<Range<u32>>> for ContiguousRanges {
+        type Error = String;
+        fn try_from(v: Vec<Range<u32>>) -> Result<Self, Self::Error> {
+            if v.is_empty() {
+                Err("ranges must not be empty".to_string())
+            } else if v.windows(2).any(|w| w[0].end != w[1].start) {
+                Err("ranges must be contiguous".to_string())
+            } else {
+                Ok(Self(v))
+            }
+        }
+    }
+
+    impl From<ContiguousRanges> for Vec<Range<u32>> {
+        fn from(r: ContiguousRanges) -> Self {
+            r.0
+        }
+    }
+
     impl<'a> IntoIterator for &'a ContiguousRanges {
         type Item = &'a Range<u32>;
         type IntoIter = std::slice::Iter<'a, Range<u32>>;
@@ -334,9 +378,43 @@ pub fn package(
     app_toml: &Path,
     tasks_to_build: Option<Vec<String>>,
     dirty_ok: bool,
+    jobs: usize,
     caboose_args: super::CabooseArgs,
 ) -> Result<BTreeMap<String, AllocationMap>> {
-    let cfg = PackageConfig::new(app_toml, verbose, edges)?;
+    package_in(
+        Path::new("target"),
+        verbose,
+        edges,
+        app_toml,
+        tasks_to_build,
+        dirty_ok,
+        jobs,
+        caboose_args,
+    )
+}
+
+/// Like `package`, but with `target_dir` as the Cargo target directory.
+///
+/// This is used to build the same app more than once without the builds
+/// sharing any intermediate artifacts; see `repro`.
+#[allow(clippy::too_many_arguments)]
+pub fn package_in(
+    target_dir: &Path,
+    verbose: bool,
+    edges: bool,
+    app_toml: &Path,
+    tasks_to_build: Option<Vec<String>>,
+    dirty_ok: bool,
+    jobs: usize,
+    caboose_args: super::CabooseArgs,
+) -> Result<BTreeMap<String, AllocationMap>> {
+    // Catch mistakes in the app.toml before we spend any time building
+    crate::validate::run(app_toml)?;
+
+    let mut cfg = PackageConfig::new(app_toml, verbose, edges)?;
+    if target_dir != Path::new("target") {
+        cfg.set_target_dir(target_dir)?;
+    }
 
     // Verify that our dump configuration is correct (or absent)
     check_dump_config(&cfg.toml)?;
@@ -365,18 +443,20 @@ pub fn package(
     if dirty_ok {
         println!("note: not doing a clean build because you asked for it");
     } else {
-        check_rebuild(&cfg.toml)?;
+        check_rebuild(&cfg.toml, &cfg.target_dir)?;
     }
 
     // Build all tasks (which are relocatable executables, so they are not
-    // statically linked yet). For now, we build them one by one and ignore the
-    // return value, because we're going to link them regardless of whether the
-    // build changed.
-    for name in cfg.toml.tasks.keys() {
-        if tasks_to_build.contains(name.as_str()) {
-            build_task(&cfg, name)?;
-        }
-    }
+    // statically linked yet). We ignore the return value, because we're going
+    // to link them regardless of whether the build changed.
+    let task_names: Vec<&str> = cfg
+        .toml
+        .tasks
+        .keys()
+        .map(|name| name.as_str())
+        .filter(|name| tasks_to_build.contains(name))
+        .collect();
+    build_tasks(&cfg, &task_names, jobs)?;
 
     // Calculate the sizes of tasks, assigning dummy sizes to tasks that
     // aren't active in this build.
@@ -428,6 +508,11 @@ pub fn package(
     let allocated =
         allocate_all(&cfg.toml, &task_reqs, cfg.toml.caboose.as_ref())?;
 
+    // Record which crates went into the build, for the archives
+    if !partial_build {
+        crate::sbom::write(&cfg)?;
+    }
+
     for image_name in &cfg.toml.image_names {
         // Build each task.
         let mut all_output_sections = BTreeMap::default();
@@ -502,6 +587,7 @@ pub fn package(
                 }
 
                 resolve_task_slots(&cfg, task_name, image_name)?;
+                check_slot_interfaces(&cfg, task_name, image_name)?;
             }
         }
         if !possible_stack_overflow.is_empty() {
@@ -528,26 +614,11 @@ pub fn package(
             }
 
             let (_, caboose_range) = allocs.caboose.as_ref().unwrap();
-            // The caboose has the format
-            // [CABOOSE_MAGIC, ..., MAX_LENGTH]
-            // where all words in between are initialized to u32::MAX
-            //
-            // The final word in the caboose is the caboose length, so that we
-            // can decode the caboose start by looking at it while only knowing
-            // total image size.  The first word is CABOOSE_MAGIC, so we can
-            // check that a valid caboose exists.  Everything else is left to
-            // the user.
-            let mut caboose_data = vec![0xFF; caboose.size as usize];
-            caboose_data[caboose.size as usize - 4..]
-                .copy_from_slice(&caboose.size.to_le_bytes());
-            caboose_data[0..4]
-                .copy_from_slice(&abi::CABOOSE_MAGIC.to_le_bytes());
-
             all_output_sections.insert(
                 caboose_range.start,
                 LoadSegment {
                     source_file: "caboose".into(),
-                    data: caboose_data,
+                    data: blank_caboose(caboose.size),
                 },
             );
             entry_points.insert("caboose".to_string(), caboose_range.start);
@@ -752,7 +823,33 @@ fn write_fwid(
         .extract_file("img/final.bin")
         .context("extracting final.bin after signing & caboosing")?;
 
-    let chip_name = Path::new(&cfg.toml.chip);
+    // after we've appended a newline fwid is immutable
+    let mut fwid = compute_fwid(&cfg.toml.chip, &bin, flash)?;
+    writeln!(fwid).context("appending newline to FWID")?;
+    let fwid = fwid;
+
+    // the archive already exists so we write the FWID to the same path in
+    // the build output and archive to keep the two consistent
+    fs::write(cfg.img_file("final.fwid", image_name), &fwid)
+        .context("writing FWID to build output")?;
+    archive
+        .add_file("img/final.fwid", fwid.as_bytes())
+        .context("writing FWID to archive")?;
+
+    archive.overwrite()?;
+
+    Ok(())
+}
+
+/// Computes the FWID of a flat image, as a hex string: the SHA3-256 hash of
+/// the image as it reads back from flash once programmed, which depends on the
+/// chip.
+pub(crate) fn compute_fwid(
+    chip: &str,
+    bin: &[u8],
+    flash: &Range<u32>,
+) -> Result<String> {
+    let chip_name = Path::new(chip);
 
     // determine length of padding
     let pad = match chip_name.file_name().and_then(OsStr::to_str) {
@@ -773,30 +870,13 @@ fn write_fwid(
     };
 
     let mut sha = Sha3_256::new();
-    sha.update(&bin);
+    sha.update(bin);
 
     if pad != 0 {
         sha.update(vec![0xff_u8; pad])
     }
 
-    let digest = sha.finalize();
-
-    // after we've appended a newline fwid is immutable
-    let mut fwid = hex::encode(digest);
-    writeln!(fwid).context("appending newline to FWID")?;
-    let fwid = fwid;
-
-    // the archive already exists so we write the FWID to the same path in
-    // the build output and archive to keep the two consistent
-    fs::write(cfg.img_file("final.fwid", image_name), &fwid)
-        .context("writing FWID to build output")?;
-    archive
-        .add_file("img/final.fwid", fwid.as_bytes())
-        .context("writing FWID to archive")?;
-
-    archive.overwrite()?;
-
-    Ok(())
+    Ok(hex::encode(sha.finalize()))
 }
 
 fn write_gdb_script(cfg: &PackageConfig, image_name: &str) -> Result<()> {
@@ -853,6 +933,11 @@ fn build_archive(
         - elf/ contains ELF images for all firmware components.\n\
         - elf/tasks/ contains each task by name.\n\
         - elf/kernel is the kernel.\n\
+        - kconfig.ron is the configuration the kernel was built with.\n\
+        - allocations.ron is where the kernel and tasks were put in memory.\n\
+        - manifest.json describes the image's tasks for external tools.\n\
+        - sbom.cdx.json lists the crates in the kernel and each task.\n\
+        - licenses.txt summarizes the licenses of those crates.\n\
         - img/ contains the final firmware images.\n\
         - debug/ contains OpenOCD and GDB scripts, if available.\n",
     )?;
@@ -896,6 +981,14 @@ fn build_archive(
         archive.copy(cfg.img_file(name, image_name), tasks_dir.join(name))?;
     }
     archive.copy(cfg.img_file("kernel", image_name), elf_dir.join("kernel"))?;
+    archive.copy(cfg.img_file("kconfig.ron", image_name), "kconfig.ron")?;
+    archive.copy(
+        cfg.img_file("allocations.ron", image_name),
+        "allocations.ron",
+    )?;
+    archive.copy(cfg.img_file("manifest.json", image_name), "manifest.json")?;
+    archive.copy(cfg.dist_file("sbom.cdx.json"), "sbom.cdx.json")?;
+    archive.copy(cfg.dist_file("licenses.txt"), "licenses.txt")?;
 
     let img_dir = PathBuf::from("img");
     archive.binary(img_dir.join("final.elf"), raw_image.to_elf()?)?;
@@ -1038,8 +1131,9 @@ fn check_task_names(toml: &Config, task_names: &[String]) -> Result<()> {
 }
 
 /// Checks the buildstamp file and runs `cargo clean` if invalid
-fn check_rebuild(toml: &Config) -> Result<()> {
-    let buildstamp_file = Path::new("target").join("buildstamp");
+fn check_rebuild(toml: &Config, target_dir: &Path) -> Result<()> {
+    std::fs::create_dir_all(target_dir)?;
+    let buildstamp_file = target_dir.join("buildstamp");
     let rebuild = match std::fs::read(&buildstamp_file) {
         Ok(contents) => {
             if let Ok(contents) = std::str::from_utf8(&contents) {
@@ -1075,7 +1169,7 @@ fn check_rebuild(toml: &Config) -> Result<()> {
             // give to `cargo`.
             names.push(toml.tasks[name].name.as_str());
         }
-        cargo_clean(&names, &toml.target)?;
+        cargo_clean(&names, &toml.target, Some(target_dir))?;
     }
 
     // now that we're clean, update our buildstamp file; any failure to build
@@ -1085,23 +1179,143 @@ fn check_rebuild(toml: &Config) -> Result<()> {
     Ok(())
 }
 
+/// Returns the contents of an empty caboose of `size` bytes.
+///
+/// The caboose has the format
+/// [CABOOSE_MAGIC, ..., MAX_LENGTH]
+/// where all words in between are initialized to u32::MAX
+///
+/// The final word in the caboose is the caboose length, so that we can decode
+/// the caboose start by looking at it while only knowing total image size.
+/// The first word is CABOOSE_MAGIC, so we can check that a valid caboose
+/// exists.  Everything else is left to the user.
+pub(crate) fn blank_caboose(size: u32) -> Vec<u8> {
+    let mut data = vec![0xFF; size as usize];
+    data[size as usize - 4..].copy_from_slice(&size.to_le_bytes());
+    data[0..4].copy_from_slice(&abi::CABOOSE_MAGIC.to_le_bytes());
+    data
+}
+
+/// Computes the image ID from everything in the image but the kernel: the
+/// segments loaded from the task ELF files (and the empty caboose), keyed by
+/// address, the kernel's configuration (as compact RON), and the memory
+/// allocations.
+///
+/// All of these go into the build archive, so that `xtask verify` can
+/// recompute the ID.
+pub(crate) fn image_id<'a>(
+    segments: impl IntoIterator<Item = (u32, &'a [u8])>,
+    kconfig: &str,
+    allocs: &Allocations,
+) -> u64 {
+    let mut image_id = fnv::FnvHasher::default();
+    for (addr, data) in segments {
+        addr.hash(&mut image_id);
+        data.hash(&mut image_id);
+    }
+    kconfig.hash(&mut image_id);
+    allocs.hash(&mut image_id);
+    image_id.finish()
+}
+
+/// Returns the loadable segments of an ELF file, as (load address, contents)
+/// pairs.
+///
+/// Note that we are using Physical, i.e. LOADADDR, rather than virtual
+/// addresses. This distinction is important for things like the rodata image,
+/// which is loaded in flash but expected to be copied to RAM.
+pub(crate) fn load_segments<'a>(
+    elf: &goblin::elf::Elf<'_>,
+    file_image: &'a [u8],
+) -> Result<Vec<(u32, &'a [u8])>> {
+    elf.program_headers
+        .iter()
+        // Skip sections that aren't intended to be loaded.
+        .filter(|phdr| phdr.p_type == goblin::elf::program_header::PT_LOAD)
+        .map(|phdr| {
+            let data = usize::try_from(phdr.p_offset)
+                .ok()
+                .zip(usize::try_from(phdr.p_filesz).ok())
+                .and_then(|(o, n)| file_image.get(o..o.checked_add(n)?))
+                .ok_or_else(|| {
+                    anyhow!("segment is past the end of the file")
+                })?;
+            Ok((phdr.p_paddr as u32, data))
+        })
+        .collect()
+}
+
 #[derive(Debug, Hash)]
 struct LoadSegment {
     source_file: PathBuf,
     data: Vec<u8>,
 }
 
+/// Builds the given tasks, running up to `jobs` builds at once.
+///
+/// All of the builds share `cfg.target_dir` with the kernel, so that crates
+/// that tasks have in common are only built once, and it's left to Cargo to
+/// run them in parallel: it compiles each task's crates on as many cores as it
+/// has, and concurrent builds in one target directory take turns holding its
+/// lock, so extra jobs only overlap the work that each build does outside of
+/// the lock. Each task gets a linker script of its own (see `build_task`), so
+/// that builds running at once don't clobber each other's.
+///
+/// Nothing else happens until all of the builds are done, and if any of them
+/// fail, we report the first failure in the order the tasks were given.
+fn build_tasks(cfg: &PackageConfig, names: &[&str], jobs: usize) -> Result<()> {
+    if jobs <= 1 {
+        for name in names {
+            build_task(cfg, name)?;
+        }
+        return Ok(());
+    }
+
+    let next = AtomicUsize::new(0);
+    let failed = AtomicBool::new(false);
+    let results = Mutex::new(BTreeMap::new());
+    std::thread::scope(|s| {
+        for _ in 0..jobs.min(names.len()) {
+            s.spawn(|| {
+                // Don't start any more builds once one has failed.
+                while !failed.load(Ordering::Relaxed) {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(name) = names.get(i) else {
+                        break;
+                    };
+                    let r = build_task(cfg, name);
+                    if r.is_err() {
+                        failed.store(true, Ordering::Relaxed);
+                    }
+                    results.lock().unwrap().insert(i, r);
+                }
+            });
+        }
+    });
+    for (_, r) in results.into_inner().unwrap() {
+        r?;
+    }
+    Ok(())
+}
+
 /// Builds a specific task
+///
+/// Its linker script goes in a directory of its own within the target
+/// directory, which we pass to the linker as a search path, so that the same
+/// script is used whether or not other tasks are being built at the same time.
 fn build_task(cfg: &PackageConfig, name: &str) -> Result<()> {
     // Use relocatable linker script for this build
-    fs::copy("build/task-rlink.x", "target/link.x")?;
+    let link_dir = cfg.target_dir.join("link").join(name);
+    std::fs::create_dir_all(&link_dir)?;
+    let link_script = link_dir.join("link.x");
+    fs::copy("build/task-rlink.x", &link_script)?;
     // Append any task-specific sections.
     {
         let task_toml = &cfg.toml.tasks[name];
         let mut linkscr = std::fs::OpenOptions::new()
             .create(false)
             .append(true)
-            .open("target/link.x")?;
+            .open(&link_script)?;
         append_task_sections(&mut linkscr, Some(&task_toml.sections))?;
     }
 
@@ -1109,7 +1323,7 @@ fn build_task(cfg: &PackageConfig, name: &str) -> Result<()> {
         .toml
         .task_build_config(name, cfg.verbose, Some(&cfg.sysroot))
         .unwrap();
-    build(cfg, name, build_config, true)
+    build(cfg, name, build_config, true, &link_dir)
         .context(format!("failed to build {}", name))
 }
 
@@ -1536,20 +1750,41 @@ fn build_kernel(
     entry_points: &HashMap<String, u32>,
     image_name: &str,
 ) -> Result<(u32, BTreeMap<String, u32>)> {
-    let mut image_id = fnv::FnvHasher::default();
-    all_output_sections.hash(&mut image_id);
-
     // Format the descriptors for the kernel build.
     let kconfig =
         make_kconfig(&cfg.toml, &allocs.tasks, entry_points, image_name)?;
+    // Keep a readable copy for the build archive, along with the allocations,
+    // so that the image ID can be checked against them.
+    fs::write(
+        cfg.img_file("kconfig.ron", image_name),
+        ron::ser::to_string_pretty(
+            &kconfig,
+            ron::ser::PrettyConfig::default(),
+        )?,
+    )?;
+    fs::write(
+        cfg.img_file("allocations.ron", image_name),
+        ron::ser::to_string_pretty(allocs, ron::ser::PrettyConfig::default())?,
+    )?;
+    // Describe the image for external tools; the image ID is filled in once
+    // we've finished computing it below.
+    let mut manifest = crate::manifest::Manifest::new(
+        &cfg.toml, allocs, &kconfig, image_name,
+    )?;
     let kconfig = ron::ser::to_string(&kconfig)?;
-
-    kconfig.hash(&mut image_id);
-    allocs.hash(&mut image_id);
+    let image_id = image_id(
+        all_output_sections
+            .iter()
+            .map(|(&addr, segment)| (addr, segment.data.as_slice())),
+        &kconfig,
+        allocs,
+    );
 
     let extern_regions = cfg.toml.kernel_extern_regions(image_name)?;
+    // The kernel's linker scripts must be in its target directory, which is
+    // where the linker looks for them.
     generate_kernel_linker_script(
-        "memory.x",
+        &cfg.target_dir.join("memory.x"),
         &allocs.kernel,
         cfg.toml.kernel.stacksize.unwrap_or(DEFAULT_KERNEL_STACK),
         &cfg.toml.all_regions("flash".to_string())?,
@@ -1557,9 +1792,10 @@ fn build_kernel(
         image_name,
     )?;
 
-    fs::copy("build/kernel-link.x", "target/link.x")?;
+    fs::copy("build/kernel-link.x", cfg.target_dir.join("link.x"))?;
 
-    let image_id = image_id.finish();
+    manifest.image_id = image_id;
+    manifest.write(&cfg.img_file("manifest.json", image_name))?;
 
     let flash_outputs = if let Some(o) = cfg.toml.outputs.get("flash") {
         ron::ser::to_string(o)?
@@ -1577,7 +1813,7 @@ fn build_kernel(
         ],
         Some(&cfg.sysroot),
     );
-    build(cfg, "kernel", build_config, false)?;
+    build(cfg, "kernel", build_config, false, &cfg.target_dir)?;
     if update_image_header(
         cfg,
         &cfg.dist_file("kernel"),
@@ -1771,6 +2007,9 @@ fn check_task_priorities(toml: &Config) -> Result<()> {
         } else if i != 0 && task.priority == 0 {
             bail!("Task {} is not the supervisor, but has priority 0", name,);
         }
+        if task.time_slice == Some(0) {
+            bail!("task {} has a time-slice of 0 ticks", name);
+        }
     }
 
     Ok(())
@@ -1897,7 +2136,7 @@ fn append_task_sections(
 }
 
 fn generate_kernel_linker_script(
-    name: &str,
+    path: &Path,
     map: &BTreeMap<String, Range<u32>>,
     stacksize: u32,
     images: &IndexMap<String, Range<u32>>,
@@ -1905,8 +2144,7 @@ fn generate_kernel_linker_script(
     image_name: &str,
 ) -> Result<()> {
     // Put the linker script somewhere the linker can find it
-    let mut linkscr =
-        File::create(Path::new(&format!("target/{}", name))).unwrap();
+    let mut linkscr = File::create(path).unwrap();
 
     let mut stack_start = None;
     let mut stack_base = None;
@@ -1977,6 +2215,7 @@ fn build(
     name: &str,
     build_config: BuildConfig,
     reloc: bool,
+    link_dir: &Path,
 ) -> Result<()> {
     println!("building crate {}", build_config.crate_name);
 
@@ -1991,8 +2230,11 @@ fn build(
     }
 
     // This works because we control the environment in which we're about
-    // to invoke cargo, and never modify CARGO_TARGET in that environment.
-    let cargo_out = Path::new("target").to_path_buf();
+    // to invoke cargo, and only modify CARGO_TARGET_DIR to build elsewhere.
+    let cargo_out = cfg.target_dir.clone();
+    if cargo_out != Path::new("target") {
+        cmd.env("CARGO_TARGET_DIR", &cargo_out);
+    }
 
     let remap_path_prefix =
         cfg.remap_paths.iter().fold(String::new(), |mut output, r| {
@@ -2028,7 +2270,7 @@ fn build(
     cmd.arg("-C")
         .arg("link-arg=-Tlink.x")
         .arg("-L")
-        .arg(format!("{}", cargo_out.display()));
+        .arg(format!("{}", link_dir.display()));
     if reloc {
         cmd.arg("-C").arg("link-arg=-r");
     }
@@ -2061,24 +2303,22 @@ fn build(
         .spawn()
         .context("Failed to start child process")?;
 
-    let mut child_stderr =
-        child.stderr.take().context("Failed to take stderr")?;
+    let child_stderr = child.stderr.take().context("Failed to take stderr")?;
     let reader_thread = std::thread::spawn(move || {
         let mut out_bytes = vec![];
-        let mut stderr = std::io::stderr();
-        loop {
-            let mut buf = [0u8; 256];
-            let num = child_stderr.read(&mut buf).unwrap();
-            if num == 0 {
-                break;
-            }
+        for line in BufReader::new(child_stderr).split(b'\n') {
+            let mut line = line.unwrap();
+            line.push(b'\n');
 
             // Immediately echo `stderr` back out, using a raw write because it
-            // may contain terminal control characters
-            stderr.write_all(&buf[0..num]).unwrap();
+            // may contain terminal control characters.  We write whole lines,
+            // so that the output of builds running in parallel doesn't get
+            // mixed up within a line.
+            let mut stderr = std::io::stderr().lock();
+            stderr.write_all(&line).unwrap();
             stderr.flush().unwrap();
 
-            out_bytes.extend(buf[0..num].iter());
+            out_bytes.extend(line);
         }
         out_bytes
     });
@@ -2225,7 +2465,7 @@ fn link(
     Ok(())
 }
 
-#[derive(Debug, Clone, Default, Hash)]
+#[derive(Debug, Clone, Default, Hash, Serialize, Deserialize)]
 pub struct Allocations {
     /// Map from memory-name to address-range
     pub kernel: BTreeMap<String, Range<u32>>,
@@ -2306,6 +2546,10 @@ pub struct TaskRequest<'a> {
 ///
 /// This means that the algorithm needs to keep track of a queue of pending
 /// requests per alignment size.
+///
+/// If the app.toml sets `allocator = "optimize"`, we instead search over the
+/// order and direction in which tasks are placed for the layout with the least
+/// padding, falling back to the greedy layout if the search can't beat it.
 pub fn allocate_all(
     toml: &Config,
     task_sizes: &HashMap<&str, TaskRequest>,
@@ -2371,7 +2615,7 @@ pub fn allocate_all(
             let mut k_req = kernel_requests.get(region.as_str());
             let t_reqs = task_requests.get_mut(region.as_str());
             let mut t_reqs_empty = IndexMap::new();
-            allocate_region(
+            let padding = allocate_region(
                 region,
                 toml,
                 &mut k_req,
@@ -2379,6 +2623,14 @@ pub fn allocate_all(
                 avail,
                 &mut allocs,
             )?;
+            if toml.allocator == Allocator::Optimize {
+                println!(
+                    "{image_name} {region}: {} bytes of padding between \
+                     tasks, {} fewer than greedy allocation",
+                    padding.actual,
+                    padding.greedy - padding.actual,
+                );
+            }
         }
 
         if let Some(caboose) = caboose {
@@ -2403,6 +2655,14 @@ pub fn allocate_all(
     Ok(result)
 }
 
+/// Padding left between task allocations in a single region, as laid out by
+/// the greedy allocator and by whichever allocator was actually used
+#[derive(Copy, Clone, Debug)]
+struct Padding {
+    greedy: u64,
+    actual: u64,
+}
+
 fn allocate_region(
     region: &str,
     toml: &Config,
@@ -2410,7 +2670,7 @@ fn allocate_region(
     t_reqs: &mut IndexMap<&str, OrderedVecDeque>,
     avail: &mut Range<u32>,
     allocs: &mut Allocations,
-) -> Result<()> {
+) -> Result<Padding> {
     // The kernel gets to go first!
     if let Some(&sz) = k_req.take() {
         allocs
@@ -2418,175 +2678,386 @@ fn allocate_region(
             .insert(region.to_string(), allocate_k(region, sz, avail)?);
     }
 
-    while !t_reqs.is_empty() {
-        // At this point, we need to find a task that fits based on our existing
-        // alignment.  This is tricky, because -- for efficient packing -- we
-        // allow tasks to span multiple regions.  For example, a task could look
-        // like this:
-        //
-        //   4444221
-        //
-        // representing three regions of size 4, 2, 1.
-        //
-        // Such a task could be placed in two ways:
-        //
-        //      |4444221 ("forward")
-        //   122|4444    ("reverse")
-        //      | where this line is the alignment for the largest chunk
-
-        #[derive(Debug)]
-        enum Direction {
-            Forward,
-            Reverse,
-        }
-        #[derive(Debug)]
-        struct Match<'a> {
-            gap: u32,
+    // Work out the order (and direction) in which to place tasks before
+    // actually placing them, so that the optimizer can compare its layout
+    // against the greedy one.
+    let start = u64::from(avail.start);
+    let total: u64 = t_reqs
+        .values()
+        .flat_map(|m| m.iter())
+        .map(|&s| u64::from(s))
+        .sum();
+    let greedy = greedy_placement(toml, t_reqs, start);
+    let greedy_end = placement_end(toml, t_reqs, &greedy, start);
+    let plan = match toml.allocator {
+        Allocator::Greedy => greedy,
+        Allocator::Optimize => {
+            optimize_placement(toml, t_reqs, start, greedy_end)
+                .unwrap_or(greedy)
+        }
+    };
+
+    for (name, dir) in plan {
+        let Some(sizes) = t_reqs.remove(name) else {
+            panic!("could not find a task");
+        };
+        place_task(region, toml, name, sizes, dir, avail, allocs)?;
+    }
+
+    Ok(Padding {
+        greedy: greedy_end - start - total,
+        actual: u64::from(avail.start) - start - total,
+    })
+}
+
+/// Direction in which a task's regions are laid out in memory
+///
+/// For efficient packing, we allow tasks to span multiple regions.  For
+/// example, a task could look like this:
+///
+///   4444221
+///
+/// representing three regions of size 4, 2, 1.
+///
+/// Such a task could be placed in two ways:
+///
+///      |4444221 ("forward")
+///   122|4444    ("reverse")
+///      | where this line is the alignment for the largest chunk
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Direction {
+    Forward,
+    Reverse,
+}
+
+/// Returns the padding needed to place a task with the given regions at
+/// `pos`, in direction `dir`.
+fn placement_gap(
+    toml: &Config,
+    mem: &OrderedVecDeque,
+    pos: u64,
+    dir: Direction,
+) -> u64 {
+    let align = u64::from(toml.task_memory_alignment(*mem.front().unwrap()));
+    let size_mask = align - 1;
+    match dir {
+        // Place the chunk with padding before it if it's not aligned.
+        Direction::Forward => ((pos + size_mask) & !size_mask) - pos,
+        // Place the chunk so that the largest region (which is last in memory)
+        // ends up aligned, with padding before it if needed.
+        Direction::Reverse => {
+            let total_size: u64 = mem.iter().map(|&s| u64::from(s)).sum();
+            ((pos + total_size + size_mask) & !size_mask) - pos - total_size
+        }
+    }
+}
+
+/// Returns the address just past the last task, if `plan` were laid out
+/// starting at `start`.
+fn placement_end(
+    toml: &Config,
+    t_reqs: &IndexMap<&str, OrderedVecDeque>,
+    plan: &[(&str, Direction)],
+    start: u64,
+) -> u64 {
+    plan.iter().fold(start, |pos, (name, dir)| {
+        let mem = &t_reqs[name];
+        let total_size: u64 = mem.iter().map(|&s| u64::from(s)).sum();
+        pos + placement_gap(toml, mem, pos, *dir) + total_size
+    })
+}
+
+/// Picks the order in which to place tasks by choosing, one at a time,
+/// whichever task fits best at the current position.
+fn greedy_placement<'a>(
+    toml: &Config,
+    t_reqs: &IndexMap<&'a str, OrderedVecDeque>,
+    start: u64,
+) -> Vec<(&'a str, Direction)> {
+    #[derive(Debug)]
+    struct Match<'a> {
+        gap: u64,
+        align: u32,
+        size: u32,
+        index: usize,
+        name: &'a str,
+        dir: Direction,
+    }
+    impl<'a> Match<'a> {
+        /// Updates our "current best" with new values, if they're better
+        ///
+        /// Our policy is to rank by
+        /// 1) smallest gap required, and then
+        /// 2) largest resulting alignment
+        /// 3) smallest task size (for backwards compatibility)
+        fn update(
+            &mut self,
+            gap: u64,
             align: u32,
             size: u32,
+            index: usize,
             name: &'a str,
             dir: Direction,
-        }
-        impl<'a> Match<'a> {
-            /// Updates our "current best" with new values, if they're better
-            ///
-            /// Our policy is to rank by
-            /// 1) smallest gap required, and then
-            /// 2) largest resulting alignment
-            /// 3) smallest task size (for backwards compatibility)
-            fn update(
-                &mut self,
-                gap: u32,
-                align: u32,
-                size: u32,
-                name: &'a str,
-                dir: Direction,
-            ) {
-                // Ignore any gap that's < 1/8 of final alignment, since that's
-                // "close enough"
-                let gap_m = gap.saturating_sub(align / 8);
-                let our_gap_m = self.gap.saturating_sub(self.align / 8);
-                if gap_m < our_gap_m
-                    || (gap_m == our_gap_m && align > self.align)
-                    || gap < self.gap
-                    || (gap == self.gap && align > self.align)
-                    || (gap == self.gap
-                        && align == self.align
-                        && size < self.size)
-                {
-                    self.gap = gap;
-                    self.align = align;
-                    self.size = size;
-                    self.name = name;
-                    self.dir = dir;
-                }
+        ) {
+            // Ignore any gap that's < 1/8 of final alignment, since that's
+            // "close enough"
+            let gap_m = gap.saturating_sub(u64::from(align / 8));
+            let our_gap_m = self.gap.saturating_sub(u64::from(self.align / 8));
+            if gap_m < our_gap_m
+                || (gap_m == our_gap_m && align > self.align)
+                || gap < self.gap
+                || (gap == self.gap && align > self.align)
+                || (gap == self.gap && align == self.align && size < self.size)
+            {
+                self.gap = gap;
+                self.align = align;
+                self.size = size;
+                self.index = index;
+                self.name = name;
+                self.dir = dir;
             }
         }
+    }
 
+    // This mirrors the order in which the tasks would be visited if we were
+    // removing them from `t_reqs` as we go, which matters for breaking ties.
+    let mut pending: Vec<(&str, &OrderedVecDeque)> =
+        t_reqs.iter().map(|(&name, mem)| (name, mem)).collect();
+    let mut pos = start;
+    let mut plan = vec![];
+    while !pending.is_empty() {
         let mut best = Match {
-            gap: u32::MAX,
+            gap: u64::MAX,
             align: 0,
             size: u32::MAX,
+            index: 0,
             name: "",
             dir: Direction::Forward,
         };
 
-        for (&task_name, mem) in t_reqs.iter() {
-            let align = toml.task_memory_alignment(*mem.front().unwrap());
-            let size_mask = align - 1;
+        for (index, &(task_name, mem)) in pending.iter().enumerate() {
+            let total_size: u32 = mem.iter().sum();
 
-            // Place the chunk using reverse orientation, with padding if it's
-            // not aligned.  The alignment (for scoring purposes) is the
+            // The alignment (for scoring purposes) of a reversed task is the
             // alignment of the largest region, since that's last in memory.
-            let total_size: u32 = mem.iter().sum();
-            let base = (avail.start + total_size + size_mask) & !size_mask;
-            let gap_reverse = base - avail.start - total_size;
             best.update(
-                gap_reverse,
-                align,
+                placement_gap(toml, mem, pos, Direction::Reverse),
+                toml.task_memory_alignment(*mem.front().unwrap()),
                 total_size,
+                index,
                 task_name,
                 Direction::Reverse,
             );
 
-            // Place the chunk using forward orientation, with padding if it's
-            // not aligned.  The alignment (for scoring purposes) is the
-            // alignment of the last region, since that may be worse than the
-            // starting alignment.
-            let base = (avail.start + size_mask) & !size_mask;
-            let gap_forward = base - avail.start;
+            // The alignment of a forward task is the alignment of the last
+            // region, since that may be worse than the starting alignment.
             best.update(
-                gap_forward,
+                placement_gap(toml, mem, pos, Direction::Forward),
                 toml.task_memory_alignment(*mem.back().unwrap()),
                 total_size,
+                index,
                 task_name,
                 Direction::Forward,
             );
         }
-        let Some(sizes) = t_reqs.remove(best.name) else {
-            panic!("could not find a task");
-        };
+        pending.swap_remove(best.index);
+        pos += best.gap + u64::from(best.size);
+        plan.push((best.name, best.dir));
+    }
+    plan
+}
 
-        // Prepare to pack values either forward or reverse
-        //
-        // At this point, we drop the "values must be ordered" constraint,
-        // because we may combine adjacent regions to reduce the total region
-        // count.  This could violate the ordering constraint, but is still
-        // valid from the MPU's perspective.
-        let mut sizes: VecDeque<u32> = match best.dir {
-            Direction::Forward => sizes.into(),
-            Direction::Reverse => sizes.into_iter().rev().collect(),
-        };
-        avail.start += best.gap;
+/// Maximum number of partial layouts that `optimize_placement` will explore in
+/// a single region.  This keeps large images from taking forever to allocate;
+/// since the search is deterministic, hitting the limit still gives the same
+/// layout on every build.
+const OPTIMIZE_SEARCH_LIMIT: usize = 100_000;
 
-        while let Some(mut size) = sizes.pop_front() {
-            // When building the size list, we split the largest size to reduce
-            // alignment requirements.  Now, we try to merge them again, to
-            // reduce the number of regions stored in the kernel's flash.
-            //
-            // For example, [256, 256, 64] => [512, 64] if the initial position
-            // is aligned for a 512-byte region.
-            let mut n = sizes.iter().filter(|s| **s == size).count() + 1;
-            if n > 1 {
-                n &= !1; // only consider an even number of regions
-                let possible_align = toml.task_memory_alignment(size * 2);
-                if avail.start & (possible_align - 1) == 0 {
-                    size *= 2;
-                    for _ in 0..n - 1 {
-                        sizes.pop_front();
-                    }
-                    for _ in 0..n / 2 - 1 {
-                        sizes.push_front(size);
+/// Searches over task orders and directions for a layout that ends before
+/// `bound` (i.e. wastes less space on padding than whatever produced `bound`).
+///
+/// Returns `None` if nothing better was found.
+fn optimize_placement<'a>(
+    toml: &Config,
+    t_reqs: &IndexMap<&'a str, OrderedVecDeque>,
+    start: u64,
+    bound: u64,
+) -> Option<Vec<(&'a str, Direction)>> {
+    // Tasks with identical region sizes are interchangeable, so we search over
+    // classes of tasks rather than individual tasks.  Within a class, tasks
+    // are placed in the order they appear in the app.toml.
+    struct Class<'a, 'b> {
+        mem: &'b OrderedVecDeque,
+        size: u64,
+        names: Vec<&'a str>,
+    }
+    let mut classes: Vec<Class> = vec![];
+    for (&name, mem) in t_reqs {
+        match classes.iter_mut().find(|c| c.mem.iter().eq(mem.iter())) {
+            Some(c) => c.names.push(name),
+            None => classes.push(Class {
+                mem,
+                size: mem.iter().map(|&s| u64::from(s)).sum(),
+                names: vec![name],
+            }),
+        }
+    }
+
+    struct Search<'c, 'a, 'b> {
+        toml: &'c Config,
+        classes: &'c [Class<'a, 'b>],
+        /// Partial layouts that we've already explored, as (number of tasks
+        /// remaining in each class, position)
+        visited: HashSet<(Vec<usize>, u64)>,
+        path: Vec<(usize, Direction)>,
+        best: Option<Vec<(usize, Direction)>>,
+        best_end: u64,
+    }
+    impl Search<'_, '_, '_> {
+        fn visit(&mut self, pos: u64, remaining: u64, counts: &mut [usize]) {
+            if remaining == 0 {
+                if pos < self.best_end {
+                    self.best_end = pos;
+                    self.best = Some(self.path.clone());
+                }
+                return;
+            }
+            if self.visited.len() >= OPTIMIZE_SEARCH_LIMIT
+                || !self.visited.insert((counts.to_vec(), pos))
+            {
+                return;
+            }
+
+            // Try the placements that waste the least space first, so that we
+            // find good layouts early and can prune more aggressively.
+            let mut options = vec![];
+            for (i, c) in self.classes.iter().enumerate() {
+                if counts[i] == 0 {
+                    continue;
+                }
+                for dir in [Direction::Forward, Direction::Reverse] {
+                    // A single region lands in the same place either way.
+                    if dir == Direction::Reverse && c.mem.iter().count() == 1 {
+                        continue;
                     }
+                    let gap = placement_gap(self.toml, c.mem, pos, dir);
+                    options.push((gap, i, dir));
                 }
             }
+            options.sort();
+
+            for (gap, i, dir) in options {
+                let size = self.classes[i].size;
+                let end = pos + gap + size;
+                // Even with no more padding, this can't beat our best.
+                if end + (remaining - size) >= self.best_end {
+                    continue;
+                }
+                counts[i] -= 1;
+                self.path.push((i, dir));
+                self.visit(end, remaining - size, counts);
+                self.path.pop();
+                counts[i] += 1;
+            }
+        }
+    }
 
-            // We do our own alignment management, so assert that we haven't
-            // messed it up:
-            let align = toml.task_memory_alignment(size);
-            assert!(avail.start & (align - 1) == 0);
+    let mut search = Search {
+        toml,
+        classes: &classes,
+        visited: HashSet::new(),
+        path: vec![],
+        best: None,
+        best_end: bound,
+    };
+    let mut counts: Vec<usize> =
+        classes.iter().map(|c| c.names.len()).collect();
+    let total = classes.iter().map(|c| c.size * c.names.len() as u64).sum();
+    search.visit(start, total, &mut counts);
+
+    let mut next = vec![0; classes.len()];
+    search.best.map(|best| {
+        best.into_iter()
+            .map(|(i, dir)| {
+                next[i] += 1;
+                (classes[i].names[next[i] - 1], dir)
+            })
+            .collect()
+    })
+}
 
-            allocs
-                .tasks
-                .entry(best.name.to_string())
-                .or_default()
-                .entry(region.to_string())
-                .or_default()
-                .push(allocate_one(region, size, align, avail)?);
-        }
+/// Places a single task's regions at the start of `avail`, padding as needed
+/// for alignment.
+fn place_task(
+    region: &str,
+    toml: &Config,
+    name: &str,
+    sizes: OrderedVecDeque,
+    dir: Direction,
+    avail: &mut Range<u32>,
+    allocs: &mut Allocations,
+) -> Result<()> {
+    let gap = placement_gap(toml, &sizes, u64::from(avail.start), dir);
+
+    // Prepare to pack values either forward or reverse
+    //
+    // At this point, we drop the "values must be ordered" constraint,
+    // because we may combine adjacent regions to reduce the total region
+    // count.  This could violate the ordering constraint, but is still
+    // valid from the MPU's perspective.
+    let mut sizes: VecDeque<u32> = match dir {
+        Direction::Forward => sizes.into(),
+        Direction::Reverse => sizes.into_iter().rev().collect(),
+    };
+    avail.start += u32::try_from(gap).unwrap();
 
-        // Check that our allocations are all aligned and contiguous
-        let mut prev: Option<Range<u32>> = None;
-        for r in &allocs.tasks[best.name][region] {
-            if let Some(prev) = prev {
-                assert_eq!(prev.end, r.start);
+    while let Some(mut size) = sizes.pop_front() {
+        // When building the size list, we split the largest size to reduce
+        // alignment requirements.  Now, we try to merge them again, to
+        // reduce the number of regions stored in the kernel's flash.
+        //
+        // For example, [256, 256, 64] => [512, 64] if the initial position
+        // is aligned for a 512-byte region.
+        let mut n = sizes.iter().filter(|s| **s == size).count() + 1;
+        if n > 1 {
+            n &= !1; // only consider an even number of regions
+            let possible_align = toml.task_memory_alignment(size * 2);
+            if avail.start & (possible_align - 1) == 0 {
+                size *= 2;
+                for _ in 0..n - 1 {
+                    sizes.pop_front();
+                }
+                for _ in 0..n / 2 - 1 {
+                    sizes.push_front(size);
+                }
             }
-            let size = r.end - r.start;
-            assert!(size >= 32); // minimum MPU size
-            let align = toml.task_memory_alignment(size);
-            assert!(r.start.trailing_zeros() >= align.trailing_zeros());
-            prev = Some(r.clone());
         }
+
+        // We do our own alignment management, so assert that we haven't
+        // messed it up:
+        let align = toml.task_memory_alignment(size);
+        assert!(avail.start & (align - 1) == 0);
+
+        allocs
+            .tasks
+            .entry(name.to_string())
+            .or_default()
+            .entry(region.to_string())
+            .or_default()
+            .push(allocate_one(region, size, align, avail)?);
+    }
+
+    // Check that our allocations are all aligned and contiguous
+    let mut prev: Option<Range<u32>> = None;
+    for r in &allocs.tasks[name][region] {
+        if let Some(prev) = prev {
+            assert_eq!(prev.end, r.start);
+        }
+        let size = r.end - r.start;
+        assert!(size >= 32); // minimum MPU size
+        let align = toml.task_memory_alignment(size);
+        assert!(r.start.trailing_zeros() >= align.trailing_zeros());
+        prev = Some(r.clone());
     }
 
     Ok(())
@@ -2813,7 +3284,9 @@ pub fn make_kconfig(
                 offset: stacksize,
             },
             priority: task.priority,
+            time_slice: task.time_slice,
             start_at_boot: task.start,
+            read_image: task.read_image,
         });
 
         // Interrupts.
@@ -2879,15 +3352,20 @@ pub fn make_kconfig(
     // Pare down the list of shared regions.
     flat_shared.retain(|name, _v| used_shared_regions.contains(name.as_str()));
 
+    let extern_regions = toml.kernel_extern_regions(image_name)?;
+    let fault_log = toml
+        .kernel
+        .fault_log
+        .as_ref()
+        .map(|name| extern_regions[name].clone());
+
     Ok(build_kconfig::KernelConfig {
         features: toml.kernel.features.clone(),
-        extern_regions: toml
-            .kernel_extern_regions(image_name)?
-            .into_iter()
-            .collect(),
+        extern_regions: extern_regions.into_iter().collect(),
         irqs,
         tasks,
         shared_regions: flat_shared,
+        fault_log,
     })
 }
 
@@ -2913,7 +3391,6 @@ fn load_elf(
     symbol_table: &mut BTreeMap<String, u32>,
 ) -> Result<usize> {
     use goblin::container::Container;
-    use goblin::elf::program_header::PT_LOAD;
 
     let file_image = std::fs::read(input)?;
     let elf = goblin::elf::Elf::parse(&file_image)?;
@@ -2925,18 +3402,10 @@ fn load_elf(
     let mut flash = 0;
 
     // Good enough.
-    for phdr in &elf.program_headers {
-        // Skip sections that aren't intended to be loaded.
-        if phdr.p_type != PT_LOAD {
-            continue;
-        }
-        let offset = phdr.p_offset as usize;
-        let size = phdr.p_filesz as usize;
-        // Note that we are using Physical, i.e. LOADADDR, rather than virtual.
-        // This distinction is important for things like the rodata image, which
-        // is loaded in flash but expected to be copied to RAM.
-        let addr = phdr.p_paddr as u32;
-
+    let segments = load_segments(&elf, &file_image)
+        .with_context(|| format!("loading {}", input.display()))?;
+    for (addr, data) in segments {
+        let size = data.len();
         flash += size;
 
         // We use this function to re-load an ELF file after we've modified
@@ -2970,7 +3439,7 @@ fn load_elf(
             addr,
             LoadSegment {
                 source_file: input.into(),
-                data: file_image[offset..offset + size].to_vec(),
+                data: data.to_vec(),
             },
         );
     }
@@ -3018,8 +3487,11 @@ impl Archive {
             final_path,
             tmp_path,
             inner,
+            // Every file gets the same (DOS epoch) timestamp, so that
+            // rebuilding an archive produces the same bytes.
             opts: zip::write::FileOptions::default()
-                .compression_method(zip::CompressionMethod::Deflated),
+                .compression_method(zip::CompressionMethod::Deflated)
+                .last_modified_time(zip::DateTime::default()),
         })
     }
 
@@ -3101,9 +3573,16 @@ fn get_git_status() -> Result<(String, bool)> {
     Ok((rev, !status.success()))
 }
 
-fn cargo_clean(names: &[&str], target: &str) -> Result<()> {
+fn cargo_clean(
+    names: &[&str],
+    target: &str,
+    target_dir: Option<&Path>,
+) -> Result<()> {
     let mut cmd = Command::new("cargo");
     cmd.arg("clean");
+    if let Some(dir) = target_dir {
+        cmd.arg("--target-dir").arg(dir);
+    }
     println!("cleaning {:?}", names);
     for name in names {
         cmd.arg("-p").arg(name);
@@ -3179,6 +3658,62 @@ fn resolve_task_slots(
     Ok(std::fs::write(task_bin, out_task_bin)?)
 }
 
+/// Checks that each task slot through which the task sends messages to an
+/// Idol interface (as recorded by `task_slot!(VAR, slot, ClientType)`) is
+/// bound to a task that serves that interface, and that the server has a
+/// higher priority than the client.
+fn check_slot_interfaces(
+    cfg: &PackageConfig,
+    task_name: &str,
+    image_name: &str,
+) -> Result<()> {
+    let task_toml = &cfg.toml.tasks[task_name];
+
+    let task_bin = std::fs::read(cfg.img_file(task_name, image_name))?;
+    let elf = goblin::elf::Elf::parse(&task_bin)?;
+
+    for (slot, interface) in
+        task_slot::get_task_slot_interfaces(&task_bin, &elf)?
+    {
+        let Some(target) = task_toml.task_slots.get(slot) else {
+            bail!(
+                "task '{task_name}' uses interface {interface} through task \
+                 slot '{slot}', but that slot is missing from the app.toml"
+            );
+        };
+        let Some(target_toml) = cfg.toml.tasks.get(target) else {
+            bail!(
+                "app.toml sets task '{task_name}' task_slot '{slot}' to task \
+                 '{target}', but no such task exists in the app.toml"
+            );
+        };
+        if !target_toml.serves.iter().any(|s| s == interface) {
+            let serves = if target_toml.serves.is_empty() {
+                "no interfaces".to_owned()
+            } else {
+                target_toml.serves.join(", ")
+            };
+            bail!(
+                "task '{task_name}' uses interface {interface} through task \
+                 slot '{slot}', but that slot is bound to task '{target}', \
+                 which serves {serves}; if it does serve {interface}, add it \
+                 to `serves` in [tasks.{target}]"
+            );
+        }
+        if target_toml.priority >= task_toml.priority && target != task_name {
+            bail!(
+                "Priority inversion: task '{task_name}' (priority {}) sends \
+                 {interface} messages through task slot '{slot}' to task \
+                 '{target}' (priority {})",
+                task_toml.priority,
+                target_toml.priority,
+            );
+        }
+    }
+
+    Ok(())
+}
+
 fn resolve_caboose_pos(
     cfg: &PackageConfig,
     task_name: &str,
@@ -3219,3 +3754,193 @@ fn resolve_caboose_pos(
 
     Ok(std::fs::write(task_bin, out_task_bin)?)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NAMES: [&str; 6] = ["a", "b", "c", "d", "e", "f"];
+
+    /// Loads an app for an MPU that needs power-of-two regions, which is where
+    /// the padding between tasks comes from.
+    fn config(allocator: Allocator) -> Config {
+        let mut toml = Config::from_file(Path::new(
+            "../../app/demo-stm32h7-nucleo/app-h753.toml",
+        ))
+        .unwrap();
+        toml.allocator = allocator;
+        toml
+    }
+
+    /// Allocates tasks with the given region sizes (largest first) starting
+    /// at `start`, naming them from `NAMES` in order.
+    fn allocate(
+        toml: &Config,
+        start: u32,
+        tasks: &[&[u32]],
+    ) -> (Padding, Allocations) {
+        let mut t_reqs: IndexMap<&str, OrderedVecDeque> = tasks
+            .iter()
+            .zip(NAMES)
+            .map(|(sizes, name)| {
+                let mut mem = OrderedVecDeque::new();
+                for &s in *sizes {
+                    mem.push_back(s);
+                }
+                (name, mem)
+            })
+            .collect();
+        let mut avail = start..0x3000_0000;
+        let mut allocs = Allocations::default();
+        let padding = allocate_region(
+            "flash",
+            toml,
+            &mut None,
+            &mut t_reqs,
+            &mut avail,
+            &mut allocs,
+        )
+        .unwrap();
+        assert!(t_reqs.is_empty());
+        (padding, allocs)
+    }
+
+    /// Returns the (start, end) of each of the task's regions in flash
+    fn ranges(allocs: &Allocations, task: &str) -> Vec<(u32, u32)> {
+        allocs.tasks[task]["flash"]
+            .into_iter()
+            .map(|r| (r.start, r.end))
+            .collect()
+    }
+
+    #[test]
+    fn greedy_layout_is_unchanged() {
+        // These are the layouts that the allocator produced before it could
+        // optimize, and must keep producing unless asked to.
+        let toml = config(Allocator::Greedy);
+        let (padding, allocs) = allocate(
+            &toml,
+            0x0800_6000,
+            &[
+                &[16384],
+                &[8192, 4096],
+                &[2048],
+                &[4096, 1024, 512],
+                &[1024],
+                &[8192],
+            ],
+        );
+        assert_eq!(padding.actual, 512);
+        assert_eq!(padding.actual, padding.greedy);
+        assert_eq!(ranges(&allocs, "a"), [(0x0800_8000, 0x0800_c000)]);
+        assert_eq!(
+            ranges(&allocs, "b"),
+            [(0x0800_c000, 0x0800_e000), (0x0800_e000, 0x0800_f000)]
+        );
+        assert_eq!(ranges(&allocs, "c"), [(0x0800_f000, 0x0800_f800)]);
+        assert_eq!(
+            ranges(&allocs, "d"),
+            [
+                (0x0800_fa00, 0x0800_fc00),
+                (0x0800_fc00, 0x0801_0000),
+                (0x0801_0000, 0x0801_1000)
+            ]
+        );
+        assert_eq!(ranges(&allocs, "e"), [(0x0801_1000, 0x0801_1400)]);
+        assert_eq!(ranges(&allocs, "f"), [(0x0800_6000, 0x0800_8000)]);
+
+        let (padding, allocs) = allocate(
+            &toml,
+            0x2400_1400,
+            &[
+                &[4096],
+                &[32768],
+                &[2048, 512],
+                &[16384, 8192],
+                &[1024, 256],
+                &[512],
+            ],
+        );
+        assert_eq!(padding.actual, 0);
+        assert_eq!(ranges(&allocs, "a"), [(0x2401_0000, 0x2401_1000)]);
+        assert_eq!(ranges(&allocs, "b"), [(0x2400_8000, 0x2401_0000)]);
+        assert_eq!(
+            ranges(&allocs, "c"),
+            [(0x2400_1600, 0x2400_1800), (0x2400_1800, 0x2400_2000)]
+        );
+        assert_eq!(
+            ranges(&allocs, "d"),
+            [(0x2400_2000, 0x2400_4000), (0x2400_4000, 0x2400_8000)]
+        );
+        assert_eq!(
+            ranges(&allocs, "e"),
+            [(0x2401_1000, 0x2401_1400), (0x2401_1400, 0x2401_1500)]
+        );
+        assert_eq!(ranges(&allocs, "f"), [(0x2400_1400, 0x2400_1600)]);
+    }
+
+    #[test]
+    fn optimized_layout_is_no_worse() {
+        let toml = config(Allocator::Optimize);
+        // (start, task regions, whether we know the greedy layout can be
+        // beaten)
+        let cases: &[(u32, &[&[u32]], bool)] = &[
+            (
+                0x0800_6000,
+                &[
+                    &[16384],
+                    &[8192, 4096],
+                    &[2048],
+                    &[4096, 1024, 512],
+                    &[1024],
+                    &[8192],
+                ],
+                false,
+            ),
+            (
+                0x0801_0000,
+                &[
+                    &[8192, 2048],
+                    &[8192, 2048],
+                    &[8192, 2048],
+                    &[4096],
+                    &[4096],
+                    &[16384, 1024],
+                ],
+                false,
+            ),
+            (
+                0x0800_2d00,
+                &[&[4096, 2048], &[16384, 4096, 2048], &[256, 256], &[16384]],
+                true,
+            ),
+            (0x0800_3700, &[&[32768], &[512], &[32768, 16384]], true),
+            (
+                0x0800_2d00,
+                &[&[1024, 512], &[16384, 8192], &[4096, 2048]],
+                true,
+            ),
+        ];
+        for (start, tasks, improves) in cases {
+            let (padding, allocs) = allocate(&toml, *start, tasks);
+            assert!(padding.actual <= padding.greedy, "{tasks:?}");
+            if *improves {
+                assert!(padding.actual < padding.greedy, "{tasks:?}");
+            }
+
+            // Whatever the order, every task must get the space it asked for
+            // without overlapping any other.
+            let mut all: Vec<(u32, u32)> = NAMES[..tasks.len()]
+                .iter()
+                .flat_map(|name| ranges(&allocs, name))
+                .collect();
+            all.sort();
+            assert!(all.windows(2).all(|w| w[0].1 <= w[1].0));
+            for (name, sizes) in NAMES.iter().zip(tasks.iter()) {
+                let got: u32 =
+                    ranges(&allocs, name).iter().map(|(s, e)| e - s).sum();
+                assert_eq!(got, sizes.iter().sum::<u32>(), "task {name}");
+            }
+        }
+    }
+}
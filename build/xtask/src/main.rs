@@ -18,15 +18,24 @@ mod auxflash;
 mod caboose_pos;
 mod clippy;
 mod config;
+mod diff;
 mod dist;
 mod elf;
 mod flash;
 mod graph;
 mod humility;
 mod lsp;
+mod manifest;
 mod print;
+mod qemu;
+mod repro;
+mod sbom;
+mod schema;
 mod sizes;
 mod task_slot;
+mod trace;
+mod validate;
+mod verify;
 
 #[derive(Debug, Parser)]
 #[clap(max_term_width = 80, about = "extra tasks to help you work on Hubris")]
@@ -48,6 +57,14 @@ enum Xtask {
         /// rebuilding even if it looks like we need to.
         #[clap(long)]
         dirty: bool,
+        /// Number of tasks to build at once.
+        #[clap(short, long, default_value_t = 1)]
+        jobs: usize,
+        /// Build the image twice from scratch, in separate target
+        /// directories, and check that every output is identical. The first
+        /// build runs one job, and the second runs `--jobs`.
+        #[clap(long, conflicts_with = "dirty")]
+        check_reproducible: bool,
         /// Configures the caboose for the generated archive.
         #[clap(flatten)]
         caboose_args: CabooseArgs,
@@ -76,6 +93,9 @@ enum Xtask {
         /// rebuilding even if it looks like we need to.
         #[clap(long)]
         dirty: bool,
+        /// Number of tasks to build at once.
+        #[clap(short, long, default_value_t = 1)]
+        jobs: usize,
     },
 
     /// Runs `xtask dist` and flashes the image onto an attached target
@@ -120,6 +140,12 @@ enum Xtask {
         /// Write JSON out to a file?
         #[clap(long)]
         save: bool,
+
+        /// Warn about tasks whose estimated stack depth is within this
+        /// percentage of their stack size
+        #[clap(long, value_name = "PERCENT")]
+        stack_margin: Option<u8>,
+
         /// Allow operation in a dirty checkout, i.e. don't clean before
         /// rebuilding even if it looks like we need to.
         #[clap(long)]
@@ -150,6 +176,67 @@ enum Xtask {
         caboose_args: CabooseArgs,
     },
 
+    /// Runs `xtask dist` and boots the image in QEMU, printing its UART output
+    ///
+    /// The board's TOML file must have a `[qemu]` section naming the QEMU
+    /// machine. With `--test`, lines of UART output ending in PASS or FAIL
+    /// are counted as test results, and this fails if any test failed, none
+    /// passed, or the image didn't finish (by exiting QEMU or printing a line
+    /// matching `--until`) before the timeout. This is for images that print
+    /// their results to the UART; the Hubris test suite reports through hiffy
+    /// instead, so use humility to check it. Example:
+    ///
+    ///   cargo xtask qemu $APP_TOML --test --until "cycle complete"
+    Qemu {
+        /// Path to the image configuration file, in TOML.
+        cfg: PathBuf,
+
+        /// Image name to run
+        #[clap(long)]
+        image_name: Option<String>,
+
+        /// Request verbosity from tools we shell out to.
+        #[clap(short, long)]
+        verbose: bool,
+
+        /// Do not build a new image; run the one from the last `xtask dist`
+        #[clap(long, short)]
+        nobuild: bool,
+
+        /// Allow operation in a dirty checkout, i.e. don't clean before
+        /// rebuilding even if it looks like we need to.
+        #[clap(long)]
+        dirty: bool,
+
+        /// Check the UART output for test results
+        #[clap(long)]
+        test: bool,
+
+        /// Stop at the first line of UART output matching this regex
+        #[clap(long, value_name = "REGEX")]
+        until: Option<String>,
+
+        /// Stop after this many seconds (default: never, or 60 with `--test`)
+        #[clap(long, value_name = "SECONDS")]
+        timeout: Option<u64>,
+
+        /// Also write the UART output to this file
+        #[clap(long)]
+        log: Option<PathBuf>,
+
+        /// Start QEMU halted, with a GDB server on localhost:1234
+        #[clap(long)]
+        gdb: bool,
+
+        /// Configures the caboose for the generated archive.
+        #[clap(flatten)]
+        caboose_args: CabooseArgs,
+
+        /// Extra options to pass to QEMU
+        #[clap(last = true)]
+        extra_options: Vec<String>,
+    },
+
     /// Runs `cargo clippy` on a specified task
     Clippy {
         /// Request verbosity from tools we shell out to.
@@ -190,6 +277,57 @@ enum Xtask {
         cfg: PathBuf,
     },
 
+    /// Compare two build archives.
+    ///
+    /// Reports differences in task sizes, memory allocations, the expanded
+    /// app.toml, task slot tables, caboose contents, kernel configuration, and
+    /// the sizes of functions and objects in each task.
+    Diff {
+        /// The build archive to compare against.
+        before: PathBuf,
+        /// The build archive to compare.
+        after: PathBuf,
+        /// Print the differences as JSON.
+        #[clap(long)]
+        json: bool,
+    },
+
+    /// Check an app.toml (and any files it inherits from) for mistakes.
+    ///
+    /// Reports unknown keys, values of the wrong type, and references that
+    /// don't resolve (peripherals in `uses`, interrupts and their
+    /// notifications, task slots, memory regions), with the file and line
+    /// responsible. This also runs at the start of `xtask dist`.
+    Validate {
+        /// Path to the image configuration file, in TOML.
+        cfg: PathBuf,
+    },
+
+    /// Print the JSON Schema for app.toml files.
+    Schema {
+        /// Write the schema to this file, rather than to stdout.
+        #[clap(short, long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Check a build archive.
+    ///
+    /// Checks the vector table, image header, image id, caboose, memory
+    /// layout, FWID and (for signed images) signature of a build archive,
+    /// independently of the build that produced it.
+    Verify {
+        /// The build archive to check.
+        archive: PathBuf,
+        /// Check the root keys of a signed image against the roots configured
+        /// in this app.toml.
+        #[clap(long)]
+        app: Option<PathBuf>,
+        /// Check the root keys of a signed image against this certificate
+        /// (may be given up to four times, in order).
+        #[clap(long = "root-cert")]
+        root_certs: Vec<PathBuf>,
+    },
+
     /// Print out information related to the build.
     ///
     /// Currently only useful to print the archive path, but may grow over time.
@@ -210,6 +348,35 @@ enum Xtask {
         expanded_config: bool,
     },
 
+    /// Decode the kernel's event trace ring into a Chrome trace JSON timeline
+    ///
+    /// The kernel must be built with the `trace` feature. The resulting file
+    /// can be opened in `chrome://tracing` or https://ui.perfetto.dev.
+    /// Example:
+    ///
+    ///   cargo xtask trace kernel hubris.core.0 --cfg $APP_TOML -o trace.json
+    Trace {
+        /// Path to the kernel ELF file, used to find the trace ring.
+        kernel: PathBuf,
+
+        /// Memory to read the trace ring from: either an ELF core dump, or a
+        /// raw image of RAM (which needs `--base`).
+        memory: PathBuf,
+
+        /// Address that a raw memory image starts at.
+        #[clap(long, value_parser = trace::parse_address)]
+        base: Option<u64>,
+
+        /// Path to the image configuration file, in TOML, to name tasks.
+        /// Without it, tasks are numbered.
+        #[clap(long)]
+        cfg: Option<PathBuf>,
+
+        /// Output file for the timeline; defaults to stdout.
+        #[clap(short, long)]
+        output: Option<PathBuf>,
+    },
+
     /// Print a JSON blob with configuration info for `rust-analyzer`
     Lsp {
         /// Existing LSP clients.
@@ -281,12 +448,24 @@ fn run(xtask: Xtask) -> Result<()> {
             edges,
             cfg,
             dirty,
+            jobs,
+            check_reproducible,
             caboose_args,
         } => {
-            let allocs =
-                dist::package(verbose, edges, &cfg, None, dirty, caboose_args)?;
+            if check_reproducible {
+                return repro::run(&cfg, verbose, edges, jobs, caboose_args);
+            }
+            let allocs = dist::package(
+                verbose,
+                edges,
+                &cfg,
+                None,
+                dirty,
+                jobs,
+                caboose_args,
+            )?;
             for (_, (a, _)) in allocs {
-                sizes::run(&cfg, &a, true, false, false, false)?;
+                sizes::run(&cfg, &a, true, false, false, false, None)?;
             }
         }
         Xtask::Build {
@@ -296,6 +475,7 @@ fn run(xtask: Xtask) -> Result<()> {
             cfg,
             tasks,
             dirty,
+            jobs,
         } => {
             if list {
                 dist::list_tasks(&cfg)?;
@@ -306,6 +486,7 @@ fn run(xtask: Xtask) -> Result<()> {
                     &cfg,
                     Some(tasks),
                     dirty,
+                    jobs,
                     CabooseArgs::default(),
                 )?;
             }
@@ -321,6 +502,7 @@ fn run(xtask: Xtask) -> Result<()> {
                 &args.cfg,
                 None,
                 dirty,
+                1,
                 caboose_args,
             )?;
             let toml = Config::from_file(&args.cfg)?;
@@ -351,6 +533,7 @@ fn run(xtask: Xtask) -> Result<()> {
             cfg,
             compare,
             save,
+            stack_margin,
             dirty,
             caboose_args,
         } => {
@@ -360,10 +543,19 @@ fn run(xtask: Xtask) -> Result<()> {
                 &cfg,
                 None,
                 dirty,
+                1,
                 caboose_args,
             )?;
             for (_, (a, _)) in allocs {
-                sizes::run(&cfg, &a, false, compare, save, verbose >= 1)?;
+                sizes::run(
+                    &cfg,
+                    &a,
+                    false,
+                    compare,
+                    save,
+                    verbose >= 1,
+                    stack_margin,
+                )?;
             }
         }
         Xtask::Humility { args } => {
@@ -399,6 +591,7 @@ fn run(xtask: Xtask) -> Result<()> {
                     &args.cfg,
                     None,
                     false,
+                    1,
                     caboose_args,
                 )?;
                 // Delegate flashing to `humility gdb`, which also modifies
@@ -430,6 +623,53 @@ fn run(xtask: Xtask) -> Result<()> {
             }
             humility::run(&args, &[], Some("test"), false, image_name)?;
         }
+        Xtask::Qemu {
+            cfg,
+            image_name,
+            verbose,
+            nobuild,
+            dirty,
+            test,
+            until,
+            timeout,
+            log,
+            gdb,
+            caboose_args,
+            extra_options,
+        } => {
+            let toml = Config::from_file(&cfg)?;
+            let image_name = if let Some(ref name) = image_name {
+                if !toml.check_image_name(name) {
+                    bail!("Image name {} not declared in TOML", name);
+                }
+                name
+            } else {
+                &toml.image_names[0]
+            };
+            if !nobuild {
+                dist::package(
+                    verbose,
+                    false,
+                    &cfg,
+                    None,
+                    dirty,
+                    1,
+                    caboose_args,
+                )?;
+            }
+            qemu::run(
+                &cfg,
+                image_name,
+                qemu::QemuArgs {
+                    test,
+                    until: until.as_deref(),
+                    timeout: timeout.map(std::time::Duration::from_secs),
+                    log: log.as_deref(),
+                    gdb,
+                    extra_options: &extra_options,
+                },
+            )?;
+        }
         Xtask::Clippy {
             verbose,
             cfg,
@@ -444,6 +684,33 @@ fn run(xtask: Xtask) -> Result<()> {
         Xtask::Graph { output, cfg } => {
             graph::task_graph(&cfg, &output)?;
         }
+        Xtask::Diff {
+            before,
+            after,
+            json,
+        } => {
+            diff::run(&before, &after, json)?;
+        }
+        Xtask::Validate { cfg } => {
+            validate::run(&cfg)?;
+        }
+        Xtask::Schema { output } => {
+            let mut schema =
+                serde_json::to_string_pretty(&schema::app_schema())?;
+            schema.push('\n');
+            match output {
+                Some(path) => std::fs::write(&path, schema)
+                    .with_context(|| format!("writing {}", path.display()))?,
+                None => print!("{schema}"),
+            }
+        }
+        Xtask::Verify {
+            archive,
+            app,
+            root_certs,
+        } => {
+            verify::run(&archive, app.as_deref(), &root_certs)?;
+        }
         Xtask::Print {
             cfg,
             archive,
@@ -453,6 +720,21 @@ fn run(xtask: Xtask) -> Result<()> {
             print::run(&cfg, archive, image_name, expanded_config)
                 .context("could not print information about the build")?;
         }
+        Xtask::Trace {
+            kernel,
+            memory,
+            base,
+            cfg,
+            output,
+        } => {
+            trace::run(
+                &kernel,
+                &memory,
+                base,
+                cfg.as_deref(),
+                output.as_deref(),
+            )?;
+        }
         Xtask::Lsp { clients, file } => {
             lsp::run(&file, &clients)?;
         }
@@ -0,0 +1,266 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Machine-readable description of a built image.
+//!
+//! `xtask dist` writes this as `manifest.json` in the build archive, so that
+//! external tools can find out what's in an image (tasks, their IDs and
+//! priorities, where they live in memory, which interrupts they handle, how
+//! their task slots are wired and which Idol interfaces they serve) without
+//! re-parsing `app.toml` or the ELF files.
+//!
+//! The format is versioned by [`MANIFEST_VERSION`], which must be bumped
+//! whenever a field is removed or changes meaning. Adding fields doesn't
+//! require a bump, so consumers should ignore fields they don't recognize.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use anyhow::{anyhow, Context, Result};
+use serde::Serialize;
+
+use crate::config::Config;
+use crate::dist::Allocations;
+
+/// Version of the manifest format.
+pub const MANIFEST_VERSION: u32 = 1;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Manifest {
+    pub version: u32,
+    /// Application name, from `app.toml`
+    pub name: String,
+    pub image_name: String,
+    pub board: String,
+    pub target: String,
+    /// Image ID, as recorded in the kernel's `HUBRIS_IMAGE_ID`
+    pub image_id: u64,
+    pub kernel: KernelManifest,
+    pub caboose: Option<RegionManifest>,
+    /// Tasks, in task index order
+    pub tasks: Vec<TaskManifest>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct KernelManifest {
+    pub features: Vec<String>,
+    /// Map from memory name to the kernel's allocation in that memory
+    pub allocations: BTreeMap<String, RegionManifest>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct TaskManifest {
+    /// Task index, i.e. the index part of its `TaskId`
+    pub id: usize,
+    pub name: String,
+    /// Name of the crate the task is built from
+    #[serde(rename = "crate")]
+    pub krate: String,
+    pub priority: u8,
+    pub start: bool,
+    pub stacksize: u32,
+    pub time_slice: Option<u32>,
+    /// Whether the task may read the image through the `read_image` kipc
+    pub read_image: bool,
+    pub features: Vec<String>,
+    /// Map from region name to the task's allocation in that region,
+    /// including extern regions
+    pub regions: BTreeMap<String, TaskRegionManifest>,
+    /// Shared regions (peripherals, extratext, caboose) mapped into the task
+    pub uses: Vec<String>,
+    pub notifications: Vec<NotificationManifest>,
+    pub interrupts: Vec<InterruptManifest>,
+    /// Map from task slot name to the index and name of the task it's bound to
+    pub task_slots: BTreeMap<String, TaskSlotManifest>,
+    /// Names of the Idol interfaces that the task serves, as declared by
+    /// `serves` in `app.toml`
+    pub idol_interfaces: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct RegionManifest {
+    pub start: u32,
+    pub end: u32,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct TaskRegionManifest {
+    pub base: u32,
+    /// Sizes of the contiguous MPU regions making up the allocation
+    pub sizes: Vec<u32>,
+    pub read: bool,
+    pub write: bool,
+    pub execute: bool,
+    pub dma: bool,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct NotificationManifest {
+    pub name: String,
+    pub mask: u32,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct InterruptManifest {
+    pub irq: u32,
+    /// Interrupt name from `app.toml`, of the form `peripheral.interrupt`
+    pub source: String,
+    pub notification: String,
+    pub mask: u32,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct TaskSlotManifest {
+    pub id: usize,
+    pub task: String,
+}
+
+impl Manifest {
+    /// Builds the manifest for `image_name`, given the allocations and the
+    /// kernel configuration produced by `make_kconfig`.
+    pub fn new(
+        toml: &Config,
+        allocs: &Allocations,
+        kconfig: &build_kconfig::KernelConfig,
+        image_name: &str,
+    ) -> Result<Self> {
+        let mut tasks = vec![];
+        for (i, ((name, task), tconfig)) in
+            toml.tasks.iter().zip(&kconfig.tasks).enumerate()
+        {
+            let notifications = task
+                .notifications
+                .iter()
+                .map(|n| {
+                    Ok(NotificationManifest {
+                        name: n.clone(),
+                        mask: task.notification_mask(n)?,
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?;
+
+            // The kconfig has already resolved the IRQ numbers; match them
+            // back up with the names used in the app.toml.
+            let mut interrupts = vec![];
+            for (source, notification) in &task.interrupts {
+                let mask = task.notification_mask(notification)?;
+                let irq = kconfig
+                    .irqs
+                    .iter()
+                    .find(|(_, c)| c.task_index == i && c.notification == mask)
+                    .map(|(irq, _)| *irq)
+                    .ok_or_else(|| {
+                        anyhow!("task {name}: no IRQ found for {source}")
+                    })?;
+                interrupts.push(InterruptManifest {
+                    irq,
+                    source: source.clone(),
+                    notification: notification.clone(),
+                    mask,
+                });
+            }
+
+            let task_slots = task
+                .task_slots
+                .iter()
+                .map(|(slot, target)| {
+                    let id =
+                        toml.tasks.get_index_of(target).ok_or_else(|| {
+                            anyhow!(
+                                "task {name}: slot {slot} refers to \
+                                 unknown task {target}"
+                            )
+                        })?;
+                    Ok((
+                        slot.clone(),
+                        TaskSlotManifest {
+                            id,
+                            task: target.clone(),
+                        },
+                    ))
+                })
+                .collect::<Result<_>>()?;
+
+            tasks.push(TaskManifest {
+                id: i,
+                name: name.clone(),
+                krate: task.name.clone(),
+                priority: tconfig.priority,
+                start: tconfig.start_at_boot,
+                stacksize: tconfig.initial_stack.offset,
+                time_slice: tconfig.time_slice,
+                read_image: tconfig.read_image,
+                features: task.features.clone(),
+                regions: tconfig
+                    .owned_regions
+                    .iter()
+                    .map(|(region, r)| {
+                        (
+                            region.clone(),
+                            TaskRegionManifest {
+                                base: r.base,
+                                sizes: r.sizes.clone(),
+                                read: r.attributes.read,
+                                write: r.attributes.write,
+                                execute: r.attributes.execute,
+                                dma: matches!(
+                                    r.attributes.special_role,
+                                    Some(build_kconfig::SpecialRole::Dma)
+                                ),
+                            },
+                        )
+                    })
+                    .collect(),
+                uses: tconfig.shared_regions.iter().cloned().collect(),
+                notifications,
+                interrupts,
+                task_slots,
+                idol_interfaces: task.serves.clone(),
+            });
+        }
+
+        Ok(Manifest {
+            version: MANIFEST_VERSION,
+            name: toml.name.clone(),
+            image_name: image_name.to_owned(),
+            board: toml.board.clone(),
+            target: toml.target.clone(),
+            image_id: 0,
+            kernel: KernelManifest {
+                features: kconfig.features.clone(),
+                allocations: allocs
+                    .kernel
+                    .iter()
+                    .map(|(region, r)| (region.clone(), r.clone().into()))
+                    .collect(),
+            },
+            caboose: allocs.caboose.as_ref().map(|(_, r)| r.clone().into()),
+            tasks,
+        })
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let mut out = serde_json::to_string_pretty(self)?;
+        out.push('\n');
+        std::fs::write(path, out)
+            .with_context(|| format!("writing {}", path.display()))
+    }
+}
+
+impl From<std::ops::Range<u32>> for RegionManifest {
+    fn from(r: std::ops::Range<u32>) -> Self {
+        Self {
+            start: r.start,
+            end: r.end,
+        }
+    }
+}
@@ -0,0 +1,277 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Runs an image under QEMU, for boards that QEMU can emulate.
+//!
+//! The board's TOML file names the QEMU machine in its `[qemu]` section. The
+//! image's `final.bin` is loaded with `-kernel`, and its first UART is
+//! connected to our stdout, where we echo it line by line.
+//!
+//! When checking test results, we look for lines of UART output that end in
+//! `PASS` or `FAIL`, e.g.
+//!
+//! ```text
+//!   Test 1: read_reg... PASS
+//! ```
+//!
+//! and fail if any test failed, none passed, or the image never finished. This
+//! is only done when asked
+//! for (with `--test`): the Hubris test suite reports its results through
+//! hiffy, to be collected by humility, rather than on the UART, so there's no
+//! telling from an image's config what its UART output will look like.
+//!
+//! A test image finishes either by making QEMU exit or by printing a line that
+//! matches `--until` (a regex). Since test images tend to run forever, the run
+//! is bounded by `--timeout` (60 seconds by default); reaching it is a
+//! failure, whatever results were seen, as is QEMU exiting before a line
+//! matches `--until`.
+
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail, Context, Result};
+use regex::Regex;
+
+use crate::config::BoardConfig;
+use crate::dist::PackageConfig;
+
+/// How long to run a test image for, if the user doesn't say.
+const DEFAULT_TEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+pub struct QemuArgs<'a> {
+    /// Check the UART output for test results.
+    pub test: bool,
+    /// Stop at the first line of UART output matching this regex.
+    pub until: Option<&'a str>,
+    /// Stop after this long.
+    pub timeout: Option<Duration>,
+    /// Also write the UART output here.
+    pub log: Option<&'a Path>,
+    /// Start halted, with a GDB server on port 1234.
+    pub gdb: bool,
+    /// Extra options to pass to QEMU.
+    pub extra_options: &'a [String],
+}
+
+/// Runs `image_name` from the (already built) image at `cfg` in QEMU.
+pub fn run(cfg: &Path, image_name: &str, args: QemuArgs) -> Result<()> {
+    let config = PackageConfig::new(cfg, false, false)
+        .context("could not create build configuration")?;
+    let board = &config.toml.board;
+    let qemu = BoardConfig::from_board(board)?.qemu.ok_or_else(|| {
+        anyhow!(
+            "can't run board in QEMU: [qemu] section missing from \
+             boards/{board}.toml"
+        )
+    })?;
+
+    let image = config.img_file("final.bin", image_name);
+    if !image.exists() {
+        bail!(
+            "{} does not exist; build it with `xtask dist`",
+            image.display()
+        );
+    }
+
+    let until = args
+        .until
+        .map(Regex::new)
+        .transpose()
+        .context("bad --until pattern")?;
+    let timeout = args.timeout.or_else(|| {
+        // A test image that never finishes shouldn't hang CI.
+        (args.test && !args.gdb).then_some(DEFAULT_TEST_TIMEOUT)
+    });
+    let mut log = args
+        .log
+        .map(|path| {
+            File::create(path)
+                .with_context(|| format!("creating {}", path.display()))
+        })
+        .transpose()?;
+
+    let qemu_path = std::env::var("HUBRIS_QEMU_PATH")
+        .unwrap_or_else(|_| "qemu-system-arm".to_string());
+    let mut cmd = Command::new(&qemu_path);
+    cmd.arg("-M")
+        .arg(&qemu.machine)
+        .args(["-display", "none", "-monitor", "none", "-serial", "stdio"])
+        .arg("-kernel")
+        .arg(&image)
+        .args(&qemu.extra_args);
+    if args.gdb {
+        cmd.args(["-s", "-S"]);
+        println!("QEMU is waiting for GDB on localhost:1234");
+    }
+    cmd.args(args.extra_options)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped());
+
+    let mut child = cmd
+        .spawn()
+        .with_context(|| format!("failed to run QEMU ({cmd:?})"))?;
+
+    // Read the UART on another thread, so that we can give up on it at the
+    // deadline even if it's gone quiet.
+    let stdout = child.stdout.take().unwrap();
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        for line in BufReader::new(stdout).split(b'\n') {
+            if tx.send(line).is_err() {
+                break;
+            }
+        }
+    });
+
+    let deadline = timeout.map(|t| Instant::now() + t);
+    let mut results = TestResults::default();
+    let end = loop {
+        let line = match deadline {
+            Some(deadline) => {
+                match rx.recv_timeout(
+                    deadline.saturating_duration_since(Instant::now()),
+                ) {
+                    Ok(line) => line,
+                    Err(RecvTimeoutError::Timeout) => break End::TimedOut,
+                    Err(RecvTimeoutError::Disconnected) => break End::Exited,
+                }
+            }
+            None => match rx.recv() {
+                Ok(line) => line,
+                Err(_) => break End::Exited,
+            },
+        };
+        let line = line.context("reading QEMU output")?;
+        let line = String::from_utf8_lossy(&line);
+        let line = line.trim_end_matches('\r');
+
+        println!("{line}");
+        if let Some(log) = &mut log {
+            writeln!(log, "{line}")?;
+        }
+        results.record(line);
+        if until.as_ref().is_some_and(|re| re.is_match(line)) {
+            break End::Matched;
+        }
+    };
+
+    if end != End::Exited {
+        // It may have exited on its own in the meantime, which is fine.
+        let _ = child.kill();
+    }
+    let status = child.wait().context("waiting for QEMU")?;
+    if end == End::Exited && !status.success() {
+        bail!("QEMU failed: {status}");
+    }
+
+    if args.test {
+        results.check(end, until.is_some())?;
+    } else if until.is_some() && end == End::TimedOut {
+        bail!("timed out waiting for a line matching --until");
+    }
+    Ok(())
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum End {
+    /// QEMU exited (or closed its output).
+    Exited,
+    /// We saw a line matching `--until`.
+    Matched,
+    /// We reached the timeout.
+    TimedOut,
+}
+
+/// Tally of test results seen on the UART.
+#[derive(Default)]
+struct TestResults {
+    passed: usize,
+    failed: Vec<String>,
+}
+
+impl TestResults {
+    fn record(&mut self, line: &str) {
+        let line = line.trim();
+        if line.ends_with("PASS") {
+            self.passed += 1;
+        } else if line.ends_with("FAIL") {
+            self.failed.push(line.to_string());
+        }
+    }
+
+    /// Checks that the run succeeded, printing a summary. `end` is how the
+    /// run ended, and `until` whether it was meant to end at a line matching
+    /// `--until`.
+    fn check(&self, end: End, until: bool) -> Result<()> {
+        println!(
+            "\ntest results: {} passed; {} failed",
+            self.passed,
+            self.failed.len()
+        );
+        for line in &self.failed {
+            println!("  {line}");
+        }
+        if !self.failed.is_empty() {
+            bail!("{} test(s) failed", self.failed.len());
+        }
+        match end {
+            End::TimedOut if until => {
+                bail!("timed out waiting for a line matching --until")
+            }
+            End::TimedOut => bail!("timed out before the image finished"),
+            End::Exited if until => {
+                bail!("QEMU exited before a line matched --until")
+            }
+            End::Exited | End::Matched => (),
+        }
+        if self.passed == 0 {
+            bail!("no test results seen on the UART");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn results(lines: &[&str]) -> TestResults {
+        let mut results = TestResults::default();
+        for line in lines {
+            results.record(line);
+        }
+        results
+    }
+
+    #[test]
+    fn finished_runs_pass() {
+        let r = results(&["Test 1: foo... PASS", "cycle complete"]);
+        r.check(End::Matched, true).unwrap();
+        r.check(End::Exited, false).unwrap();
+    }
+
+    #[test]
+    fn timeout_fails_despite_passes() {
+        let r = results(&["Test 1: foo... PASS", "Test 2: bar... PASS"]);
+        assert!(r.check(End::TimedOut, false).is_err());
+        assert!(r.check(End::TimedOut, true).is_err());
+    }
+
+    #[test]
+    fn early_exit_fails_with_until() {
+        let r = results(&["Test 1: foo... PASS"]);
+        assert!(r.check(End::Exited, true).is_err());
+    }
+
+    #[test]
+    fn failures_and_empty_runs_fail() {
+        let r = results(&["Test 1: foo... PASS", "Test 2: bar... FAIL"]);
+        assert!(r.check(End::Matched, true).is_err());
+        assert!(results(&["hello"]).check(End::Exited, false).is_err());
+    }
+}
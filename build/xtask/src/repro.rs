@@ -0,0 +1,296 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Checks that an app builds reproducibly.
+//!
+//! We build the app twice, each time from scratch in a Cargo target directory
+//! of its own (`target/$NAME/repro/a` and `.../b`), then compare everything in
+//! the two dist directories: every ELF file, the final images, and the build
+//! archive. The first build's outputs are moved to `target/$NAME/repro/dist`,
+//! and the second build's are left in the usual place.
+//!
+//! The first build builds one task at a time, and the second builds as many
+//! at once as we're asked to, so with `-j N` this also checks that building
+//! tasks in parallel doesn't change the image.
+//!
+//! When a file differs, we report where: for ELF files, the first section that
+//! differs and the symbol containing the first differing byte; for the build
+//! archive, the first file within it that differs.
+
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use goblin::elf::{section_header::SHT_NOBITS, Elf};
+
+use crate::config::Config;
+
+pub fn run(
+    app_toml: &Path,
+    verbose: bool,
+    edges: bool,
+    jobs: usize,
+    caboose_args: crate::CabooseArgs,
+) -> Result<()> {
+    let toml = Config::from_file(app_toml)?;
+    let dist_dir = Path::new("target").join(&toml.name).join("dist");
+    let repro_dir = Path::new("target").join(&toml.name).join("repro");
+    let first_dist = repro_dir.join("dist");
+
+    // Start from nothing, so that neither build can pick up anything left
+    // behind by an earlier one.
+    for dir in [&repro_dir, &dist_dir] {
+        if dir.exists() {
+            std::fs::remove_dir_all(dir)
+                .with_context(|| format!("removing {}", dir.display()))?;
+        }
+    }
+
+    for (i, (target_dir, jobs)) in [("a", 1), ("b", jobs)].iter().enumerate() {
+        println!(
+            "reproducibility check: build {} of 2, with {jobs} job(s)",
+            i + 1
+        );
+        crate::dist::package_in(
+            &repro_dir.join(target_dir),
+            verbose,
+            edges,
+            app_toml,
+            None,
+            false,
+            *jobs,
+            caboose_args.clone(),
+        )?;
+        if i == 0 {
+            std::fs::rename(&dist_dir, &first_dist)?;
+        }
+    }
+
+    let differences = compare_dirs(&first_dist, &dist_dir)?;
+    if differences.is_empty() {
+        println!("{} builds reproducibly", toml.name);
+        return Ok(());
+    }
+    for (path, what) in &differences {
+        println!("{}: {what}", path.display());
+    }
+    bail!(
+        "{} files differ between {} and {}",
+        differences.len(),
+        first_dist.display(),
+        dist_dir.display()
+    )
+}
+
+/// Compares every file in two directories, returning the path (relative to
+/// the directories) and a description of each difference.
+fn compare_dirs(a: &Path, b: &Path) -> Result<Vec<(PathBuf, String)>> {
+    let files_a = list_files(a)?;
+    let files_b = list_files(b)?;
+
+    let mut out = vec![];
+    for path in &files_a {
+        if !files_b.contains(path) {
+            out.push((path.clone(), "only in the first build".to_owned()));
+            continue;
+        }
+        let data_a = std::fs::read(a.join(path))?;
+        let data_b = std::fs::read(b.join(path))?;
+        if data_a != data_b {
+            let what = describe_difference(path, &data_a, &data_b)
+                .with_context(|| format!("comparing {}", path.display()))?;
+            out.push((path.clone(), what));
+        }
+    }
+    for path in files_b.iter().filter(|p| !files_a.contains(p)) {
+        out.push((path.clone(), "only in the second build".to_owned()));
+    }
+    Ok(out)
+}
+
+/// Lists the files in `dir` and its subdirectories, relative to `dir`.
+fn list_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut out = vec![];
+    for entry in walkdir::WalkDir::new(dir).sort_by_file_name() {
+        let entry = entry?;
+        if entry.file_type().is_file() {
+            out.push(entry.path().strip_prefix(dir)?.to_path_buf());
+        }
+    }
+    Ok(out)
+}
+
+/// Describes the first difference between two versions of a file, which are
+/// known to differ.
+fn describe_difference(path: &Path, a: &[u8], b: &[u8]) -> Result<String> {
+    if a.starts_with(b"\x7fELF") && b.starts_with(b"\x7fELF") {
+        elf_difference(a, b)
+    } else if path.extension().is_some_and(|e| e == "zip") {
+        zip_difference(a, b)
+    } else {
+        Ok(byte_difference(a, b))
+    }
+}
+
+fn byte_difference(a: &[u8], b: &[u8]) -> String {
+    match first_difference(a, b) {
+        Some(i) => format!("first difference at offset {i:#x}"),
+        None => format!("length differs ({} vs {} bytes)", a.len(), b.len()),
+    }
+}
+
+/// Returns the offset of the first byte that differs between `a` and `b`, or
+/// `None` if one is a prefix of the other.
+fn first_difference(a: &[u8], b: &[u8]) -> Option<usize> {
+    a.iter().zip(b).position(|(x, y)| x != y)
+}
+
+fn elf_difference(a: &[u8], b: &[u8]) -> Result<String> {
+    let elf_a = Elf::parse(a)?;
+    let elf_b = Elf::parse(b)?;
+
+    let name = |elf: &Elf, sh_name| {
+        elf.shdr_strtab
+            .get_at(sh_name)
+            .unwrap_or("<unnamed>")
+            .to_owned()
+    };
+
+    for (index, sec) in elf_a.section_headers.iter().enumerate().skip(1) {
+        let sec_name = name(&elf_a, sec.sh_name);
+        let Some(other) = crate::elf::get_section_by_name(&elf_b, &sec_name)
+        else {
+            return Ok(format!(
+                "section {sec_name} is only in the first build"
+            ));
+        };
+        if sec.sh_size != other.sh_size {
+            return Ok(format!(
+                "section {sec_name} changed size ({:#x} vs {:#x} bytes)",
+                sec.sh_size, other.sh_size
+            ));
+        }
+        if sec.sh_addr != other.sh_addr {
+            return Ok(format!(
+                "section {sec_name} moved ({:#x} vs {:#x})",
+                sec.sh_addr, other.sh_addr
+            ));
+        }
+        if sec.sh_type == SHT_NOBITS {
+            continue;
+        }
+        let data_a = section_data(a, sec)?;
+        let data_b = section_data(b, other)?;
+        if let Some(offset) = first_difference(data_a, data_b) {
+            let mut what = format!(
+                "section {sec_name} differs at {:#x}",
+                sec.sh_addr + offset as u64
+            );
+            if let Some(sym) = containing_symbol(&elf_a, index, offset as u64) {
+                what += &format!(" (in {sym})");
+            }
+            return Ok(what);
+        }
+    }
+    for sec in elf_b.section_headers.iter().skip(1) {
+        let sec_name = name(&elf_b, sec.sh_name);
+        if crate::elf::get_section_by_name(&elf_a, &sec_name).is_none() {
+            return Ok(format!(
+                "section {sec_name} is only in the second build"
+            ));
+        }
+    }
+
+    // The sections all match, so the difference must be in the headers or in
+    // padding between sections.
+    Ok(format!("outside of any section: {}", byte_difference(a, b)))
+}
+
+fn section_data<'a>(
+    data: &'a [u8],
+    sec: &goblin::elf::SectionHeader,
+) -> Result<&'a [u8]> {
+    let start = sec.sh_offset as usize;
+    data.get(start..start + sec.sh_size as usize)
+        .context("section extends past end of file")
+}
+
+/// Finds the function or object that contains (or, failing that, most closely
+/// precedes) `offset` within the given section, as `name+0x...`.
+fn containing_symbol(elf: &Elf, section: usize, offset: u64) -> Option<String> {
+    use goblin::elf::header::ET_REL;
+    use goblin::elf::sym::{STT_FUNC, STT_OBJECT};
+
+    let sec = &elf.section_headers[section];
+    let mut best: Option<(u64, &str)> = None;
+    for sym in elf.syms.iter() {
+        if sym.st_shndx != section
+            || !matches!(sym.st_type(), STT_FUNC | STT_OBJECT)
+        {
+            continue;
+        }
+        // Thumb functions have the low bit of their address set.
+        let mut start = sym.st_value;
+        if sym.st_type() == STT_FUNC {
+            start &= !1;
+        }
+        // Symbols in relocatable files are relative to their section;
+        // elsewhere, they're absolute.
+        if elf.header.e_type != ET_REL {
+            start = start.wrapping_sub(sec.sh_addr);
+        }
+        if start > offset || best.is_some_and(|(s, _)| s >= start) {
+            continue;
+        }
+        if let Some(name) = elf.strtab.get_at(sym.st_name) {
+            best = Some((start, name));
+        }
+    }
+    best.map(|(start, name)| {
+        format!(
+            "`{:#}`+{:#x}",
+            rustc_demangle::demangle(name),
+            offset - start
+        )
+    })
+}
+
+/// Compares two build archives entry by entry, reporting the first one that
+/// differs.
+fn zip_difference(a: &[u8], b: &[u8]) -> Result<String> {
+    let mut zip_a = zip::ZipArchive::new(std::io::Cursor::new(a))?;
+    let mut zip_b = zip::ZipArchive::new(std::io::Cursor::new(b))?;
+
+    for i in 0..zip_a.len() {
+        let mut file_a = zip_a.by_index(i)?;
+        let name = file_a.name().to_owned();
+        let mut file_b = match zip_b.by_name(&name) {
+            Ok(f) => f,
+            Err(zip::result::ZipError::FileNotFound) => {
+                return Ok(format!("{name} is only in the first build"));
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let mut data_a = vec![];
+        file_a.read_to_end(&mut data_a)?;
+        let mut data_b = vec![];
+        file_b.read_to_end(&mut data_b)?;
+        if data_a != data_b {
+            let what = describe_difference(Path::new(&name), &data_a, &data_b)?;
+            return Ok(format!("{name} differs: {what}"));
+        }
+    }
+    for name in zip_b.file_names() {
+        if zip_a.by_name(name).is_err() {
+            return Ok(format!("{name} is only in the second build"));
+        }
+    }
+
+    // Every file matches, so it must be the archive's own metadata (such as
+    // timestamps or the order of files) that differs.
+    Ok(format!(
+        "archive metadata differs: {}",
+        byte_difference(a, b)
+    ))
+}
@@ -0,0 +1,471 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Software bill of materials for an app.
+//!
+//! We ask `cargo tree` which crates go into the kernel and each task, run
+//! with the same target and features that we build them with, and write that
+//! out in two forms:
+//!
+//! - `sbom.cdx.json`, a CycloneDX SBOM with a component for the kernel and
+//!   each task (by task name), each depending on its crate.
+//! - `licenses.txt`, a human-readable summary of the licenses of the crates
+//!   in each component.
+//!
+//! Only normal dependencies are included; build- and dev-dependencies don't
+//! end up in the image. A crate's license is what the `license` field in its
+//! `Cargo.toml` says. Crates that only point to a file with `license-file` are
+//! reported as `LicenseRef-` followed by the file's name, and crates in this
+//! repository that say nothing are under its license, `REPO_LICENSE`. Any
+//! other crate without a license is an error; we refuse to guess.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde_json::json;
+
+use crate::dist::PackageConfig;
+
+/// Source that `cargo tree` leaves out when printing a package
+const CRATES_IO: &str = "registry+https://github.com/rust-lang/crates.io-index";
+
+/// License of crates in this repository that don't declare one, as given in
+/// the header of every file
+const REPO_LICENSE: &str = "MPL-2.0";
+
+/// A crate, as found in one or more components' dependency graphs
+#[derive(Debug)]
+struct Crate {
+    name: String,
+    version: String,
+    /// Registry or git source; `None` for crates in this repository
+    source: Option<String>,
+    license: String,
+    /// Direct dependencies, by `bom_ref`
+    deps: BTreeSet<String>,
+}
+
+impl Crate {
+    /// Reference to this crate in the SBOM.  This doesn't include the path to
+    /// crates in this repository, so it doesn't depend on where the
+    /// repository was checked out.
+    fn bom_ref(&self) -> String {
+        match &self.source {
+            Some(s) => format!("{}@{} ({s})", self.name, self.version),
+            None => format!("{}@{}", self.name, self.version),
+        }
+    }
+}
+
+/// The kernel or a task, along with the crates that go into it
+struct Component {
+    /// `kernel` or the task name
+    name: String,
+    crate_name: String,
+    /// Reference to the component's own crate
+    root: String,
+    /// References to every crate that goes into it, including its own
+    crates: BTreeSet<String>,
+}
+
+/// Collects the crates used by the kernel and every task in `cfg`, writing
+/// the SBOM and license summary into the dist directory.
+pub fn write(cfg: &PackageConfig) -> Result<()> {
+    println!("collecting crate dependencies for SBOM");
+
+    let mut build_configs =
+        vec![("kernel", cfg.toml.kernel_build_config(false, &[], None))];
+    for name in cfg.toml.tasks.keys() {
+        let c = cfg
+            .toml
+            .task_build_config(name, false, None)
+            .map_err(|s| anyhow!(s))?;
+        build_configs.push((name.as_str(), c));
+    }
+
+    let mut crates = BTreeMap::new();
+    let mut components = vec![];
+    // Tasks built from the same crate with the same features resolve the same
+    // way, so there's no need to ask cargo again.
+    let mut cache: HashMap<(String, Vec<String>), (String, BTreeSet<String>)> =
+        HashMap::new();
+    for (name, build_config) in build_configs {
+        let crate_name = build_config.crate_name.clone();
+        let key = (crate_name.clone(), build_config.args.clone());
+        let (root, closure) = match cache.get(&key) {
+            Some(r) => r.clone(),
+            None => {
+                // This is the same `cargo tree` that `dist --edges` runs, so
+                // it sees the same target and features as the build.
+                let out = build_config
+                    .cmd("tree")
+                    .args(["--edges", "normal", "--prefix", "depth"])
+                    .args(["--format", "{p}\t{l}"])
+                    .output()
+                    .context("failed to run cargo tree")?;
+                if !out.status.success() {
+                    bail!(
+                        "cargo tree failed for {name}:\n{}",
+                        String::from_utf8_lossy(&out.stderr)
+                    );
+                }
+                let r =
+                    parse_tree(&String::from_utf8(out.stdout)?, &mut crates)
+                        .with_context(|| {
+                            format!("collecting dependencies of {name}")
+                        })?;
+                cache.insert(key, r.clone());
+                r
+            }
+        };
+        components.push(Component {
+            name: name.to_owned(),
+            crate_name,
+            root,
+            crates: closure,
+        });
+    }
+
+    // `cargo tree` can't tell us about license files, so if any crate might
+    // have one, ask `cargo metadata` for them all.
+    let mut files = HashMap::new();
+    if crates
+        .values()
+        .any(|c| c.license.is_empty() && c.source.is_some())
+    {
+        let metadata = cargo_metadata::MetadataCommand::new()
+            .manifest_path("./Cargo.toml")
+            .exec()?;
+        for p in metadata.packages {
+            if let Some(file) = p.license_file {
+                files.insert((p.name, p.version.to_string()), file);
+            }
+        }
+    }
+    fill_licenses(&mut crates, &files)?;
+
+    let bom = cyclonedx(cfg, &crates, &components);
+    let mut bom = serde_json::to_string_pretty(&bom)?;
+    bom.push('\n');
+    std::fs::write(cfg.dist_file("sbom.cdx.json"), bom)?;
+    std::fs::write(
+        cfg.dist_file("licenses.txt"),
+        license_summary(&crates, &components),
+    )?;
+    Ok(())
+}
+
+/// Reads the output of `cargo tree --prefix depth --format "{p}\t{l}"`,
+/// adding the crates in it to `crates` and returning the reference of the
+/// root crate and of everything it uses.
+fn parse_tree(
+    tree: &str,
+    crates: &mut BTreeMap<String, Crate>,
+) -> Result<(String, BTreeSet<String>)> {
+    // References to the crates on the path to the current line, by depth
+    let mut path: Vec<String> = vec![];
+    let mut closure = BTreeSet::new();
+    for line in tree.lines().filter(|l| !l.is_empty()) {
+        let digits = line
+            .find(|c: char| !c.is_ascii_digit())
+            .ok_or_else(|| anyhow!("bad line in cargo tree output: {line}"))?;
+        let depth: usize = line[..digits].parse()?;
+        if depth > path.len() {
+            bail!("bad depth in cargo tree output: {line}");
+        }
+        // Crates whose dependencies have already been listed are marked.
+        let line = line[digits..].trim_end_matches(" (*)");
+        let (pkg, license) = line
+            .split_once('\t')
+            .ok_or_else(|| anyhow!("bad line in cargo tree output: {line}"))?;
+        let c = parse_package(pkg, license)?;
+        let r = c.bom_ref();
+
+        path.truncate(depth);
+        if let Some(parent) = path.last() {
+            crates.get_mut(parent).unwrap().deps.insert(r.clone());
+        }
+        crates.entry(r.clone()).or_insert(c);
+        closure.insert(r.clone());
+        path.push(r);
+    }
+    let root = path
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow!("cargo tree didn't list any crates"))?;
+    Ok((root, closure))
+}
+
+/// Parses a package as `cargo tree` prints it (`name v1.2.3`, followed by its
+/// path or source if it's not from crates.io, and then by `(proc-macro)` if
+/// it's a proc macro) along with its license.
+fn parse_package(pkg: &str, license: &str) -> Result<Crate> {
+    let pkg = pkg.trim_end_matches(" (proc-macro)");
+    let (name, rest) = pkg
+        .split_once(' ')
+        .ok_or_else(|| anyhow!("bad package in cargo tree output: {pkg}"))?;
+    let (version, source) = match rest.split_once(' ') {
+        Some((v, s)) => (v, Some(s)),
+        None => (rest, None),
+    };
+    let version = version
+        .strip_prefix('v')
+        .ok_or_else(|| anyhow!("bad version in cargo tree output: {pkg}"))?;
+    let source = match source {
+        None => Some(CRATES_IO.to_owned()),
+        Some(s) => {
+            let s = s
+                .strip_prefix('(')
+                .and_then(|s| s.strip_suffix(')'))
+                .ok_or_else(|| {
+                    anyhow!("bad source in cargo tree output: {pkg}")
+                })?;
+            if Path::new(s).is_absolute() {
+                None
+            } else {
+                Some(s.to_owned())
+            }
+        }
+    };
+    Ok(Crate {
+        name: name.to_owned(),
+        version: version.to_owned(),
+        source,
+        // Some older crates separate alternatives with slashes.
+        license: license.replace('/', " OR "),
+        deps: BTreeSet::new(),
+    })
+}
+
+/// Fills in the licenses of crates in `crates` that `cargo tree` left blank:
+/// from `files`, which maps crates' names and versions to their
+/// `license-file`, or for crates in this repository, `REPO_LICENSE`.
+fn fill_licenses(
+    crates: &mut BTreeMap<String, Crate>,
+    files: &HashMap<(String, String), PathBuf>,
+) -> Result<()> {
+    for c in crates.values_mut().filter(|c| c.license.is_empty()) {
+        if let Some(file) = files.get(&(c.name.clone(), c.version.clone())) {
+            c.license = license_ref(file);
+        } else if c.source.is_none() {
+            c.license = REPO_LICENSE.to_owned();
+        } else {
+            bail!(
+                "{} {} doesn't declare a license; it must set `license` or \
+                 `license-file` in its Cargo.toml",
+                c.name,
+                c.version
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Returns the SPDX reference for a license that's only given as a file:
+/// `LicenseRef-` followed by the file's name, with anything not allowed in a
+/// reference replaced by `-`.
+fn license_ref(file: &Path) -> String {
+    let name = file
+        .file_name()
+        .map(|n| n.to_string_lossy())
+        .unwrap_or_default();
+    let name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' || c == '-' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    format!("LicenseRef-{name}")
+}
+
+fn cyclonedx(
+    cfg: &PackageConfig,
+    crates: &BTreeMap<String, Crate>,
+    components: &[Component],
+) -> serde_json::Value {
+    let app_ref = format!("app:{}", cfg.toml.name);
+    let component_ref = |c: &Component| format!("component:{}", c.name);
+
+    let mut bom_components = vec![];
+    for c in components {
+        bom_components.push(json!({
+            "type": "application",
+            "bom-ref": component_ref(c),
+            "name": c.name,
+            "description": format!("{} (crate {})", c.name, c.crate_name),
+        }));
+    }
+    for c in crates.values() {
+        let mut component = json!({
+            "type": "library",
+            "bom-ref": c.bom_ref(),
+            "name": c.name,
+            "version": c.version,
+        });
+        component["licenses"] = json!([{ "expression": c.license }]);
+        match &c.source {
+            Some(s) if s.starts_with("registry+") => {
+                component["purl"] =
+                    json!(format!("pkg:cargo/{}@{}", c.name, c.version));
+            }
+            Some(s) => {
+                component["externalReferences"] =
+                    json!([{ "type": "vcs", "url": s }]);
+            }
+            None => (),
+        }
+        bom_components.push(component);
+    }
+
+    let mut dependencies = vec![json!({
+        "ref": app_ref,
+        "dependsOn": components.iter().map(component_ref).collect::<Vec<_>>(),
+    })];
+    for c in components {
+        dependencies.push(json!({
+            "ref": component_ref(c),
+            "dependsOn": [crates[&c.root].bom_ref()],
+        }));
+    }
+    for c in crates.values() {
+        dependencies.push(json!({
+            "ref": c.bom_ref(),
+            "dependsOn": c.deps.iter().map(|d| crates[d].bom_ref()).collect::<Vec<_>>(),
+        }));
+    }
+
+    json!({
+        "bomFormat": "CycloneDX",
+        "specVersion": "1.5",
+        "version": 1,
+        "metadata": {
+            "component": {
+                "type": "firmware",
+                "bom-ref": app_ref,
+                "name": cfg.toml.name,
+                "version": cfg.toml.version.to_string(),
+            },
+        },
+        "components": bom_components,
+        "dependencies": dependencies,
+    })
+}
+
+fn license_summary(
+    crates: &BTreeMap<String, Crate>,
+    components: &[Component],
+) -> String {
+    let mut s = String::new();
+    let mut section = |title: &str, ids: &mut dyn Iterator<Item = &String>| {
+        // Map from license to crates, as "name version"
+        let mut licenses: BTreeMap<&str, BTreeSet<String>> = BTreeMap::new();
+        for id in ids {
+            let c = &crates[id];
+            licenses
+                .entry(&c.license)
+                .or_default()
+                .insert(format!("{} {}", c.name, c.version));
+        }
+        writeln!(s, "{title}:").unwrap();
+        for (license, names) in licenses {
+            let names: Vec<_> = names.into_iter().collect();
+            writeln!(s, "    {license}: {}", names.join(", ")).unwrap();
+        }
+        writeln!(s).unwrap();
+    };
+
+    section("all components", &mut crates.keys());
+    for c in components {
+        let title = if c.name == "kernel" {
+            format!("kernel ({})", c.crate_name)
+        } else {
+            format!("task {} ({})", c.name, c.crate_name)
+        };
+        section(&title, &mut c.crates.iter());
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tree() {
+        let tree = "\
+0task-foo v0.1.0 (/hubris/task/foo)\tMPL-2.0
+1serde v1.0.229\tMIT OR Apache-2.0
+2serde_derive v1.0.229 (proc-macro)\tMIT/Apache-2.0
+3proc-macro2 v1.0.107\tMIT OR Apache-2.0
+1idol-runtime v0.1.0 (https://github.com/oxidecomputer/idol#a1b2c3)\tMPL-2.0
+2serde v1.0.229\tMIT OR Apache-2.0 (*)
+1userlib v0.1.0 (/hubris/sys/userlib)\tMPL-2.0
+";
+        let mut crates = BTreeMap::new();
+        let (root, closure) = parse_tree(tree, &mut crates).unwrap();
+        assert_eq!(root, "task-foo@0.1.0");
+        assert_eq!(closure.len(), 6);
+
+        let serde = format!("serde@1.0.229 ({CRATES_IO})");
+        let idol =
+            "idol-runtime@0.1.0 (https://github.com/oxidecomputer/idol#a1b2c3)";
+        assert_eq!(
+            crates[&root].deps,
+            BTreeSet::from([
+                serde.clone(),
+                idol.to_owned(),
+                "userlib@0.1.0".to_owned()
+            ])
+        );
+        assert_eq!(crates[idol].deps, BTreeSet::from([serde.clone()]));
+        assert_eq!(crates[&serde].deps.len(), 1);
+        assert_eq!(
+            crates[&format!("serde_derive@1.0.229 ({CRATES_IO})")].license,
+            "MIT OR Apache-2.0"
+        );
+    }
+
+    #[test]
+    fn missing_licenses() {
+        let tree = "\
+0task-foo v0.1.0 (/hubris/task/foo)\t
+1ring v0.17.8\t
+1weird v1.0.0\t
+";
+        let mut crates = BTreeMap::new();
+        parse_tree(tree, &mut crates).unwrap();
+        let files = HashMap::from([(
+            ("ring".to_owned(), "0.17.8".to_owned()),
+            PathBuf::from("LICENSE"),
+        )]);
+        let err = fill_licenses(&mut crates, &files).unwrap_err();
+        assert!(err.to_string().contains("weird 1.0.0 doesn't declare"));
+
+        crates.remove(&format!("weird@1.0.0 ({CRATES_IO})"));
+        fill_licenses(&mut crates, &files).unwrap();
+        assert_eq!(crates["task-foo@0.1.0"].license, REPO_LICENSE);
+        assert_eq!(
+            crates[&format!("ring@0.17.8 ({CRATES_IO})")].license,
+            "LicenseRef-LICENSE"
+        );
+    }
+
+    #[test]
+    fn license_refs() {
+        assert_eq!(
+            license_ref(Path::new("../LICENSE-APACHE.md")),
+            "LicenseRef-LICENSE-APACHE.md"
+        );
+        assert_eq!(
+            license_ref(Path::new("docs/my license.txt")),
+            "LicenseRef-my-license.txt"
+        );
+    }
+}
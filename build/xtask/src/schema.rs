@@ -0,0 +1,274 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! JSON Schema for app.toml files.
+//!
+//! This describes what `RawConfig` (and the types it contains) will accept,
+//! and is used both by editors (via `cargo xtask schema`) and by `xtask
+//! validate`, which checks each file in an app's inheritance chain against it.
+//! It's written out by hand, so it must be kept in sync with those types; a
+//! test in `config.rs` checks that it has a property for each of their fields.
+//!
+//! Since an app.toml may inherit from other files, which are merged before
+//! being deserialized, fields that `RawConfig` requires are only required at
+//! the top level of the schema; each individual file may be partial.
+
+use serde_json::{json, Value};
+
+/// Returns the schema for an app.toml file.
+pub fn app_schema() -> Value {
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": "Hubris app.toml",
+        "type": "object",
+        "additionalProperties": false,
+        "required": ["name", "target", "board", "chip", "kernel", "tasks"],
+        "properties": {
+            "inherit": {
+                "description": "Other files to merge underneath this one, \
+                                in order",
+                "oneOf": [string(), strings()],
+            },
+            "name": string(),
+            "target": string(),
+            "board": string(),
+            "chip": {
+                "description": "Directory containing chip.toml and \
+                                memory.toml, relative to this file",
+                "type": "string",
+            },
+            "mmio": table(
+                json!({
+                    "peripheral-region": string(),
+                    "register-map": string(),
+                }),
+                &["peripheral-region", "register-map"],
+            ),
+            "epoch": u32(),
+            "version": u32(),
+            "fwid": boolean(),
+            "allocator": {
+                "description": "Strategy used to lay out tasks in memory",
+                "enum": ["greedy", "optimize"],
+            },
+            "memory": {
+                "description": "Name of the memory map file in the chip \
+                                directory, if not memory.toml",
+                "type": "string",
+            },
+            "image-names": strings(),
+            "signing": table(
+                json!({
+                    "certs": { "type": "object" },
+                }),
+                &["certs"],
+            ),
+            "stacksize": u32(),
+            "kernel": kernel(),
+            "tasks": map_of(task()),
+            "extratext": map_of(peripheral()),
+            "config": {
+                "description": "Application-wide configuration",
+                "type": "object",
+            },
+            "auxflash": table(
+                json!({
+                    "blobs": {
+                        "type": "array",
+                        "items": table(
+                            json!({
+                                "file": string(),
+                                "unzip": string(),
+                                "compress": boolean(),
+                                "tag": string(),
+                            }),
+                            &["file", "compress", "tag"],
+                        ),
+                    },
+                }),
+                &["blobs"],
+            ),
+            "caboose": table(
+                json!({
+                    "tasks": {
+                        "description": "Tasks that are allowed to access \
+                                        the caboose",
+                        "type": "array",
+                        "items": string(),
+                    },
+                    "region": {
+                        "description": "Memory region in which the caboose \
+                                        is placed",
+                        "type": "string",
+                    },
+                    "size": u32(),
+                }),
+                &["region", "size"],
+            ),
+        },
+    })
+}
+
+fn kernel() -> Value {
+    table(
+        json!({
+            "name": string(),
+            "requires": {
+                "description": "Memory required by the kernel, by region",
+                "type": "object",
+                "additionalProperties": u32(),
+            },
+            "stacksize": u32(),
+            "features": strings(),
+            "no-default-features": boolean(),
+            "extern-regions": strings(),
+            "fault-log": {
+                "description": "Name of one of `extern-regions` to use as \
+                                retained RAM for the kernel's fault log",
+                "type": "string",
+            },
+            "tick-rate": {
+                "description": "Rate of the kernel's tick, in Hz, as set up \
+                                by the app's call to `start_kernel`; \
+                                defaults to 1000",
+                "type": "integer",
+                "minimum": 1,
+                "maximum": u32::MAX,
+            },
+        }),
+        &["name", "requires"],
+    )
+}
+
+fn task() -> Value {
+    table(
+        json!({
+            "name": {
+                "description": "Crate that the task is built from",
+                "type": "string",
+            },
+            "priority": {
+                "type": "integer",
+                "minimum": 0,
+                "maximum": u8::MAX,
+            },
+            "stacksize": u32(),
+            "start": boolean(),
+            "time-slice": {
+                "description": "Kernel ticks this task may run before the \
+                                kernel rotates to another runnable task of \
+                                the same priority",
+                "type": "integer",
+                "minimum": 0,
+                "maximum": u32::MAX,
+            },
+            "read-image": {
+                "description": "Whether the task may read the whole image \
+                                (the kernel's and every task's flash) \
+                                through the `read_image` kipc",
+                "type": "boolean",
+            },
+            "uses": {
+                "description": "Peripherals (or extratext regions) mapped \
+                                into the task",
+                "type": "array",
+                "items": string(),
+            },
+            "features": strings(),
+            "notifications": strings(),
+            "copy-to-archive": {
+                "description": "Keys in the task's config naming files to \
+                                copy into the build archive",
+                "type": "array",
+                "items": string(),
+            },
+            "extern-regions": {
+                "description": "Memory regions which should be mapped as \
+                                accessible to this task",
+                "type": "array",
+                "items": string(),
+            },
+            "serves": {
+                "description": "Idol interfaces served by this task",
+                "type": "array",
+                "items": string(),
+            },
+            "task-slots": {
+                "description": "Tasks that this task refers to, either by \
+                                name or as `{slot = task}`",
+                "type": "array",
+                "items": {
+                    "oneOf": [string(), slot_map()],
+                },
+            },
+            "config": {
+                "description": "Task-specific configuration",
+                "type": "object",
+            },
+            "interrupts": {
+                "description": "Map from `peripheral.interrupt` to the \
+                                notification it's delivered as",
+                "type": "object",
+                "additionalProperties": string(),
+            },
+            "sections": map_of(string()),
+            "max-sizes": map_of(u32()),
+            "no-default-features": boolean(),
+        }),
+        &["name", "priority"],
+    )
+}
+
+fn peripheral() -> Value {
+    table(
+        json!({
+            "address": u32(),
+            "size": u32(),
+            "interrupts": map_of(u32()),
+        }),
+        &["address", "size"],
+    )
+}
+
+/// A single `{slot = task}` entry in `task-slots`
+fn slot_map() -> Value {
+    let mut v = map_of(string());
+    v["minProperties"] = json!(1);
+    v["maxProperties"] = json!(1);
+    v
+}
+
+/// A table with the given properties, and no others.
+fn table(properties: Value, required: &[&str]) -> Value {
+    json!({
+        "type": "object",
+        "properties": properties,
+        "required": required,
+        "additionalProperties": false,
+    })
+}
+
+/// A table with arbitrary keys, whose values all match `values`.
+fn map_of(values: Value) -> Value {
+    json!({
+        "type": "object",
+        "additionalProperties": values,
+    })
+}
+
+fn string() -> Value {
+    json!({ "type": "string" })
+}
+
+fn strings() -> Value {
+    json!({ "type": "array", "items": string() })
+}
+
+fn boolean() -> Value {
+    json!({ "type": "boolean" })
+}
+
+fn u32() -> Value {
+    json!({ "type": "integer", "minimum": 0, "maximum": u32::MAX })
+}
@@ -30,6 +30,10 @@ struct TaskSizes<'a> {
 /// When `only_suggest` is true, prints only the suggested improvements to
 /// stderr, rather than printing all sizes.  Suggestions are formatted to
 /// match compiler warnings.
+///
+/// When `stack_margin` is given (as a percentage of each task's stack size),
+/// tasks whose estimated maximum stack depth comes within that margin of their
+/// stack size are flagged with a warning.
 pub fn run(
     cfg: &Path,
     allocs: &Allocations,
@@ -37,6 +41,7 @@ pub fn run(
     compare: bool,
     save: bool,
     verbose: bool,
+    stack_margin: Option<u8>,
 ) -> Result<()> {
     let toml = Config::from_file(cfg)?;
     let sizes = create_sizes(&toml)?;
@@ -70,7 +75,7 @@ pub fn run(
         print!("\n\n");
         print_task_table(&toml, &map)?;
         print!("\n\n");
-        print_task_stacks(&toml)?;
+        print_task_stacks(&toml, stack_margin)?;
     }
 
     // Because tasks are autosized, the only place where we can improve
@@ -427,7 +432,8 @@ fn print_memory_map(
     Ok(())
 }
 
-fn print_task_stacks(toml: &Config) -> Result<()> {
+fn print_task_stacks(toml: &Config, stack_margin: Option<u8>) -> Result<()> {
+    let mut near_overflow = vec![];
     for (i, (task_name, task)) in toml.tasks.iter().enumerate() {
         let task_stack_size =
             task.stacksize.unwrap_or_else(|| toml.stacksize.unwrap());
@@ -442,6 +448,31 @@ fn print_task_stacks(toml: &Config) -> Result<()> {
         if i + 1 < toml.tasks.len() {
             println!();
         }
+
+        if let Some(margin) = stack_margin {
+            let limit = u64::from(task_stack_size);
+            let headroom = limit.saturating_sub(total);
+            if headroom * 100 < limit * u64::from(margin) {
+                near_overflow.push((task_name, total, limit, headroom));
+            }
+        }
+    }
+
+    if let Some(margin) = stack_margin {
+        if !near_overflow.is_empty() {
+            println!();
+            print!("{}", "warning".bold().yellow());
+            println!(
+                ": {} task(s) within {margin}% of overflowing the stack",
+                near_overflow.len()
+            );
+            for (task_name, total, limit, headroom) in near_overflow {
+                println!(
+                    "  {task_name}: {total} of {limit} bytes \
+                     ({headroom} bytes spare)"
+                );
+            }
+        }
     }
     Ok(())
 }
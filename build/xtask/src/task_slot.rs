@@ -8,6 +8,7 @@ use scroll::Pread;
 use std::path::Path;
 
 pub const TASK_SLOT_TABLE_SECTION: &str = ".task_slot_table";
+pub const TASK_SLOT_INTERFACES_SECTION: &str = ".task_slot_interfaces";
 
 #[derive(Debug)]
 pub struct TaskSlotTableEntry<'a> {
@@ -87,12 +88,51 @@ pub fn get_task_slot_table_entries<'a>(
     Ok(entries)
 }
 
+/// Returns the `(slot, interface)` pairs recorded by the three-argument form
+/// of `task_slot!`, or nothing if the task didn't record any.
+pub fn get_task_slot_interfaces<'a>(
+    src: &'a [u8],
+    elf: &goblin::elf::Elf<'a>,
+) -> Result<Vec<(&'a str, &'a str)>> {
+    let Some(section) =
+        elf::get_section_by_name(elf, TASK_SLOT_INTERFACES_SECTION)
+    else {
+        return Ok(vec![]);
+    };
+    let data = &src[section.sh_offset as usize
+        ..(section.sh_offset + section.sh_size) as usize];
+
+    if data.is_empty() {
+        return Ok(vec![]);
+    }
+
+    // Entries are NUL-terminated slot and interface names, back to back
+    let Some(data) = data.strip_suffix(b"\0") else {
+        bail!("{TASK_SLOT_INTERFACES_SECTION} is not NUL-terminated");
+    };
+    let names = data
+        .split(|b| *b == 0)
+        .map(std::str::from_utf8)
+        .collect::<Result<Vec<_>, _>>()
+        .context(format!("bad name in {TASK_SLOT_INTERFACES_SECTION}"))?;
+    if names.len() % 2 != 0 {
+        bail!("{TASK_SLOT_INTERFACES_SECTION} has an unpaired slot name");
+    }
+    Ok(names.chunks(2).map(|c| (c[0], c[1])).collect())
+}
+
 pub fn dump_task_slot_table(task_path: &Path) -> Result<()> {
     let task_bin = std::fs::read(task_path)?;
     let elf = goblin::elf::Elf::parse(&task_bin)?;
 
-    println!("Task Slot          Address      File Offset   Task Index");
-    println!("------------------------------------------------------------");
+    let interfaces = get_task_slot_interfaces(&task_bin, &elf)?;
+
+    println!(
+        "Task Slot          Address      File Offset   Task Index   Interfaces"
+    );
+    println!(
+        "-------------------------------------------------------------------------"
+    );
 
     for entry in get_task_slot_table_entries(&task_bin, &elf)? {
         let task_idx = task_bin.pread_with::<u16>(
@@ -100,12 +140,19 @@ pub fn dump_task_slot_table(task_path: &Path) -> Result<()> {
             elf::get_endianness(&elf),
         )?;
 
+        let slot_interfaces: Vec<&str> = interfaces
+            .iter()
+            .filter(|(slot, _)| *slot == entry.slot_name)
+            .map(|(_, interface)| *interface)
+            .collect();
+
         println!(
-            "{:16}   {:#010x}   {:#010x}    {:#04x}",
+            "{:16}   {:#010x}   {:#010x}    {:#04x}         {}",
             entry.slot_name,
             entry.taskidx_address,
             entry.taskidx_file_offset as usize,
-            task_idx
+            task_idx,
+            slot_interfaces.join(", ")
         );
     }
 
@@ -0,0 +1,505 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Decoder for the kernel's event trace ring.
+//!
+//! A kernel built with the `trace` feature records its events into a ring in
+//! RAM, `KERNEL_TRACE` (see `kern::trace`). This finds the ring using the
+//! kernel's ELF file, reads it out of a memory image, and converts it into the
+//! Chrome trace event format, which can be loaded into `chrome://tracing` or
+//! Perfetto (<https://ui.perfetto.dev>).
+
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use abi::{Sysnum, TraceEvent, TraceHeader, TraceRecord};
+use anyhow::{anyhow, bail, Context, Result};
+use serde_json::{json, Value};
+use zerocopy::FromBytes;
+
+use crate::Config;
+
+const TRACE_SYMBOL: &str = "KERNEL_TRACE";
+
+/// Parses an address given on the command line, in decimal or (with a `0x`
+/// prefix) hex.
+pub fn parse_address(s: &str) -> Result<u64> {
+    let r = if let Some(hex) = s.strip_prefix("0x") {
+        u64::from_str_radix(&hex.replace('_', ""), 16)
+    } else {
+        s.parse()
+    };
+    r.with_context(|| format!("invalid address {s:?}"))
+}
+
+/// Decodes the trace ring of `kernel` from `memory`, writing the timeline to
+/// `output` (or stdout).
+///
+/// `memory` can be an ELF core dump, in which case the ring is found in its
+/// load segments, or a raw image of memory starting at address `base`.
+///
+/// If `cfg` is given, tasks are named as in that app.toml; otherwise they're
+/// just numbered.
+pub fn run(
+    kernel: &Path,
+    memory: &Path,
+    base: Option<u64>,
+    cfg: Option<&Path>,
+    output: Option<&Path>,
+) -> Result<()> {
+    let kernel_image = fs::read(kernel)
+        .with_context(|| format!("reading {}", kernel.display()))?;
+    let kernel_elf = goblin::elf::Elf::parse(&kernel_image)
+        .with_context(|| format!("parsing {}", kernel.display()))?;
+    let (addr, size) =
+        find_symbol(&kernel_elf, TRACE_SYMBOL).ok_or_else(|| {
+            anyhow!(
+                "{} has no `{TRACE_SYMBOL}`; was the kernel built with the \
+                 `trace` feature?",
+                kernel.display()
+            )
+        })?;
+
+    let memory_image = fs::read(memory)
+        .with_context(|| format!("reading {}", memory.display()))?;
+    let ring = if memory_image.starts_with(b"\x7fELF") {
+        let dump = goblin::elf::Elf::parse(&memory_image)
+            .with_context(|| format!("parsing {}", memory.display()))?;
+        read_from_dump(&dump, &memory_image, addr, size)?
+    } else {
+        let Some(base) = base else {
+            bail!(
+                "{} is not an ELF dump, so --base is needed to say where it \
+                 was read from",
+                memory.display()
+            );
+        };
+        read_from_raw(&memory_image, base, addr, size)?
+    };
+
+    let task_names: Vec<String> = match cfg {
+        Some(cfg) => Config::from_file(cfg)?.tasks.keys().cloned().collect(),
+        None => vec![],
+    };
+
+    let (header, records) = parse_ring(ring)?;
+    let timeline = to_chrome_trace(&header, &records, &task_names)?;
+
+    let json = serde_json::to_string_pretty(&timeline)?;
+    match output {
+        Some(path) => fs::write(path, json)?,
+        None => writeln!(std::io::stdout(), "{json}")?,
+    }
+    Ok(())
+}
+
+/// Finds the address and size of the symbol `name`.
+fn find_symbol(elf: &goblin::elf::Elf, name: &str) -> Option<(u64, u64)> {
+    elf.syms.iter().find_map(|sym| {
+        (elf.strtab.get_at(sym.st_name) == Some(name))
+            .then_some((sym.st_value, sym.st_size))
+    })
+}
+
+/// Reads `size` bytes at `addr` out of the load segments of a core dump.
+fn read_from_dump<'a>(
+    dump: &goblin::elf::Elf,
+    image: &'a [u8],
+    addr: u64,
+    size: u64,
+) -> Result<&'a [u8]> {
+    for phdr in &dump.program_headers {
+        if phdr.p_type != goblin::elf::program_header::PT_LOAD {
+            continue;
+        }
+        let start = phdr.p_vaddr;
+        let end = start.saturating_add(phdr.p_filesz);
+        if start <= addr && addr.saturating_add(size) <= end {
+            // The segment header says it holds the ring, but a dump that was
+            // cut short still parses, so check that the file does too.
+            let offset = phdr.p_offset.checked_add(addr - start);
+            return offset
+                .and_then(|offset| {
+                    let offset = usize::try_from(offset).ok()?;
+                    image.get(offset..offset.checked_add(size as usize)?)
+                })
+                .ok_or_else(|| {
+                    anyhow!(
+                        "dump is truncated: {TRACE_SYMBOL} ({size} bytes at \
+                         {addr:#x}) is past its end ({} bytes)",
+                        image.len()
+                    )
+                });
+        }
+    }
+    bail!("dump does not contain {TRACE_SYMBOL} ({size} bytes at {addr:#x})")
+}
+
+/// Reads `size` bytes at `addr` out of a raw memory image starting at `base`.
+fn read_from_raw(
+    image: &[u8],
+    base: u64,
+    addr: u64,
+    size: u64,
+) -> Result<&[u8]> {
+    addr.checked_sub(base)
+        .map(|offset| offset as usize)
+        .and_then(|offset| image.get(offset..offset + size as usize))
+        .ok_or_else(|| {
+            anyhow!(
+                "image at {base:#x} ({} bytes) does not contain \
+                 {TRACE_SYMBOL} ({size} bytes at {addr:#x})",
+                image.len()
+            )
+        })
+}
+
+/// Splits the ring into its header and its records, oldest first.
+fn parse_ring(ring: &[u8]) -> Result<(TraceHeader, Vec<TraceRecord>)> {
+    let (header, rest) = TraceHeader::read_from_prefix(ring)
+        .map_err(|_| anyhow!("trace ring is too small for its header"))?;
+    if header.magic != abi::TRACE_MAGIC {
+        bail!(
+            "trace ring has bad magic {:#x} (expected {:#x}); has the kernel \
+             started?",
+            header.magic,
+            abi::TRACE_MAGIC
+        );
+    }
+
+    let capacity = header.capacity as usize;
+    let mut records = Vec::with_capacity(capacity);
+    let mut rest = rest;
+    for _ in 0..capacity {
+        let (record, tail) = TraceRecord::read_from_prefix(rest)
+            .map_err(|_| anyhow!("trace ring is truncated"))?;
+        records.push(record);
+        rest = tail;
+    }
+
+    let next = header.next as usize;
+    if next >= capacity {
+        bail!("trace ring's next index {next} is out of range");
+    }
+    if header.wrapped != 0 {
+        records.rotate_left(next);
+    } else {
+        records.truncate(next);
+    }
+    Ok((header, records))
+}
+
+/// Converts trace records into a Chrome trace event format timeline.
+///
+/// Kernel activity (syscalls, interrupts, and so on) goes on one track, and
+/// the time each task spends as the current task goes on a track per task.
+///
+/// Timestamps count at `header.clock_khz` kHz.
+fn to_chrome_trace(
+    header: &TraceHeader,
+    records: &[TraceRecord],
+    task_names: &[String],
+) -> Result<Value> {
+    if header.clock_khz == 0 {
+        bail!("trace ring has no clock rate");
+    }
+    // Chrome traces are in microseconds.
+    let us_per_count = 1000.0 / f64::from(header.clock_khz);
+    let start = records.first().map(|r| r.time()).unwrap_or(0);
+    let to_us = |time: u64| time.saturating_sub(start) as f64 * us_per_count;
+    let task_name = |index: u32| {
+        task_names
+            .get(index as usize)
+            .cloned()
+            .unwrap_or_else(|| format!("task {index}"))
+    };
+    let slice = |name: String, tid: u32, from: u64, to: u64| {
+        json!({
+            "name": name,
+            "ph": "X",
+            "pid": 0,
+            "tid": tid,
+            "ts": to_us(from),
+            "dur": to_us(to) - to_us(from),
+        })
+    };
+
+    let mut events = vec![
+        json!({
+            "name": "process_name",
+            "ph": "M",
+            "pid": 0,
+            "args": { "name": "hubris" },
+        }),
+        json!({
+            "name": "thread_name",
+            "ph": "M",
+            "pid": 0,
+            "tid": 0,
+            "args": { "name": "kernel" },
+        }),
+    ];
+    let mut tasks_seen = std::collections::BTreeSet::new();
+
+    // Kernel entry points don't nest, but we keep a stack anyway so that a
+    // dropped record doesn't throw off everything after it.
+    let mut kernel: Vec<(String, u64)> = vec![];
+    let mut current_task: Option<(u32, u64)> = None;
+
+    for record in records {
+        let time = record.time();
+        let Ok(event) = TraceEvent::try_from(record.event) else {
+            bail!("unknown trace event {}", record.event);
+        };
+        match event {
+            TraceEvent::SyscallEnter => {
+                let name = match Sysnum::try_from(record.arg) {
+                    Ok(nr) => format!("{nr:?}"),
+                    Err(()) => format!("syscall {}", record.arg),
+                };
+                kernel.push((name, time));
+            }
+            TraceEvent::SecondarySyscallEnter => {
+                kernel.push(("secondary syscall".to_string(), time));
+            }
+            TraceEvent::IsrEnter => kernel.push(("ISR".to_string(), time)),
+            TraceEvent::TimerIsrEnter => {
+                kernel.push(("timer".to_string(), time));
+            }
+            TraceEvent::Irq => {
+                if let Some((name, _)) = kernel.last_mut() {
+                    *name = format!("IRQ {}", record.arg);
+                }
+            }
+            TraceEvent::SyscallExit
+            | TraceEvent::SecondarySyscallExit
+            | TraceEvent::IsrExit
+            | TraceEvent::TimerIsrExit => {
+                // If the ring wrapped in the middle of this, we never saw it
+                // start, so just skip it.
+                if let Some((name, from)) = kernel.pop() {
+                    events.push(slice(name, 0, from, time));
+                }
+            }
+            TraceEvent::ContextSwitch => {
+                if let Some((task, from)) = current_task {
+                    events.push(slice(task_name(task), task + 1, from, time));
+                }
+                current_task = Some((record.arg, time));
+                tasks_seen.insert(record.arg);
+            }
+        }
+    }
+
+    // Close off whatever was still running when the ring was read.
+    if let Some(end) = records.last().map(|r| r.time()) {
+        for (name, from) in kernel {
+            events.push(slice(name, 0, from, end));
+        }
+        if let Some((task, from)) = current_task {
+            events.push(slice(task_name(task), task + 1, from, end));
+        }
+    }
+
+    for task in tasks_seen {
+        events.push(json!({
+            "name": "thread_name",
+            "ph": "M",
+            "pid": 0,
+            "tid": task + 1,
+            "args": { "name": task_name(task) },
+        }));
+    }
+
+    Ok(json!({
+        "traceEvents": events,
+        "displayTimeUnit": "ns",
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zerocopy::IntoBytes;
+
+    fn header(capacity: u32, next: u32, wrapped: bool) -> TraceHeader {
+        TraceHeader {
+            magic: abi::TRACE_MAGIC,
+            capacity,
+            clock_per_tick: 64_000,
+            clock_khz: 64_000,
+            next,
+            wrapped: wrapped.into(),
+        }
+    }
+
+    fn record(time: u64, event: TraceEvent, arg: u32) -> TraceRecord {
+        TraceRecord {
+            time_lo: time as u32,
+            time_hi: (time >> 32) as u32,
+            event: event as u32,
+            arg,
+        }
+    }
+
+    /// Lays out a ring as the kernel does, with `records` in slot order.
+    fn ring(header: &TraceHeader, records: &[TraceRecord]) -> Vec<u8> {
+        let mut ring = header.as_bytes().to_vec();
+        for r in records {
+            ring.extend(r.as_bytes());
+        }
+        ring
+    }
+
+    fn times(records: &[TraceRecord]) -> Vec<u64> {
+        records.iter().map(|r| r.time()).collect()
+    }
+
+    #[test]
+    fn partial_ring() {
+        let slots = [
+            record(10, TraceEvent::IsrEnter, 0),
+            record(20, TraceEvent::IsrExit, 0),
+            // Never written.
+            record(0, TraceEvent::IsrEnter, 0),
+        ];
+        let (header, records) =
+            parse_ring(&ring(&header(3, 2, false), &slots)).unwrap();
+        assert_eq!(header.clock_khz, 64_000);
+        assert_eq!(times(&records), [10, 20]);
+    }
+
+    #[test]
+    fn wrapped_ring() {
+        // The oldest record is the one about to be overwritten.
+        let slots = [
+            record(40, TraceEvent::IsrEnter, 0),
+            record(20, TraceEvent::IsrEnter, 0),
+            record(30, TraceEvent::IsrExit, 0),
+        ];
+        let (_, records) =
+            parse_ring(&ring(&header(3, 1, true), &slots)).unwrap();
+        assert_eq!(times(&records), [20, 30, 40]);
+    }
+
+    #[test]
+    fn bad_rings() {
+        let slots = [record(10, TraceEvent::IsrEnter, 0); 2];
+        let good = header(2, 1, false);
+
+        let mut bad = good;
+        bad.magic = 0x7ACE_0001;
+        assert!(parse_ring(&ring(&bad, &slots))
+            .unwrap_err()
+            .to_string()
+            .starts_with("trace ring has bad magic 0x7ace0001"));
+
+        let data = ring(&good, &slots);
+        assert_eq!(
+            parse_ring(&data[..data.len() - 1]).unwrap_err().to_string(),
+            "trace ring is truncated"
+        );
+        assert_eq!(
+            parse_ring(&data[..8]).unwrap_err().to_string(),
+            "trace ring is too small for its header"
+        );
+
+        let mut bad = good;
+        bad.next = 2;
+        assert_eq!(
+            parse_ring(&ring(&bad, &slots)).unwrap_err().to_string(),
+            "trace ring's next index 2 is out of range"
+        );
+    }
+
+    /// Returns the complete ("X") events in a timeline, as (track, name,
+    /// start, duration) in microseconds.
+    fn slices(timeline: &Value) -> Vec<(u64, String, f64, f64)> {
+        timeline["traceEvents"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter(|e| e["ph"] == "X")
+            .map(|e| {
+                (
+                    e["tid"].as_u64().unwrap(),
+                    e["name"].as_str().unwrap().to_string(),
+                    e["ts"].as_f64().unwrap(),
+                    e["dur"].as_f64().unwrap(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn timeline() {
+        // At 64 MHz, 64 counts is a microsecond. Timestamps start from the
+        // first record.
+        let records = [
+            record(1000, TraceEvent::ContextSwitch, 1),
+            record(1000 + 64, TraceEvent::SyscallEnter, Sysnum::Recv as u32),
+            record(1000 + 128, TraceEvent::ContextSwitch, 0),
+            record(1000 + 192, TraceEvent::SyscallExit, 0),
+            record(1000 + 256, TraceEvent::IsrEnter, 0),
+            record(1000 + 256, TraceEvent::Irq, 33),
+            record(1000 + 320, TraceEvent::IsrExit, 0),
+            // An exit whose entry was lost is skipped.
+            record(1000 + 576, TraceEvent::SyscallExit, 0),
+            // The timer ISR was still running when the ring was read.
+            record(1000 + 640, TraceEvent::TimerIsrEnter, 0),
+            record(1000 + 704, TraceEvent::ContextSwitch, 1),
+        ];
+        let names = vec!["super".to_string()];
+        let timeline =
+            to_chrome_trace(&header(16, 10, false), &records, &names).unwrap();
+        assert_eq!(
+            slices(&timeline),
+            [
+                (2, "task 1".to_string(), 0.0, 2.0),
+                (0, "Recv".to_string(), 1.0, 2.0),
+                (0, "IRQ 33".to_string(), 4.0, 1.0),
+                (1, "super".to_string(), 2.0, 9.0),
+                (0, "timer".to_string(), 10.0, 1.0),
+                (2, "task 1".to_string(), 11.0, 0.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn no_clock_rate() {
+        let mut header = header(16, 0, false);
+        header.clock_khz = 0;
+        assert_eq!(
+            to_chrome_trace(&header, &[], &[]).unwrap_err().to_string(),
+            "trace ring has no clock rate"
+        );
+    }
+
+    #[test]
+    fn unknown_event() {
+        let mut bad = record(0, TraceEvent::IsrEnter, 0);
+        bad.event = 99;
+        assert_eq!(
+            to_chrome_trace(&header(16, 1, false), &[bad], &[])
+                .unwrap_err()
+                .to_string(),
+            "unknown trace event 99"
+        );
+    }
+
+    #[test]
+    fn raw_images() {
+        let image = [0u8; 0x100];
+        assert_eq!(
+            read_from_raw(&image, 0x2000_0000, 0x2000_0010, 0x20)
+                .unwrap()
+                .len(),
+            0x20
+        );
+        for addr in [0x1fff_fff0, 0x2000_00f0] {
+            assert!(read_from_raw(&image, 0x2000_0000, addr, 0x20).is_err());
+        }
+    }
+}
@@ -0,0 +1,701 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Strict checking of app.toml files.
+//!
+//! This runs in two passes. First, every file in the app's inheritance chain
+//! is checked against the schema from [`crate::schema`], which catches
+//! misspelled keys and values of the wrong type. Then the merged
+//! configuration is loaded and checked for references that don't resolve:
+//! peripherals in `uses`, interrupts and the notifications they're delivered
+//! as, task slots, memory regions and so on. These would otherwise fail late
+//! in the build (or not at all), usually without pointing at the offending
+//! line.
+//!
+//! Diagnostics point at the file that (last) sets the offending key, which
+//! may be one of the inherited files rather than the app.toml itself.
+
+use std::collections::BTreeSet;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use colored::*;
+use serde_json::Value;
+use toml::de::{DeTable, DeValue};
+use toml::Spanned;
+
+use crate::config::Config;
+
+/// Only suggest keys or names that are this close to what was written
+const MAX_DISTANCE: usize = 3;
+
+/// Checks the app.toml at `cfg` (and any files it inherits from), printing
+/// diagnostics for any problems found. Returns an error if there were any
+/// errors; warnings are printed but otherwise ignored.
+pub fn run(cfg: &Path) -> Result<()> {
+    let mut sources = vec![];
+    let mut report = Report::default();
+    collect(cfg, &mut sources, &mut BTreeSet::new(), &mut report)?;
+    if report.errors == 0 {
+        let docs = sources
+            .iter()
+            .map(|s| DeTable::parse(&s.text).map(Spanned::into_inner))
+            .collect::<Result<Vec<_>, _>>()?;
+        let validator = Validator {
+            sources: &sources,
+            docs,
+            report: &mut report,
+        };
+        validator.run()?;
+    }
+    report.finish()
+}
+
+/// A single file from the inheritance chain
+struct Source {
+    path: PathBuf,
+    text: String,
+}
+
+impl Source {
+    /// Converts a byte offset into a 1-based line and column
+    fn line_col(&self, offset: usize) -> (usize, usize) {
+        let before = &self.text[..offset.min(self.text.len())];
+        let line = before.matches('\n').count() + 1;
+        let col = before.rsplit('\n').next().unwrap().chars().count() + 1;
+        (line, col)
+    }
+}
+
+/// Reads `cfg` and everything it inherits from into `out`, in the order in
+/// which they're merged (so the most-derived file comes last).
+fn collect(
+    cfg: &Path,
+    out: &mut Vec<Source>,
+    seen: &mut BTreeSet<PathBuf>,
+    report: &mut Report,
+) -> Result<()> {
+    // Diamond inheritance is rejected when the config is loaded; here, we
+    // just need to avoid looping forever.
+    if !seen.insert(cfg.to_owned()) {
+        return Ok(());
+    }
+    let text = std::fs::read_to_string(cfg)
+        .with_context(|| format!("could not read {}", cfg.display()))?;
+    let source = Source {
+        path: cfg.to_owned(),
+        text,
+    };
+
+    // Files to inherit from, along with the span of their names
+    let mut inherit = vec![];
+    match DeTable::parse(&source.text) {
+        Ok(doc) => {
+            let v = doc.get_ref().get("inherit");
+            match v.map(|v| (v.get_ref(), v.span())) {
+                Some((DeValue::String(s), span)) => {
+                    inherit.push((s.to_string(), span))
+                }
+                Some((DeValue::Array(a), _)) => {
+                    inherit.extend(a.iter().filter_map(|v| {
+                        Some((v.get_ref().as_str()?.to_owned(), v.span()))
+                    }))
+                }
+                // A bad type is reported by the schema check
+                _ => (),
+            }
+        }
+        Err(e) => {
+            report.error(
+                format!("failed to parse TOML: {}", e.message().trim_end()),
+                e.span().map(|s| (&source, s)),
+            );
+        }
+    }
+
+    for (file, span) in inherit {
+        let file = cfg.parent().unwrap().join(file);
+        if !file.exists() {
+            report.error(
+                format!("inherited file {} does not exist", file.display()),
+                Some((&source, span)),
+            );
+            continue;
+        }
+        collect(&file, out, seen, report)?;
+    }
+    out.push(source);
+    Ok(())
+}
+
+#[derive(Default)]
+struct Report {
+    errors: usize,
+    warnings: usize,
+}
+
+impl Report {
+    fn error(&mut self, msg: String, at: Option<(&Source, Range<usize>)>) {
+        self.errors += 1;
+        Self::print("error".bold().red(), msg, at);
+    }
+
+    fn warning(&mut self, msg: String, at: Option<(&Source, Range<usize>)>) {
+        self.warnings += 1;
+        Self::print("warning".bold().yellow(), msg, at);
+    }
+
+    fn print(
+        level: ColoredString,
+        msg: String,
+        at: Option<(&Source, Range<usize>)>,
+    ) {
+        eprintln!("{level}: {}", msg.bold());
+        if let Some((source, span)) = at {
+            let (line, col) = source.line_col(span.start);
+            eprintln!(
+                "  {} {}:{line}:{col}",
+                "-->".bold().blue(),
+                source.path.display()
+            );
+        }
+    }
+
+    fn finish(&self) -> Result<()> {
+        if self.errors > 0 {
+            bail!(
+                "app.toml validation failed with {} error(s) and {} \
+                 warning(s)",
+                self.errors,
+                self.warnings
+            );
+        }
+        Ok(())
+    }
+}
+
+/// A diagnostic from the schema check, before it's printed
+struct Diagnostic {
+    msg: String,
+    span: Range<usize>,
+}
+
+struct Validator<'a> {
+    sources: &'a [Source],
+    docs: Vec<DeTable<'a>>,
+    report: &'a mut Report,
+}
+
+impl<'a> Validator<'a> {
+    fn run(mut self) -> Result<()> {
+        let schema = crate::schema::app_schema();
+        for (source, doc) in self.sources.iter().zip(&self.docs) {
+            let mut out = vec![];
+            check_table(&schema, doc, "", &mut out);
+            for d in out {
+                self.report.error(d.msg, Some((source, d.span)));
+            }
+        }
+        // If the files don't match the schema, loading the config will fail
+        // with a less helpful message, so stop here.
+        if self.report.errors > 0 {
+            return Ok(());
+        }
+
+        let toml = Config::from_file(&self.sources.last().unwrap().path)?;
+        self.check_config(&toml);
+        Ok(())
+    }
+
+    /// Checks references between different parts of the config.
+    fn check_config(&mut self, toml: &Config) {
+        let memories: Vec<&str> =
+            toml.outputs.keys().map(String::as_str).collect();
+        let shared: Vec<&str> = toml
+            .peripherals
+            .keys()
+            .chain(toml.extratext.keys())
+            .map(String::as_str)
+            .collect();
+        let tasks: Vec<&str> = toml.tasks.keys().map(String::as_str).collect();
+
+        for (name, size) in &toml.kernel.requires {
+            if !memories.contains(&name.as_str()) {
+                let msg = format!(
+                    "kernel requires {size} bytes of unknown memory \
+                     `{name}`{}",
+                    suggest(name, &memories)
+                );
+                self.error(msg, &["kernel", "requires", name], None);
+            }
+        }
+        for r in &toml.kernel.extern_regions {
+            if !memories.contains(&r.as_str()) {
+                let msg = format!(
+                    "kernel uses unknown extern region `{r}`{}",
+                    suggest(r, &memories)
+                );
+                self.error(msg, &["kernel", "extern-regions"], Some(r));
+            }
+        }
+
+        // Notifications bound to GPIO interrupts by the `sys` task, as
+        // (task, notification), with `-` and `_` treated as equivalent since
+        // both are turned into the same constant name.
+        let mut gpio_bound = BTreeSet::new();
+        for task in toml.tasks.values() {
+            let irqs = task
+                .config
+                .as_ref()
+                .and_then(|c| c.get("gpio-irqs"))
+                .and_then(|c| c.as_table());
+            for irq in irqs.into_iter().flat_map(|t| t.values()) {
+                let owner = irq.get("owner");
+                let field = |f| owner.and_then(|o| o.get(f)?.as_str());
+                if let (Some(t), Some(n)) =
+                    (field("name"), field("notification"))
+                {
+                    gpio_bound.insert((t.to_owned(), n.replace('-', "_")));
+                }
+            }
+        }
+
+        for (name, task) in &toml.tasks {
+            let path = |key| ["tasks", name.as_str(), key];
+
+            for u in &task.uses {
+                if !shared.contains(&u.as_str()) {
+                    let msg = format!(
+                        "task `{name}` uses unknown peripheral `{u}`{}",
+                        suggest(u, &shared)
+                    );
+                    self.error(msg, &path("uses"), Some(u));
+                }
+            }
+
+            let mut seen = BTreeSet::new();
+            for n in &task.notifications {
+                if !seen.insert(n) {
+                    let msg = format!(
+                        "task `{name}` declares notification `{n}` more than \
+                         once"
+                    );
+                    self.error(msg, &path("notifications"), Some(n));
+                }
+            }
+            if task.notifications.len() > 32 {
+                let msg = format!(
+                    "task `{name}` declares {} notifications, but only 32 \
+                     fit in a notification mask",
+                    task.notifications.len()
+                );
+                self.error(msg, &path("notifications"), None);
+            }
+            let notifications: Vec<&str> =
+                task.notifications.iter().map(String::as_str).collect();
+
+            for (irq, notification) in &task.interrupts {
+                let at = ["tasks", name.as_str(), "interrupts", irq.as_str()];
+                match irq.split_once('.') {
+                    Some((p, i)) => match toml.peripherals.get(p) {
+                        Some(periph) => {
+                            if !periph.interrupts.contains_key(i) {
+                                let names: Vec<&str> = periph
+                                    .interrupts
+                                    .keys()
+                                    .map(String::as_str)
+                                    .collect();
+                                let msg = format!(
+                                    "task `{name}`: peripheral `{p}` has no \
+                                     interrupt named `{i}`{}",
+                                    suggest(i, &names)
+                                );
+                                self.error(msg, &at, None);
+                            }
+                        }
+                        None => {
+                            let names: Vec<&str> = toml
+                                .peripherals
+                                .keys()
+                                .map(String::as_str)
+                                .collect();
+                            let msg = format!(
+                                "task `{name}`: interrupt `{irq}` refers to \
+                                 unknown peripheral `{p}`{}",
+                                suggest(p, &names)
+                            );
+                            self.error(msg, &at, None);
+                        }
+                    },
+                    None => {
+                        let msg = format!(
+                            "task `{name}`: interrupt `{irq}` must be of the \
+                             form `peripheral.interrupt`"
+                        );
+                        self.error(msg, &at, None);
+                    }
+                }
+
+                if !notifications.contains(&notification.as_str()) {
+                    let msg = format!(
+                        "task `{name}`: interrupt `{irq}` is delivered as \
+                         notification `{notification}`, which is not in the \
+                         task's `notifications`{}",
+                        suggest(notification, &notifications)
+                    );
+                    self.error(msg, &at, None);
+                } else if !notification.ends_with("-irq") {
+                    let msg = format!(
+                        "task `{name}`: notification `{notification}` is \
+                         bound to an interrupt, so its name must end in `-irq`"
+                    );
+                    self.error(msg, &at, None);
+                }
+            }
+
+            // Declaring an `-irq` notification that nothing delivers is
+            // allowed (some tasks share code with configurations that do
+            // use it), but is often a typo.
+            for n in seen {
+                if n.ends_with("-irq")
+                    && !task.interrupts.values().any(|v| v == n)
+                    && !gpio_bound
+                        .contains(&(name.clone(), n.replace('-', "_")))
+                {
+                    let msg = format!(
+                        "task `{name}`: notification `{n}` looks like an \
+                         interrupt notification, but no interrupt is bound \
+                         to it"
+                    );
+                    self.warning(msg, &path("notifications"), Some(n));
+                }
+            }
+
+            for (slot, target) in &task.task_slots {
+                if !tasks.contains(&target.as_str()) {
+                    let msg = format!(
+                        "task `{name}`: task slot `{slot}` refers to unknown \
+                         task `{target}`{}",
+                        suggest(target, &tasks)
+                    );
+                    self.error(msg, &path("task-slots"), Some(target));
+                }
+            }
+
+            for r in &task.extern_regions {
+                if !memories.contains(&r.as_str()) {
+                    let msg = format!(
+                        "task `{name}` uses unknown extern region `{r}`{}",
+                        suggest(r, &memories)
+                    );
+                    self.error(msg, &path("extern-regions"), Some(r));
+                }
+            }
+
+            for m in task.max_sizes.keys() {
+                if !memories.contains(&m.as_str()) {
+                    let msg = format!(
+                        "task `{name}` has a maximum size for unknown memory \
+                         `{m}`{}",
+                        suggest(m, &memories)
+                    );
+                    self.error(msg, &["tasks", name, "max-sizes", m], None);
+                }
+            }
+
+            for c in &task.copy_to_archive {
+                if task.config.as_ref().and_then(|t| t.get(c)).is_none() {
+                    let msg = format!(
+                        "task `{name}` copies `{c}` into the archive, but \
+                         there is no `{c}` in its config"
+                    );
+                    self.error(msg, &path("copy-to-archive"), Some(c));
+                }
+            }
+        }
+
+        if let Some(caboose) = &toml.caboose {
+            if !memories.contains(&caboose.region.as_str()) {
+                let msg = format!(
+                    "caboose is placed in unknown memory `{}`{}",
+                    caboose.region,
+                    suggest(&caboose.region, &memories)
+                );
+                self.error(msg, &["caboose", "region"], None);
+            }
+            for t in &caboose.tasks {
+                if !tasks.contains(&t.as_str()) {
+                    let msg = format!(
+                        "caboose is shared with unknown task `{t}`{}",
+                        suggest(t, &tasks)
+                    );
+                    self.error(msg, &["caboose", "tasks"], Some(t));
+                }
+            }
+        }
+    }
+
+    fn error(&mut self, msg: String, path: &[&str], item: Option<&str>) {
+        let at = self.locate(path, item);
+        self.report.error(msg, at);
+    }
+
+    fn warning(&mut self, msg: String, path: &[&str], item: Option<&str>) {
+        let at = self.locate(path, item);
+        self.report.warning(msg, at);
+    }
+
+    /// Finds the most-derived file that sets the key at `path`, returning the
+    /// span of the key itself or, if `item` is given, of the array element
+    /// that is (or contains) that string.
+    fn locate(
+        &self,
+        path: &[&str],
+        item: Option<&str>,
+    ) -> Option<(&'a Source, Range<usize>)> {
+        let (last, parents) = path.split_last()?;
+        'docs: for (source, doc) in self.sources.iter().zip(&self.docs).rev() {
+            let mut table = doc;
+            for key in parents {
+                match table.get(*key).map(|v| v.get_ref()) {
+                    Some(DeValue::Table(t)) => table = t,
+                    _ => continue 'docs,
+                }
+            }
+            let Some((key, value)) = table.get_key_value(*last) else {
+                continue;
+            };
+            let Some(item) = item else {
+                return Some((source, key.span()));
+            };
+            let Some(array) = value.get_ref().as_array() else {
+                continue;
+            };
+            for v in array.iter() {
+                let found = match v.get_ref() {
+                    DeValue::String(s) => s == item,
+                    // Inline tables such as `{slot = "task"}`
+                    DeValue::Table(t) => {
+                        t.values().any(|v| v.get_ref().as_str() == Some(item))
+                    }
+                    _ => false,
+                };
+                if found {
+                    return Some((source, v.span()));
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Checks a table (at `path`, which is empty for the top level) against an
+/// object schema.
+fn check_table(
+    schema: &Value,
+    table: &DeTable,
+    path: &str,
+    out: &mut Vec<Diagnostic>,
+) {
+    let properties = schema.get("properties").and_then(Value::as_object);
+    let additional = schema.get("additionalProperties");
+    for (key, value) in table {
+        let key_path = if path.is_empty() {
+            key.get_ref().to_string()
+        } else {
+            format!("{path}.{}", key.get_ref())
+        };
+        let sub = properties
+            .and_then(|p| p.get(key.get_ref().as_ref()))
+            .or(additional);
+        match sub {
+            Some(Value::Bool(false)) => {
+                let known: Vec<&str> = properties
+                    .into_iter()
+                    .flat_map(|p| p.keys())
+                    .map(String::as_str)
+                    .collect();
+                let place = if path.is_empty() {
+                    "at the top level".to_owned()
+                } else {
+                    format!("in `{path}`")
+                };
+                out.push(Diagnostic {
+                    msg: format!(
+                        "unknown key `{}` {place}{}",
+                        key.get_ref(),
+                        suggest(key.get_ref(), &known)
+                    ),
+                    span: key.span(),
+                });
+            }
+            Some(sub) if sub.is_object() => check(sub, value, &key_path, out),
+            // Anything goes
+            _ => (),
+        }
+    }
+
+    let len = table.len() as u64;
+    let span = || table.keys().next().map(|k| k.span()).unwrap_or(0..0);
+    if let Some(min) = schema.get("minProperties").and_then(Value::as_u64) {
+        if len < min {
+            out.push(Diagnostic {
+                msg: format!(
+                    "`{path}` has {len} entries, but needs at least {min}"
+                ),
+                span: span(),
+            });
+        }
+    }
+    if let Some(max) = schema.get("maxProperties").and_then(Value::as_u64) {
+        if len > max {
+            out.push(Diagnostic {
+                msg: format!(
+                    "`{path}` has {len} entries, but may have at most {max}"
+                ),
+                span: span(),
+            });
+        }
+    }
+}
+
+/// Checks a single value against `schema`.
+fn check(
+    schema: &Value,
+    value: &Spanned<DeValue>,
+    path: &str,
+    out: &mut Vec<Diagnostic>,
+) {
+    if let Some(alternatives) = schema.get("oneOf").and_then(Value::as_array) {
+        // Report problems within whichever alternative has the right type,
+        // or that none of them do.
+        match alternatives
+            .iter()
+            .find(|a| type_matches(a, value.get_ref()))
+        {
+            Some(a) => check(a, value, path, out),
+            None => {
+                let expected: Vec<_> =
+                    alternatives.iter().map(describe).collect();
+                out.push(Diagnostic {
+                    msg: format!(
+                        "`{path}` should be {}, found {}",
+                        expected.join(" or "),
+                        value.get_ref().type_str()
+                    ),
+                    span: value.span(),
+                });
+            }
+        }
+        return;
+    }
+
+    if let Some(options) = schema.get("enum").and_then(Value::as_array) {
+        let s = value.get_ref().as_str();
+        if !options.iter().any(|o| o.as_str() == s) {
+            let names: Vec<&str> =
+                options.iter().filter_map(Value::as_str).collect();
+            let quoted: Vec<String> =
+                names.iter().map(|n| format!("\"{n}\"")).collect();
+            out.push(Diagnostic {
+                msg: format!(
+                    "`{path}` must be one of {}{}",
+                    quoted.join(", "),
+                    s.map(|s| suggest(s, &names)).unwrap_or_default()
+                ),
+                span: value.span(),
+            });
+        }
+        return;
+    }
+
+    if !type_matches(schema, value.get_ref()) {
+        out.push(Diagnostic {
+            msg: format!(
+                "`{path}` should be {}, found {}",
+                describe(schema),
+                value.get_ref().type_str()
+            ),
+            span: value.span(),
+        });
+        return;
+    }
+
+    match value.get_ref() {
+        DeValue::Table(t) => check_table(schema, t, path, out),
+        DeValue::Array(a) => {
+            if let Some(items) = schema.get("items") {
+                for (i, v) in a.iter().enumerate() {
+                    check(items, v, &format!("{path}[{i}]"), out);
+                }
+            }
+        }
+        DeValue::Integer(n) => {
+            let min = schema.get("minimum").and_then(Value::as_i64);
+            let max = schema.get("maximum").and_then(Value::as_i64);
+            let n = i128::from_str_radix(n.as_str(), n.radix()).ok();
+            let in_range = n.is_some_and(|n| {
+                min.is_none_or(|m| n >= m.into())
+                    && max.is_none_or(|m| n <= m.into())
+            });
+            if !in_range {
+                out.push(Diagnostic {
+                    msg: format!(
+                        "`{path}` must be between {} and {}",
+                        min.unwrap_or(i64::MIN),
+                        max.unwrap_or(i64::MAX)
+                    ),
+                    span: value.span(),
+                });
+            }
+        }
+        _ => (),
+    }
+}
+
+fn type_matches(schema: &Value, value: &DeValue) -> bool {
+    match schema.get("type").and_then(Value::as_str) {
+        Some("object") => value.is_table(),
+        Some("array") => value.is_array(),
+        Some("string") => value.is_str(),
+        Some("integer") => value.is_integer(),
+        Some("boolean") => value.is_bool(),
+        _ => true,
+    }
+}
+
+/// Describes the type that `schema` expects, for error messages
+fn describe(schema: &Value) -> &'static str {
+    match schema.get("type").and_then(Value::as_str) {
+        Some("object") => "a table",
+        Some("array") => {
+            match schema.get("items").and_then(|i| i.get("type")) {
+                Some(Value::String(t)) if t == "string" => {
+                    "an array of strings"
+                }
+                _ => "an array",
+            }
+        }
+        Some("string") => "a string",
+        Some("integer") => "an integer",
+        Some("boolean") => "a boolean",
+        _ => "a value",
+    }
+}
+
+/// Returns a " (did you mean ...?)" suffix if one of `options` is close to
+/// `name`, or an empty string otherwise.
+fn suggest(name: &str, options: &[&str]) -> String {
+    let mut scored: Vec<_> = options
+        .iter()
+        .map(|o| (strsim::damerau_levenshtein(name, o), *o))
+        .filter(|(d, _)| *d <= MAX_DISTANCE)
+        .collect();
+    scored.sort();
+    match scored.first() {
+        Some((_, s)) => format!(" (did you mean `{s}`?)"),
+        None => String::new(),
+    }
+}
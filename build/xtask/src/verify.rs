@@ -0,0 +1,1202 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Checks a finished build archive, as produced by `xtask dist`, using only
+//! what's in the archive (and, for signed images, the root certificates).
+//!
+//! We check that
+//!
+//! - the kernel's vector table, at the start of the image, points into RAM and
+//!   the image;
+//! - the image header follows the vector table, and has the right magic,
+//!   version and epoch, and the length of the image;
+//! - the image id in the image is the one the kernel was built with, and is
+//!   the hash of the task ELF files, `kconfig.ron` and `allocations.ron` in
+//!   the archive;
+//! - the caboose ends where the image header says the image does, and parses;
+//! - the kernel, each task's allocations and the caboose are in the chip's
+//!   memory, and don't overlap;
+//! - the FWID, if the app has one, is the hash of the image;
+//! - a signed image's certificate chain starts at a key in its root key table
+//!   and ends at the key that signed the image, and the table holds exactly
+//!   the roots we were given.
+//!
+//! Every check is run and reported, and we fail at the end if any of them
+//! failed.
+
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Context, Result};
+use indexmap::IndexMap;
+use rsa::pkcs1::DecodeRsaPublicKey;
+use rsa::traits::PublicKeyParts;
+use rsa::{Pkcs1v15Sign, RsaPublicKey};
+use sha2::{Digest, Sha256};
+use x509_cert::der::{Decode, Encode, SliceReader};
+use x509_cert::spki::ObjectIdentifier;
+use x509_cert::Certificate;
+use zerocopy::FromBytes;
+
+use crate::config::{Config, Output};
+use crate::diff;
+use crate::dist::{self, Allocations};
+
+/// `sha256WithRSAEncryption`, the only certificate signature algorithm that
+/// the LPC55 ROM supports.
+const SHA256_WITH_RSA: ObjectIdentifier =
+    ObjectIdentifier::new_unwrap("1.2.840.113549.1.1.11");
+
+/// Number of entries in the root key table of a signed image.
+const ROOT_KEY_SLOTS: usize = 4;
+
+pub fn run(
+    archive: &Path,
+    app: Option<&Path>,
+    root_certs: &[PathBuf],
+) -> Result<()> {
+    let archive = Archive::load(archive)?;
+    let roots = read_roots(app, root_certs)?;
+
+    println!(
+        "verifying {} (image {})",
+        archive.path.display(),
+        archive.image_name
+    );
+    let mut report = Report::default();
+    report.record("vector table", check_vector_table(&archive));
+    report.record("image header", check_header(&archive));
+    report.record("image id", check_image_id(&archive));
+    report.record("caboose", check_caboose(&archive));
+    report.record("regions", check_regions(&archive));
+    report.record("fwid", check_fwid(&archive));
+    report.record("signature", check_signature(&archive));
+    report.record("root keys", check_root_keys(&archive, roots.as_deref()));
+
+    if report.failures > 0 {
+        bail!("{} check(s) failed", report.failures);
+    }
+    println!("all checks passed");
+    Ok(())
+}
+
+/// What a check found, if it didn't fail.
+enum Outcome {
+    /// The check passed; here's what it checked.
+    Pass(String),
+    /// The check doesn't apply, for this reason.
+    Skip(String),
+}
+
+#[derive(Default)]
+struct Report {
+    failures: usize,
+}
+
+impl Report {
+    fn record(&mut self, check: &str, outcome: Result<Outcome>) {
+        match outcome {
+            Ok(Outcome::Pass(what)) => println!("  ok    {check}: {what}"),
+            Ok(Outcome::Skip(why)) => println!("  skip  {check}: {why}"),
+            Err(e) => {
+                println!("  FAIL  {check}: {e:#}");
+                self.failures += 1;
+            }
+        }
+    }
+}
+
+/// The parts of an archive that we check.
+struct Archive {
+    path: PathBuf,
+    files: BTreeMap<String, Vec<u8>>,
+    image_name: String,
+    app: toml::Table,
+    task_names: Vec<String>,
+    /// The chip's memory regions, for this image.
+    memories: IndexMap<String, Range<u32>>,
+    kconfig: Option<build_kconfig::KernelConfig>,
+}
+
+impl Archive {
+    fn load(path: &Path) -> Result<Self> {
+        let files = diff::read_archive(path)?;
+        let text = |name: &str| -> Result<&str> {
+            let data = files.get(name).ok_or_else(|| {
+                anyhow!(
+                    "{} has no {name}; is it a build archive?",
+                    path.display()
+                )
+            })?;
+            std::str::from_utf8(data)
+                .with_context(|| format!("{name} in {}", path.display()))
+        };
+
+        let image_name = text("image-name")?.trim().to_string();
+        let app: toml::Table =
+            toml::from_str(text("app.toml")?).with_context(|| {
+                format!("parsing app.toml in {}", path.display())
+            })?;
+        // Tasks are numbered in the order they appear in app.toml.
+        let task_names = app
+            .get("tasks")
+            .and_then(toml::Value::as_table)
+            .map(|tasks| tasks.keys().cloned().collect())
+            .unwrap_or_default();
+
+        let outputs: IndexMap<String, Vec<Output>> =
+            toml::from_str(text("memory.toml")?).with_context(|| {
+                format!("parsing memory.toml in {}", path.display())
+            })?;
+        let mut memories = IndexMap::new();
+        for (name, outputs) in outputs {
+            let mut outputs = outputs.iter().filter(|o| o.name == image_name);
+            let (Some(o), None) = (outputs.next(), outputs.next()) else {
+                bail!("memory.toml: need one {name} region for {image_name}");
+            };
+            let end = o.address.checked_add(o.size).ok_or_else(|| {
+                anyhow!("memory.toml: {name} region overflows")
+            })?;
+            memories.insert(name, o.address..end);
+        }
+        if !memories.contains_key("flash") {
+            bail!("memory.toml has no flash region");
+        }
+
+        let kconfig = files
+            .get("kconfig.ron")
+            .map(|data| -> Result<build_kconfig::KernelConfig> {
+                Ok(ron::de::from_bytes(data)?)
+            })
+            .transpose()
+            .with_context(|| {
+                format!("parsing kconfig.ron in {}", path.display())
+            })?;
+
+        for file in ["elf/kernel", "img/final.bin"] {
+            if !files.contains_key(file) {
+                bail!("{} has no {file}", path.display());
+            }
+        }
+
+        Ok(Self {
+            path: path.to_owned(),
+            files,
+            image_name,
+            app,
+            task_names,
+            memories,
+            kconfig,
+        })
+    }
+
+    fn bin(&self) -> &[u8] {
+        &self.files["img/final.bin"]
+    }
+
+    fn flash(&self) -> &Range<u32> {
+        &self.memories["flash"]
+    }
+
+    fn kernel(&self) -> Result<goblin::elf::Elf<'_>> {
+        goblin::elf::Elf::parse(&self.files["elf/kernel"])
+            .context("parsing elf/kernel")
+    }
+
+    /// Returns the contents of the image at `addr`, which must be in flash.
+    fn read(&self, addr: u64, len: usize) -> Option<&[u8]> {
+        let offset = addr.checked_sub(u64::from(self.flash().start))?;
+        self.bin().get(usize::try_from(offset).ok()?..)?.get(..len)
+    }
+
+    fn app_u32(&self, key: &str) -> Result<u32> {
+        match self.app.get(key) {
+            None => Ok(0),
+            Some(v) => v
+                .as_integer()
+                .and_then(|v| u32::try_from(v).ok())
+                .ok_or_else(|| anyhow!("app.toml: bad {key}")),
+        }
+    }
+
+    fn app_str(&self, key: &str) -> Option<&str> {
+        self.app.get(key).and_then(toml::Value::as_str)
+    }
+}
+
+fn check_vector_table(a: &Archive) -> Result<Outcome> {
+    let kernel = a.kernel()?;
+    let table = section_range(&kernel, ".vector_table")
+        .ok_or_else(|| anyhow!("kernel has no .vector_table section"))?;
+    let flash = a.flash();
+    if table.start != u64::from(flash.start) {
+        bail!(
+            "vector table is at {:#x}, not the start of flash ({:#x})",
+            table.start,
+            flash.start
+        );
+    }
+    let words = a
+        .read(table.start, (table.end - table.start) as usize)
+        .ok_or_else(|| anyhow!("image is too short for the vector table"))?
+        .chunks_exact(4)
+        .map(|w| u32::from_le_bytes(w.try_into().unwrap()))
+        .collect::<Vec<_>>();
+    if words.len() < 16 {
+        bail!("vector table has only {} entries", words.len());
+    }
+
+    let mut problems = vec![];
+    let sp = words[0];
+    let in_ram = a
+        .memories
+        .iter()
+        .any(|(name, r)| name != "flash" && r.start < sp && sp <= r.end);
+    if !in_ram || sp % 8 != 0 {
+        problems.push(format!("bad initial stack pointer {sp:#010x}"));
+    }
+    if u64::from(words[1] & !1) != kernel.entry & !1 {
+        problems.push(format!(
+            "reset vector {:#010x} isn't the kernel entry point {:#010x}",
+            words[1], kernel.entry
+        ));
+    }
+    let image = flash.start..flash.start + a.bin().len() as u32;
+    for (i, &w) in words.iter().enumerate().skip(1) {
+        // Entries 7 to 10 and 13 are reserved, and the LPC55 keeps its
+        // signed image header in some of them.
+        if matches!(i, 7..=10 | 13) || w == 0 {
+            continue;
+        }
+        if w & 1 == 0 || !image.contains(&(w & !1)) {
+            problems.push(format!("entry {i} ({w:#010x}) is not in the image"));
+        }
+    }
+    if !problems.is_empty() {
+        bail!("{}", problems.join("; "));
+    }
+    Ok(Outcome::Pass(format!(
+        "{} entries, reset {:#010x}, stack {sp:#010x}",
+        words.len(),
+        words[1]
+    )))
+}
+
+/// Reads the image header, which must directly follow the vector table.
+fn image_header(a: &Archive) -> Result<abi::ImageHeader> {
+    let kernel = a.kernel()?;
+    let header = section_range(&kernel, ".header")
+        .ok_or_else(|| anyhow!("kernel has no .header section"))?;
+    let table = section_range(&kernel, ".vector_table")
+        .ok_or_else(|| anyhow!("kernel has no .vector_table section"))?;
+    if header.start != table.end {
+        bail!("header doesn't directly follow the vector table");
+    }
+    let size = std::mem::size_of::<abi::ImageHeader>();
+    if header.end - header.start < size as u64 {
+        bail!("header section is too small");
+    }
+    let data = a
+        .read(header.start, size)
+        .ok_or_else(|| anyhow!("image is too short for the header"))?;
+    let (header, _) = abi::ImageHeader::read_from_prefix(data)
+        .map_err(|_| anyhow!("can't read header"))?;
+    Ok(header)
+}
+
+/// Returns the length of the image as recorded in its header, which doesn't
+/// include the signature and certificates of a signed image.
+fn image_len(a: &Archive) -> Result<usize> {
+    let header = image_header(a).context("reading image header")?;
+    let len = header.total_image_len as usize;
+    if len > a.bin().len() {
+        bail!(
+            "header says the image is {len} bytes, but final.bin is only {}",
+            a.bin().len()
+        );
+    }
+    Ok(len)
+}
+
+fn check_header(a: &Archive) -> Result<Outcome> {
+    let header = image_header(a)?;
+    let mut problems = vec![];
+    if header.magic != abi::HEADER_MAGIC {
+        problems.push(format!("bad magic {:#010x}", header.magic));
+    }
+    for (field, actual, expected) in [
+        ("version", header.version, a.app_u32("version")?),
+        ("epoch", header.epoch, a.app_u32("epoch")?),
+    ] {
+        if actual != expected {
+            problems
+                .push(format!("{field} is {actual}, app.toml says {expected}"));
+        }
+    }
+
+    // An unsigned image is all image; a signed one has its certificates and
+    // signature at the end.
+    let len = header.total_image_len as usize;
+    let end = match signed_image(a.bin())? {
+        Some(signed) => signed.cert_offset,
+        None => a.bin().len(),
+    };
+    if len > end || end - len >= 4 {
+        problems.push(format!(
+            "image length is {len}, but the image is {end} bytes"
+        ));
+    }
+    if !problems.is_empty() {
+        bail!("{}", problems.join("; "));
+    }
+    Ok(Outcome::Pass(format!(
+        "version {}, epoch {}, {len} bytes",
+        header.version, header.epoch
+    )))
+}
+
+fn check_image_id(a: &Archive) -> Result<Outcome> {
+    let kernel = a.kernel()?;
+    let sym = kernel
+        .syms
+        .iter()
+        .find(|s| kernel.strtab.get_at(s.st_name) == Some("HUBRIS_IMAGE_ID"))
+        .ok_or_else(|| anyhow!("kernel has no HUBRIS_IMAGE_ID"))?;
+    let (offset, addr) = locate(&kernel, sym.st_value)
+        .ok_or_else(|| anyhow!("HUBRIS_IMAGE_ID isn't loaded from flash"))?;
+    let expected = a.files["elf/kernel"]
+        .get(offset..offset + 8)
+        .ok_or_else(|| anyhow!("HUBRIS_IMAGE_ID is past the end of the ELF"))?;
+    let actual = a.read(addr, 8).ok_or_else(|| {
+        anyhow!("HUBRIS_IMAGE_ID is past the end of the image")
+    })?;
+    let id = |b: &[u8]| u64::from_le_bytes(b.try_into().unwrap());
+    if actual != expected {
+        bail!(
+            "image has id {:#018x}, but the kernel was built with {:#018x}",
+            id(actual),
+            id(expected)
+        );
+    }
+    let computed = compute_image_id(a)?;
+    if computed != id(actual) {
+        bail!(
+            "image has id {:#018x}, but the archive's tasks, kconfig and \
+             allocations hash to {computed:#018x}",
+            id(actual)
+        );
+    }
+    Ok(Outcome::Pass(format!("{:#018x}", id(actual))))
+}
+
+/// Recomputes the image ID from the archive, as `xtask dist` computes it (see
+/// `dist::image_id`).
+fn compute_image_id(a: &Archive) -> Result<u64> {
+    let kconfig = a
+        .kconfig
+        .as_ref()
+        .ok_or_else(|| anyhow!("archive has no kconfig.ron"))?;
+    let allocs: Allocations = a
+        .files
+        .get("allocations.ron")
+        .ok_or_else(|| anyhow!("archive has no allocations.ron"))
+        .and_then(|data| {
+            ron::de::from_bytes(data).context("parsing allocations.ron")
+        })?;
+
+    let mut segments: BTreeMap<u32, Vec<u8>> = BTreeMap::new();
+    for name in &a.task_names {
+        let file = format!("elf/task/{name}");
+        let data = a
+            .files
+            .get(&file)
+            .ok_or_else(|| anyhow!("archive has no {file}"))?;
+        let elf = goblin::elf::Elf::parse(data)
+            .with_context(|| format!("parsing {file}"))?;
+        for (addr, data) in dist::load_segments(&elf, data)
+            .with_context(|| format!("loading {file}"))?
+        {
+            segments.insert(addr, data.to_vec());
+        }
+    }
+    if let Some((_, range)) = &allocs.caboose {
+        let size = a
+            .app
+            .get("caboose")
+            .and_then(|c| c.get("size"))
+            .and_then(toml::Value::as_integer)
+            .and_then(|s| u32::try_from(s).ok())
+            .filter(|&s| s >= 8)
+            .ok_or_else(|| anyhow!("app.toml: bad caboose size"))?;
+        segments.insert(range.start, dist::blank_caboose(size));
+    }
+
+    Ok(dist::image_id(
+        segments.iter().map(|(&addr, data)| (addr, data.as_slice())),
+        &ron::ser::to_string(kconfig)?,
+        &allocs,
+    ))
+}
+
+fn check_caboose(a: &Archive) -> Result<Outcome> {
+    let Some(caboose) = a.app.get("caboose") else {
+        return Ok(Outcome::Skip("app has no caboose".to_string()));
+    };
+    let size = caboose
+        .get("size")
+        .and_then(toml::Value::as_integer)
+        .ok_or_else(|| anyhow!("app.toml: caboose has no size"))?
+        as usize;
+    let len = image_len(a)?;
+    caboose_outcome(&a.bin()[..len], size, &a.app)
+}
+
+/// Checks the caboose at the end of `image`, which should be `size` bytes
+/// long, against the name and board in app.toml.
+fn caboose_outcome(
+    image: &[u8],
+    size: usize,
+    app: &toml::Table,
+) -> Result<Outcome> {
+    let len = image.len();
+    let start = len
+        .checked_sub(size)
+        .ok_or_else(|| anyhow!("image is smaller than the caboose"))?;
+    let word = |offset: usize| {
+        u32::from_le_bytes(image[offset..offset + 4].try_into().unwrap())
+    };
+    if size < 8 || word(len - 4) as usize != size {
+        bail!("image doesn't end with a {size}-byte caboose");
+    }
+    if word(start) != abi::CABOOSE_MAGIC {
+        bail!("bad magic {:#010x}", word(start));
+    }
+
+    let tags = diff::read_caboose(image)?;
+    let mut problems = vec![];
+    for (tag, key) in [("NAME", "name"), ("BORD", "board")] {
+        let value = tags.iter().find(|(t, _)| t == tag).map(|(_, v)| v);
+        let expected = app.get(key).and_then(toml::Value::as_str);
+        if let (Some(value), Some(expected)) = (value, expected) {
+            if value != expected {
+                problems.push(format!(
+                    "{tag} is {value:?}, but app.toml says {expected:?}"
+                ));
+            }
+        }
+    }
+    if !problems.is_empty() {
+        bail!("{}", problems.join("; "));
+    }
+    let tags = tags
+        .iter()
+        .map(|(t, v)| format!("{t}={v}"))
+        .collect::<Vec<_>>();
+    Ok(Outcome::Pass(if tags.is_empty() {
+        "empty".to_string()
+    } else {
+        tags.join(", ")
+    }))
+}
+
+fn check_regions(a: &Archive) -> Result<Outcome> {
+    let mut problems = vec![];
+    // Who has laid claim to what memory.
+    let mut claims: Vec<(String, Range<u64>)> = vec![];
+
+    let mut elves = vec![("kernel".to_string(), "elf/kernel".to_string())];
+    for name in &a.task_names {
+        elves.push((name.clone(), format!("elf/task/{name}")));
+    }
+    for (i, (name, file)) in elves.iter().enumerate() {
+        let data = a
+            .files
+            .get(file)
+            .ok_or_else(|| anyhow!("archive has no {file}"))?;
+        let elf = goblin::elf::Elf::parse(data)
+            .with_context(|| format!("parsing {file}"))?;
+        // Tasks are held to their allocations, where we have them, and
+        // otherwise to the chip's memory like the kernel.
+        let allocations = match (i, &a.kconfig) {
+            (0, _) | (_, None) => None,
+            (_, Some(kconfig)) => {
+                let task = kconfig.tasks.get(i - 1).ok_or_else(|| {
+                    anyhow!("kconfig.ron doesn't have task {name}")
+                })?;
+                let mut allocations = vec![];
+                for (region, r) in &task.owned_regions {
+                    let end = r
+                        .sizes
+                        .iter()
+                        .try_fold(r.base, |end, &size| end.checked_add(size))
+                        .ok_or_else(|| {
+                            anyhow!(
+                                "kconfig.ron: {name}'s {region} allocation \
+                                 at {:#x} overflows",
+                                r.base
+                            )
+                        })?;
+                    allocations
+                        .push((region, u64::from(r.base)..u64::from(end)));
+                }
+                Some(allocations)
+            }
+        };
+        for range in load_ranges(&elf) {
+            match &allocations {
+                Some(allocations) => {
+                    if !allocations.iter().any(|(_, r)| contains(r, &range)) {
+                        problems.push(format!(
+                            "{name}: {range:#x?} is outside its allocations"
+                        ));
+                    }
+                }
+                None => claims.push((name.clone(), range)),
+            }
+        }
+        for (region, range) in allocations.into_iter().flatten() {
+            match a.memories.get(region.as_str()) {
+                Some(r) if contains(&widen(r), &range) => (),
+                _ => problems.push(format!(
+                    "{name}: {range:#x?} is outside the chip's {region}"
+                )),
+            }
+            claims.push((name.clone(), range));
+        }
+    }
+
+    if let Some(size) = a
+        .app
+        .get("caboose")
+        .and_then(|c| c.get("size"))
+        .and_then(toml::Value::as_integer)
+    {
+        let end = u64::from(a.flash().start) + image_len(a)? as u64;
+        claims.push(("caboose".to_string(), end - size as u64..end));
+    }
+
+    for (name, range) in &claims {
+        if !a.memories.values().any(|r| contains(&widen(r), range)) {
+            problems.push(format!("{name}: {range:#x?} is outside memory"));
+        }
+    }
+    problems.extend(overlaps(&claims));
+
+    if !problems.is_empty() {
+        bail!("{}", problems.join("; "));
+    }
+    Ok(Outcome::Pass(format!(
+        "{} ranges, no overlaps",
+        claims.len()
+    )))
+}
+
+/// Describes each pair of claims, by different owners, on the same memory.
+fn overlaps(claims: &[(String, Range<u64>)]) -> Vec<String> {
+    let mut problems = vec![];
+    for (i, (a_name, a_range)) in claims.iter().enumerate() {
+        for (b_name, b_range) in &claims[i + 1..] {
+            if a_name != b_name
+                && a_range.start < b_range.end
+                && b_range.start < a_range.end
+            {
+                problems.push(format!(
+                    "{a_name} ({a_range:#x?}) overlaps {b_name} ({b_range:#x?})"
+                ));
+            }
+        }
+    }
+    problems
+}
+
+fn check_fwid(a: &Archive) -> Result<Outcome> {
+    if !a
+        .app
+        .get("fwid")
+        .and_then(toml::Value::as_bool)
+        .unwrap_or(false)
+    {
+        return Ok(Outcome::Skip("app has no FWID".to_string()));
+    }
+    let chip = a
+        .app_str("chip")
+        .ok_or_else(|| anyhow!("app.toml has no chip"))?;
+    let recorded = a
+        .files
+        .get("img/final.fwid")
+        .ok_or_else(|| anyhow!("archive has no img/final.fwid"))?;
+    let recorded = std::str::from_utf8(recorded)
+        .context("img/final.fwid")?
+        .trim();
+    let fwid = crate::dist::compute_fwid(chip, a.bin(), a.flash())?;
+    if fwid != recorded {
+        bail!("image hashes to {fwid}, but img/final.fwid says {recorded}");
+    }
+    Ok(Outcome::Pass(fwid))
+}
+
+fn check_signature(a: &Archive) -> Result<Outcome> {
+    let Some(signed) = signed_image(a.bin())? else {
+        if a.app.contains_key("signing") {
+            bail!("app is signed, but the image has no signature");
+        }
+        return Ok(Outcome::Skip("image isn't signed".to_string()));
+    };
+
+    let keys = signed
+        .certs
+        .iter()
+        .map(public_key)
+        .collect::<Result<Vec<_>>>()?;
+    let first = key_hash(&keys[0]);
+    if !signed.root_key_hashes.contains(&first) {
+        bail!(
+            "{} isn't in the root key table",
+            signed.certs[0].tbs_certificate.subject
+        );
+    }
+    for (i, cert) in signed.certs.iter().enumerate().skip(1) {
+        if cert.signature_algorithm.oid != SHA256_WITH_RSA {
+            bail!(
+                "{} is signed with unsupported algorithm {}",
+                cert.tbs_certificate.subject,
+                cert.signature_algorithm.oid
+            );
+        }
+        let tbs = cert.tbs_certificate.to_der()?;
+        keys[i - 1]
+            .verify(
+                Pkcs1v15Sign::new::<Sha256>(),
+                &Sha256::digest(&tbs),
+                cert.signature.raw_bytes(),
+            )
+            .map_err(|e| {
+                anyhow!(
+                    "{} isn't signed by {}: {e}",
+                    cert.tbs_certificate.subject,
+                    signed.certs[i - 1].tbs_certificate.subject
+                )
+            })?;
+    }
+
+    let leaf = signed.certs.last().unwrap();
+    keys.last()
+        .unwrap()
+        .verify(
+            Pkcs1v15Sign::new::<Sha256>(),
+            &Sha256::digest(signed.signed),
+            signed.signature,
+        )
+        .map_err(|e| {
+            anyhow!(
+                "image isn't signed by {}: {e}",
+                leaf.tbs_certificate.subject
+            )
+        })?;
+    Ok(Outcome::Pass(format!(
+        "signed by {} ({} certificate(s))",
+        leaf.tbs_certificate.subject,
+        signed.certs.len()
+    )))
+}
+
+fn check_root_keys(
+    a: &Archive,
+    roots: Option<&[Certificate]>,
+) -> Result<Outcome> {
+    let Some(signed) = signed_image(a.bin())? else {
+        return Ok(Outcome::Skip("image isn't signed".to_string()));
+    };
+    let rkth = hex::encode(Sha256::digest(signed.root_key_hashes.concat()));
+    let Some(roots) = roots else {
+        return Ok(Outcome::Skip(format!(
+            "no roots given (use --app or --root-cert); RKTH is {rkth}"
+        )));
+    };
+    if roots.len() > ROOT_KEY_SLOTS {
+        bail!("at most {ROOT_KEY_SLOTS} roots are allowed");
+    }
+
+    // Unused slots are zero.
+    let mut expected = [[0u8; 32]; ROOT_KEY_SLOTS];
+    for (slot, root) in expected.iter_mut().zip(roots) {
+        *slot = key_hash(&public_key(root)?);
+    }
+    let mut problems = vec![];
+    for (i, (actual, expected)) in
+        signed.root_key_hashes.iter().zip(&expected).enumerate()
+    {
+        if actual != expected {
+            problems.push(format!(
+                "slot {i} is {}, expected {}",
+                hex::encode(actual),
+                hex::encode(expected)
+            ));
+        }
+    }
+    if !problems.is_empty() {
+        bail!("{}", problems.join("; "));
+    }
+    Ok(Outcome::Pass(format!(
+        "{} root(s), RKTH {rkth}",
+        roots.len()
+    )))
+}
+
+/// Reads the root certificates to check a signed image against, either from
+/// the command line or as configured in `app`.
+fn read_roots(
+    app: Option<&Path>,
+    root_certs: &[PathBuf],
+) -> Result<Option<Vec<Certificate>>> {
+    let paths = if !root_certs.is_empty() {
+        root_certs.to_vec()
+    } else if let Some(app) = app {
+        let toml = Config::from_file(app)?;
+        let signing = toml.signing.as_ref().ok_or_else(|| {
+            anyhow!("{} has no [signing] section", app.display())
+        })?;
+        // Certificate paths are relative to the app.toml.
+        let dir = app.parent().unwrap_or(Path::new("."));
+        signing
+            .certs
+            .root_certs
+            .iter()
+            .map(|c| dir.join(c))
+            .collect()
+    } else {
+        return Ok(None);
+    };
+    Ok(Some(lpc55_sign::cert::read_certs(&paths)?))
+}
+
+/// The parts of an LPC55 signed image.
+///
+/// The image is followed by a certificate block: a header, a table of X.509 certificates (each preceded by its
+/// length) and the SHA-256 hashes of up to four root keys. That's all signed
+/// by the key of the last certificate, and the signature is appended. The
+/// vector table entry at 0x28 gives the offset of the certificate block.
+struct SignedImage<'a> {
+    cert_offset: usize,
+    certs: Vec<Certificate>,
+    root_key_hashes: Vec<[u8; 32]>,
+    /// The signed part of the image.
+    signed: &'a [u8],
+    signature: &'a [u8],
+}
+
+/// Parses the certificate block of a signed image, returning `None` if the
+/// image isn't signed.
+fn signed_image(bin: &[u8]) -> Result<Option<SignedImage<'_>>> {
+    let word = |offset: usize| {
+        bin.get(offset..offset + 4)
+            .map(|b| u32::from_le_bytes(b.try_into().unwrap()) as usize)
+            .ok_or_else(|| anyhow!("certificate block is truncated"))
+    };
+    let cert_offset = word(0x28)?;
+    if bin.get(cert_offset..cert_offset + 4) != Some(b"cert") {
+        return Ok(None);
+    }
+    let header_len = word(cert_offset + 8)?;
+    let signed_len = word(cert_offset + 20)?;
+    let cert_count = word(cert_offset + 24)?;
+    let table_len = word(cert_offset + 28)?;
+
+    let table_start = cert_offset + header_len;
+    let table = bin
+        .get(table_start..table_start + table_len)
+        .ok_or_else(|| anyhow!("certificate table is truncated"))?;
+    let mut certs = vec![];
+    let mut pos = 0;
+    for i in 0..cert_count {
+        let len = table
+            .get(pos..pos + 4)
+            .map(|b| u32::from_le_bytes(b.try_into().unwrap()) as usize)
+            .ok_or_else(|| anyhow!("certificate table is truncated"))?;
+        let der = table
+            .get(pos + 4..pos + 4 + len)
+            .ok_or_else(|| anyhow!("certificate {i} is truncated"))?;
+        // The certificate may be followed by padding.
+        let cert = SliceReader::new(der)
+            .and_then(|mut r| Certificate::decode(&mut r))
+            .map_err(|e| anyhow!("parsing certificate {i}: {e}"))?;
+        certs.push(cert);
+        pos += 4 + len.next_multiple_of(4);
+    }
+    if certs.is_empty() {
+        bail!("certificate table is empty");
+    }
+
+    let rkh_start = table_start + table_len;
+    let root_key_hashes = bin
+        .get(rkh_start..rkh_start + 32 * ROOT_KEY_SLOTS)
+        .ok_or_else(|| anyhow!("root key table is truncated"))?
+        .chunks_exact(32)
+        .map(|h| h.try_into().unwrap())
+        .collect();
+    if signed_len != rkh_start + 32 * ROOT_KEY_SLOTS || signed_len > bin.len() {
+        bail!("certificate block has bad length {signed_len}");
+    }
+
+    Ok(Some(SignedImage {
+        cert_offset,
+        certs,
+        root_key_hashes,
+        signed: &bin[..signed_len],
+        signature: &bin[signed_len..],
+    }))
+}
+
+fn public_key(cert: &Certificate) -> Result<RsaPublicKey> {
+    let spki = &cert.tbs_certificate.subject_public_key_info;
+    RsaPublicKey::from_pkcs1_der(spki.subject_public_key.raw_bytes()).map_err(
+        |e| anyhow!("{}: not an RSA key: {e}", cert.tbs_certificate.subject),
+    )
+}
+
+/// Hashes a public key the way the LPC55 ROM does for its root key table:
+/// the modulus, then the exponent, both big-endian.
+fn key_hash(key: &RsaPublicKey) -> [u8; 32] {
+    let mut sha = Sha256::new();
+    sha.update(key.n().to_bytes_be());
+    sha.update(key.e().to_bytes_be());
+    sha.finalize().into()
+}
+
+/// Returns the address range of a section of an ELF file.
+fn section_range(elf: &goblin::elf::Elf, name: &str) -> Option<Range<u64>> {
+    crate::elf::get_section_by_name(elf, name)
+        .map(|s| s.sh_addr..s.sh_addr + s.sh_size)
+}
+
+/// Finds where the contents of `addr` are in an ELF file, returning their
+/// file offset and the address they're loaded from.
+fn locate(elf: &goblin::elf::Elf, addr: u64) -> Option<(usize, u64)> {
+    elf.program_headers
+        .iter()
+        .filter(|p| p.p_type == goblin::elf::program_header::PT_LOAD)
+        .find(|p| p.p_vaddr <= addr && addr < p.p_vaddr + p.p_filesz)
+        .map(|p| {
+            let offset = addr - p.p_vaddr;
+            ((p.p_offset + offset) as usize, p.p_paddr + offset)
+        })
+}
+
+/// Returns the memory used by an ELF file's loadable segments, both where
+/// they run and where they're loaded from.
+fn load_ranges(elf: &goblin::elf::Elf) -> Vec<Range<u64>> {
+    let mut ranges = vec![];
+    for phdr in &elf.program_headers {
+        if phdr.p_type != goblin::elf::program_header::PT_LOAD {
+            continue;
+        }
+        if phdr.p_memsz != 0 {
+            ranges.push(phdr.p_vaddr..phdr.p_vaddr + phdr.p_memsz);
+        }
+        if phdr.p_vaddr != phdr.p_paddr && phdr.p_filesz != 0 {
+            ranges.push(phdr.p_paddr..phdr.p_paddr + phdr.p_filesz);
+        }
+    }
+    ranges
+}
+
+fn widen(r: &Range<u32>) -> Range<u64> {
+    u64::from(r.start)..u64::from(r.end)
+}
+
+fn contains(outer: &Range<u64>, inner: &Range<u64>) -> bool {
+    outer.start <= inner.start && inner.end <= outer.end
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dist::ContiguousRanges;
+
+    const FLASH: Range<u32> = 0x0800_0000..0x0801_0000;
+
+    fn archive(
+        files: BTreeMap<String, Vec<u8>>,
+        app: &str,
+        kconfig: build_kconfig::KernelConfig,
+    ) -> Archive {
+        let app: toml::Table = toml::from_str(app).unwrap();
+        let task_names = app
+            .get("tasks")
+            .and_then(toml::Value::as_table)
+            .map(|tasks| tasks.keys().cloned().collect())
+            .unwrap_or_default();
+        Archive {
+            path: PathBuf::from("test.zip"),
+            files,
+            image_name: "default".to_string(),
+            app,
+            task_names,
+            memories: [("flash".to_string(), FLASH)].into_iter().collect(),
+            kconfig: Some(kconfig),
+        }
+    }
+
+    fn kconfig() -> build_kconfig::KernelConfig {
+        ron::de::from_str(
+            "(features: [], extern_regions: {}, tasks: [], \
+             shared_regions: {}, irqs: {})",
+        )
+        .unwrap()
+    }
+
+    /// Returns a 32-bit ELF file with one loadable segment, holding `data`
+    /// at `addr`.
+    fn elf(addr: u32, data: &[u8]) -> Vec<u8> {
+        let mut elf = vec![];
+        elf.extend(b"\x7fELF\x01\x01\x01");
+        elf.resize(16, 0);
+        // An ARM executable, with its program header right after this one.
+        for half in [2u16, 40] {
+            elf.extend(half.to_le_bytes());
+        }
+        for word in [1u32, addr, 52, 0, 0] {
+            elf.extend(word.to_le_bytes());
+        }
+        for half in [52u16, 32, 1, 40, 0, 0] {
+            elf.extend(half.to_le_bytes());
+        }
+        let len = data.len() as u32;
+        for word in [1u32, 84, addr, addr, len, len, 5, 4] {
+            elf.extend(word.to_le_bytes());
+        }
+        elf.extend(data);
+        elf
+    }
+
+    fn message<T>(result: Result<T>) -> String {
+        match result {
+            Ok(_) => panic!("expected an error"),
+            Err(e) => format!("{e:#}"),
+        }
+    }
+
+    #[test]
+    fn image_id_matches_dist() {
+        let task = [0x55; 32];
+        let mut allocs = Allocations::default();
+        allocs.tasks.insert(
+            "task".to_string(),
+            [(
+                "flash".to_string(),
+                ContiguousRanges::new(0x0800_0400..0x0800_0800),
+            )]
+            .into_iter()
+            .collect(),
+        );
+        allocs.caboose = Some(("flash".to_string(), 0x0800_1000..0x0800_1100));
+        let files = [
+            ("elf/task/task".to_string(), elf(0x0800_0400, &task)),
+            (
+                "allocations.ron".to_string(),
+                ron::ser::to_string(&allocs).unwrap().into_bytes(),
+            ),
+        ]
+        .into_iter()
+        .collect();
+        let app = "[tasks.task]\n[caboose]\nsize = 256\n";
+        let mut a = archive(files, app, kconfig());
+
+        let caboose = dist::blank_caboose(256);
+        let expected = dist::image_id(
+            [(0x0800_0400, &task[..]), (0x0800_1000, &caboose[..])],
+            &ron::ser::to_string(&kconfig()).unwrap(),
+            &allocs,
+        );
+        assert_eq!(compute_image_id(&a).unwrap(), expected);
+
+        // Anything that goes into the hash changes it.
+        a.kconfig
+            .as_mut()
+            .unwrap()
+            .features
+            .push("dump".to_string());
+        assert_ne!(compute_image_id(&a).unwrap(), expected);
+        a.kconfig = Some(kconfig());
+        a.files.get_mut("elf/task/task").unwrap()[84] ^= 1;
+        assert_ne!(compute_image_id(&a).unwrap(), expected);
+
+        a.files.remove("allocations.ron");
+        assert_eq!(
+            message(compute_image_id(&a)),
+            "archive has no allocations.ron"
+        );
+    }
+
+    /// Returns an image that ends in a `size`-byte caboose holding `tags`.
+    fn image_with_caboose(size: usize, tags: &[(&[u8; 4], &str)]) -> Vec<u8> {
+        let pieces = tags
+            .iter()
+            .map(|(tag, value)| {
+                tlvc_text::Piece::Chunk(
+                    tlvc_text::Tag::new(**tag),
+                    vec![tlvc_text::Piece::Bytes(value.as_bytes().to_vec())],
+                )
+            })
+            .collect::<Vec<_>>();
+        let contents = tlvc_text::pack(&pieces);
+        let mut caboose = dist::blank_caboose(size as u32);
+        caboose[4..4 + contents.len()].copy_from_slice(&contents);
+
+        let mut image = vec![0; 64];
+        image.extend(caboose);
+        image
+    }
+
+    fn pass(outcome: Result<Outcome>) -> String {
+        match outcome.unwrap() {
+            Outcome::Pass(what) => what,
+            Outcome::Skip(why) => panic!("skipped: {why}"),
+        }
+    }
+
+    #[test]
+    fn caboose_tags() {
+        let app: toml::Table =
+            toml::from_str("name = \"demo\"\nboard = \"gimlet-c\"").unwrap();
+        let image =
+            image_with_caboose(128, &[(b"NAME", "demo"), (b"VERS", "1.0.2")]);
+        assert_eq!(
+            pass(caboose_outcome(&image, 128, &app)),
+            "NAME=demo, VERS=1.0.2"
+        );
+        let image = image_with_caboose(128, &[]);
+        assert_eq!(pass(caboose_outcome(&image, 128, &app)), "empty");
+
+        let image = image_with_caboose(
+            128,
+            &[(b"NAME", "other"), (b"BORD", "gimlet-c")],
+        );
+        assert_eq!(
+            message(caboose_outcome(&image, 128, &app)),
+            "NAME is \"other\", but app.toml says \"demo\""
+        );
+    }
+
+    #[test]
+    fn bad_caboose() {
+        let app = toml::Table::new();
+        let image = image_with_caboose(128, &[]);
+        assert_eq!(
+            message(caboose_outcome(&image, 64, &app)),
+            "image doesn't end with a 64-byte caboose"
+        );
+        assert_eq!(
+            message(caboose_outcome(&image[64..], 256, &app)),
+            "image is smaller than the caboose"
+        );
+        let mut image = image;
+        image[64] ^= 1;
+        assert!(message(caboose_outcome(&image, 128, &app))
+            .starts_with("bad magic"));
+    }
+
+    #[test]
+    fn overlapping_claims() {
+        let claims = [
+            ("kernel".to_string(), 0x100..0x200),
+            ("a".to_string(), 0x200..0x300),
+            // A task's own ranges may overlap, such as its flash and the
+            // copy of its data that's loaded from it.
+            ("a".to_string(), 0x280..0x290),
+            ("b".to_string(), 0x2ff..0x400),
+            ("caboose".to_string(), 0x400..0x500),
+            // Ranges that only touch don't overlap, in either order.
+            ("c".to_string(), 0x80..0x100),
+        ];
+        assert_eq!(
+            overlaps(&claims),
+            ["a (0x200..0x300) overlaps b (0x2ff..0x400)"]
+        );
+        assert!(overlaps(&claims[..3]).is_empty());
+    }
+
+    const CERT: &[u8] =
+        include_bytes!("../../../support/fake_certs/fake_certificate.der.crt");
+
+    /// Returns a signed image, with its certificate block at `cert_offset`:
+    /// a header, a table holding `CERT`, the root key table and a signature.
+    fn signed(cert_offset: usize, roots: [[u8; 32]; 4]) -> Vec<u8> {
+        let table_len = 4 + CERT.len().next_multiple_of(4);
+        let signed_len = cert_offset + 32 + table_len + 128;
+        let mut bin = vec![0; cert_offset];
+        bin[0x28..0x2c].copy_from_slice(&(cert_offset as u32).to_le_bytes());
+        bin.extend(b"cert");
+        for word in [1, 32, 0, 0, signed_len, 1, table_len] {
+            bin.extend((word as u32).to_le_bytes());
+        }
+        bin.extend((CERT.len() as u32).to_le_bytes());
+        bin.extend(CERT);
+        bin.resize(cert_offset + 32 + table_len, 0);
+        bin.extend(roots.as_flattened());
+        bin.extend([0xa5; 256]);
+        bin
+    }
+
+    #[test]
+    fn signed_images() {
+        let roots = [[1; 32], [2; 32], [0; 32], [0; 32]];
+        let bin = signed(0x400, roots);
+        let image = signed_image(&bin).unwrap().unwrap();
+        assert_eq!(image.cert_offset, 0x400);
+        assert_eq!(image.certs.len(), 1);
+        assert_eq!(image.certs[0].to_der().unwrap(), CERT);
+        assert_eq!(image.root_key_hashes, roots);
+        assert_eq!(image.signed.len(), bin.len() - 256);
+        assert_eq!(image.signature, [0xa5; 256]);
+    }
+
+    #[test]
+    fn unsigned_image() {
+        let mut bin = vec![0; 0x400];
+        bin[0x28..0x2c].copy_from_slice(&0x200u32.to_le_bytes());
+        assert!(signed_image(&bin).unwrap().is_none());
+        // An offset past the end of the image isn't a certificate block.
+        bin[0x28..0x2c].copy_from_slice(&0x1000u32.to_le_bytes());
+        assert!(signed_image(&bin).unwrap().is_none());
+        assert_eq!(
+            message(signed_image(&bin[..0x20])),
+            "certificate block is truncated"
+        );
+    }
+
+    #[test]
+    fn bad_signed_images() {
+        let bin = signed(0x400, [[0; 32]; 4]);
+        let table_end = bin.len() - 256 - 128;
+        assert_eq!(
+            message(signed_image(&bin[..table_end - 4])),
+            "certificate table is truncated"
+        );
+        assert_eq!(
+            message(signed_image(&bin[..table_end + 64])),
+            "root key table is truncated"
+        );
+
+        // The signed length must end at the root key table.
+        let mut bad = bin.clone();
+        bad[0x414..0x418].copy_from_slice(&0x2000u32.to_le_bytes());
+        assert_eq!(
+            message(signed_image(&bad)),
+            "certificate block has bad length 8192"
+        );
+
+        // A certificate that runs past the table.
+        let mut bad = bin.clone();
+        bad[0x420..0x424].copy_from_slice(&0x2000u32.to_le_bytes());
+        assert_eq!(message(signed_image(&bad)), "certificate 0 is truncated");
+
+        let mut bad = bin;
+        bad[0x424] ^= 0xff;
+        assert!(
+            message(signed_image(&bad)).starts_with("parsing certificate 0")
+        );
+    }
+}
@@ -0,0 +1,44 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use serde::Deserialize;
+use std::io::Write;
+
+/// Where the auxiliary flash starts in the SPI flash, which also holds the
+/// image that the boot ROM loads
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TaskConfig {
+    base: u32,
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    idol::Generator::new()
+        .with_counters(
+            idol::CounterSettings::default().with_server_counters(false),
+        )
+        .build_server_support(
+            "../../idl/auxflash.idol",
+            "server_stub.rs",
+            idol::server::ServerStyle::InOrder,
+        )?;
+
+    let config = build_util::task_config::<TaskConfig>()?;
+    let out_dir = build_util::out_dir();
+    let mut file = std::fs::File::create(out_dir.join("config.rs"))?;
+    writeln!(&mut file, "const BASE: u32 = {:#x};", config.base)?;
+
+    // Unlike on other boards, auxiliary data is optional here: an app may use
+    // the auxiliary flash only for its reserved slots.
+    let checksum = match build_util::env_var("HUBRIS_AUXFLASH_CHECKSUM") {
+        Ok(e) => format!("Some({e})"),
+        Err(_) => "None".to_string(),
+    };
+    writeln!(
+        &mut file,
+        "const AUXI_CHECKSUM: Option<[u8; 32]> = {checksum};"
+    )?;
+
+    Ok(())
+}
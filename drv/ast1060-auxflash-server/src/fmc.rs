@@ -0,0 +1,150 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! The SPI flash on the firmware memory controller's (FMC's) first chip
+//! select, driven in user mode.
+//!
+//! In user mode, the FMC sends every byte that we write to the chip select's
+//! AHB window out on the SPI bus, and clocks in a byte for every one that we
+//! read from it. The chip select is held from the start of a command to its
+//! end, so a command is whatever we write and read in between. Outside of
+//! user mode, the FMC goes back to whatever it was doing for the boot ROM.
+
+use core::ptr::{addr_of, addr_of_mut};
+
+/// The FMC's registers, up to CE0's control register
+#[repr(C)]
+struct Registers {
+    conf: u32,
+    _reserved: [u32; 3],
+    ce0_ctrl: u32,
+}
+
+/// Where the registers are, as the `fmc` peripheral in the chip's memory map
+const REGISTERS: *mut Registers = 0x7e62_0000 as *mut Registers;
+
+/// CE0's AHB window, as the `fmc_flash` peripheral in the chip's memory map
+const WINDOW: *mut u8 = 0x8000_0000 as *mut u8;
+
+/// Configuration register: CE0 is a SPI flash...
+const CONF_CE0_TYPE_SPI: u32 = 0b10;
+/// ...and we can write to its window
+const CONF_CE0_WRITE: u32 = 1 << 16;
+
+/// CE0 control register: user mode, in the I/O mode field
+const CTRL_IO_MODE_USER: u32 = 0b11;
+/// Deasserts the chip select
+const CTRL_CE_STOP_ACTIVE: u32 = 1 << 2;
+/// Dual and quad I/O, which we don't use in user mode
+const CTRL_IO_WIDTH: u32 = 0b1111 << 28;
+
+#[derive(Copy, Clone)]
+enum Command {
+    PageProgram = 0x02,
+    Read = 0x03,
+    ReadStatusReg = 0x05,
+    WriteEnable = 0x06,
+    /// Reads a 8-byte unique ID from Winbond parts
+    WinbondReadUniqueId = 0x4B,
+    ReadId = 0x9F,
+    /// Erases 64 KiB
+    SectorErase = 0xD8,
+}
+
+pub struct Fmc {
+    /// CE0's control register as the boot ROM left it, which we go back to
+    /// after every command
+    normal: u32,
+}
+
+impl Fmc {
+    pub fn new() -> Self {
+        // Safety: these are the FMC's registers, which are ours alone.
+        let normal = unsafe {
+            let conf = addr_of_mut!((*REGISTERS).conf);
+            conf.write_volatile(
+                conf.read_volatile() | CONF_CE0_TYPE_SPI | CONF_CE0_WRITE,
+            );
+            addr_of!((*REGISTERS).ce0_ctrl).read_volatile()
+        };
+        Self { normal }
+    }
+
+    /// Reads the JEDEC ID into `buf`.
+    pub fn read_id(&self, buf: &mut [u8]) {
+        self.command(Command::ReadId, None, &[], buf);
+    }
+
+    /// Reads the Device unique ID buffer for Winbond parts
+    pub fn read_winbond_unique_id(&self) -> [u8; 8] {
+        let mut buf = [0u8; 12];
+        self.command(Command::WinbondReadUniqueId, None, &[], &mut buf);
+        // First 4 bytes are dummy values
+        let mut out = [0u8; 8];
+        out.copy_from_slice(&buf[4..]);
+        out
+    }
+
+    /// Reads the Status register.
+    pub fn read_status(&self) -> u8 {
+        let mut status = [0u8];
+        self.command(Command::ReadStatusReg, None, &[], &mut status);
+        status[0]
+    }
+
+    /// Reads from flash starting at `address` into `data`.
+    pub fn read_memory(&self, address: u32, data: &mut [u8]) {
+        self.command(Command::Read, Some(address), &[], data);
+    }
+
+    /// Sets the write enable latch, which every erase and program needs.
+    pub fn write_enable(&self) {
+        self.command(Command::WriteEnable, None, &[], &mut []);
+    }
+
+    /// Erases the 64 KiB sector containing `address`.
+    pub fn sector_erase(&self, address: u32) {
+        self.command(Command::SectorErase, Some(address), &[], &mut []);
+    }
+
+    /// Writes `data`, which mustn't cross a page, starting at `address`.
+    pub fn page_program(&self, address: u32, data: &[u8]) {
+        self.command(Command::PageProgram, Some(address), data, &mut []);
+    }
+
+    /// Sends `command`, then `address` (in three bytes), then `write`, then
+    /// reads `read`.
+    fn command(
+        &self,
+        command: Command,
+        address: Option<u32>,
+        write: &[u8],
+        read: &mut [u8],
+    ) {
+        let user = (self.normal & !CTRL_IO_WIDTH) | CTRL_IO_MODE_USER;
+        // Safety: CE0's control register and window are ours alone, and in
+        // user mode, accessing the window has no effect but on the SPI bus.
+        unsafe {
+            let ctrl = addr_of_mut!((*REGISTERS).ce0_ctrl);
+            ctrl.write_volatile(user | CTRL_CE_STOP_ACTIVE);
+            ctrl.write_volatile(user);
+
+            WINDOW.write_volatile(command as u8);
+            if let Some(address) = address {
+                for &b in &address.to_be_bytes()[1..] {
+                    WINDOW.write_volatile(b);
+                }
+            }
+            for &b in write {
+                WINDOW.write_volatile(b);
+            }
+            for b in read {
+                *b = WINDOW.read_volatile();
+            }
+
+            ctrl.write_volatile(user | CTRL_CE_STOP_ACTIVE);
+            ctrl.write_volatile(self.normal);
+        }
+    }
+}
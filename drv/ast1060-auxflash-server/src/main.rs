@@ -0,0 +1,410 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Auxiliary flash server for the AST1060.
+//!
+//! The AST1060 has no flash of its own to spare: the auxiliary flash is a
+//! region of the SPI flash that the boot ROM loads the image from, starting
+//! at the `base` in the task's config and above the image. Reserved slots are
+//! left to their users; auxiliary data, if the app has any, goes in the rest
+//! as on other boards.
+
+#![no_std]
+#![no_main]
+
+mod fmc;
+
+use drv_auxflash_api::{
+    AuxFlashBlob, AuxFlashChecksum, AuxFlashError, AuxFlashId,
+    TlvcReadAuxFlash, DATA_SLOT_COUNT, PAGE_SIZE_BYTES, SECTOR_SIZE_BYTES,
+    SLOT_COUNT, SLOT_SIZE,
+};
+use fmc::Fmc;
+use idol_runtime::{
+    ClientError, Leased, NotificationHandler, RequestError, R, W,
+};
+use tlvc::{TlvcRead, TlvcReadError, TlvcReader};
+use userlib::{hl, RecvMessage, UnwrapLite};
+
+// We send three-byte addresses, so the auxiliary flash must be in the first
+// 16 MiB, and erase by sector, so it must start on one.
+const _: () =
+    assert!(BASE as usize + SLOT_COUNT as usize * SLOT_SIZE <= 1 << 24);
+const _: () = assert!(BASE as usize % SECTOR_SIZE_BYTES == 0);
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Simple handle which holds a `&Fmc` and allows us to implement `TlvcRead`
+#[derive(Copy, Clone)]
+struct SlotReader<'a> {
+    fmc: &'a Fmc,
+    slot: u32,
+}
+
+impl<'a> TlvcRead for SlotReader<'a> {
+    type Error = AuxFlashError;
+
+    fn extent(&self) -> Result<u64, TlvcReadError<Self::Error>> {
+        Ok(SLOT_SIZE as u64)
+    }
+    fn read_exact(
+        &self,
+        offset: u64,
+        dest: &mut [u8],
+    ) -> Result<(), TlvcReadError<Self::Error>> {
+        let offset = u32::try_from(offset).unwrap_lite();
+        self.fmc.read_memory(address(self.slot, offset), dest);
+        Ok(())
+    }
+}
+
+/// Returns the address in the SPI flash of `offset` into `slot`.
+fn address(slot: u32, offset: u32) -> u32 {
+    BASE + slot * SLOT_SIZE as u32 + offset
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[export_name = "main"]
+fn main() -> ! {
+    let fmc = Fmc::new();
+
+    let mut buffer = [0; idl::INCOMING_SIZE];
+    let active_slot = scan_for_active_slot(&fmc);
+    let mut server = ServerImpl { fmc, active_slot };
+
+    let _ = server.ensure_redundancy();
+
+    loop {
+        idol_runtime::dispatch(&mut buffer, &mut server);
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+struct ServerImpl {
+    fmc: Fmc,
+    active_slot: Option<u32>,
+}
+
+impl ServerImpl {
+    /// Polls for the "Write Complete" flag, sleeping for `sleep` ticks
+    /// between polls if given.
+    fn poll_for_write_complete(&self, sleep: Option<u64>) {
+        while self.fmc.read_status() & 1 != 0 {
+            if let Some(sleep) = sleep {
+                hl::sleep_for(sleep);
+            }
+        }
+    }
+
+    fn set_and_check_write_enable(&self) -> Result<(), AuxFlashError> {
+        self.fmc.write_enable();
+        if self.fmc.read_status() & 0b10 == 0 {
+            return Err(AuxFlashError::WriteEnableFailed);
+        }
+        Ok(())
+    }
+
+    fn erase_sector(&self, addr: u32) -> Result<(), AuxFlashError> {
+        self.set_and_check_write_enable()?;
+        self.fmc.sector_erase(addr);
+        self.poll_for_write_complete(Some(1));
+        Ok(())
+    }
+
+    fn program_page(
+        &self,
+        addr: u32,
+        data: &[u8],
+    ) -> Result<(), AuxFlashError> {
+        self.set_and_check_write_enable()?;
+        self.fmc.page_program(addr, data);
+        self.poll_for_write_complete(None);
+        Ok(())
+    }
+
+    /// Checks that the matched slot in this even/odd pair also has valid data.
+    ///
+    /// If not, writes the auxiliary data to the spare slot.
+    fn ensure_redundancy(&mut self) -> Result<(), AuxFlashError> {
+        let active_slot =
+            self.active_slot.ok_or(AuxFlashError::NoActiveSlot)?;
+
+        let spare_slot = active_slot ^ 1;
+        let spare_checksum =
+            read_and_check_slot_checksum(&self.fmc, spare_slot);
+        if spare_checksum.map(|c| Some(c.0)) == Ok(AUXI_CHECKSUM) {
+            return Ok(());
+        }
+
+        // Find the length of data by finding the final TLV-C slot
+        let handle = SlotReader {
+            fmc: &self.fmc,
+            slot: active_slot,
+        };
+        let mut reader = TlvcReader::begin(handle)
+            .map_err(|_| AuxFlashError::TlvcReaderBeginFailed)?;
+        while let Ok(Some(..)) = reader.next() {
+            // Nothing to do here
+        }
+        let data_size = SLOT_SIZE - reader.remaining() as usize;
+
+        let mut buf = [0u8; PAGE_SIZE_BYTES];
+        let mut offset = 0;
+        while offset < data_size {
+            let amount = (data_size - offset).min(buf.len());
+            let read_addr = address(active_slot, offset as u32);
+            let write_addr = address(spare_slot, offset as u32);
+
+            self.fmc.read_memory(read_addr, &mut buf[..amount]);
+
+            // If we're at the start of a sector, erase it before we start
+            // writing the copy.
+            if offset.is_multiple_of(SECTOR_SIZE_BYTES) {
+                self.erase_sector(write_addr)?;
+            }
+            self.program_page(write_addr, &buf[..amount])?;
+            offset += amount;
+        }
+
+        // Confirm that the spare write worked
+        let spare_checksum =
+            read_and_check_slot_checksum(&self.fmc, spare_slot)?;
+        if Some(spare_checksum.0) == AUXI_CHECKSUM {
+            Ok(())
+        } else {
+            Err(AuxFlashError::ChckMismatch)
+        }
+    }
+}
+
+impl idl::InOrderAuxFlashImpl for ServerImpl {
+    fn read_id(
+        &mut self,
+        _: &RecvMessage,
+    ) -> Result<AuxFlashId, RequestError<AuxFlashError>> {
+        let mut idbuf = [0; 3];
+        self.fmc.read_id(&mut idbuf);
+        Ok(AuxFlashId {
+            mfr_id: idbuf[0],
+            memory_type: idbuf[1],
+            capacity: idbuf[2],
+            unique_id: self.fmc.read_winbond_unique_id(),
+        })
+    }
+
+    fn read_status(
+        &mut self,
+        _: &RecvMessage,
+    ) -> Result<u8, RequestError<AuxFlashError>> {
+        Ok(self.fmc.read_status())
+    }
+
+    fn slot_count(
+        &mut self,
+        _: &RecvMessage,
+    ) -> Result<u32, RequestError<AuxFlashError>> {
+        Ok(SLOT_COUNT)
+    }
+
+    fn slot_size(
+        &mut self,
+        _: &RecvMessage,
+    ) -> Result<u32, RequestError<AuxFlashError>> {
+        Ok(SLOT_SIZE as u32)
+    }
+
+    fn read_slot_chck(
+        &mut self,
+        _: &RecvMessage,
+        slot: u32,
+    ) -> Result<AuxFlashChecksum, RequestError<AuxFlashError>> {
+        Ok(read_and_check_slot_checksum(&self.fmc, slot)?)
+    }
+
+    fn erase_slot(
+        &mut self,
+        _: &RecvMessage,
+        slot: u32,
+    ) -> Result<(), RequestError<AuxFlashError>> {
+        if slot >= SLOT_COUNT {
+            return Err(AuxFlashError::InvalidSlot.into());
+        }
+        for offset in (0..SLOT_SIZE).step_by(SECTOR_SIZE_BYTES) {
+            self.erase_sector(address(slot, offset as u32))?;
+        }
+        Ok(())
+    }
+
+    fn slot_sector_erase(
+        &mut self,
+        _: &RecvMessage,
+        slot: u32,
+        offset: u32,
+    ) -> Result<(), RequestError<AuxFlashError>> {
+        if slot >= SLOT_COUNT {
+            return Err(AuxFlashError::InvalidSlot.into());
+        }
+        if offset >= SLOT_SIZE as u32 {
+            return Err(AuxFlashError::AddressOverflow.into());
+        }
+        self.erase_sector(address(slot, offset))?;
+        Ok(())
+    }
+
+    fn write_slot_with_offset(
+        &mut self,
+        _: &RecvMessage,
+        slot: u32,
+        offset: u32,
+        data: Leased<R, [u8]>,
+    ) -> Result<(), RequestError<AuxFlashError>> {
+        if slot >= SLOT_COUNT {
+            return Err(AuxFlashError::InvalidSlot.into());
+        }
+        if Some(slot) == self.active_slot {
+            return Err(AuxFlashError::SlotActive.into());
+        }
+        if !(offset as usize).is_multiple_of(PAGE_SIZE_BYTES) {
+            return Err(AuxFlashError::UnalignedAddress.into());
+        } else if offset as usize + data.len() > SLOT_SIZE {
+            return Err(AuxFlashError::AddressOverflow.into());
+        }
+
+        // The flash chip has a limited write buffer!
+        let mut buf = [0u8; PAGE_SIZE_BYTES];
+        let mut read = 0;
+        while read < data.len() {
+            let amount = (data.len() - read).min(buf.len());
+            data.read_range(read..(read + amount), &mut buf[..amount])
+                .map_err(|_| RequestError::Fail(ClientError::WentAway))?;
+            self.program_page(
+                address(slot, offset + read as u32),
+                &buf[..amount],
+            )?;
+            read += amount;
+        }
+        Ok(())
+    }
+
+    fn read_slot_with_offset(
+        &mut self,
+        _: &RecvMessage,
+        slot: u32,
+        offset: u32,
+        dest: Leased<W, [u8]>,
+    ) -> Result<(), RequestError<AuxFlashError>> {
+        if slot >= SLOT_COUNT {
+            return Err(AuxFlashError::InvalidSlot.into());
+        }
+        if offset as usize + dest.len() > SLOT_SIZE {
+            return Err(AuxFlashError::AddressOverflow.into());
+        }
+
+        let mut write = 0;
+        let mut buf = [0u8; 256];
+        while write < dest.len() {
+            let amount = (dest.len() - write).min(buf.len());
+            self.fmc.read_memory(
+                address(slot, offset + write as u32),
+                &mut buf[..amount],
+            );
+            dest.write_range(write..(write + amount), &buf[..amount])
+                .map_err(|_| RequestError::Fail(ClientError::WentAway))?;
+            write += amount;
+        }
+        Ok(())
+    }
+
+    fn scan_and_get_active_slot(
+        &mut self,
+        msg: &RecvMessage,
+    ) -> Result<u32, RequestError<AuxFlashError>> {
+        // Deprecated in the idl file; kept for compatibility with humility.
+        self.get_active_slot(msg)
+    }
+
+    fn get_active_slot(
+        &mut self,
+        _: &RecvMessage,
+    ) -> Result<u32, RequestError<AuxFlashError>> {
+        self.active_slot
+            .ok_or_else(|| AuxFlashError::NoActiveSlot.into())
+    }
+
+    fn ensure_redundancy(
+        &mut self,
+        _: &RecvMessage,
+    ) -> Result<(), RequestError<AuxFlashError>> {
+        ServerImpl::ensure_redundancy(self).map_err(Into::into)
+    }
+
+    fn get_blob_by_tag(
+        &mut self,
+        _: &RecvMessage,
+        tag: [u8; 4],
+    ) -> Result<AuxFlashBlob, RequestError<AuxFlashError>> {
+        let active_slot = self
+            .active_slot
+            .ok_or_else(|| RequestError::from(AuxFlashError::NoActiveSlot))?;
+        let handle = SlotReader {
+            fmc: &self.fmc,
+            slot: active_slot,
+        };
+        handle
+            .get_blob_by_tag(active_slot, tag)
+            .map_err(RequestError::from)
+    }
+}
+
+impl NotificationHandler for ServerImpl {
+    fn current_notification_mask(&self) -> u32 {
+        // We don't use notifications, don't listen for any.
+        0
+    }
+
+    fn handle_notification(&mut self, _bits: userlib::NotificationBits) {
+        unreachable!()
+    }
+}
+
+/// Finds the data slot that holds our auxiliary data, if we have any.
+fn scan_for_active_slot(fmc: &Fmc) -> Option<u32> {
+    let checksum = AUXI_CHECKSUM?;
+    // Reserved slots never hold image data, whatever they happen to contain.
+    (0..DATA_SLOT_COUNT).find(|&slot| {
+        let handle = SlotReader { fmc, slot };
+        handle.read_stored_checksum().map(|c| c.0) == Ok(checksum)
+            && handle.calculate_checksum().map(|c| c.0) == Ok(checksum)
+    })
+}
+
+fn read_and_check_slot_checksum(
+    fmc: &Fmc,
+    slot: u32,
+) -> Result<AuxFlashChecksum, AuxFlashError> {
+    if slot >= SLOT_COUNT {
+        return Err(AuxFlashError::InvalidSlot);
+    }
+    let handle = SlotReader { fmc, slot };
+    let claimed = handle.read_stored_checksum()?;
+    let actual = handle.calculate_checksum()?;
+    if claimed == actual {
+        Ok(actual)
+    } else {
+        Err(AuxFlashError::ChckMismatch)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+mod idl {
+    use super::AuxFlashError;
+    use drv_auxflash_api::{AuxFlashBlob, AuxFlashChecksum, AuxFlashId};
+
+    include!(concat!(env!("OUT_DIR"), "/server_stub.rs"));
+}
+
+include!(concat!(env!("OUT_DIR"), "/config.rs"));
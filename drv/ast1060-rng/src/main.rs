@@ -0,0 +1,123 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Driver for the AST1060 random number generator.
+//!
+//! The RNG is part of the SCU: a control register (SCU520), whose low bit
+//! turns it off, and a data register (SCU524), which takes on a new random
+//! word every microsecond or so. There's no flag to say that a word is new,
+//! so we wait for it to change before handing it out again.
+//!
+//! Use the rng-api crate to interact with this driver.
+
+#![no_std]
+#![no_main]
+
+use ast1060_pac as device;
+use core::mem::size_of;
+use core::ptr::{addr_of, addr_of_mut};
+use drv_rng_api::RngError;
+use idol_runtime::{ClientError, NotificationHandler, RequestError};
+
+/// The RNG's registers in the SCU
+#[repr(C)]
+struct Registers {
+    ctrl: u32,
+    data: u32,
+}
+
+/// Where the registers are: SCU520 onwards, which the `rng` peripheral in the
+/// chip's memory map covers
+const REGISTERS: *mut Registers =
+    device::Scu::ptr().wrapping_byte_add(0x520) as *mut Registers;
+
+/// Bit of the control register that turns the RNG off
+const CTRL_DISABLE: u32 = 1 << 0;
+
+/// How many times to read the data register for a new word before giving
+/// up; far more than the RNG should ever need.
+const MAX_READS: usize = 10_000;
+
+struct Ast1060Rng {
+    /// The last word that we handed out
+    last: u32,
+}
+
+impl Ast1060Rng {
+    fn new() -> Self {
+        // Safety: this is the RNG's control register, which is ours alone.
+        unsafe {
+            let ctrl = addr_of_mut!((*REGISTERS).ctrl);
+            ctrl.write_volatile(ctrl.read_volatile() & !CTRL_DISABLE);
+        }
+        Ast1060Rng { last: 0 }
+    }
+
+    /// Returns the next word from the RNG, which is never the same as the
+    /// one before it.
+    fn read(&mut self) -> Result<u32, RngError> {
+        // Safety: this is the RNG's control register, which is ours alone.
+        let ctrl = unsafe { addr_of!((*REGISTERS).ctrl).read_volatile() };
+        if ctrl & CTRL_DISABLE != 0 {
+            return Err(RngError::PoweredOff);
+        }
+        for _ in 0..MAX_READS {
+            // Safety: reading the data register has no side effects.
+            let word = unsafe { addr_of!((*REGISTERS).data).read_volatile() };
+            if word != self.last {
+                self.last = word;
+                return Ok(word);
+            }
+        }
+        Err(RngError::NoData)
+    }
+}
+
+struct Ast1060RngServer(Ast1060Rng);
+
+impl idl::InOrderRngImpl for Ast1060RngServer {
+    fn fill(
+        &mut self,
+        _: &userlib::RecvMessage,
+        dest: idol_runtime::Leased<idol_runtime::W, [u8]>,
+    ) -> Result<usize, RequestError<RngError>> {
+        const STEP: usize = size_of::<u32>();
+        let mut cnt = 0;
+        while cnt < dest.len() {
+            let word = self.0.read()?.to_ne_bytes();
+            let n = STEP.min(dest.len() - cnt);
+            dest.write_range(cnt..cnt + n, &word[..n])
+                .map_err(|_| RequestError::Fail(ClientError::WentAway))?;
+            cnt += n;
+        }
+        Ok(cnt)
+    }
+}
+
+impl NotificationHandler for Ast1060RngServer {
+    fn current_notification_mask(&self) -> u32 {
+        // We don't use notifications, don't listen for any.
+        0
+    }
+
+    fn handle_notification(&mut self, _bits: userlib::NotificationBits) {
+        unreachable!()
+    }
+}
+
+#[export_name = "main"]
+fn main() -> ! {
+    let mut srv = Ast1060RngServer(Ast1060Rng::new());
+    let mut buffer = [0u8; idl::INCOMING_SIZE];
+
+    loop {
+        idol_runtime::dispatch(&mut buffer, &mut srv);
+    }
+}
+
+mod idl {
+    use drv_rng_api::RngError;
+
+    include!(concat!(env!("OUT_DIR"), "/server_stub.rs"));
+}
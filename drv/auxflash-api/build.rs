@@ -3,6 +3,7 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 use serde::Deserialize;
+use std::collections::BTreeMap;
 use std::io::Write;
 
 fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
@@ -18,12 +19,15 @@ fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         writeln!(out, "pub const MEMORY_SIZE: u32 = 0;")?;
         writeln!(out, "pub const SLOT_COUNT: u32 = 0;")?;
         writeln!(out, "pub const SLOT_SIZE: usize = 0;")?;
+        writeln!(out, "pub const DATA_SLOT_COUNT: u32 = 0;")?;
+        writeln!(out, "pub mod reserved_slots {{}}")?;
     }
 
     idol::client::build_client_stub(
         "../../idl/auxflash.idol",
         "client_stub.rs",
     )?;
+    build_util::build_client_interface("../../idl/auxflash.idol")?;
     Ok(())
 }
 
@@ -40,6 +44,10 @@ struct GlobalConfig {
 struct AuxFlashConfig {
     memory_size: u32,
     slot_count: u32,
+    /// Slots set aside for other uses, by name, which image data (and its
+    /// spare copy) is never written to.
+    #[serde(default)]
+    reserved_slots: BTreeMap<String, u32>,
 }
 
 fn generate_auxflash_config<W: Write>(
@@ -63,10 +71,43 @@ fn generate_auxflash_config<W: Write>(
         "auxflash slots must be page aligned"
     );
 
+    // d. Reserved slots are distinct, and above every slot that holds image
+    //    data. Image data lives in even/odd pairs, so the data slots end at
+    //    an even slot, and a reserved slot can't be the spare of a data slot.
+    let mut reserved: Vec<u32> =
+        config.reserved_slots.values().copied().collect();
+    reserved.sort_unstable();
+    reserved.dedup();
+    assert_eq!(
+        reserved.len(),
+        config.reserved_slots.len(),
+        "auxflash reserved slots must be distinct"
+    );
+    for (name, &slot) in &config.reserved_slots {
+        assert!(
+            slot < config.slot_count,
+            "auxflash reserved slot {name} ({slot}) is out of range"
+        );
+    }
+    let data_slot_count =
+        reserved.first().map_or(config.slot_count, |s| s & !1);
+    // e. We still have at least 6 slots for image data
+    assert!(
+        data_slot_count >= 6,
+        "auxflash requires at least 6 slots that aren't reserved"
+    );
+
     let slot_size = (config.memory_size / config.slot_count) as usize;
     writeln!(out, "pub const MEMORY_SIZE: u32 = {};", config.memory_size)?;
     writeln!(out, "pub const SLOT_COUNT: u32 = {};", config.slot_count)?;
     writeln!(out, "pub const SLOT_SIZE: usize = {slot_size};")?;
+    writeln!(out, "pub const DATA_SLOT_COUNT: u32 = {data_slot_count};")?;
+    writeln!(out, "pub mod reserved_slots {{")?;
+    for (name, slot) in &config.reserved_slots {
+        let name = name.to_uppercase().replace('-', "_");
+        writeln!(out, "    pub const {name}: u32 = {slot};")?;
+    }
+    writeln!(out, "}}")?;
 
     Ok(())
 }
@@ -233,10 +233,11 @@ impl AuxFlash {
 ////////////////////////////////////////////////////////////////////////////////
 
 include!(concat!(env!("OUT_DIR"), "/client_stub.rs"));
+include!(concat!(env!("OUT_DIR"), "/client_interface.rs"));
 
 #[allow(unused)]
 mod config {
     include!(concat!(env!("OUT_DIR"), "/auxflash_config.rs"));
 }
 
-pub use config::{SLOT_COUNT, SLOT_SIZE};
+pub use config::{reserved_slots, DATA_SLOT_COUNT, SLOT_COUNT, SLOT_SIZE};
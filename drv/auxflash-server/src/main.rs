@@ -7,8 +7,8 @@
 
 use drv_auxflash_api::{
     AuxFlashBlob, AuxFlashChecksum, AuxFlashError, AuxFlashId,
-    TlvcReadAuxFlash, PAGE_SIZE_BYTES, SECTOR_SIZE_BYTES, SLOT_COUNT,
-    SLOT_SIZE,
+    TlvcReadAuxFlash, DATA_SLOT_COUNT, PAGE_SIZE_BYTES, SECTOR_SIZE_BYTES,
+    SLOT_COUNT, SLOT_SIZE,
 };
 use idol_runtime::{
     ClientError, Leased, NotificationHandler, RequestError, R, W,
@@ -22,7 +22,7 @@ use stm32h7::stm32h753 as device;
 use drv_stm32h7_qspi::{Qspi, QspiError, ReadSetting};
 use drv_stm32xx_sys_api as sys_api;
 
-task_slot!(SYS, sys);
+task_slot!(SYS, sys, sys_api::Sys);
 
 ////////////////////////////////////////////////////////////////////////////////
 
@@ -490,7 +490,8 @@ impl NotificationHandler for ServerImpl {
 }
 
 fn scan_for_active_slot(qspi: &Qspi) -> Option<u32> {
-    for i in 0..SLOT_COUNT {
+    // Reserved slots never hold image data, whatever they happen to contain.
+    for i in 0..DATA_SLOT_COUNT {
         let handle = SlotReader {
             qspi,
             base: i * SLOT_SIZE as u32,
@@ -19,7 +19,11 @@ use userlib::{hl::sleep_for, task_slot, UnwrapLite};
 mod apob; // Details for APOB structs
 mod hf; // Implementation of `HostFlash` API
 
-task_slot!(LOADER, spartan7_loader);
+task_slot!(
+    LOADER,
+    spartan7_loader,
+    drv_spartan7_loader_api::Spartan7Loader
+);
 
 #[derive(Debug, Clone, Copy, PartialEq, counters::Count)]
 enum Trace {
@@ -30,14 +30,14 @@ include!(concat!(env!("OUT_DIR"), "/i2c_config.rs"));
 mod vcore;
 use vcore::VCore;
 
-task_slot!(JEFE, jefe);
-task_slot!(LOADER, spartan7_loader);
-task_slot!(HF, hf);
-task_slot!(SYS, sys);
-task_slot!(SPI_FRONT, spi_front);
-task_slot!(AUXFLASH, auxflash);
+task_slot!(JEFE, jefe, Jefe);
+task_slot!(LOADER, spartan7_loader, Spartan7Loader);
+task_slot!(HF, hf, HostFlash);
+task_slot!(SYS, sys, Sys);
+task_slot!(SPI_FRONT, spi_front, drv_spi_api::Spi);
+task_slot!(AUXFLASH, auxflash, drv_auxflash_api::AuxFlash);
 task_slot!(I2C, i2c_driver);
-task_slot!(PACKRAT, packrat);
+task_slot!(PACKRAT, packrat, Packrat);
 
 ////////////////////////////////////////////////////////////////////////////////
 
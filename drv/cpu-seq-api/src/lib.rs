@@ -109,3 +109,4 @@ pub const NUM_SPD_BANKS: usize = 2;
 use crate as drv_cpu_seq_api;
 
 include!(concat!(env!("OUT_DIR"), "/client_stub.rs"));
+include!(concat!(env!("OUT_DIR"), "/client_interface.rs"));
@@ -4,5 +4,6 @@
 
 fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     idol::client::build_client_stub("../../idl/fpga.idol", "client_stub.rs")?;
+    build_util::build_client_interface("../../idl/fpga.idol")?;
     Ok(())
 }
@@ -481,6 +481,7 @@ pub mod idl {
     use userlib::sys_send;
 
     include!(concat!(env!("OUT_DIR"), "/client_stub.rs"));
+    include!(concat!(env!("OUT_DIR"), "/client_interface.rs"));
 }
 
 #[cfg(feature = "hiffy")]
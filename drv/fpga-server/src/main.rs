@@ -17,7 +17,7 @@ use drv_spi_api::SpiServer;
 use drv_stm32xx_sys_api::{self as sys_api, Sys};
 use idol_runtime::{ClientError, Leased, LenLimit, R, W};
 
-task_slot!(SYS, sys);
+task_slot!(SYS, sys, Sys);
 
 cfg_if::cfg_if! {
     if #[cfg(feature = "front_io")] {
@@ -40,7 +40,7 @@ cfg_if::cfg_if! {
         }
     } else {
         pub fn claim_spi(_sys: &sys_api::Sys) -> drv_spi_api::Spi {
-            task_slot!(SPI, spi_driver);
+            task_slot!(SPI, spi_driver, drv_spi_api::Spi);
             drv_spi_api::Spi::from(SPI.get_task_id())
         }
     }
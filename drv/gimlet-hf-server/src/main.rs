@@ -47,7 +47,7 @@ use drv_hf_api::{
     HfRawPersistentData, HF_PERSISTENT_DATA_STRIDE, PAGE_SIZE_BYTES,
 };
 
-task_slot!(SYS, sys);
+task_slot!(SYS, sys, sys_api::Sys);
 task_slot!(HASH, hash_driver);
 
 struct Config {
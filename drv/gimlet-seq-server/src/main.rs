@@ -29,12 +29,12 @@ use seq_spi::{Addr, Reg};
 use static_assertions::const_assert;
 use task_jefe_api::Jefe;
 
-task_slot!(SYS, sys);
-task_slot!(SPI, spi_driver);
+task_slot!(SYS, sys, sys_api::Sys);
+task_slot!(SPI, spi_driver, drv_spi_api::Spi);
 task_slot!(I2C, i2c_driver);
-task_slot!(HF, hf);
-task_slot!(JEFE, jefe);
-task_slot!(PACKRAT, packrat);
+task_slot!(HF, hf, hf_api::HostFlash);
+task_slot!(JEFE, jefe, Jefe);
+task_slot!(PACKRAT, packrat, Packrat);
 
 include!(concat!(env!("OUT_DIR"), "/i2c_config.rs"));
 
@@ -17,8 +17,8 @@ use userlib::{hl, task_slot, FromPrimitive, RecvMessage, UnwrapLite};
 
 use ringbuf::{counted_ringbuf, ringbuf_entry, Count};
 
-task_slot!(JEFE, jefe);
-task_slot!(LOADER, spartan7_loader);
+task_slot!(JEFE, jefe, Jefe);
+task_slot!(LOADER, spartan7_loader, Spartan7Loader);
 
 #[derive(Copy, Clone, PartialEq, Count)]
 enum Trace {
@@ -31,8 +31,8 @@ enum Trace {
 
 counted_ringbuf!(Trace, 128, Trace::None);
 
-task_slot!(SYS, sys);
-task_slot!(PACKRAT, packrat);
+task_slot!(SYS, sys, sys_api::Sys);
+task_slot!(PACKRAT, packrat, Packrat);
 
 #[export_name = "main"]
 fn main() -> ! {
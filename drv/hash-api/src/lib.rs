@@ -29,3 +29,4 @@ pub enum HashError {
 }
 
 include!(concat!(env!("OUT_DIR"), "/client_stub.rs"));
+include!(concat!(env!("OUT_DIR"), "/client_interface.rs"));
@@ -272,3 +272,4 @@ pub struct HfChipId {
 }
 
 include!(concat!(env!("OUT_DIR"), "/client_stub.rs"));
+include!(concat!(env!("OUT_DIR"), "/client_interface.rs"));
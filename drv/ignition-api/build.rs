@@ -10,6 +10,7 @@ fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         "../../idl/ignition.idol",
         "client_stub.rs",
     )?;
+    build_util::build_client_interface("../../idl/ignition.idol")?;
 
     let out_dir = build_util::out_dir();
     let mut reg_map = fs::File::create(out_dir.join("ignition_controller.rs"))?;
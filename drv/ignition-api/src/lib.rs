@@ -775,4 +775,5 @@ mod idl {
     use userlib::sys_send;
 
     include!(concat!(env!("OUT_DIR"), "/client_stub.rs"));
+    include!(concat!(env!("OUT_DIR"), "/client_interface.rs"));
 }
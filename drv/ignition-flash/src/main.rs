@@ -16,8 +16,12 @@ use userlib::{hl, task_slot, FromPrimitive, RecvMessage};
 
 const PAGE_SIZE_BYTES: usize = 256;
 
-task_slot!(SPI_FRONT, spi_front);
-task_slot!(LOADER, spartan7_loader);
+task_slot!(SPI_FRONT, spi_front, drv_spi_api::Spi);
+task_slot!(
+    LOADER,
+    spartan7_loader,
+    drv_spartan7_loader_api::Spartan7Loader
+);
 
 #[allow(unused)]
 struct ServerImpl<'a, S: SpiServer> {
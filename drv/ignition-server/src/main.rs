@@ -14,7 +14,7 @@ use userlib::{hl, sys_get_timer, sys_set_timer, task_slot, UnwrapLite};
 
 task_slot!(FPGA, fpga);
 #[cfg(feature = "sequencer")]
-task_slot!(SEQUENCER, sequencer);
+task_slot!(SEQUENCER, sequencer, drv_sidecar_seq_api::Sequencer);
 
 #[allow(dead_code)]
 #[derive(Copy, Clone, Debug, PartialEq)]
@@ -8,5 +8,6 @@ fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         "../../idl/lpc55-pins.idol",
         "client_stub.rs",
     )?;
+    build_util::build_client_interface("../../idl/lpc55-pins.idol")?;
     Ok(())
 }
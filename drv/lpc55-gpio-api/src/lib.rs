@@ -359,3 +359,4 @@ impl Pins {
 }
 
 include!(concat!(env!("OUT_DIR"), "/client_stub.rs"));
+include!(concat!(env!("OUT_DIR"), "/client_interface.rs"));
@@ -47,7 +47,7 @@ use drv_lpc55_syscon_api::*;
 use idol_runtime::{NotificationHandler, RequestError};
 use userlib::{task_slot, RecvMessage};
 
-task_slot!(SYSCON, syscon_driver);
+task_slot!(SYSCON, syscon_driver, Syscon);
 
 struct ServerImpl<'a> {
     gpio: &'a device::gpio::RegisterBlock,
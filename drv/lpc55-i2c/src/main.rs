@@ -26,7 +26,7 @@ use drv_lpc55_syscon_api::{Peripheral, Syscon};
 use lpc55_pac as device;
 use userlib::{hl, task_slot, FromPrimitive, LeaseAttributes};
 
-task_slot!(SYSCON, syscon_driver);
+task_slot!(SYSCON, syscon_driver, Syscon);
 task_slot!(GPIO, gpio_driver);
 
 #[derive(FromPrimitive)]
@@ -14,7 +14,7 @@ use lpc55_pac as device;
 use ringbuf::*;
 use userlib::{sys_irq_control, sys_recv_notification, task_slot};
 
-task_slot!(SYSCON, syscon_driver);
+task_slot!(SYSCON, syscon_driver, Syscon);
 task_slot!(GPIO, gpio_driver);
 
 #[derive(Copy, Clone, PartialEq)]
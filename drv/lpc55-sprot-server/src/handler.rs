@@ -17,15 +17,15 @@ use lpc55_romapi::bootrom;
 use ringbuf::ringbuf_entry_root as ringbuf_entry;
 use userlib::{task_slot, UnwrapLite};
 
-task_slot!(UPDATE_SERVER, update_server);
+task_slot!(UPDATE_SERVER, update_server, Update);
 
 #[cfg(feature = "sp-ctrl")]
-task_slot!(DUMPER, dumper);
+task_slot!(DUMPER, dumper, dumper_api::Dumper);
 
-task_slot!(ATTEST, attest);
+task_slot!(ATTEST, attest, Attest);
 
 #[cfg(feature = "sp-ctrl")]
-task_slot!(SP_CTRL, swd);
+task_slot!(SP_CTRL, swd, drv_sp_ctrl_api::SpCtrl);
 
 pub const CRC32: Crc<u32> = Crc::<u32>::new(&CRC_32_CKSUM);
 
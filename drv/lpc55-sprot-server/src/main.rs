@@ -82,7 +82,7 @@ pub(crate) enum Trace {
 }
 ringbuf!(Trace, 32, Trace::None);
 
-task_slot!(SYSCON, syscon_driver);
+task_slot!(SYSCON, syscon_driver, Syscon);
 task_slot!(GPIO, gpio_driver);
 
 /// Setup spi and its associated GPIO pins
@@ -201,7 +201,7 @@ ringbuf!(Trace, 128, Trace::None);
 
 task_slot!(SYSCON, syscon_driver);
 task_slot!(GPIO, gpio_driver);
-task_slot!(ATTEST, attest);
+task_slot!(ATTEST, attest, Attest);
 
 #[derive(Copy, Clone, PartialEq)]
 enum Ack {
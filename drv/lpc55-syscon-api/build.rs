@@ -5,5 +5,6 @@
 fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     build_util::expose_target_board();
     idol::client::build_client_stub("../../idl/syscon.idol", "client_stub.rs")?;
+    build_util::build_client_interface("../../idl/syscon.idol")?;
     Ok(())
 }
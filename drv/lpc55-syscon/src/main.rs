@@ -116,7 +116,7 @@ use lpc55_pac as device;
 use task_jefe_api::{Jefe, ResetReason};
 use userlib::{task_slot, RecvMessage};
 
-task_slot!(JEFE, jefe);
+task_slot!(JEFE, jefe, Jefe);
 
 macro_rules! set_bit {
     ($reg:expr, $mask:expr) => {
@@ -7,5 +7,6 @@ fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         "../../idl/lpc55-update.idol",
         "client_stub.rs",
     )?;
+    build_util::build_client_interface("../../idl/lpc55-update.idol")?;
     Ok(())
 }
@@ -296,3 +296,4 @@ pub use stage0_handoff::{
 pub const BLOCK_SIZE_BYTES: usize = 512;
 
 include!(concat!(env!("OUT_DIR"), "/client_stub.rs"));
+include!(concat!(env!("OUT_DIR"), "/client_interface.rs"));
@@ -1341,7 +1341,7 @@ fn round_up_to_flash_page(offset: u32) -> Option<u32> {
     offset.checked_next_multiple_of(BYTES_PER_FLASH_PAGE as u32)
 }
 
-task_slot!(SYSCON, syscon);
+task_slot!(SYSCON, syscon, drv_lpc55_syscon_api::Syscon);
 task_slot!(JEFE, jefe);
 
 #[export_name = "main"]
@@ -23,7 +23,7 @@ use lpc55_pac as device;
 use userlib::*;
 use zerocopy::IntoBytes;
 
-task_slot!(SYSCON, syscon_driver);
+task_slot!(SYSCON, syscon_driver, Syscon);
 
 const OP_WRITE: u32 = 1;
 
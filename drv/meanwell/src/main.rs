@@ -100,7 +100,7 @@ impl idl::InOrderMeanwellImpl for ServerImpl {
     }
 }
 
-task_slot!(USER_LEDS, user_leds);
+task_slot!(USER_LEDS, user_leds, drv_user_leds_api::UserLeds);
 task_slot!(SYS, sys);
 
 const TIMER_INTERVAL_LONG: u32 = 900;
@@ -46,3 +46,4 @@ impl From<FpgaError> for MedusaError {
 }
 
 include!(concat!(env!("OUT_DIR"), "/client_stub.rs"));
+include!(concat!(env!("OUT_DIR"), "/client_interface.rs"));
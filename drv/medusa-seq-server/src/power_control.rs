@@ -8,7 +8,7 @@ use crate::*;
 use drv_stm32xx_sys_api as sys_api;
 use sys_api::{OutputType, Port, Pull, Speed, Sys};
 
-task_slot!(SYS, sys);
+task_slot!(SYS, sys, Sys);
 
 pub struct PowerRail {
     /// The output GPIO for the power rail's enable pin
@@ -15,7 +15,7 @@ use userlib::{hl, sys_get_timer, sys_set_timer, task_slot};
 mod ignition;
 
 task_slot!(FPGA, fpga);
-task_slot!(SEQUENCER, sequencer);
+task_slot!(SEQUENCER, sequencer, Sequencer);
 
 #[allow(dead_code)]
 #[derive(Copy, Clone, Debug, PartialEq)]
@@ -37,3 +37,4 @@ pub use reg_map::Addr;
 pub use reg_map::Reg;
 
 include!(concat!(env!("OUT_DIR"), "/client_stub.rs"));
+include!(concat!(env!("OUT_DIR"), "/client_interface.rs"));
@@ -26,7 +26,7 @@ use userlib::{
 task_slot!(I2C, i2c_driver);
 task_slot!(FPGA, ecp5);
 task_slot!(AUXFLASH, auxflash);
-task_slot!(PACKRAT, packrat);
+task_slot!(PACKRAT, packrat, Packrat);
 
 include!(concat!(env!("OUT_DIR"), "/i2c_config.rs"));
 include!(concat!(env!("OUT_DIR"), "/notifications.rs"));
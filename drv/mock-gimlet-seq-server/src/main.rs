@@ -12,7 +12,7 @@ use idol_runtime::{NotificationHandler, RequestError};
 use task_jefe_api::Jefe;
 use userlib::{FromPrimitive, RecvMessage, UnwrapLite};
 
-userlib::task_slot!(JEFE, jefe);
+userlib::task_slot!(JEFE, jefe, Jefe);
 
 #[export_name = "main"]
 fn main() -> ! {
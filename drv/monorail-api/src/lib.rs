@@ -261,3 +261,4 @@ pub struct MacTableEntry {
 
 use crate as drv_monorail_api;
 include!(concat!(env!("OUT_DIR"), "/client_stub.rs"));
+include!(concat!(env!("OUT_DIR"), "/client_interface.rs"));
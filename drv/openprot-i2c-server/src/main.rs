@@ -128,6 +128,10 @@ fn main() -> ! {
                 
                 // Configure slave mode on hardware
                 driver.configure_slave_mode(controller, &config)?;
+                ringbuf_entry!(Trace::SlaveConfigured {
+                    controller: controller as u8,
+                    addr: slave_address,
+                });
                 
                 caller.reply(0usize);
                 Ok(())
@@ -157,19 +161,52 @@ fn main() -> ! {
                 Ok(())
             }
             Op::CheckSlaveBuffer => {
+                let lease_count = msg.lease_count();
+
                 // Use the same marshal format as WriteRead operations
                 let (payload, caller) = msg
                     .fixed::<[u8; 4], usize>()
                     .ok_or(ResponseCode::BadArg)?;
 
+                if lease_count != 1 {
+                    return Err(ResponseCode::IllegalLeaseCount);
+                }
+
                 let (_address, controller, _port, _segment) = Marshal::unmarshal(payload)?;
-                
-                // Check for slave messages - for now just return count
-                // A full implementation would need to handle message data formatting
-                let temp_messages: [u8; 0] = []; // Empty for mock
-                let count = temp_messages.len();
-                
-                caller.reply(count);
+
+                let buf = caller.borrow(0);
+                let info = buf.info().ok_or(ResponseCode::BadArg)?;
+                if !info.attributes.contains(LeaseAttributes::WRITE) {
+                    return Err(ResponseCode::BadArg);
+                }
+
+                // Take messages one at a time, for as long as the largest
+                // one would still fit, so that none are lost for want of
+                // room; each goes out as [source, length, data...]
+                let mut message = [SlaveMessage {
+                    source_address: 0,
+                    data_length: 0,
+                    data: [0; 255],
+                }];
+                let mut total = 0;
+                while info.len - total >= 2 + message[0].data.len()
+                    && driver.poll_slave_messages(controller, &mut message)? != 0
+                {
+                    let m = &message[0];
+                    buf.write_fully_at(total, &[m.source_address, m.data_length])
+                        .ok_or(ResponseCode::BadArg)?;
+                    buf.write_fully_at(total + 2, m.data())
+                        .ok_or(ResponseCode::BadArg)?;
+                    total += 2 + m.data().len();
+
+                    ringbuf_entry!(Trace::SlaveMessage {
+                        controller: controller as u8,
+                        addr: m.source_address,
+                        len: m.data().len(),
+                    });
+                }
+
+                caller.reply(total);
                 Ok(())
             }
         });
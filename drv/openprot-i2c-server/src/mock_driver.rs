@@ -21,6 +21,8 @@ pub struct MockI2cDriver {
     slave_receive_enabled: bool,
     /// Mock slave message buffer (minimal size for testing)
     slave_messages: heapless::Vec<SlaveMessage, 4>,
+    /// Address and data of the most recent write, as a controller
+    last_write: Option<(u8, heapless::Vec<u8, 255>)>,
 }
 
 impl MockI2cDriver {
@@ -32,6 +34,7 @@ impl MockI2cDriver {
             slave_config: None,
             slave_receive_enabled: false,
             slave_messages: heapless::Vec::new(),
+            last_write: None,
         }
     }
 
@@ -85,8 +88,33 @@ impl MockI2cDriver {
         self.slave_config = None;
         self.slave_receive_enabled = false;
         self.slave_messages.clear();
+        self.last_write = None;
     }
     
+    /// Queue a message as if another controller on the bus had written it
+    /// to our slave address
+    ///
+    /// This is how tests play the part of a peer, e.g. an MCTP bus owner.
+    ///
+    /// # Example
+    /// ```rust,ignore
+    /// driver.queue_slave_message(Controller::I2C0, 0x10, &frame)?;
+    /// ```
+    pub fn queue_slave_message(&mut self, _controller: Controller, source_address: u8, data: &[u8]) -> Result<(), ResponseCode> {
+        if !self.slave_receive_enabled {
+            return Err(ResponseCode::SlaveNotEnabled);
+        }
+        let msg = SlaveMessage::new(source_address, data)?;
+        self.slave_messages.push(msg).map_err(|_| ResponseCode::SlaveBufferFull)
+    }
+
+    /// Get the address and data of the most recent write, as a controller
+    ///
+    /// Useful for checking what was sent to a peer, e.g. an MCTP response.
+    pub fn last_write(&self) -> Option<(u8, &[u8])> {
+        self.last_write.as_ref().map(|(addr, data)| (*addr, data.as_slice()))
+    }
+
     /// Get the number of transactions processed
     /// 
     /// Useful for verifying expected number of I2C operations in tests
@@ -106,6 +134,9 @@ impl I2cHardware for MockI2cDriver {
         read_buffer: &mut [u8],
     ) -> Result<usize, Self::Error> {
         self.transaction_counter = self.transaction_counter.wrapping_add(1);
+        if !write_data.is_empty() {
+            self.last_write = heapless::Vec::from_slice(write_data).ok().map(|data| (addr, data));
+        }
         
         // Get configured response or generate default
         let response = if let Some(configured_response) = &self.test_response {
@@ -181,13 +212,13 @@ impl I2cHardware for MockI2cDriver {
     }
 
     fn poll_slave_messages(&mut self, _controller: Controller, messages: &mut [SlaveMessage]) -> Result<usize, Self::Error> {
-        // Mock implementation - copy any buffered messages
+        // Mock implementation - copy out as many buffered messages as fit,
+        // oldest first, and keep the rest for the next poll
         let count = core::cmp::min(self.slave_messages.len(), messages.len());
-        for i in 0..count {
-            messages[i] = self.slave_messages[i];
-        }
-        // Clear the messages after reading (typical hardware behavior)
-        self.slave_messages.clear();
+        messages[..count].copy_from_slice(&self.slave_messages[..count]);
+        let remaining = self.slave_messages.len() - count;
+        self.slave_messages.rotate_left(count);
+        self.slave_messages.truncate(remaining);
         Ok(count)
     }
 
@@ -252,4 +283,91 @@ mod tests {
         assert!(result.is_err());
         assert_eq!(result.unwrap_err(), ResponseCode::NoDevice);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_slave_messages() {
+        let mut driver = MockI2cDriver::new();
+        assert_eq!(
+            driver.queue_slave_message(Controller::I2C0, 0x10, &[1]),
+            Err(ResponseCode::SlaveNotEnabled)
+        );
+
+        driver.enable_slave_receive(Controller::I2C0).unwrap();
+        for i in 0..4 {
+            driver.queue_slave_message(Controller::I2C0, 0x10, &[i]).unwrap();
+        }
+        assert_eq!(
+            driver.queue_slave_message(Controller::I2C0, 0x10, &[4]),
+            Err(ResponseCode::SlaveBufferFull)
+        );
+
+        // Messages that don't fit wait for the next poll
+        let mut messages = [SlaveMessage::new(0, &[]).unwrap(); 3];
+        assert_eq!(driver.poll_slave_messages(Controller::I2C0, &mut messages), Ok(3));
+        assert_eq!(messages[2].data(), &[2]);
+        assert_eq!(driver.poll_slave_messages(Controller::I2C0, &mut messages), Ok(1));
+        assert_eq!(messages[0].data(), &[3]);
+        assert_eq!(driver.poll_slave_messages(Controller::I2C0, &mut messages), Ok(0));
+    }
+
+    /// An SPDM GET_VERSION, carried over MCTP from a requester on the bus,
+    /// and the VERSION response that goes back to it, handled the way the
+    /// MCTP task does, with the SPDM responder's message codec.
+    #[test]
+    fn test_mctp_end_to_end() {
+        use drv_i2c_types::PortIndex;
+        use mctp::{msg_type, smbus, Endpoint};
+
+        const US: u8 = 0x42;
+        const REQUESTER: u8 = 0x10;
+        const EID: u8 = 0x1D;
+        const REQUESTER_EID: u8 = 0x08;
+
+        let mut driver = MockI2cDriver::new();
+        let config = SlaveConfig::new(Controller::I2C0, PortIndex(0), US).unwrap();
+        driver.configure_slave_mode(Controller::I2C0, &config).unwrap();
+        driver.enable_slave_receive(Controller::I2C0).unwrap();
+
+        // The requester writes a single-packet message (SOM, EOM, TO, tag 0)
+        let packet = [0x01, EID, REQUESTER_EID, 0xC8, 0x05, 0x10, 0x84, 0x00, 0x00];
+        let mut frame = [0; smbus::MAX_FRAME_SIZE];
+        let n = smbus::encode(US, REQUESTER, &packet, &mut frame).unwrap();
+        driver.queue_slave_message(Controller::I2C0, REQUESTER, &frame[..n]).unwrap();
+
+        let mut endpoint = Endpoint::<64>::new(Some(EID), &[msg_type::SPDM]);
+        let mut messages = [SlaveMessage::new(0, &[]).unwrap(); 2];
+        assert_eq!(driver.poll_slave_messages(Controller::I2C0, &mut messages), Ok(1));
+        assert_eq!(messages[0].source_address, REQUESTER);
+
+        let mut response = [0; 16];
+        let sent = smbus::handle(
+            US,
+            &mut endpoint,
+            messages[0].data(),
+            &mut response,
+            |msg, body, out| {
+                assert_eq!(msg.msg_type, msg_type::SPDM);
+                let (version, request) = spdm::Request::parse(body, None).unwrap();
+                assert_eq!(version, spdm::Version::V1_0);
+                assert!(matches!(request, spdm::Request::GetVersion));
+                let mut w = spdm::Writer::new(out);
+                spdm::response::version(&mut w, &[spdm::Version::V1_2]).unwrap();
+                Some(w.len())
+            },
+            |peer, frame| {
+                driver.write_read(Controller::I2C0, peer, frame, &mut []).map(|_| ())
+            },
+        );
+        assert_eq!(sent, Ok(Some(smbus::Sent { dest: REQUESTER_EID, len: 8 })));
+
+        // The requester gets VERSION back, with the tag it sent
+        let (addr, sent) = driver.last_write().unwrap();
+        assert_eq!(addr, REQUESTER);
+        let (from, packet) = smbus::decode(REQUESTER, sent).unwrap();
+        assert_eq!(from, US);
+        assert_eq!(
+            packet,
+            &[0x01, REQUESTER_EID, EID, 0xC0, 0x05, 0x10, 0x04, 0x00, 0x00, 0x00, 0x01, 0x00, 0x12]
+        );
+    }
+}
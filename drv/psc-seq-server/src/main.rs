@@ -110,10 +110,10 @@ use userlib::*;
 
 use ringbuf::{counted_ringbuf, ringbuf_entry};
 
-task_slot!(SYS, sys);
+task_slot!(SYS, sys, sys_api::Sys);
 task_slot!(I2C, i2c_driver);
-task_slot!(JEFE, jefe);
-task_slot!(PACKRAT, packrat);
+task_slot!(JEFE, jefe, Jefe);
+task_slot!(PACKRAT, packrat, Packrat);
 
 #[derive(Copy, Clone, PartialEq, Eq, counters::Count)]
 enum Event {
@@ -76,7 +76,7 @@ impl From<Error> for RngError {
 // }
 #[cfg(feature = "custom-getrandom")]
 pub fn rng_getrandom(dest: &mut [u8]) -> Result<(), Error> {
-    task_slot!(RNG, rng_driver);
+    task_slot!(RNG, rng_driver, Rng);
     let task_id = RNG.get_task_id();
     match Rng::from(task_id).fill(dest) {
         Ok(_) => Ok(()),
@@ -89,12 +89,13 @@ pub fn rng_getrandom(dest: &mut [u8]) -> Result<(), Error> {
 // interface directly or to use the Rng in interfaces with traits bound to
 // RngCore.
 include!(concat!(env!("OUT_DIR"), "/client_stub.rs"));
+include!(concat!(env!("OUT_DIR"), "/client_interface.rs"));
 
 // fn main() {
 //     use drv_rng_api::Rng;
 //     use rand::RngCore;
 //
-//     task_slot!(RNG, rng_driver);
+//     task_slot!(RNG, rng_driver, Rng);
 //
 //     let mut buf: [u8; 32] = [0; 32];
 //     let rng = Rng::from(RNG.get_task_id());
@@ -57,3 +57,4 @@ impl From<sbrmi::Error> for SbrmiError {
 }
 
 include!(concat!(env!("OUT_DIR"), "/client_stub.rs"));
+include!(concat!(env!("OUT_DIR"), "/client_interface.rs"));
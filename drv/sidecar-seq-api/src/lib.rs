@@ -73,3 +73,4 @@ pub struct FanModulePresence(pub [bool; NUM_FAN_MODULES]);
 pub use drv_sidecar_mainboard_controller::fan_modules::FanModuleIndex;
 
 include!(concat!(env!("OUT_DIR"), "/client_stub.rs"));
+include!(concat!(env!("OUT_DIR"), "/client_interface.rs"));
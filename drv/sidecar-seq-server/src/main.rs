@@ -33,8 +33,8 @@ task_slot!(I2C, i2c_driver);
 task_slot!(MAINBOARD, mainboard);
 task_slot!(FRONT_IO, front_io);
 task_slot!(AUXFLASH, auxflash);
-task_slot!(PACKRAT, packrat);
-task_slot!(SYS, sys);
+task_slot!(PACKRAT, packrat, Packrat);
+task_slot!(SYS, sys, sys_api::Sys);
 
 include!(concat!(env!("OUT_DIR"), "/i2c_config.rs"));
 
@@ -9,5 +9,6 @@ fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
             "../../idl/spartan7-loader.idol",
             "client_stub.rs",
         )?;
+    build_util::build_client_interface("../../idl/spartan7-loader.idol")?;
     Ok(())
 }
@@ -42,7 +42,7 @@ pub fn claim_spi(
 
 #[cfg(not(feature = "use-spi-core"))]
 pub fn claim_spi(_sys: &sys_api::Sys) -> drv_spi_api::Spi {
-    task_slot!(SPI, spi);
+    task_slot!(SPI, spi, drv_spi_api::Spi);
     drv_spi_api::Spi::from(SPI.get_task_id())
 }
 
@@ -83,8 +83,8 @@ impl From<Spartan7Error> for LoaderError {
 
 counted_ringbuf!(Trace, 128, Trace::None);
 
-task_slot!(SYS, sys);
-task_slot!(AUXFLASH, auxflash);
+task_slot!(SYS, sys, sys_api::Sys);
+task_slot!(AUXFLASH, auxflash, drv_auxflash_api::AuxFlash);
 
 #[export_name = "main"]
 fn main() -> ! {
@@ -3,6 +3,10 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    idol::client::build_client_stub("../../idl/spdm-responder.idol", "client_stub.rs")?;
+    idol::client::build_client_stub(
+        "../../idl/spdm-responder.idol",
+        "client_stub.rs",
+    )?;
+    build_util::build_client_interface("../../idl/spdm-responder.idol")?;
     Ok(())
-}
\ No newline at end of file
+}
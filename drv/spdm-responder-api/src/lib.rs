@@ -2,7 +2,10 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-//! SPDM Responder API - Minimal version for IPC testing
+//! SPDM Responder API
+//!
+//! Besides raw SPDM messages, the responder takes a few requests in a
+//! pre-parsed form; these are the types of their arguments and replies.
 
 #![no_std]
 
@@ -15,9 +18,35 @@ pub use userlib::RecvMessage;
 
 // Include generated client stub
 include!(concat!(env!("OUT_DIR"), "/client_stub.rs"));
-
-/// SPDM Error codes for IPC testing
-#[derive(Debug, Copy, Clone, PartialEq, Eq, IdolError, Serialize, Deserialize, SerializedSize, FromPrimitive, counters::Count)]
+include!(concat!(env!("OUT_DIR"), "/client_interface.rs"));
+
+/// Largest SPDM message that the responder sends or receives through
+/// `SpdmResponder::respond`, and largest secured message through
+/// `SpdmResponder::respond_secured`. This must match the lease limits in the
+/// interface definition.
+pub const MAX_MESSAGE_SIZE: usize = 1024;
+
+/// Size of the pre-shared key provisioned through `SpdmResponder::set_psk`
+pub const PSK_SIZE: usize = 32;
+
+/// Largest hint that picks the pre-shared key. This and `PSK_SIZE` must
+/// match the lease limits in the interface definition.
+pub const MAX_PSK_HINT_SIZE: usize = 16;
+
+/// Errors from the responder
+#[derive(
+    Debug,
+    Copy,
+    Clone,
+    PartialEq,
+    Eq,
+    IdolError,
+    Serialize,
+    Deserialize,
+    SerializedSize,
+    FromPrimitive,
+    counters::Count,
+)]
 #[repr(u32)]
 pub enum SpdmError {
     InvalidRequest = 1,
@@ -30,13 +59,16 @@ pub enum SpdmError {
     SessionNotEstablished = 8,
     MeasurementUnavailable = 9,
     InternalError = 10,
+    AlreadyProvisioned = 11,
 
     #[idol(server_death)]
     ServerRestarted,
 }
 
-/// SPDM Version for testing
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize, SerializedSize)]
+/// An SPDM version, as listed in a VERSION response
+#[derive(
+    Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize, SerializedSize,
+)]
 #[repr(u8)]
 pub enum SpdmVersion {
     V1_0 = 0x10,
@@ -44,8 +76,10 @@ pub enum SpdmVersion {
     V1_2 = 0x12,
 }
 
-/// SPDM Request codes for testing
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize, SerializedSize)]
+/// SPDM request codes
+#[derive(
+    Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize, SerializedSize,
+)]
 #[repr(u8)]
 pub enum SpdmRequestCode {
     GetVersion = 0x84,
@@ -54,67 +88,72 @@ pub enum SpdmRequestCode {
     ChallengeAuth = 0x83,
 }
 
-/// Simple version response for testing
-#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, SerializedSize)]
+/// The versions listed in a VERSION response
+#[derive(
+    Clone, Debug, PartialEq, Eq, Serialize, Deserialize, SerializedSize,
+)]
 pub struct SpdmVersionResponse {
+    /// Number of entries of `versions` that are filled in
     pub version_count: u8,
     pub versions: [SpdmVersion; 4],
 }
 
-impl Default for SpdmVersionResponse {
-    fn default() -> Self {
-        Self {
-            version_count: 3,
-            versions: [SpdmVersion::V1_0, SpdmVersion::V1_1, SpdmVersion::V1_2, SpdmVersion::V1_0],
-        }
-    }
-}
-
-/// Minimal capabilities for testing
-#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, SerializedSize)]
+/// What's in a CAPABILITIES response
+#[derive(
+    Clone, Debug, PartialEq, Eq, Serialize, Deserialize, SerializedSize,
+)]
 pub struct SpdmCapabilities {
     pub ct_exponent: u8,
     pub flags: u32,
 }
 
-/// Minimal algorithm request/response for testing
-#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize, SerializedSize)]
+/// The algorithms offered by a NEGOTIATE_ALGORITHMS request, as bitfields
+/// with one bit per algorithm
+#[derive(
+    Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize, SerializedSize,
+)]
 pub struct AlgorithmRequest {
     pub base_asym_algo: u32,
     pub base_hash_algo: u32,
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize, SerializedSize)]
+/// The algorithms selected by an ALGORITHMS response
+#[derive(
+    Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize, SerializedSize,
+)]
 pub struct AlgorithmResponse {
     pub base_asym_sel: u32,
     pub base_hash_sel: u32,
 }
 
-/// Minimal challenge auth response for testing
-#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, SerializedSize)]
+/// What's in a CHALLENGE_AUTH response, other than its signature, which is
+/// written into a lease
+#[derive(
+    Clone, Debug, PartialEq, Eq, Serialize, Deserialize, SerializedSize,
+)]
 pub struct ChallengeAuthResponse {
+    /// Slots that have certificate chains
+    pub slot_mask: u8,
     pub cert_chain_hash: [u8; 32],
     pub nonce: [u8; 32],
-    pub signature: [u8; 32], // Simplified for testing
+    /// All zeros unless a measurement summary was asked for
+    pub measurement_summary_hash: [u8; 32],
+    pub signature_len: u16,
 }
 
-/// Minimal measurement response for testing
-#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, SerializedSize)]
+/// What's in a MEASUREMENTS response, other than its measurement record and
+/// its signature, which are written into leases
+#[derive(
+    Clone, Debug, PartialEq, Eq, Serialize, Deserialize, SerializedSize,
+)]
 pub struct MeasurementResponse {
-    pub measurement_index: u8,
-    pub measurement_hash: [u8; 32],
-}
-
-/// Minimal key exchange response for testing
-#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, SerializedSize)]
-pub struct KeyExchangeResponse {
-    pub heartbeat_period: u8,
-    pub public_key: [u8; 32], // Simplified for testing
+    /// How many measurements there are, if that's what was asked for, and
+    /// otherwise zero
+    pub total_count: u8,
+    /// Measurement blocks in the record
+    pub block_count: u8,
+    pub record_len: u32,
+    pub nonce: [u8; 32],
+    /// Zero unless a signature was asked for
+    pub signature_len: u16,
 }
-
-/// Minimal PSK exchange response for testing
-#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, SerializedSize)]
-pub struct PskExchangeResponse {
-    pub heartbeat_period: u8,
-    pub context: [u8; 32],
-}
\ No newline at end of file
@@ -2,7 +2,80 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+#[cfg(feature = "dice")]
+mod config {
+    include!("src/config.rs");
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    idol::server::build_server_support("../../idl/spdm-responder.idol", "server_stub.rs", idol::server::ServerStyle::InOrder)?;
+    idol::server::build_server_support(
+        "../../idl/spdm-responder.idol",
+        "server_stub.rs",
+        idol::server::ServerStyle::InOrder,
+    )?;
+
+    #[cfg(feature = "dice")]
+    dice_config()?;
+
+    #[cfg(feature = "auxflash")]
+    check_auxflash_slot()?;
+
+    Ok(())
+}
+
+/// Checks that the app reserves an auxiliary flash slot for certificates, so
+/// that we never write over image data (or have it written over ours).
+#[cfg(feature = "auxflash")]
+fn check_auxflash_slot() -> Result<(), Box<dyn std::error::Error + Send + Sync>>
+{
+    use serde::Deserialize;
+    use std::collections::BTreeMap;
+
+    /// Our _subset_ of global config, which mustn't deny unknown fields
+    #[derive(Deserialize)]
+    struct GlobalConfig {
+        auxflash: Option<AuxFlashConfig>,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "kebab-case")]
+    struct AuxFlashConfig {
+        #[serde(default)]
+        reserved_slots: BTreeMap<String, u32>,
+    }
+
+    let config = build_util::config::<GlobalConfig>()?;
+    let auxflash = config
+        .auxflash
+        .ok_or("the auxflash feature needs [config.auxflash] in the app")?;
+    if !auxflash.reserved_slots.contains_key("spdm-certs") {
+        return Err("the auxflash feature needs a slot of its own: add \
+            `spdm-certs` to `reserved-slots` in [config.auxflash]"
+            .into());
+    }
+    Ok(())
+}
+
+/// Writes out where stage0 left the DICE certificates and Alias key.
+#[cfg(feature = "dice")]
+fn dice_config() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    use std::io::Write;
+
+    let regions = build_util::task_extern_regions::<config::DataRegion>()?;
+    let mut out =
+        std::fs::File::create(build_util::out_dir().join("dice-config.rs"))?;
+    writeln!(out, "use crate::config::DataRegion;")?;
+    for (name, region) in
+        [("CERT_DATA", "dice_certs"), ("ALIAS_DATA", "dice_alias")]
+    {
+        let region = regions
+            .get(region)
+            .ok_or_else(|| format!("{region} data region not found"))?;
+        writeln!(
+            out,
+            "pub const {name}: DataRegion = DataRegion {{ address: {:#x}, size: {:#x} }};",
+            region.address, region.size
+        )?;
+    }
     Ok(())
-}
\ No newline at end of file
+}
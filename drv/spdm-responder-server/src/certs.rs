@@ -0,0 +1,301 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Certificate chains, one to a slot.
+//!
+//! Slot 0 has our own chain, which is put together from our identity's
+//! certificates, and can't be changed. The others start out empty, and are
+//! provisioned with SET_CERTIFICATE; we keep what's provisioned in `flash`,
+//! each chain behind a header that says which slot it's for, and find the
+//! newest good copy of each one again at startup.
+//!
+//! Whatever the slot, its chain's leaf must certify our signing key, since
+//! that's the only key that we can sign with.
+
+use crate::flash::{Flash, FlashError, CHAIN_OFFSET, COPIES, MAX_CHAIN_SIZE};
+use crate::identity::Identity;
+use salty::signature::Keypair;
+use sha2::{Digest, Sha256};
+use spdm::chain::{self, Chain};
+use spdm::{ErrorCode, Writer, SLOT_COUNT};
+use zerocopy::{FromBytes, FromZeros, Immutable, IntoBytes, KnownLayout};
+
+/// Number of flash areas: enough for every copy of every slot but the first
+pub const AREAS: usize = (SLOT_COUNT as usize - 1) * COPIES;
+
+/// Size of our root hashes and digests, which are all SHA-256
+const HASH_SIZE: usize = 32;
+
+/// Marks the start of a chain's header in flash
+const MAGIC: u32 = 0x5C3E_71D2;
+
+/// DER encoding of an Ed25519 `SubjectPublicKeyInfo`, less the key itself
+/// (RFC 8410)
+const ED25519_SPKI_PREFIX: [u8; 12] = [
+    0x30, 0x2A, 0x30, 0x05, 0x06, 0x03, 0x2B, 0x65, 0x70, 0x03, 0x21, 0x00,
+];
+
+/// What comes before a provisioned chain in flash. This is written after the
+/// chain, so that a chain whose write didn't finish has no header.
+#[derive(FromBytes, IntoBytes, Immutable, KnownLayout)]
+#[repr(C)]
+pub struct Header {
+    magic: u32,
+    /// Counts writes to the slot; of its copies, the one with the highest
+    /// generation is the newest.
+    generation: u32,
+    slot: u8,
+    _reserved: u8,
+    len: u16,
+    /// Hash of the chain, which is its digest too
+    digest: [u8; HASH_SIZE],
+}
+
+/// Slot 0's chain, as it's put together from our identity's certificates
+struct Own {
+    /// The chain's header and root hash, which come before the certificates
+    prefix: [u8; chain::PREFIX_SIZE + HASH_SIZE],
+    len: u16,
+    digest: [u8; HASH_SIZE],
+}
+
+/// Where a provisioned chain is in flash
+#[derive(Copy, Clone)]
+struct Stored {
+    area: usize,
+    generation: u32,
+    len: u16,
+    digest: [u8; HASH_SIZE],
+}
+
+pub struct CertStore {
+    identity: Option<Identity>,
+    own: Option<Own>,
+    flash: Flash,
+    /// Provisioned chains, by slot (of which the first is always `None`)
+    stored: [Option<Stored>; SLOT_COUNT as usize],
+}
+
+impl CertStore {
+    /// Puts together slot 0's chain from `identity`, if we have one, and
+    /// finds what's been provisioned into the other slots.
+    pub fn new(identity: Option<Identity>, flash: Flash) -> Self {
+        let own = identity.as_ref().and_then(own_chain);
+        let mut stored: [Option<Stored>; SLOT_COUNT as usize] =
+            [None; SLOT_COUNT as usize];
+        for (slot, newest) in stored.iter_mut().enumerate().skip(1) {
+            for area in areas(slot) {
+                let Ok(found) = find(&flash, slot, area) else {
+                    continue;
+                };
+                if newest.is_none_or(|s| found.generation > s.generation) {
+                    *newest = Some(found);
+                }
+            }
+        }
+        Self {
+            identity,
+            own,
+            flash,
+            stored,
+        }
+    }
+
+    /// Returns the key that every chain's leaf certifies, if we have one.
+    pub fn signing_key(&self) -> Option<&Keypair> {
+        self.identity.as_ref().map(Identity::keypair)
+    }
+
+    /// Returns the size and digest of the chain in `slot`, if there is one.
+    pub fn chain(&self, slot: u8) -> Option<(u16, &[u8; HASH_SIZE])> {
+        match usize::from(slot) {
+            0 => self.own.as_ref().map(|c| (c.len, &c.digest)),
+            i => self.stored.get(i)?.as_ref().map(|s| (s.len, &s.digest)),
+        }
+    }
+
+    /// Returns a mask with the bits of the slots that have chains.
+    pub fn slot_mask(&self) -> u8 {
+        (0..SLOT_COUNT)
+            .filter(|&s| self.chain(s).is_some())
+            .fold(0, |mask, s| mask | 1 << s)
+    }
+
+    /// Reads the part of the chain in `slot` that starts at `offset` into
+    /// `buf`, all of which it must fill.
+    pub fn read(
+        &self,
+        slot: u8,
+        offset: usize,
+        buf: &mut [u8],
+    ) -> Result<(), ErrorCode> {
+        let (len, _) = self.chain(slot).ok_or(ErrorCode::InvalidRequest)?;
+        if offset + buf.len() > usize::from(len) {
+            return Err(ErrorCode::InvalidRequest);
+        }
+        match (slot, &self.identity, &self.own) {
+            (0, Some(identity), Some(own)) => {
+                let parts =
+                    [&own.prefix[..]].into_iter().chain(identity.certs());
+                copy(parts, offset, buf);
+                Ok(())
+            }
+            _ => {
+                let Some(s) = self.stored[usize::from(slot)] else {
+                    unreachable!()
+                };
+                self.flash
+                    .read(s.area, CHAIN_OFFSET + offset, buf)
+                    .map_err(|_| ErrorCode::Unspecified)
+            }
+        }
+    }
+
+    /// Provisions `slot` with `cert_chain`, as SET_CERTIFICATE asks, writing
+    /// it to flash.
+    pub fn provision(
+        &mut self,
+        slot: u8,
+        cert_chain: &[u8],
+    ) -> Result<(), ErrorCode> {
+        let slot = usize::from(slot);
+        // Slot 0 is our own.
+        if slot == 0 || slot >= SLOT_COUNT.into() {
+            return Err(ErrorCode::InvalidRequest);
+        }
+        let key = self.signing_key().ok_or(ErrorCode::OperationFailed)?;
+        let chain = Chain::parse(cert_chain, HASH_SIZE)?;
+        let spki = chain::subject_public_key_info(chain.leaf())?;
+        if cert_chain.len() > MAX_CHAIN_SIZE
+            || spki.get(..ED25519_SPKI_PREFIX.len())
+                != Some(&ED25519_SPKI_PREFIX)
+            || spki[ED25519_SPKI_PREFIX.len()..] != *key.public.as_bytes()
+        {
+            return Err(ErrorCode::InvalidRequest);
+        }
+
+        // The new chain goes over the older copy (or the only one).
+        let copies = areas(slot);
+        let (area, generation) = match self.stored[slot] {
+            Some(s) => {
+                let generation = s
+                    .generation
+                    .checked_add(1)
+                    .ok_or(ErrorCode::OperationFailed)?;
+                let next = s.area + 1;
+                let area = if next == copies.end {
+                    copies.start
+                } else {
+                    next
+                };
+                (area, generation)
+            }
+            None => (copies.start, 0),
+        };
+        if self.stored[slot].is_some_and(|s| s.area == area) {
+            self.stored[slot] = None;
+        }
+
+        let stored = Stored {
+            area,
+            generation,
+            // This fits, since it's the same as the chain's length field.
+            len: cert_chain.len() as u16,
+            digest: Sha256::digest(cert_chain).into(),
+        };
+        let header = Header {
+            magic: MAGIC,
+            generation,
+            slot: slot as u8,
+            _reserved: 0,
+            len: stored.len,
+            digest: stored.digest,
+        };
+        self.flash
+            .erase(area)
+            .and_then(|()| self.flash.write(area, CHAIN_OFFSET, cert_chain))
+            .and_then(|()| self.flash.write(area, 0, header.as_bytes()))
+            .map_err(|_| ErrorCode::OperationFailed)?;
+        self.stored[slot] = Some(stored);
+        Ok(())
+    }
+}
+
+/// Returns the flash areas that hold copies of `slot`'s chain.
+fn areas(slot: usize) -> core::ops::Range<usize> {
+    (slot - 1) * COPIES..slot * COPIES
+}
+
+/// Puts together the header and root hash of `identity`'s chain, and works
+/// out its digest, if it's a chain at all.
+fn own_chain(identity: &Identity) -> Option<Own> {
+    let root = chain::certificates(identity.certs().next()?).next()?.ok()?;
+    let certs_len = identity.certs().map(<[u8]>::len).sum();
+
+    let mut prefix = [0; chain::PREFIX_SIZE + HASH_SIZE];
+    let mut w = Writer::new(&mut prefix);
+    chain::header(&mut w, &Sha256::digest(root), certs_len).ok()?;
+    let len = u16::from_le_bytes([prefix[0], prefix[1]]);
+
+    let mut digest = Sha256::new();
+    digest.update(prefix);
+    identity.certs().for_each(|c| digest.update(c));
+    Some(Own {
+        prefix,
+        len,
+        digest: digest.finalize().into(),
+    })
+}
+
+/// Checks that `area` has a good copy of the chain for `slot`.
+fn find(flash: &Flash, slot: usize, area: usize) -> Result<Stored, FlashError> {
+    let mut header = Header::new_zeroed();
+    flash.read(area, 0, header.as_mut_bytes())?;
+    if header.magic != MAGIC
+        || usize::from(header.slot) != slot
+        || usize::from(header.len) > MAX_CHAIN_SIZE
+    {
+        return Err(FlashError);
+    }
+
+    let mut digest = Sha256::new();
+    let mut buf = [0; 64];
+    let len = usize::from(header.len);
+    for start in (0..len).step_by(buf.len()) {
+        let buf = &mut buf[..(len - start).min(64)];
+        flash.read(area, CHAIN_OFFSET + start, buf)?;
+        digest.update(&buf);
+    }
+    if digest.finalize()[..] != header.digest {
+        return Err(FlashError);
+    }
+    Ok(Stored {
+        area,
+        generation: header.generation,
+        len: header.len,
+        digest: header.digest,
+    })
+}
+
+/// Copies what's at `offset` in the concatenation of `parts` into `buf`.
+fn copy<'a>(
+    parts: impl Iterator<Item = &'a [u8]>,
+    mut offset: usize,
+    buf: &mut [u8],
+) {
+    let mut done = 0;
+    for part in parts {
+        if done == buf.len() {
+            break;
+        }
+        if offset >= part.len() {
+            offset -= part.len();
+            continue;
+        }
+        let n = (part.len() - offset).min(buf.len() - done);
+        buf[done..done + n].copy_from_slice(&part[offset..offset + n]);
+        done += n;
+        offset = 0;
+    }
+}
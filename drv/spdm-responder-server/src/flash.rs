@@ -0,0 +1,155 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Where provisioned certificate chains are kept.
+//!
+//! This is a number of areas, each big enough for one chain and its header,
+//! that can be erased and written separately. With the `auxflash` feature,
+//! they're sectors of an auxiliary flash slot that the app reserves for us as
+//! `spdm-certs`, which outlast a reset; otherwise, they're in RAM, which
+//! doesn't, and is for boards whose flash we have no driver for yet.
+
+use drv_spdm_responder_api::MAX_MESSAGE_SIZE;
+use spdm::HEADER_SIZE;
+
+/// Largest chain that can be provisioned: whatever fits in a
+/// SET_CERTIFICATE, which must arrive in one message
+pub const MAX_CHAIN_SIZE: usize = MAX_MESSAGE_SIZE - HEADER_SIZE;
+
+/// A read, erase or write that didn't work out
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct FlashError;
+
+#[cfg(feature = "auxflash")]
+pub use aux::*;
+#[cfg(not(feature = "auxflash"))]
+pub use ram::*;
+
+#[cfg(feature = "auxflash")]
+mod aux {
+    use super::{FlashError, MAX_CHAIN_SIZE};
+    use crate::certs::AREAS;
+    use drv_auxflash_api::{
+        reserved_slots, AuxFlash, PAGE_SIZE_BYTES, SECTOR_SIZE_BYTES, SLOT_SIZE,
+    };
+    use userlib::task_slot;
+
+    task_slot!(AUXFLASH, auxflash, AuxFlash);
+
+    /// Slot of the auxiliary flash that we use, which nothing else writes:
+    /// the build checks that the app reserves it.
+    const SLOT: u32 = reserved_slots::SPDM_CERTS;
+
+    /// Number of copies of each chain. A chain is written over the older
+    /// copy, so that losing power part way through leaves the newer one.
+    pub const COPIES: usize = 2;
+
+    /// Where a chain starts in its area: after its header, at the start of
+    /// the next page, since writes must start on one.
+    pub const CHAIN_OFFSET: usize = PAGE_SIZE_BYTES;
+
+    // Every area must fit in the slot, and every chain in its area.
+    const _: () = assert!(AREAS * SECTOR_SIZE_BYTES <= SLOT_SIZE);
+    const _: () = assert!(CHAIN_OFFSET + MAX_CHAIN_SIZE <= SECTOR_SIZE_BYTES);
+
+    pub struct Flash {
+        aux: AuxFlash,
+    }
+
+    impl Flash {
+        pub fn new() -> Self {
+            Self {
+                aux: AuxFlash::from(AUXFLASH.get_task_id()),
+            }
+        }
+
+        pub fn read(
+            &self,
+            area: usize,
+            offset: usize,
+            buf: &mut [u8],
+        ) -> Result<(), FlashError> {
+            self.aux
+                .read_slot_with_offset(SLOT, address(area, offset), buf)
+                .map_err(|_| FlashError)
+        }
+
+        pub fn erase(&mut self, area: usize) -> Result<(), FlashError> {
+            self.aux
+                .slot_sector_erase(SLOT, address(area, 0))
+                .map_err(|_| FlashError)
+        }
+
+        pub fn write(
+            &mut self,
+            area: usize,
+            offset: usize,
+            data: &[u8],
+        ) -> Result<(), FlashError> {
+            self.aux
+                .write_slot_with_offset(SLOT, address(area, offset), data)
+                .map_err(|_| FlashError)
+        }
+    }
+
+    /// Each area is a sector, since that's what we can erase.
+    fn address(area: usize, offset: usize) -> u32 {
+        (area * SECTOR_SIZE_BYTES + offset) as u32
+    }
+}
+
+#[cfg(not(feature = "auxflash"))]
+mod ram {
+    use super::{FlashError, MAX_CHAIN_SIZE};
+    use crate::certs::{Header, AREAS};
+
+    /// RAM doesn't lose power part way through a write, or rather, it loses
+    /// everything when it does, so one copy will do.
+    pub const COPIES: usize = 1;
+
+    pub const CHAIN_OFFSET: usize = core::mem::size_of::<Header>();
+
+    pub const AREA_SIZE: usize = CHAIN_OFFSET + MAX_CHAIN_SIZE;
+
+    pub struct Flash {
+        areas: &'static mut [[u8; AREA_SIZE]; AREAS],
+    }
+
+    impl Flash {
+        pub fn new(areas: &'static mut [[u8; AREA_SIZE]; AREAS]) -> Self {
+            Self { areas }
+        }
+
+        pub fn read(
+            &self,
+            area: usize,
+            offset: usize,
+            buf: &mut [u8],
+        ) -> Result<(), FlashError> {
+            let src = self.areas[area]
+                .get(offset..offset + buf.len())
+                .ok_or(FlashError)?;
+            buf.copy_from_slice(src);
+            Ok(())
+        }
+
+        pub fn erase(&mut self, area: usize) -> Result<(), FlashError> {
+            self.areas[area].fill(0xFF);
+            Ok(())
+        }
+
+        pub fn write(
+            &mut self,
+            area: usize,
+            offset: usize,
+            data: &[u8],
+        ) -> Result<(), FlashError> {
+            self.areas[area]
+                .get_mut(offset..offset + data.len())
+                .ok_or(FlashError)?
+                .copy_from_slice(data);
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,151 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Who we are: the certificates for slot 0, and the key that the leaf of
+//! them certifies, which is what we sign with.
+//!
+//! With the `dice` feature, these come from stage0, which hands off the
+//! DeviceId and PersistId certificates (and an intermediate, if there is
+//! one) in the `dice_certs` region, and the Alias key and its certificate in
+//! `dice_alias`. Without it, we have a mock chain with the `mock-crypto`
+//! feature, whose leaf certifies a mock key that's good for testing and
+//! nothing else; and with neither, we have no identity at all.
+
+use salty::signature::Keypair;
+
+#[cfg(feature = "dice")]
+pub use dice::*;
+#[cfg(all(not(feature = "dice"), feature = "mock-crypto"))]
+pub use mock::*;
+#[cfg(not(any(feature = "dice", feature = "mock-crypto")))]
+pub use none::*;
+
+#[cfg(feature = "dice")]
+mod dice {
+    use super::Keypair;
+    use crate::config::DataRegion;
+    use core::slice;
+    use hubpack::SerializedSize;
+    use lib_dice::{AliasData, CertData, SeedBuf};
+    use serde::Deserialize;
+    use stage0_handoff::{HandoffData, HandoffDataLoadError};
+    use zerocopy::IntoBytes;
+
+    // This file is generated by the crate build.rs, from the regions that
+    // the hubris build exposes to this task.
+    mod build {
+        include!(concat!(env!("OUT_DIR"), "/dice-config.rs"));
+    }
+
+    pub struct Identity {
+        keypair: Keypair,
+        cert_data: CertData,
+        alias_data: AliasData,
+    }
+
+    impl Identity {
+        pub fn load() -> Result<Self, HandoffDataLoadError> {
+            let cert_data: CertData = load_data_from_region(&build::CERT_DATA)?;
+            let alias_data: AliasData =
+                load_data_from_region(&build::ALIAS_DATA)?;
+            Ok(Self {
+                keypair: Keypair::from(alias_data.alias_seed.as_bytes()),
+                cert_data,
+                alias_data,
+            })
+        }
+
+        pub fn keypair(&self) -> &Keypair {
+            &self.keypair
+        }
+
+        /// Returns our certificates, from the one nearest the root down to
+        /// the Alias certificate.
+        pub fn certs(&self) -> impl Iterator<Item = &[u8]> {
+            let d = &self.cert_data;
+            let intermediate = d
+                .intermediate_cert
+                .as_ref()
+                .map(|c| &c.0.as_bytes()[..c.0.size as usize]);
+            let persistid = &d.persistid_cert.0.as_bytes()
+                [..d.persistid_cert.0.size as usize];
+            intermediate.into_iter().chain([
+                persistid,
+                d.deviceid_cert.as_bytes(),
+                self.alias_data.alias_cert.as_bytes(),
+            ])
+        }
+    }
+
+    /// Loads handoff data from one of our regions.
+    fn load_data_from_region<
+        T: for<'a> Deserialize<'a> + HandoffData + SerializedSize,
+    >(
+        region: &DataRegion,
+    ) -> Result<T, HandoffDataLoadError> {
+        // Safety: This memory is setup by code executed before hubris and
+        // exposed using the kernel `extern-regions` mechanism. The safety of
+        // this code is an extension of our trust in the hubris kernel / build.
+        let data = unsafe {
+            slice::from_raw_parts(
+                region.address as *mut u8,
+                region.size as usize,
+            )
+        };
+        T::load_from_addr(data)
+    }
+}
+
+#[cfg(all(not(feature = "dice"), feature = "mock-crypto"))]
+mod mock {
+    use super::Keypair;
+
+    /// Seed of our signing key
+    const SIGNING_SEED: [u8; 32] = *b"mock SPDM responder signing key!";
+
+    /// A root certificate, self-signed with the key whose seed is
+    /// `b"mock SPDM root certificate key!!"`, and then a leaf certificate
+    /// for the key from `SIGNING_SEED`, issued by the root
+    const MOCK_CERTS: &[u8] = include_bytes!("../mock-certs.der");
+
+    pub struct Identity {
+        keypair: Keypair,
+    }
+
+    impl Identity {
+        pub fn mock() -> Self {
+            Self {
+                keypair: Keypair::from(&SIGNING_SEED),
+            }
+        }
+
+        pub fn keypair(&self) -> &Keypair {
+            &self.keypair
+        }
+
+        /// Returns our certificates, from the root down to the leaf. They're
+        /// all in one piece.
+        pub fn certs(&self) -> impl Iterator<Item = &[u8]> {
+            [MOCK_CERTS].into_iter()
+        }
+    }
+}
+
+#[cfg(not(any(feature = "dice", feature = "mock-crypto")))]
+mod none {
+    use super::Keypair;
+
+    /// An identity that we can never have
+    pub enum Identity {}
+
+    impl Identity {
+        pub fn keypair(&self) -> &Keypair {
+            match *self {}
+        }
+
+        pub fn certs(&self) -> core::iter::Empty<&[u8]> {
+            match *self {}
+        }
+    }
+}
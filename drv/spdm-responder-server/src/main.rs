@@ -2,62 +2,186 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-//! SPDM Responder Server - Minimal version for IPC testing
+//! SPDM Responder Server
+//!
+//! SPDM requests arrive as raw messages through the `respond` operation, and
+//! secured messages for open sessions through `respond_secured`; both are
+//! handled by the `responder` module. The other operations are a pre-parsed
+//! version of the protocol: `requester` turns each of them into the request
+//! that it stands for, which the responder handles like any other.
+//!
+//! Slot 0's chain is ours, from stage0's DICE certificates with the `dice`
+//! feature (or, without it, a mock chain with the `mock-crypto` feature, and
+//! none at all otherwise). Chains provisioned into the other slots, which
+//! only a `provisioning` image does, are kept in an auxiliary flash slot that
+//! the app reserves for them with the `auxflash` feature, and otherwise in
+//! RAM, where they don't survive a restart.
+//!
+//! Measurements include our own digest of the image, stage0's digests of the
+//! image and of itself with the `bootstate` feature, and the caboose's
+//! version with the `caboose` feature.
+//!
+//! Our random numbers are seeded from the RNG driver at startup.
+//!
+//! PSK sessions are only offered with the `psk` feature, by an app that has a
+//! task to provision the pre-shared key: only the task in the
+//! `psk_provisioner` slot may call `set_psk`. The key is kept in RAM, so it
+//! has to provision it again after every restart.
 
 #![no_std]
 #![no_main]
 
+mod certs;
+#[cfg(feature = "dice")]
+mod config;
+mod flash;
+mod identity;
+mod measurements;
+mod requester;
+mod responder;
+
+use drv_rng_api::Rng;
 use drv_spdm_responder_api::*;
-use idol_runtime::{NotificationHandler, RequestError, Leased, W};
+use idol_runtime::{ClientError, Leased, RequestError, R, W};
 use ringbuf::{ringbuf, ringbuf_entry};
 use userlib::*;
 
+task_slot!(RNG, rng_driver, Rng);
+#[cfg(feature = "psk")]
+task_slot!(PSK_PROVISIONER, psk_provisioner);
+
 // Include generated server support
 include!(concat!(env!("OUT_DIR"), "/server_stub.rs"));
 
 #[derive(Copy, Clone, PartialEq)]
 enum Trace {
     None,
+    Respond {
+        request: u8,
+        response: u8,
+        len: usize,
+    },
     GetVersion,
     GetCapabilities,
     NegotiateAlgorithms,
-    GetCertificate { slot: u8, offset: u16, length: u16 },
-    ChallengeAuth { slot: u8 },
-    GetMeasurements { operation: u8, slot: u8 },
-    KeyExchange { slot: u8 },
-    PskExchange,
-    Heartbeat,
-    EndSession,
+    GetCertificate {
+        slot: u8,
+        offset: u16,
+        length: u16,
+    },
+    ChallengeAuth {
+        slot: u8,
+    },
+    GetMeasurements {
+        operation: u8,
+        slot: u8,
+    },
+    RespondSecured {
+        session: u32,
+        len: usize,
+    },
+    SecuredError(SpdmError),
+    SetPsk(Result<(), SpdmError>),
+    #[cfg(feature = "dice")]
+    HandoffError(stage0_handoff::HandoffDataLoadError),
+    #[cfg(feature = "bootstate")]
+    BootStateError(stage0_handoff::HandoffDataLoadError),
 }
 
 ringbuf!(Trace, 64, Trace::None);
 
-/// Minimal SPDM responder implementation for IPC testing
-struct TestSpdmResponder;
-
-impl TestSpdmResponder {
-    fn new() -> Self {
-        Self
-    }
+struct ServerImpl {
+    responder: responder::Responder,
+    request: &'static mut [u8; MAX_MESSAGE_SIZE],
+    response: &'static mut [u8; MAX_MESSAGE_SIZE],
 }
 
-impl idol_runtime::NotificationHandler for TestSpdmResponder {
+impl idol_runtime::NotificationHandler for ServerImpl {
     fn current_notification_mask(&self) -> u32 {
         0
     }
 
     fn handle_notification(&mut self, _bits: u32) {
-        // No notifications to handle for testing
+        unreachable!()
     }
 }
 
-impl InOrderSpdmResponderImpl for TestSpdmResponder {
+impl ServerImpl {
+    fn requester(&mut self) -> requester::Requester<'_> {
+        requester::Requester {
+            responder: &mut self.responder,
+            request: &mut self.request[..],
+            response: &mut self.response[..],
+        }
+    }
+}
+
+impl InOrderSpdmResponderImpl for ServerImpl {
+    fn respond(
+        &mut self,
+        _msg: &RecvMessage,
+        request: Leased<R, [u8]>,
+        response: Leased<W, [u8]>,
+    ) -> Result<u32, RequestError<SpdmError>> {
+        let req = self
+            .request
+            .get_mut(..request.len())
+            .ok_or(RequestError::Fail(ClientError::BadLease))?;
+        request
+            .read_range(0..req.len(), req)
+            .map_err(|_| RequestError::Fail(ClientError::WentAway))?;
+
+        // Limiting the response to what fits in the lease means that a
+        // response that's too big turns into an SPDM error.
+        let len = response.len().min(MAX_MESSAGE_SIZE);
+        let len = self.responder.respond(req, &mut self.response[..len]);
+        let rsp = &self.response[..len];
+        ringbuf_entry!(Trace::Respond {
+            request: req.get(1).copied().unwrap_or(0),
+            response: rsp.get(1).copied().unwrap_or(0),
+            len,
+        });
+
+        response
+            .write_range(0..len, rsp)
+            .map_err(|_| RequestError::Fail(ClientError::WentAway))?;
+        Ok(len as u32)
+    }
+
+    fn respond_secured(
+        &mut self,
+        _msg: &RecvMessage,
+        request: Leased<R, [u8]>,
+        response: Leased<W, [u8]>,
+    ) -> Result<u32, RequestError<SpdmError>> {
+        let req = self
+            .request
+            .get_mut(..request.len())
+            .ok_or(RequestError::Fail(ClientError::BadLease))?;
+        request
+            .read_range(0..req.len(), req)
+            .map_err(|_| RequestError::Fail(ClientError::WentAway))?;
+
+        let session = spdm::session::secured_session_id(req).unwrap_or(0);
+        let len = response.len().min(MAX_MESSAGE_SIZE);
+        let len = self
+            .responder
+            .respond_secured(req, &mut self.response[..len])
+            .inspect_err(|&e| ringbuf_entry!(Trace::SecuredError(e)))?;
+        ringbuf_entry!(Trace::RespondSecured { session, len });
+
+        response
+            .write_range(0..len, &self.response[..len])
+            .map_err(|_| RequestError::Fail(ClientError::WentAway))?;
+        Ok(len as u32)
+    }
+
     fn get_version(
         &mut self,
         _msg: &RecvMessage,
     ) -> Result<SpdmVersionResponse, RequestError<SpdmError>> {
         ringbuf_entry!(Trace::GetVersion);
-        Ok(SpdmVersionResponse::default())
+        Ok(self.requester().get_version()?)
     }
 
     fn get_capabilities(
@@ -65,10 +189,7 @@ impl InOrderSpdmResponderImpl for TestSpdmResponder {
         _msg: &RecvMessage,
     ) -> Result<SpdmCapabilities, RequestError<SpdmError>> {
         ringbuf_entry!(Trace::GetCapabilities);
-        Ok(SpdmCapabilities {
-            ct_exponent: 12,
-            flags: 0x01,
-        })
+        Ok(self.requester().get_capabilities()?)
     }
 
     fn negotiate_algorithms(
@@ -77,10 +198,7 @@ impl InOrderSpdmResponderImpl for TestSpdmResponder {
         algorithms: AlgorithmRequest,
     ) -> Result<AlgorithmResponse, RequestError<SpdmError>> {
         ringbuf_entry!(Trace::NegotiateAlgorithms);
-        Ok(AlgorithmResponse {
-            base_asym_sel: algorithms.base_asym_algo & 0x1, // Select first supported
-            base_hash_sel: algorithms.base_hash_algo & 0x1, // Select first supported
-        })
+        Ok(self.requester().negotiate_algorithms(&algorithms)?)
     }
 
     fn get_certificate(
@@ -91,25 +209,30 @@ impl InOrderSpdmResponderImpl for TestSpdmResponder {
         length: u16,
         buffer: Leased<W, [u8]>,
     ) -> Result<u16, RequestError<SpdmError>> {
-        ringbuf_entry!(Trace::GetCertificate { slot, offset, length });
-
-        // Mock certificate data for testing
-        let cert_data = b"MOCK_CERTIFICATE_FOR_TESTING";
-        let cert_len = cert_data.len() as u16;
-
-        if offset >= cert_len {
+        ringbuf_entry!(Trace::GetCertificate {
+            slot,
+            offset,
+            length
+        });
+
+        let (len, _) = self
+            .responder
+            .certs()
+            .chain(slot)
+            .ok_or(SpdmError::CertificateNotFound)?;
+        if offset >= len {
             return Ok(0);
         }
 
-        let start = offset as usize;
-        let available = cert_len - offset;
-        let copy_len = core::cmp::min(length, available) as usize;
-        let end = start + copy_len;
-
-        buffer.write_range(0..copy_len, &cert_data[start..end])
+        // Whatever doesn't fit in one response, whoever wants more can come
+        // back for.
+        let length = length.min(buffer.len().try_into().unwrap_or(u16::MAX));
+        let data = self.requester().get_certificate(slot, offset, length)?;
+        buffer
+            .write_range(0..data.len(), data)
             .map_err(|_| RequestError::went_away())?;
 
-        Ok(copy_len as u16)
+        Ok(data.len() as u16)
     }
 
     fn challenge_auth(
@@ -117,101 +240,167 @@ impl InOrderSpdmResponderImpl for TestSpdmResponder {
         _msg: &RecvMessage,
         slot: u8,
         measurement_summary: u8,
-        nonce: &[u8],
+        nonce: Leased<R, [u8]>,
         signature: Leased<W, [u8]>,
     ) -> Result<ChallengeAuthResponse, RequestError<SpdmError>> {
         ringbuf_entry!(Trace::ChallengeAuth { slot });
 
-        let mut response_nonce = [0u8; 32];
-        if nonce.len() >= 32 {
-            response_nonce.copy_from_slice(&nonce[..32]);
+        let mut requester_nonce = [0; spdm::NONCE_SIZE];
+        if nonce.len() != requester_nonce.len() {
+            return Err(SpdmError::InvalidNonce.into());
+        }
+        nonce
+            .read_range(0..requester_nonce.len(), &mut requester_nonce)
+            .map_err(|_| RequestError::went_away())?;
+
+        if signature.len() < requester::SIGNATURE_SIZE {
+            return Err(SpdmError::InvalidParameter.into());
         }
 
-        let mock_signature = [0x42u8; 32]; // Mock signature
-        signature.write_range(0..32, &mock_signature)
+        let (rsp, sig) = self.requester().challenge(
+            slot,
+            measurement_summary,
+            &requester_nonce,
+        )?;
+        signature
+            .write_range(0..sig.len(), sig)
             .map_err(|_| RequestError::went_away())?;
 
-        Ok(ChallengeAuthResponse {
-            cert_chain_hash: [0x12u8; 32],
-            nonce: response_nonce,
-            signature: mock_signature,
-        })
+        Ok(rsp)
     }
 
     fn get_measurements(
         &mut self,
         _msg: &RecvMessage,
-        measurement_operation: u8,
         slot: u8,
-        content_changed: u8,
+        measurement_operation: u8,
+        nonce: Option<[u8; 32]>,
+        measurements: Leased<W, [u8]>,
+        signature: Leased<W, [u8]>,
     ) -> Result<MeasurementResponse, RequestError<SpdmError>> {
-        ringbuf_entry!(Trace::GetMeasurements { operation: measurement_operation, slot });
-
-        Ok(MeasurementResponse {
-            measurement_index: slot,
-            measurement_hash: [0x34u8; 32],
-        })
-    }
+        ringbuf_entry!(Trace::GetMeasurements {
+            operation: measurement_operation,
+            slot
+        });
+
+        let single = !matches!(
+            measurement_operation,
+            0 | spdm::request::GetMeasurements::MEASUREMENTS_ALL
+        );
+        if single && !self.responder.manifest().contains(measurement_operation)
+        {
+            return Err(SpdmError::MeasurementUnavailable.into());
+        }
+        if nonce.is_some() {
+            self.responder
+                .certs()
+                .chain(slot)
+                .ok_or(SpdmError::CertificateNotFound)?;
+            if signature.len() < requester::SIGNATURE_SIZE {
+                return Err(SpdmError::InvalidParameter.into());
+            }
+        }
 
-    fn key_exchange(
-        &mut self,
-        _msg: &RecvMessage,
-        slot: u8,
-        session_id: u16,
-        random_data: &[u8],
-        exchange_data: &[u8],
-    ) -> Result<KeyExchangeResponse, RequestError<SpdmError>> {
-        ringbuf_entry!(Trace::KeyExchange { slot });
-
-        Ok(KeyExchangeResponse {
-            heartbeat_period: 60,
-            public_key: [0x56u8; 32],
-        })
-    }
+        let (rsp, record, sig) = self.requester().get_measurements(
+            measurement_operation,
+            slot,
+            nonce.as_ref(),
+        )?;
+        if measurements.len() < record.len() {
+            return Err(SpdmError::InvalidParameter.into());
+        }
+        measurements
+            .write_range(0..record.len(), record)
+            .map_err(|_| RequestError::went_away())?;
+        signature
+            .write_range(0..sig.len(), sig)
+            .map_err(|_| RequestError::went_away())?;
 
-    fn psk_exchange(
-        &mut self,
-        _msg: &RecvMessage,
-        measurement_summary: u8,
-        session_id: u16,
-        psk_hint: &[u8],
-        context: &[u8],
-        exchange_data: &[u8],
-    ) -> Result<PskExchangeResponse, RequestError<SpdmError>> {
-        ringbuf_entry!(Trace::PskExchange);
-
-        Ok(PskExchangeResponse {
-            heartbeat_period: 30,
-            context: [0x78u8; 32],
-        })
+        Ok(rsp)
     }
 
-    fn heartbeat(
+    fn set_psk(
         &mut self,
-        _msg: &RecvMessage,
-        session_id: u16,
+        msg: &RecvMessage,
+        hint: Leased<R, [u8]>,
+        key: Leased<R, [u8]>,
     ) -> Result<(), RequestError<SpdmError>> {
-        ringbuf_entry!(Trace::Heartbeat);
-        Ok(())
-    }
+        if !cfg!(feature = "psk") {
+            return Err(SpdmError::UnsupportedOperation.into());
+        }
+        if !is_psk_provisioner(msg.sender) {
+            return Err(ClientError::AccessViolation.fail());
+        }
 
-    fn end_session(
-        &mut self,
-        _msg: &RecvMessage,
-        session_id: u16,
-    ) -> Result<(), RequestError<SpdmError>> {
-        ringbuf_entry!(Trace::EndSession);
-        Ok(())
+        let mut h = [0; MAX_PSK_HINT_SIZE];
+        let h = h.get_mut(..hint.len()).ok_or(SpdmError::InvalidParameter)?;
+        hint.read_range(0..h.len(), h)
+            .map_err(|_| RequestError::went_away())?;
+        let mut k = [0; PSK_SIZE];
+        if key.len() != k.len() {
+            return Err(SpdmError::InvalidParameter.into());
+        }
+        key.read_range(0..k.len(), &mut k)
+            .map_err(|_| RequestError::went_away())?;
+
+        let r = self.responder.set_psk(h, &k);
+        ringbuf_entry!(Trace::SetPsk(r));
+        Ok(r?)
     }
 }
 
-#[no_mangle]
-fn main() -> ! {
-    let mut server = TestSpdmResponder::new();
+/// Returns whether `task` is the one that the app has provision the
+/// pre-shared key, which there's none of without the `psk` feature.
+#[cfg(feature = "psk")]
+fn is_psk_provisioner(task: TaskId) -> bool {
+    task.index() == PSK_PROVISIONER.get_task_id().index()
+}
 
-    let mut incoming = [0u8; 1024]; // Fixed buffer size for testing
+#[cfg(not(feature = "psk"))]
+fn is_psk_provisioner(_task: TaskId) -> bool {
+    false
+}
 
+#[export_name = "main"]
+fn main() -> ! {
+    let (request, response) = mutable_statics::mutable_statics! {
+        static mut REQUEST: [u8; MAX_MESSAGE_SIZE] = [Default::default; _];
+        static mut RESPONSE: [u8; MAX_MESSAGE_SIZE] = [Default::default; _];
+    };
+
+    #[cfg(feature = "dice")]
+    let identity = identity::Identity::load()
+        .inspect_err(|&e| ringbuf_entry!(Trace::HandoffError(e)))
+        .ok();
+    #[cfg(all(not(feature = "dice"), feature = "mock-crypto"))]
+    let identity = Some(identity::Identity::mock());
+    #[cfg(not(any(feature = "dice", feature = "mock-crypto")))]
+    let identity = None;
+
+    #[cfg(feature = "auxflash")]
+    let flash = flash::Flash::new();
+    #[cfg(not(feature = "auxflash"))]
+    let flash = flash::Flash::new(mutable_statics::mutable_statics! {
+        static mut CERT_AREAS: [[u8; flash::AREA_SIZE]; certs::AREAS] =
+            [|| [0xFF; flash::AREA_SIZE]; _];
+    });
+
+    // Without entropy, there's nothing that we can do safely.
+    let mut seed = [0; responder::SEED_SIZE];
+    Rng::from(RNG.get_task_id()).fill(&mut seed).unwrap_lite();
+
+    let mut server = ServerImpl {
+        responder: responder::Responder::new(
+            certs::CertStore::new(identity, flash),
+            measurements::Manifest::collect(),
+            seed,
+        ),
+        request,
+        response,
+    };
+
+    let mut incoming = [0u8; INCOMING_SIZE];
     loop {
         idol_runtime::dispatch(&mut incoming, &mut server);
     }
-}
\ No newline at end of file
+}
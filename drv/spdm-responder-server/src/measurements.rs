@@ -0,0 +1,307 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! What GET_MEASUREMENTS reports: a manifest of measurements, collected once
+//! at startup, since none of them can change until the next reset.
+//!
+//! Each measurement has a fixed index, whether or not this image can take
+//! it, so that a verifier can tell them apart by index alone; the ones that
+//! we can't take are left out of every response.
+//!
+//! - `IMAGE`, with the `bootstate` feature, is stage0's SHA3-256 digest of
+//!   the Hubris image that it booted. That's the image's FWID, as `xtask
+//!   dist` writes it to the archive, so a verifier can check it against the
+//!   build. `STAGE0` is stage0's digest of itself. These are stage0's
+//!   digests, not ours, so they're raw bit streams, like any other value
+//!   that we report as we found it.
+//! - `IMAGE_ID` is the ID that `xtask dist` derives from the kernel's and
+//!   every task's linked output and the app's configuration. The image
+//!   carries it, so it identifies the build without measuring anything, and
+//!   is a free-form manifest rather than firmware.
+//! - `VERSION`, with the `caboose` feature, is the caboose's `VERS`.
+//! - `MODE` is the operational mode, which says whether we're running with
+//!   mock keys.
+//! - `FIRMWARE` is our own digest of the image that's running: the kernel's
+//!   and every task's flash, which the kernel reads out for us if the app
+//!   grants us `read-image` (and faults us otherwise). That's
+//!   `final.bin` up to the length in its header, so a verifier can work it
+//!   out from the archive.
+//!
+//! We'd like to report whether the SP handoff token (`measurement-handoff`)
+//! was present at boot as well, but the SP's startup code consumes it before
+//! any task runs, so there's nothing left to report.
+//!
+//! Digests are SHA-256, which any requester that wants measurements can be
+//! expected to support.
+
+use sha2::digest::{Digest, Output};
+use sha2::Sha256;
+use spdm::measurement::{
+    self, operational_mode, value_type, DeviceMode, BLOCK_HEADER_SIZE,
+};
+use spdm::{ErrorCode, Writer};
+
+/// Measurement indices, including those of measurements that this image
+/// can't take
+#[allow(dead_code)]
+pub mod index {
+    pub const IMAGE: u8 = 1;
+    pub const STAGE0: u8 = 2;
+    pub const IMAGE_ID: u8 = 3;
+    pub const VERSION: u8 = 4;
+    pub const MODE: u8 = 5;
+    pub const FIRMWARE: u8 = 6;
+}
+
+/// Size of a SHA-256 digest
+const DIGEST_SIZE: usize = 32;
+
+/// Largest raw measurement that we keep: a version, or one of stage0's
+/// digests, will fit.
+const MAX_RAW_SIZE: usize = 32;
+
+/// How much of the image we read from the kernel at a time
+const IMAGE_CHUNK_SIZE: usize = 256;
+
+/// Number of measurements that we can take
+const MAX_ENTRIES: usize = 6;
+
+/// Largest record that a MEASUREMENTS response can have: every block, none
+/// of them bigger than `MAX_RAW_SIZE`.
+pub const RECORD_SIZE: usize = MAX_ENTRIES * (BLOCK_HEADER_SIZE + MAX_RAW_SIZE);
+
+enum Value {
+    /// A measurement that we have only as a digest
+    Digest([u8; DIGEST_SIZE]),
+    /// A measurement that we have as a raw bit stream, and can hash
+    Raw(heapless::Vec<u8, MAX_RAW_SIZE>),
+}
+
+struct Entry {
+    index: u8,
+    /// One of `value_type::*`, without `RAW_BIT_STREAM`
+    value_type: u8,
+    /// Whether it's part of the TCB, and so part of the TCB summary hash
+    tcb: bool,
+    value: Value,
+}
+
+impl Entry {
+    fn encode(&self, w: &mut Writer<'_>, raw: bool) -> Result<(), ErrorCode> {
+        match &self.value {
+            Value::Raw(v) if raw => measurement::block(
+                w,
+                self.index,
+                self.value_type | value_type::RAW_BIT_STREAM,
+                v,
+            ),
+            Value::Raw(v) => measurement::block(
+                w,
+                self.index,
+                self.value_type,
+                &Sha256::digest(v),
+            ),
+            Value::Digest(d) => {
+                measurement::block(w, self.index, self.value_type, d)
+            }
+        }
+    }
+}
+
+pub struct Manifest {
+    /// In order of index
+    entries: heapless::Vec<Entry, MAX_ENTRIES>,
+}
+
+impl Manifest {
+    /// Takes every measurement that this image can.
+    pub fn collect() -> Self {
+        let mut m = Self {
+            entries: heapless::Vec::new(),
+        };
+
+        #[cfg(feature = "bootstate")]
+        if let Some(state) = boot::state() {
+            if let Some(image) = state.active_image() {
+                m.push_raw(
+                    index::IMAGE,
+                    value_type::MUTABLE_FIRMWARE,
+                    true,
+                    &image.digest,
+                );
+            }
+            if state.stage0.status.is_ok() {
+                m.push_raw(
+                    index::STAGE0,
+                    value_type::MUTABLE_FIRMWARE,
+                    true,
+                    &state.stage0.digest,
+                );
+            }
+        }
+
+        m.push_raw(
+            index::IMAGE_ID,
+            value_type::FREEFORM_MANIFEST,
+            false,
+            &userlib::kipc::read_image_id().to_le_bytes(),
+        );
+
+        #[cfg(feature = "caboose")]
+        if let Some(caboose) = drv_caboose_pos::CABOOSE_POS.as_slice() {
+            let reader = drv_caboose::CabooseReader::new(caboose);
+            if let Ok(vers) = reader.get(*b"VERS") {
+                m.push_raw(
+                    index::VERSION,
+                    value_type::FIRMWARE_VERSION,
+                    false,
+                    vers,
+                );
+            }
+        }
+
+        m.push_raw(
+            index::MODE,
+            value_type::DEVICE_MODE,
+            false,
+            &mode().to_bytes(),
+        );
+
+        m.push(
+            index::FIRMWARE,
+            value_type::MUTABLE_FIRMWARE,
+            true,
+            Value::Digest(image_digest().into()),
+        );
+        m
+    }
+
+    fn push(&mut self, index: u8, value_type: u8, tcb: bool, value: Value) {
+        // There's room for every index.
+        let _ = self.entries.push(Entry {
+            index,
+            value_type,
+            tcb,
+            value,
+        });
+    }
+
+    /// Adds a measurement that we have as a raw bit stream, unless it's too
+    /// big to keep.
+    fn push_raw(&mut self, index: u8, value_type: u8, tcb: bool, value: &[u8]) {
+        if let Ok(v) = heapless::Vec::from_slice(value) {
+            self.push(index, value_type, tcb, Value::Raw(v));
+        }
+    }
+
+    /// Returns the number of measurements that we have.
+    pub fn count(&self) -> u8 {
+        self.entries.len() as u8
+    }
+
+    /// Returns whether we have a measurement at `index`.
+    pub fn contains(&self, index: u8) -> bool {
+        self.entries.iter().any(|e| e.index == index)
+    }
+
+    /// Encodes the block for the measurement at `index`, as a raw bit stream
+    /// if `raw` and we have one, and as a digest otherwise.
+    pub fn encode(
+        &self,
+        w: &mut Writer<'_>,
+        index: u8,
+        raw: bool,
+    ) -> Result<(), ErrorCode> {
+        self.entries
+            .iter()
+            .find(|e| e.index == index)
+            .ok_or(ErrorCode::InvalidRequest)?
+            .encode(w, raw)
+    }
+
+    /// Encodes the blocks for all of our measurements, returning how many
+    /// there are.
+    pub fn encode_all(
+        &self,
+        w: &mut Writer<'_>,
+        raw: bool,
+    ) -> Result<u8, ErrorCode> {
+        for e in &self.entries {
+            e.encode(w, raw)?;
+        }
+        Ok(self.count())
+    }
+
+    /// Returns the hash of every measurement block (or, if `tcb_only`, every
+    /// block in the TCB), each as a digest, for a measurement summary.
+    pub fn summary(&self, tcb_only: bool) -> Output<Sha256> {
+        let mut hash = Sha256::new();
+        for e in self.entries.iter().filter(|e| e.tcb || !tcb_only) {
+            let mut buf = [0; BLOCK_HEADER_SIZE + DIGEST_SIZE];
+            let mut w = Writer::new(&mut buf);
+            // Every digest fits.
+            if e.encode(&mut w, false).is_ok() {
+                hash.update(w.written());
+            }
+        }
+        hash.finalize()
+    }
+}
+
+/// Hashes the image, as the kernel reads it out for us.
+fn image_digest() -> Output<Sha256> {
+    let mut hash = Sha256::new();
+    let mut buf = [0; IMAGE_CHUNK_SIZE];
+    let mut offset = 0;
+    loop {
+        let n = userlib::kipc::read_image(offset, &mut buf);
+        if n == 0 {
+            break hash.finalize();
+        }
+        hash.update(&buf[..n]);
+        offset += n;
+    }
+}
+
+/// Works out the operational mode. We can't tell whether a debugger's
+/// attached, so we report no debug modes.
+fn mode() -> DeviceMode {
+    let state = if cfg!(all(feature = "mock-crypto", not(feature = "dice"))) {
+        // A mock identity is good for testing and nothing else.
+        operational_mode::VALIDATION
+    } else {
+        operational_mode::NORMAL
+    };
+    DeviceMode {
+        operational_mode_capabilities: operational_mode::VALIDATION
+            | operational_mode::NORMAL,
+        operational_mode_state: state,
+        ..Default::default()
+    }
+}
+
+#[cfg(feature = "bootstate")]
+mod boot {
+    use crate::Trace;
+    use core::mem::MaybeUninit;
+    use ringbuf::ringbuf_entry_root;
+    use stage0_handoff::{HandoffData, RotBootStateV2};
+
+    // Stage0 leaves what it measured here.
+    #[used]
+    #[link_section = ".bootstate"]
+    static BOOTSTATE: MaybeUninit<[u8; 0x1000]> = MaybeUninit::uninit();
+
+    pub fn state() -> Option<RotBootStateV2> {
+        // Safety: Data is published by stage0
+        let addr = unsafe { BOOTSTATE.assume_init_ref() };
+        match RotBootStateV2::load_from_addr(addr) {
+            Ok(state) => Some(state),
+            Err(e) => {
+                ringbuf_entry_root!(Trace::BootStateError(e));
+                None
+            }
+        }
+    }
+}
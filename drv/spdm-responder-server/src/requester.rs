@@ -0,0 +1,327 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! The pre-parsed operations, as SPDM requests.
+//!
+//! Each operation is written out as the request message that it stands for,
+//! which goes through the responder like any that arrives through `respond`:
+//! it's sequenced the same way, and it's part of the same transcripts. The
+//! response is then picked apart into the operation's reply, and an ERROR
+//! response into an `SpdmError`.
+
+use crate::responder::{Responder, VERSIONS};
+use drv_spdm_responder_api::{
+    AlgorithmRequest, AlgorithmResponse, ChallengeAuthResponse,
+    MeasurementResponse, SpdmCapabilities, SpdmError, SpdmVersion,
+    SpdmVersionResponse, MAX_MESSAGE_SIZE,
+};
+use spdm::request::GetMeasurements;
+use spdm::{
+    algo, measurement_summary, ErrorCode, Reader, RequestCode, ResponseCode,
+    Version, Writer, HEADER_SIZE, NONCE_SIZE,
+};
+
+/// Size of the digests in responses: transcripts are always hashed with
+/// SHA-256.
+const HASH_SIZE: usize = 32;
+
+/// Size of an Ed25519 signature, which is all that we sign with
+pub const SIGNATURE_SIZE: usize = 64;
+
+/// Sends requests through `responder`, writing them into `request` and
+/// their responses into `response`.
+pub struct Requester<'a> {
+    pub responder: &'a mut Responder,
+    pub request: &'a mut [u8],
+    pub response: &'a mut [u8],
+}
+
+impl<'a> Requester<'a> {
+    /// Sends GET_VERSION, which starts everything over.
+    pub fn get_version(self) -> Result<SpdmVersionResponse, SpdmError> {
+        let (_, _, mut r) = self.exchange(
+            Version::V1_0,
+            RequestCode::GetVersion,
+            [0, 0],
+            |_| Ok(()),
+            ResponseCode::Version,
+        )?;
+        parse_version(&mut r).map_err(malformed)
+    }
+
+    /// Sends GET_CAPABILITIES, in the latest version that we speak, saying
+    /// that we can take messages as big as the responder can send.
+    pub fn get_capabilities(self) -> Result<SpdmCapabilities, SpdmError> {
+        let version = VERSIONS[VERSIONS.len() - 1];
+        let (_, _, mut r) = self.exchange(
+            version,
+            RequestCode::GetCapabilities,
+            [0, 0],
+            |w| {
+                w.u8(0)?;
+                // No cryptographic timeout, and no capabilities.
+                w.u8(0)?;
+                w.u16(0)?;
+                w.u32(0)?;
+                w.u32(MAX_MESSAGE_SIZE as u32)?;
+                w.u32(MAX_MESSAGE_SIZE as u32)
+            },
+            ResponseCode::Capabilities,
+        )?;
+        parse_capabilities(&mut r).map_err(malformed)
+    }
+
+    /// Sends NEGOTIATE_ALGORITHMS, offering `offered` and DMTF measurements.
+    pub fn negotiate_algorithms(
+        self,
+        offered: &AlgorithmRequest,
+    ) -> Result<AlgorithmResponse, SpdmError> {
+        let version = self.responder.version();
+        let (_, _, mut r) = self.exchange(
+            version,
+            RequestCode::NegotiateAlgorithms,
+            [0, 0],
+            |w| {
+                w.u16(32)?;
+                w.u8(algo::measurement_spec::DMTF)?;
+                w.u8(0)?;
+                w.u32(offered.base_asym_algo)?;
+                w.u32(offered.base_hash_algo)?;
+                // No extended algorithms, and no algorithm structures.
+                w.zeros(16)
+            },
+            ResponseCode::Algorithms,
+        )?;
+        parse_algorithms(&mut r).map_err(malformed)
+    }
+
+    /// Sends GET_CERTIFICATE, returning the portion of the chain in the
+    /// response.
+    pub fn get_certificate(
+        self,
+        slot: u8,
+        offset: u16,
+        length: u16,
+    ) -> Result<&'a [u8], SpdmError> {
+        let version = self.responder.version();
+        let (_, _, mut r) = self.exchange(
+            version,
+            RequestCode::GetCertificate,
+            [slot, 0],
+            |w| {
+                w.u16(offset)?;
+                w.u16(length)
+            },
+            ResponseCode::Certificate,
+        )?;
+        parse_certificate(&mut r).map_err(malformed)
+    }
+
+    /// Sends CHALLENGE with `nonce`, returning what's in the response along
+    /// with its signature.
+    pub fn challenge(
+        self,
+        slot: u8,
+        summary: u8,
+        nonce: &[u8; NONCE_SIZE],
+    ) -> Result<(ChallengeAuthResponse, &'a [u8]), SpdmError> {
+        let version = self.responder.version();
+        let (_, slot_mask, mut r) = self.exchange(
+            version,
+            RequestCode::Challenge,
+            [slot, summary],
+            |w| w.bytes(nonce),
+            ResponseCode::ChallengeAuth,
+        )?;
+        parse_challenge_auth(&mut r, slot_mask, summary).map_err(malformed)
+    }
+
+    /// Sends GET_MEASUREMENTS for `operation`, signed with `slot`'s key if
+    /// there's a `nonce`, returning what's in the response along with its
+    /// measurement record and its signature (which is empty if there's no
+    /// `nonce`).
+    pub fn get_measurements(
+        self,
+        operation: u8,
+        slot: u8,
+        nonce: Option<&[u8; NONCE_SIZE]>,
+    ) -> Result<(MeasurementResponse, &'a [u8], &'a [u8]), SpdmError> {
+        let version = self.responder.version();
+        let attributes = match nonce {
+            Some(_) => GetMeasurements::SIGNATURE_REQUESTED,
+            None => 0,
+        };
+        let (total_count, _, mut r) = self.exchange(
+            version,
+            RequestCode::GetMeasurements,
+            [attributes, operation],
+            |w| match nonce {
+                Some(nonce) => {
+                    w.bytes(nonce)?;
+                    w.u8(slot)
+                }
+                None => Ok(()),
+            },
+            ResponseCode::Measurements,
+        )?;
+        parse_measurements(&mut r, total_count, nonce.is_some())
+            .map_err(malformed)
+    }
+
+    /// Sends a request with `params` and the body that `body` writes, and
+    /// returns the response's parameters and the rest of it, if it's the
+    /// `expected` response.
+    fn exchange(
+        self,
+        version: Version,
+        code: RequestCode,
+        params: [u8; 2],
+        body: impl FnOnce(&mut Writer<'_>) -> Result<(), ErrorCode>,
+        expected: ResponseCode,
+    ) -> Result<(u8, u8, Reader<'a>), SpdmError> {
+        let Self {
+            responder,
+            request,
+            response,
+        } = self;
+        let mut w = Writer::new(request);
+        w.bytes(&[version.0, code as u8, params[0], params[1]])
+            .and_then(|()| body(&mut w))
+            .map_err(|_| SpdmError::InvalidParameter)?;
+        let len = responder.respond(w.written(), response);
+
+        let response: &'a [u8] = response;
+        let (header, rest) = response[..len]
+            .split_at_checked(HEADER_SIZE)
+            .ok_or(SpdmError::InternalError)?;
+        if header[1] == expected as u8 {
+            Ok((header[2], header[3], Reader::new(rest)))
+        } else if header[1] == ResponseCode::Error as u8 {
+            Err(error(header[2]))
+        } else {
+            Err(SpdmError::InternalError)
+        }
+    }
+}
+
+fn parse_version(r: &mut Reader<'_>) -> Result<SpdmVersionResponse, ErrorCode> {
+    let _reserved = r.u8()?;
+    let count = r.u8()?;
+    let mut out = SpdmVersionResponse {
+        version_count: 0,
+        versions: [SpdmVersion::V1_0; 4],
+    };
+    for _ in 0..count {
+        let version = match r.u16()? >> 8 {
+            0x10 => SpdmVersion::V1_0,
+            0x11 => SpdmVersion::V1_1,
+            0x12 => SpdmVersion::V1_2,
+            _ => continue,
+        };
+        if let Some(v) = out.versions.get_mut(usize::from(out.version_count)) {
+            *v = version;
+            out.version_count += 1;
+        }
+    }
+    Ok(out)
+}
+
+fn parse_capabilities(
+    r: &mut Reader<'_>,
+) -> Result<SpdmCapabilities, ErrorCode> {
+    let _reserved = r.u8()?;
+    let ct_exponent = r.u8()?;
+    let _reserved = r.u16()?;
+    Ok(SpdmCapabilities {
+        ct_exponent,
+        flags: r.u32()?,
+    })
+}
+
+fn parse_algorithms(
+    r: &mut Reader<'_>,
+) -> Result<AlgorithmResponse, ErrorCode> {
+    let _length = r.u16()?;
+    let _measurement_spec = r.u8()?;
+    let _other_params = r.u8()?;
+    let _measurement_hash = r.u32()?;
+    Ok(AlgorithmResponse {
+        base_asym_sel: r.u32()?,
+        base_hash_sel: r.u32()?,
+    })
+}
+
+fn parse_certificate<'a>(r: &mut Reader<'a>) -> Result<&'a [u8], ErrorCode> {
+    let portion = r.u16()?;
+    let _remainder = r.u16()?;
+    r.bytes(portion.into())
+}
+
+fn parse_challenge_auth<'a>(
+    r: &mut Reader<'a>,
+    slot_mask: u8,
+    summary: u8,
+) -> Result<(ChallengeAuthResponse, &'a [u8]), ErrorCode> {
+    let mut out = ChallengeAuthResponse {
+        slot_mask,
+        cert_chain_hash: *r.array::<HASH_SIZE>()?,
+        nonce: *r.array::<NONCE_SIZE>()?,
+        measurement_summary_hash: [0; HASH_SIZE],
+        signature_len: SIGNATURE_SIZE as u16,
+    };
+    if summary != measurement_summary::NONE {
+        out.measurement_summary_hash = *r.array()?;
+    }
+    let opaque_len = r.u16()?;
+    r.bytes(opaque_len.into())?;
+    let signature = r.bytes(SIGNATURE_SIZE)?;
+    Ok((out, signature))
+}
+
+fn parse_measurements<'a>(
+    r: &mut Reader<'a>,
+    total_count: u8,
+    signed: bool,
+) -> Result<(MeasurementResponse, &'a [u8], &'a [u8]), ErrorCode> {
+    let block_count = r.u8()?;
+    let record_len = r.u24()?;
+    let record = r.bytes(record_len as usize)?;
+    let out = MeasurementResponse {
+        total_count,
+        block_count,
+        record_len,
+        nonce: *r.array::<NONCE_SIZE>()?,
+        signature_len: if signed { SIGNATURE_SIZE as u16 } else { 0 },
+    };
+    let opaque_len = r.u16()?;
+    r.bytes(opaque_len.into())?;
+    let signature = r.bytes(out.signature_len.into())?;
+    Ok((out, record, signature))
+}
+
+/// Error codes of ERROR responses, and the `SpdmError`s that they become;
+/// anything else is an `InternalError`
+const ERRORS: [(ErrorCode, SpdmError); 5] = [
+    (ErrorCode::InvalidRequest, SpdmError::InvalidParameter),
+    (ErrorCode::UnexpectedRequest, SpdmError::InvalidRequest),
+    (
+        ErrorCode::UnsupportedRequest,
+        SpdmError::UnsupportedOperation,
+    ),
+    (ErrorCode::VersionMismatch, SpdmError::UnsupportedVersion),
+    (ErrorCode::SessionRequired, SpdmError::SessionNotEstablished),
+];
+
+fn error(code: u8) -> SpdmError {
+    ERRORS
+        .iter()
+        .find(|&&(c, _)| c as u8 == code)
+        .map_or(SpdmError::InternalError, |&(_, e)| e)
+}
+
+/// Reports a response that we couldn't pick apart, which means that the
+/// responder and this module disagree about its format.
+fn malformed(_: ErrorCode) -> SpdmError {
+    SpdmError::InternalError
+}
@@ -0,0 +1,1023 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! SPDM responder, driven by raw request messages.
+//!
+//! This sequences the protocol: version, capability and algorithm negotiation
+//! must happen in that order (and GET_VERSION starts over at any point), and
+//! every other request must wait until they're done. We only speak SPDM 1.2.
+//!
+//! There's one connection's worth of state, which doesn't know who it's
+//! talking to, so the transport must keep requesters from interleaving; the
+//! MCTP task talks to one at a time.
+//!
+//! CHALLENGE authenticates us outside of a session, with a signature over the
+//! negotiation, the certificate retrieval since, and the challenge itself
+//! (M1).
+//!
+//! KEY_EXCHANGE and PSK_EXCHANGE start secure sessions, several of which can
+//! be open at once. The rest of a session arrives in secured messages, through
+//! `respond_secured`: FINISH (or PSK_FINISH) to complete the handshake, then
+//! KEY_UPDATE and END_SESSION. We authenticate ourselves with the
+//! certificate chains in `certs`, which GET_DIGESTS and GET_CERTIFICATE
+//! retrieve, in a session or out of one. We never ask the requester to
+//! authenticate itself, so SET_CERTIFICATE, which provisions them, is only
+//! for images built with the `provisioning` feature (and only in a session).
+//! PSK_EXCHANGE, which is only offered with the `psk` feature, needs a
+//! pre-shared key, which must be provisioned first, with `set_psk`.
+//!
+//! GET_MEASUREMENTS reports the measurements in `measurements`, signed if the
+//! requester asks, over the measurement requests and responses since the last
+//! signed one (L1 out of a session, or that session's own L2 in one).
+
+use crate::certs::CertStore;
+use crate::measurements::{self, Manifest};
+use drv_spdm_responder_api::{
+    SpdmError, MAX_MESSAGE_SIZE, MAX_PSK_HINT_SIZE, PSK_SIZE,
+};
+use p256::elliptic_curve::point::AffineCoordinates;
+use p256::elliptic_curve::sec1::{FromEncodedPoint, ToEncodedPoint};
+use p256::{AffinePoint, EncodedPoint, NonZeroScalar, ProjectivePoint};
+use rand_chacha::rand_core::{RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use sha2::digest::{Digest, Output};
+use sha2::Sha256;
+use spdm::algo::{self, Algorithms};
+use spdm::request::{
+    self, Challenge, GetCapabilities, GetCertificate, GetMeasurements,
+    KeyExchange, KeyOperation, NegotiateAlgorithms, PskExchange, Request,
+};
+use spdm::response::{
+    self, Capabilities, ChallengeAuth, KeyExchangeRsp, Measurements,
+    PskExchangeRsp,
+};
+use spdm::session::{self, Channel, Handshake, TAG_SIZE};
+use spdm::transcript::{
+    signing_prefix, Transcript, CHALLENGE_AUTH_CONTEXT,
+    KEY_EXCHANGE_RSP_CONTEXT, MEASUREMENTS_CONTEXT, SIGNING_PREFIX_SIZE,
+};
+use spdm::{
+    capabilities, measurement_summary, opaque, ErrorCode, Version, Writer,
+    HEADER_SIZE, NONCE_SIZE, RANDOM_SIZE, SLOT_COUNT,
+};
+
+/// Versions that we speak
+pub const VERSIONS: [Version; 1] = [Version::V1_2];
+
+/// Versions of the secured message format (DSP0277) that we speak, in order
+/// of preference
+const SECURED_VERSIONS: [Version; 2] = [Version::V1_1, Version::V1_0];
+
+/// Cryptographic timeout, as a power of two in microseconds (about a second)
+const CT_EXPONENT: u8 = 20;
+
+/// Capabilities that we advertise
+const CAPABILITIES: u32 = capabilities::CERT
+    | capabilities::CHAL
+    | capabilities::MEAS_SIG
+    | capabilities::ENCRYPT
+    | capabilities::MAC
+    | capabilities::KEY_EX
+    | capabilities::KEY_UPD
+    | if cfg!(feature = "psk") {
+        capabilities::PSK_WITH_CONTEXT
+    } else {
+        0
+    }
+    | if cfg!(feature = "provisioning") {
+        capabilities::SET_CERT
+    } else {
+        0
+    };
+
+/// Base hash algorithms that we support, in order of preference. Transcripts
+/// are always hashed with SHA-256, so this can't grow without them changing.
+const BASE_HASH: [u32; 1] = [algo::base_hash::SHA_256];
+
+/// Hash algorithm of our measurements' digests. The requester doesn't get a
+/// say in this; it's ours to choose, as long as it asked for measurements in
+/// a specification that we speak.
+const MEASUREMENT_HASH: u32 = algo::measurement_hash::SHA_256;
+
+/// Algorithms that we support for sessions. A requester that doesn't offer
+/// these can still negotiate, but can't start a session.
+const BASE_ASYM: [u32; 1] = [algo::base_asym::ED25519];
+const DHE: [u16; 1] = [algo::dhe::SECP256R1];
+const AEAD: [u16; 1] = [algo::aead::AES_256_GCM];
+const KEY_SCHEDULE: [u16; 1] = [algo::key_schedule::SPDM];
+
+/// Number of sessions that can be open at once
+const MAX_SESSIONS: usize = 4;
+
+/// Size of the sequence number in a secured message, as the MCTP binding
+/// (DSP0275) has it
+const SEQUENCE_NUMBER_SIZE: usize = 2;
+
+/// MCTP message type that precedes the SPDM message in a secured message's
+/// application data
+const SPDM_MSG_TYPE: u8 = 0x05;
+
+/// Size of the context that we contribute to PSK_EXCHANGE_RSP
+const PSK_CONTEXT_SIZE: usize = 32;
+
+/// Size of the seed of our random numbers
+pub const SEED_SIZE: usize = 32;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum State {
+    /// Waiting for GET_VERSION
+    Idle,
+    /// Waiting for GET_CAPABILITIES
+    Version,
+    /// Waiting for NEGOTIATE_ALGORITHMS
+    Capabilities,
+    /// Ready for anything else
+    Negotiated,
+}
+
+/// The one pre-shared key that we know, and the hint that picks it
+struct Psk {
+    hint: [u8; MAX_PSK_HINT_SIZE],
+    hint_len: usize,
+    key: [u8; PSK_SIZE],
+}
+
+struct Session {
+    id: u32,
+    /// Whether this session started with PSK_EXCHANGE, not KEY_EXCHANGE
+    psk: bool,
+    /// Transcript of the session's handshake so far
+    transcript: Sha256,
+    /// Handshake secrets, until FINISH (or PSK_FINISH) is done with them
+    handshake: Option<Handshake<Sha256>>,
+    /// Transcript of the measurement requests and responses in this session
+    /// since the last signed one (L2)
+    l2: Sha256,
+    request: Channel<Sha256>,
+    response: Channel<Sha256>,
+}
+
+/// What becomes of a session once the response to a secured message has
+/// been sealed
+enum Then {
+    Continue,
+    /// Responses switch to the data secret, as requests already have
+    Established(Output<Sha256>),
+    /// Responses switch to the next secret, as requests already have
+    UpdateResponses,
+    End,
+}
+
+pub struct Responder {
+    state: State,
+    /// Version chosen by the requester, once it's sent GET_CAPABILITIES
+    version: Version,
+    requester: GetCapabilities,
+    algorithms: Algorithms,
+    transcript: Transcript<Sha256>,
+    sessions: [Option<Session>; MAX_SESSIONS],
+    /// Our half of the last session ID that we handed out
+    last_session_id: u16,
+    certs: CertStore,
+    manifest: Manifest,
+    psk: Option<Psk>,
+    rng: ChaCha20Rng,
+}
+
+impl Responder {
+    /// Makes a responder whose random numbers come from `seed`, which must
+    /// be different every time.
+    pub fn new(
+        certs: CertStore,
+        manifest: Manifest,
+        seed: [u8; SEED_SIZE],
+    ) -> Self {
+        Self {
+            state: State::Idle,
+            version: Version::V1_0,
+            requester: GetCapabilities::default(),
+            algorithms: Algorithms::default(),
+            transcript: Transcript::new(),
+            sessions: Default::default(),
+            last_session_id: 0,
+            certs,
+            manifest,
+            psk: None,
+            rng: ChaCha20Rng::from_seed(seed),
+        }
+    }
+
+    /// Provisions the pre-shared key that PSK_EXCHANGE picks with `hint`.
+    /// This can only be done once, so that nothing can replace the key once
+    /// it's been set.
+    pub fn set_psk(
+        &mut self,
+        hint: &[u8],
+        key: &[u8; PSK_SIZE],
+    ) -> Result<(), SpdmError> {
+        let mut psk = Psk {
+            hint: [0; MAX_PSK_HINT_SIZE],
+            hint_len: hint.len(),
+            key: *key,
+        };
+        psk.hint
+            .get_mut(..hint.len())
+            .ok_or(SpdmError::InvalidParameter)?
+            .copy_from_slice(hint);
+        if self.psk.is_some() {
+            return Err(SpdmError::AlreadyProvisioned);
+        }
+        self.psk = Some(psk);
+        Ok(())
+    }
+
+    /// Handles the request in `msg`, writing a response into `out` and
+    /// returning its length. Failures are reported with ERROR responses.
+    pub fn respond(&mut self, msg: &[u8], out: &mut [u8]) -> usize {
+        let limit = self.limit();
+        let out = if out.len() > limit {
+            &mut out[..limit]
+        } else {
+            out
+        };
+
+        let mut w = Writer::new(out);
+        match self.handle(msg, &mut w) {
+            Ok(()) => w.len(),
+            Err(code) => {
+                // Not even GET_MEASUREMENTS continues L1 once it's failed.
+                self.transcript.reset_l1();
+                let version = self.error_version(msg);
+                let mut w = Writer::new(out);
+                // An ERROR response is only a header; if even that doesn't
+                // fit, there's nothing that we can send.
+                match response::error(&mut w, version, code, 0) {
+                    Ok(()) => w.len(),
+                    Err(_) => 0,
+                }
+            }
+        }
+    }
+
+    /// Handles the secured message in `msg`, opening it in place, and writes
+    /// the secured message that answers it into `out`, returning its length.
+    ///
+    /// Failures of the request inside are reported with (secured) ERROR
+    /// responses, as for `respond`. Those of the secured message itself are
+    /// returned, since we can't answer them: a message for a session that we
+    /// don't have, or one that won't open, which ends its session.
+    pub fn respond_secured(
+        &mut self,
+        msg: &mut [u8],
+        out: &mut [u8],
+    ) -> Result<usize, SpdmError> {
+        // There must be room for at least an ERROR response.
+        let min = session::secured_size(SEQUENCE_NUMBER_SIZE, 1 + HEADER_SIZE);
+        if out.len() < min {
+            return Err(SpdmError::InvalidParameter);
+        }
+        let id = session::secured_session_id(msg)
+            .ok_or(SpdmError::InvalidRequest)?;
+        let i = self
+            .sessions
+            .iter()
+            .position(|s| matches!(s, Some(s) if s.id == id))
+            .ok_or(SpdmError::SessionNotEstablished)?;
+        let Some(mut session) = self.sessions[i].take() else {
+            unreachable!()
+        };
+
+        // Returning early from here on ends the session.
+        let data = session
+            .request
+            .open(SEQUENCE_NUMBER_SIZE, msg)
+            .map_err(|_| SpdmError::CryptoOperationFailed)?;
+        let [SPDM_MSG_TYPE, req @ ..] = data else {
+            // That's not something that we can answer, but it's no reason to
+            // give up on the session.
+            self.sessions[i] = Some(session);
+            return Err(SpdmError::InvalidRequest);
+        };
+
+        let start = session::app_data_offset(SEQUENCE_NUMBER_SIZE);
+        let end = (out.len() - TAG_SIZE).min(start + 1 + self.limit());
+        out[start] = SPDM_MSG_TYPE;
+        let body = &mut out[start + 1..end];
+
+        let mut w = Writer::new(body);
+        let (then, len) = match self.handle_secured(&mut session, req, &mut w) {
+            Ok(then) => (then, w.len()),
+            Err(code) => {
+                // A FINISH that doesn't check out gets no second chance.
+                let then = if code == ErrorCode::DecryptError {
+                    Then::End
+                } else {
+                    Then::Continue
+                };
+                let mut w = Writer::new(body);
+                // We checked that there's room for this at the start.
+                let _ = response::error(&mut w, self.version, code, 0);
+                (then, w.len())
+            }
+        };
+
+        let size = session
+            .response
+            .seal(id, SEQUENCE_NUMBER_SIZE, out, 1 + len)
+            .map_err(|_| SpdmError::InternalError);
+        match then {
+            Then::Continue => (),
+            Then::Established(secret) => {
+                session.response = Channel::new(self.version, secret)
+            }
+            Then::UpdateResponses => session.response.update(),
+            Then::End => return size,
+        }
+        self.sessions[i] = Some(session);
+        size
+    }
+
+    pub fn certs(&self) -> &CertStore {
+        &self.certs
+    }
+
+    pub fn manifest(&self) -> &Manifest {
+        &self.manifest
+    }
+
+    /// Returns the version that the requester chose, or 1.0 if it hasn't yet.
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    /// Returns the size of the largest message that we may send: no bigger
+    /// than the requester can take.
+    fn limit(&self) -> usize {
+        match self.requester.data_transfer_size {
+            0 => MAX_MESSAGE_SIZE,
+            n => MAX_MESSAGE_SIZE.min(n as usize),
+        }
+    }
+
+    fn handle(
+        &mut self,
+        msg: &[u8],
+        w: &mut Writer<'_>,
+    ) -> Result<(), ErrorCode> {
+        let negotiated =
+            (self.state == State::Negotiated).then_some(&self.algorithms);
+        let (version, req) = Request::parse(msg, negotiated)?;
+        if !matches!(req, Request::GetMeasurements(_)) {
+            self.transcript.reset_l1();
+        }
+
+        match req {
+            Request::GetVersion => {
+                // This starts everything over, whatever state we were in,
+                // and whatever sessions were open.
+                self.state = State::Idle;
+                self.version = Version::V1_0;
+                self.requester = GetCapabilities::default();
+                self.algorithms = Algorithms::default();
+                self.transcript.reset();
+                self.sessions = Default::default();
+
+                response::version(w, &VERSIONS)?;
+                self.state = State::Version;
+            }
+            Request::GetCapabilities(caps) => {
+                if self.state != State::Version {
+                    return Err(ErrorCode::UnexpectedRequest);
+                }
+                if !VERSIONS.contains(&version) {
+                    return Err(ErrorCode::VersionMismatch);
+                }
+                Capabilities {
+                    ct_exponent: CT_EXPONENT,
+                    flags: CAPABILITIES,
+                    data_transfer_size: MAX_MESSAGE_SIZE as u32,
+                    max_message_size: MAX_MESSAGE_SIZE as u32,
+                }
+                .encode(w, version)?;
+                self.version = version;
+                self.requester = caps;
+                self.state = State::Capabilities;
+            }
+            Request::NegotiateAlgorithms(offered) => {
+                self.check_version(version, State::Capabilities)?;
+                self.algorithms = select_algorithms(&offered)?;
+                response::algorithms(
+                    w,
+                    self.version,
+                    &self.algorithms,
+                    offered.alg_structs,
+                )?;
+                self.state = State::Negotiated;
+            }
+            Request::GetDigests => {
+                self.check_version(version, State::Negotiated)?;
+                self.digests(w)?;
+                // This, and GET_CERTIFICATE, are message B.
+                self.transcript.append_m1(msg);
+                self.transcript.append_m1(w.written());
+                return Ok(());
+            }
+            Request::GetCertificate(req) => {
+                self.check_version(version, State::Negotiated)?;
+                self.certificate(&req, w)?;
+                self.transcript.append_m1(msg);
+                self.transcript.append_m1(w.written());
+                return Ok(());
+            }
+            Request::GetMeasurements(req) => {
+                self.check_version(version, State::Negotiated)?;
+                self.measurements(&req, w)?;
+                self.transcript.append_l1(msg);
+                self.transcript.append_l1(w.written());
+                if req.signature_requested() {
+                    let hash = self.transcript.finish_l1();
+                    w.bytes(&self.sign(MEASUREMENTS_CONTEXT, &hash)?)?;
+                }
+                return Ok(());
+            }
+            Request::Challenge(req) => {
+                self.check_version(version, State::Negotiated)?;
+                return self.challenge(msg, &req, w);
+            }
+            Request::KeyExchange(req) => {
+                self.check_version(version, State::Negotiated)?;
+                return self.key_exchange(msg, &req, w);
+            }
+            Request::PskExchange(req) => {
+                self.check_version(version, State::Negotiated)?;
+                return self.psk_exchange(msg, &req, w);
+            }
+            Request::Finish(_)
+            | Request::PskFinish(_)
+            | Request::KeyUpdate(_)
+            | Request::EndSession(_)
+            | Request::SetCertificate(_) => {
+                self.check_version(version, State::Negotiated)?;
+                return Err(ErrorCode::SessionRequired);
+            }
+        }
+
+        // Everything above is part of message A.
+        self.transcript.append_vca(msg);
+        self.transcript.append_vca(w.written());
+        Ok(())
+    }
+
+    /// Handles a request that arrived in `session`.
+    fn handle_secured(
+        &mut self,
+        session: &mut Session,
+        msg: &[u8],
+        w: &mut Writer<'_>,
+    ) -> Result<Then, ErrorCode> {
+        let (version, req) = Request::parse(msg, Some(&self.algorithms))?;
+        // GET_VERSION is always version 1.0, and it's just out of place here.
+        if version != self.version && req != Request::GetVersion {
+            return Err(ErrorCode::VersionMismatch);
+        }
+        // Until the handshake is done, it's all that we'll hear of.
+        let handshake =
+            matches!(req, Request::Finish(_) | Request::PskFinish(_));
+        if session.handshake.is_some() != handshake {
+            return Err(ErrorCode::UnexpectedRequest);
+        }
+        if !matches!(req, Request::GetMeasurements(_)) {
+            session.l2 = self.transcript.vca();
+        }
+
+        match req {
+            Request::Finish(req) if !session.psk => {
+                // We never ask the requester to sign.
+                if req.signature.is_some() {
+                    return Err(ErrorCode::InvalidRequest);
+                }
+                self.finish(session, msg, req.verify_data, w)
+            }
+            Request::PskFinish(req) if session.psk => {
+                self.finish(session, msg, req.verify_data, w)
+            }
+            Request::KeyUpdate(req) => {
+                response::key_update_ack(
+                    w,
+                    self.version,
+                    req.operation,
+                    req.tag,
+                )?;
+                // The requester switches as soon as it has our ACK, but that
+                // goes out under the old secret.
+                Ok(match req.operation {
+                    KeyOperation::UpdateKey => {
+                        session.request.update();
+                        Then::Continue
+                    }
+                    KeyOperation::UpdateAllKeys => {
+                        session.request.update();
+                        Then::UpdateResponses
+                    }
+                    KeyOperation::VerifyNewKey => Then::Continue,
+                })
+            }
+            Request::EndSession(_) => {
+                // There's no state to preserve beyond what we keep anyway.
+                response::end_session_ack(w, self.version)?;
+                Ok(Then::End)
+            }
+            Request::GetDigests => {
+                self.digests(w)?;
+                Ok(Then::Continue)
+            }
+            Request::GetCertificate(req) => {
+                self.certificate(&req, w)?;
+                Ok(Then::Continue)
+            }
+            Request::SetCertificate(req) => {
+                // We don't know who the requester is, so only an image that's
+                // being provisioned can take its word for its chains.
+                if !cfg!(feature = "provisioning") {
+                    return Err(ErrorCode::UnsupportedRequest);
+                }
+                response::set_certificate_rsp(w, self.version, req.slot)?;
+                self.certs.provision(req.slot, req.cert_chain)?;
+                Ok(Then::Continue)
+            }
+            Request::GetMeasurements(req) => {
+                if let Err(e) = self.measurements(&req, w) {
+                    session.l2 = self.transcript.vca();
+                    return Err(e);
+                }
+                session.l2.update(msg);
+                session.l2.update(w.written());
+                if req.signature_requested() {
+                    let l2 = core::mem::replace(
+                        &mut session.l2,
+                        self.transcript.vca(),
+                    );
+                    let hash = l2.finalize();
+                    w.bytes(&self.sign(MEASUREMENTS_CONTEXT, &hash)?)?;
+                }
+                Ok(Then::Continue)
+            }
+            Request::GetVersion
+            | Request::GetCapabilities(_)
+            | Request::NegotiateAlgorithms(_)
+            | Request::Challenge(_)
+            | Request::KeyExchange(_)
+            | Request::PskExchange(_)
+            | Request::Finish(_)
+            | Request::PskFinish(_) => Err(ErrorCode::UnexpectedRequest),
+        }
+    }
+
+    /// Encodes a DIGESTS response, with the digest of each slot's chain.
+    fn digests(&self, w: &mut Writer<'_>) -> Result<(), ErrorCode> {
+        let mut digests: [&[u8]; SLOT_COUNT as usize] = Default::default();
+        let mut n = 0;
+        for slot in 0..SLOT_COUNT {
+            if let Some((_, digest)) = self.certs.chain(slot) {
+                digests[n] = digest;
+                n += 1;
+            }
+        }
+        response::digests(
+            w,
+            self.version,
+            self.certs.slot_mask(),
+            &digests[..n],
+        )
+    }
+
+    /// Encodes a CERTIFICATE response, with as much of the chain that `req`
+    /// asks for as fits.
+    fn certificate(
+        &self,
+        req: &GetCertificate,
+        w: &mut Writer<'_>,
+    ) -> Result<(), ErrorCode> {
+        let (len, _) = self
+            .certs
+            .chain(req.slot)
+            .ok_or(ErrorCode::InvalidRequest)?;
+        if req.offset >= len {
+            return Err(ErrorCode::InvalidRequest);
+        }
+        let room = w
+            .remaining()
+            .saturating_sub(response::CERTIFICATE_HEADER_SIZE);
+        let portion = (len - req.offset)
+            .min(req.length)
+            .min(room.try_into().unwrap_or(u16::MAX));
+        let remainder = len - req.offset - portion;
+        let buf = response::certificate(
+            w,
+            self.version,
+            req.slot,
+            portion,
+            remainder,
+        )?;
+        self.certs.read(req.slot, req.offset.into(), buf)
+    }
+
+    /// Encodes a MEASUREMENTS response to `req`, up to the signature if it
+    /// asked for one.
+    fn measurements(
+        &mut self,
+        req: &GetMeasurements<'_>,
+        w: &mut Writer<'_>,
+    ) -> Result<(), ErrorCode> {
+        let a = &self.algorithms;
+        if a.measurement_spec == 0 || a.measurement_hash == 0 {
+            // We can't describe our measurements in a way that the requester
+            // understands.
+            return Err(ErrorCode::UnsupportedRequest);
+        }
+        if req.signature_requested() {
+            if a.base_asym == 0 {
+                return Err(ErrorCode::UnsupportedRequest);
+            }
+            self.certs
+                .chain(req.slot)
+                .ok_or(ErrorCode::InvalidRequest)?;
+        }
+
+        let raw =
+            req.attributes & GetMeasurements::RAW_BIT_STREAM_REQUESTED != 0;
+        let mut record = [0; measurements::RECORD_SIZE];
+        let mut r = Writer::new(&mut record);
+        let (total_count, block_count) = match req.operation {
+            0 => (self.manifest.count(), 0),
+            GetMeasurements::MEASUREMENTS_ALL => {
+                (0, self.manifest.encode_all(&mut r, raw)?)
+            }
+            index => {
+                self.manifest.encode(&mut r, index, raw)?;
+                (0, 1)
+            }
+        };
+
+        let mut nonce = [0; NONCE_SIZE];
+        self.rng.fill_bytes(&mut nonce);
+        Measurements {
+            total_count,
+            slot: req.slot,
+            // Our measurements are taken once, at startup.
+            content_changed: if req.signature_requested() {
+                Measurements::CONTENT_UNCHANGED
+            } else {
+                Measurements::CONTENT_CHANGED_UNSUPPORTED
+            },
+            block_count,
+            record: r.written(),
+            nonce: &nonce,
+            opaque_data: &[],
+        }
+        .encode(w, self.version)
+    }
+
+    /// Returns the measurement summary hash that CHALLENGE, KEY_EXCHANGE or
+    /// PSK_EXCHANGE asked for with `summary`, if it asked for one.
+    fn measurement_summary(
+        &self,
+        summary: u8,
+    ) -> Result<Option<Output<Sha256>>, ErrorCode> {
+        if summary == measurement_summary::NONE {
+            return Ok(None);
+        }
+        let a = &self.algorithms;
+        if a.measurement_spec == 0 || a.measurement_hash == 0 {
+            return Err(ErrorCode::InvalidRequest);
+        }
+        Ok(Some(
+            self.manifest.summary(summary == measurement_summary::TCB),
+        ))
+    }
+
+    /// Signs a transcript's `hash`, for a response that signs with `context`.
+    fn sign(&self, context: &str, hash: &[u8]) -> Result<[u8; 64], ErrorCode> {
+        let mut signed = [0; SIGNING_PREFIX_SIZE + 32];
+        let (prefix, h) = signed.split_at_mut(SIGNING_PREFIX_SIZE);
+        prefix.copy_from_slice(&signing_prefix(self.version, context));
+        h.copy_from_slice(hash);
+        Ok(self
+            .certs
+            .signing_key()
+            .ok_or(ErrorCode::Unspecified)?
+            .sign(&signed)
+            .to_bytes())
+    }
+
+    /// Encodes a CHALLENGE_AUTH response to `msg`, which is `req`, signed
+    /// over M1.
+    fn challenge(
+        &mut self,
+        msg: &[u8],
+        req: &Challenge<'_>,
+        w: &mut Writer<'_>,
+    ) -> Result<(), ErrorCode> {
+        if self.algorithms.base_asym == 0 {
+            return Err(ErrorCode::UnsupportedRequest);
+        }
+        let summary = self.measurement_summary(req.measurement_summary)?;
+        let (_, ct) = self
+            .certs
+            .chain(req.slot)
+            .ok_or(ErrorCode::InvalidRequest)?;
+
+        let mut nonce = [0; NONCE_SIZE];
+        self.rng.fill_bytes(&mut nonce);
+        ChallengeAuth {
+            slot: req.slot,
+            slot_mask: self.certs.slot_mask(),
+            cert_chain_hash: ct,
+            nonce: &nonce,
+            measurement_summary_hash: summary.as_deref().unwrap_or_default(),
+            opaque_data: &[],
+        }
+        .encode(w, self.version)?;
+
+        self.transcript.append_m1(msg);
+        self.transcript.append_m1(w.written());
+        let hash = self.transcript.finish_m1();
+        w.bytes(&self.sign(CHALLENGE_AUTH_CONTEXT, &hash)?)
+    }
+
+    fn key_exchange(
+        &mut self,
+        msg: &[u8],
+        req: &KeyExchange<'_>,
+        w: &mut Writer<'_>,
+    ) -> Result<(), ErrorCode> {
+        let a = &self.algorithms;
+        if a.base_asym == 0 || a.dhe == 0 || a.aead == 0 || a.key_schedule == 0
+        {
+            return Err(ErrorCode::UnsupportedRequest);
+        }
+        let summary = self.measurement_summary(req.measurement_summary)?;
+        let (_, &ct) = self
+            .certs
+            .chain(req.slot)
+            .ok_or(ErrorCode::InvalidRequest)?;
+        let peer = EncodedPoint::from_untagged_bytes(req.exchange_data.into());
+        let peer =
+            Option::<AffinePoint>::from(AffinePoint::from_encoded_point(&peer))
+                .ok_or(ErrorCode::InvalidRequest)?;
+        let (i, id, secured_version) =
+            self.new_session(req.req_session_id, req.opaque_data)?;
+
+        let mut random = [0; RANDOM_SIZE];
+        self.rng.fill_bytes(&mut random);
+        let secret = NonZeroScalar::random(&mut self.rng);
+        let public = (ProjectivePoint::GENERATOR * *secret)
+            .to_affine()
+            .to_encoded_point(false);
+        let shared = (ProjectivePoint::from(peer) * *secret).to_affine().x();
+
+        KeyExchangeRsp {
+            heartbeat_period: 0,
+            rsp_session_id: (id >> 16) as u16,
+            mut_auth_requested: 0,
+            req_slot: 0,
+            random: &random,
+            exchange_data: &public.as_bytes()[1..],
+            measurement_summary_hash: summary.as_deref().unwrap_or_default(),
+            opaque_data: &opaque::version_selection(secured_version),
+        }
+        .encode(w, self.version)?;
+
+        // The transcript has the digest of the chain that the requester
+        // picked, which certifies the key that we sign with.
+        let mut transcript = self.transcript.vca();
+        transcript.update(ct);
+        transcript.update(msg);
+        transcript.update(w.written());
+
+        let signature = self
+            .sign(KEY_EXCHANGE_RSP_CONTEXT, &transcript.clone().finalize())?;
+        w.bytes(&signature)?;
+        transcript.update(signature);
+
+        let handshake = Handshake::new(
+            self.version,
+            &shared,
+            &transcript.clone().finalize(),
+        );
+        self.start_session(i, id, false, transcript, handshake, w)
+    }
+
+    fn psk_exchange(
+        &mut self,
+        msg: &[u8],
+        req: &PskExchange<'_>,
+        w: &mut Writer<'_>,
+    ) -> Result<(), ErrorCode> {
+        let a = &self.algorithms;
+        if a.aead == 0 || a.key_schedule == 0 {
+            return Err(ErrorCode::UnsupportedRequest);
+        }
+        let psk = self.psk.as_ref().ok_or(ErrorCode::UnsupportedRequest)?;
+        if req.psk_hint != &psk.hint[..psk.hint_len] {
+            return Err(ErrorCode::InvalidRequest);
+        }
+        let key = psk.key;
+        let summary = self.measurement_summary(req.measurement_summary)?;
+        let (i, id, secured_version) =
+            self.new_session(req.req_session_id, req.opaque_data)?;
+
+        let mut context = [0; PSK_CONTEXT_SIZE];
+        self.rng.fill_bytes(&mut context);
+        PskExchangeRsp {
+            heartbeat_period: 0,
+            rsp_session_id: (id >> 16) as u16,
+            measurement_summary_hash: summary.as_deref().unwrap_or_default(),
+            context: &context,
+            opaque_data: &opaque::version_selection(secured_version),
+        }
+        .encode(w, self.version)?;
+
+        let mut transcript = self.transcript.vca();
+        transcript.update(msg);
+        transcript.update(w.written());
+
+        let handshake =
+            Handshake::new(self.version, &key, &transcript.clone().finalize());
+        self.start_session(i, id, true, transcript, handshake, w)
+    }
+
+    /// Picks a slot and an ID for a new session, and the version of the
+    /// secured message format that it uses, from the requester's list in
+    /// `opaque_data`.
+    fn new_session(
+        &mut self,
+        req_session_id: u16,
+        opaque_data: &[u8],
+    ) -> Result<(usize, u32, Version), ErrorCode> {
+        if self.algorithms.other_params & algo::other_params::OPAQUE_DATA_FMT1
+            == 0
+        {
+            // We can't understand the requester's list of versions.
+            return Err(ErrorCode::InvalidRequest);
+        }
+        let version =
+            opaque::select_secured_version(opaque_data, &SECURED_VERSIONS)?;
+        let i = self
+            .sessions
+            .iter()
+            .position(Option::is_none)
+            .ok_or(ErrorCode::SessionLimitExceeded)?;
+
+        // Our half of the ID must be neither zero nor in use, which at most
+        // MAX_SESSIONS tries will get us.
+        let mut rsp_session_id = self.last_session_id;
+        loop {
+            rsp_session_id = rsp_session_id.wrapping_add(1);
+            let in_use = self
+                .sessions
+                .iter()
+                .flatten()
+                .any(|s| s.id >> 16 == u32::from(rsp_session_id));
+            if rsp_session_id != 0 && !in_use {
+                break;
+            }
+        }
+        self.last_session_id = rsp_session_id;
+        Ok((
+            i,
+            session::session_id(req_session_id, rsp_session_id),
+            version,
+        ))
+    }
+
+    /// Finishes KEY_EXCHANGE_RSP or PSK_EXCHANGE_RSP, whose transcript
+    /// (through everything but its verify data) has given us the handshake
+    /// secrets, and opens the session in slot `i`.
+    fn start_session(
+        &mut self,
+        i: usize,
+        id: u32,
+        psk: bool,
+        mut transcript: Sha256,
+        handshake: Handshake<Sha256>,
+        w: &mut Writer<'_>,
+    ) -> Result<(), ErrorCode> {
+        let verify_data =
+            handshake.responder_verify_data(&transcript.clone().finalize());
+        w.bytes(&verify_data)?;
+        transcript.update(verify_data);
+
+        self.sessions[i] = Some(Session {
+            id,
+            psk,
+            transcript,
+            l2: self.transcript.vca(),
+            request: Channel::new(self.version, handshake.requester),
+            response: Channel::new(self.version, handshake.responder),
+            handshake: Some(handshake),
+        });
+        Ok(())
+    }
+
+    /// Completes the handshake of `session` with `msg`, a FINISH or
+    /// PSK_FINISH that has the requester's `verify_data`.
+    fn finish(
+        &self,
+        session: &mut Session,
+        msg: &[u8],
+        verify_data: &[u8],
+        w: &mut Writer<'_>,
+    ) -> Result<Then, ErrorCode> {
+        let Some(handshake) = &session.handshake else {
+            return Err(ErrorCode::UnexpectedRequest);
+        };
+        let th = session
+            .transcript
+            .clone()
+            .chain_update(&msg[..msg.len() - verify_data.len()])
+            .finalize();
+        if !equal(&handshake.requester_verify_data(&th), verify_data) {
+            return Err(ErrorCode::DecryptError);
+        }
+
+        if session.psk {
+            response::psk_finish_rsp(w, self.version)?;
+        } else {
+            response::finish_rsp(w, self.version)?;
+        }
+        session.transcript.update(msg);
+        session.transcript.update(w.written());
+
+        let th2 = session.transcript.clone().finalize();
+        let (request, response) = handshake.data_secrets(&th2);
+        session.request = Channel::new(self.version, request);
+        session.handshake = None;
+        Ok(Then::Established(response))
+    }
+
+    /// Checks that a request arrived in `state`, and with the negotiated
+    /// version.
+    fn check_version(
+        &self,
+        version: Version,
+        state: State,
+    ) -> Result<(), ErrorCode> {
+        if self.state < state {
+            Err(ErrorCode::UnexpectedRequest)
+        } else if version != self.version {
+            Err(ErrorCode::VersionMismatch)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Picks the version for an ERROR response to `msg`: the negotiated
+    /// version if there is one, or else the request's own version if we
+    /// speak it.
+    fn error_version(&self, msg: &[u8]) -> Version {
+        if self.state >= State::Capabilities {
+            return self.version;
+        }
+        match request::version(msg) {
+            Some(v) if VERSIONS.contains(&v) => v,
+            _ => Version::V1_0,
+        }
+    }
+}
+
+fn select_algorithms(
+    offered: &NegotiateAlgorithms,
+) -> Result<Algorithms, ErrorCode> {
+    let base_hash = algo::select(offered.base_hash, &BASE_HASH);
+    if base_hash == 0 {
+        // We couldn't hash transcripts in a way the requester understands.
+        return Err(ErrorCode::InvalidRequest);
+    }
+    let measurement_spec =
+        offered.measurement_spec & algo::measurement_spec::DMTF;
+    Ok(Algorithms {
+        measurement_spec,
+        measurement_hash: if measurement_spec != 0 {
+            MEASUREMENT_HASH
+        } else {
+            0
+        },
+        other_params: offered.other_params
+            & algo::other_params::OPAQUE_DATA_FMT1,
+        base_asym: algo::select(offered.base_asym, &BASE_ASYM),
+        base_hash,
+        dhe: algo::select(offered.dhe, &DHE),
+        aead: algo::select(offered.aead, &AEAD),
+        key_schedule: algo::select(offered.key_schedule, &KEY_SCHEDULE),
+        ..Default::default()
+    })
+}
+
+/// Compares verify data in constant time, so that a forgery can't be worked
+/// out a byte at a time.
+fn equal(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len()
+        && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
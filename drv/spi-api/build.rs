@@ -12,6 +12,7 @@ fn main() -> Result<()> {
         .with_op_enum_derives(std::iter::once("counters::Count"))?
         .build_client_stub("../../idl/spi.idol", "client_stub.rs")
         .map_err(|e| anyhow!(e))?;
+    build_util::build_client_interface("../../idl/spi.idol")?;
 
     let out_dir = build_util::out_dir();
     let dest_path = out_dir.join("spi_devices.rs");
@@ -259,4 +259,5 @@ impl<S: SpiServer> SpiDevice<S> {
 }
 
 include!(concat!(env!("OUT_DIR"), "/client_stub.rs"));
+include!(concat!(env!("OUT_DIR"), "/client_interface.rs"));
 include!(concat!(env!("OUT_DIR"), "/spi_devices.rs"));
@@ -836,3 +836,4 @@ impl tlvc::TlvcRead for &RotCabooseReader<'_> {
 }
 
 include!(concat!(env!("OUT_DIR"), "/client_stub.rs"));
+include!(concat!(env!("OUT_DIR"), "/client_interface.rs"));
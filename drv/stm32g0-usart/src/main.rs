@@ -24,7 +24,7 @@ use stm32g0::stm32g0b1 as device;
 
 use userlib::*;
 
-task_slot!(SYS, sys);
+task_slot!(SYS, sys, Sys);
 
 #[derive(Copy, Clone, Debug, FromPrimitive)]
 enum Operation {
@@ -28,7 +28,7 @@ cfg_if::cfg_if! {
 use idol_runtime::{NotificationHandler, RequestError};
 
 task_slot!(SYS, sys);
-task_slot!(NET, net);
+task_slot!(NET, net, Net);
 
 #[derive(Count, Copy, Clone)]
 enum Event {
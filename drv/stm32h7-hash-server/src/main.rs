@@ -24,7 +24,7 @@ use stm32h7::stm32h753 as device;
 
 use drv_hash_api::{HashError, SHA256_SZ};
 
-task_slot!(SYS, sys);
+task_slot!(SYS, sys, sys_api::Sys);
 
 fn hash_hw_reset() {
     let sys = sys_api::Sys::from(SYS.get_task_id());
@@ -23,10 +23,10 @@ use stm32h7::stm32h753 as device;
 
 use userlib::*;
 
-task_slot!(SYS, sys);
+task_slot!(SYS, sys, Sys);
 
 #[cfg(feature = "ereport")]
-task_slot!(PACKRAT, packrat);
+task_slot!(PACKRAT, packrat, Packrat);
 
 counted_ringbuf!(Trace, 32, Trace::Blank);
 
@@ -22,7 +22,7 @@ use userlib::*;
 use drv_stm32h7_spi_server_core::SpiServerCore;
 use drv_stm32xx_sys_api as sys_api;
 
-task_slot!(SYS, sys);
+task_slot!(SYS, sys, sys_api::Sys);
 
 // This lets us amortize the cost of the borrow syscalls for retrieving data
 // from the caller. It doesn't appear to be useful to make this any larger than
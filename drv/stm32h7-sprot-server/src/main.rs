@@ -36,13 +36,13 @@ cfg_if::cfg_if! {
         }
     } else {
         pub fn claim_spi(_sys: &sys_api::Sys) -> drv_spi_api::Spi {
-            task_slot!(SPI, spi_driver);
+            task_slot!(SPI, spi_driver, drv_spi_api::Spi);
             drv_spi_api::Spi::from(SPI.get_task_id())
         }
     }
 }
 
-task_slot!(SYS, sys);
+task_slot!(SYS, sys, sys_api::Sys);
 
 #[derive(Copy, Clone, PartialEq)]
 enum Trace {
@@ -7,5 +7,6 @@ fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         "../../idl/stm32h7-update.idol",
         "client_stub.rs",
     )?;
+    build_util::build_client_interface("../../idl/stm32h7-update.idol")?;
     Ok(())
 }
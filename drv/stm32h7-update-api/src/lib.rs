@@ -60,3 +60,4 @@ impl From<SlotId> for u16 {
 }
 
 include!(concat!(env!("OUT_DIR"), "/client_stub.rs"));
+include!(concat!(env!("OUT_DIR"), "/client_interface.rs"));
@@ -15,7 +15,7 @@ use fixedmap::*;
 use ringbuf::*;
 use userlib::*;
 
-task_slot!(SYS, sys);
+task_slot!(SYS, sys, Sys);
 
 fn lookup_controller<'a, 'b>(
     controllers: &'a [I2cController<'b>],
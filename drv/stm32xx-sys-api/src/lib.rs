@@ -383,3 +383,4 @@ impl From<Option<bool>> for IrqControl {
 }
 
 include!(concat!(env!("OUT_DIR"), "/client_stub.rs"));
+include!(concat!(env!("OUT_DIR"), "/client_interface.rs"));
@@ -188,7 +188,7 @@
 //! use drv_stm32xx_sys_api::{PinSet, Port, Pull};
 //! use userlib::*;
 //!
-//! task_slot!(SYS, sys);
+//! task_slot!(SYS, sys, drv_stm32xx_sys_api::Sys);
 //!
 //! #[export_name = "main"]
 //! pub fn main() -> ! {
@@ -231,7 +231,7 @@
 //! use drv_stm32xx_sys_api::{PinSet, Port, Pull, Edge, IrqControl};
 //! use userlib::*;
 //!
-//! task_slot!(SYS, sys);
+//! task_slot!(SYS, sys, drv_stm32xx_sys_api::Sys);
 //!
 //! #[export_name = "main"]
 //! pub fn main() -> ! {
@@ -329,7 +329,7 @@ use task_jefe_api::{Jefe, ResetReason};
 use userlib::*;
 
 #[cfg(not(feature = "test"))]
-task_slot!(JEFE, jefe);
+task_slot!(JEFE, jefe, Jefe);
 
 /// This part of the world contains a _lot_ of registers that are essentially
 /// indexed arrays of bits. The PAC wants us to decide which bit we are altering
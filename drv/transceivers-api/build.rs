@@ -7,6 +7,7 @@ fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         "../../idl/transceivers.idol",
         "client_stub.rs",
     )?;
+    build_util::build_client_interface("../../idl/transceivers.idol")?;
 
     let disposition = build_i2c::Disposition::Sensors;
     if let Err(e) = build_i2c::codegen(disposition) {
@@ -39,12 +39,12 @@ mod udp; // UDP API is implemented in a separate file
 
 task_slot!(I2C, i2c_driver);
 task_slot!(FRONT_IO, front_io);
-task_slot!(SEQ, seq);
-task_slot!(NET, net);
-task_slot!(SENSOR, sensor);
+task_slot!(SEQ, seq, Sequencer);
+task_slot!(NET, net, task_net_api::Net);
+task_slot!(SENSOR, sensor, Sensor);
 
 #[cfg(feature = "thermal-control")]
-task_slot!(THERMAL, thermal);
+task_slot!(THERMAL, thermal, Thermal);
 
 include!(concat!(env!("OUT_DIR"), "/i2c_config.rs"));
 
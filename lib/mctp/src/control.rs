@@ -0,0 +1,258 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! MCTP control messages (DSP0236, section 12).
+//!
+//! A control message starts with a byte holding the request and datagram bits
+//! and an instance ID, followed by the command code; responses then carry a
+//! completion code. We answer the handful of commands that a simple endpoint
+//! must, which is enough for a bus owner to assign our EID and discover what
+//! we speak.
+
+use crate::{EID_BROADCAST, EID_NULL};
+
+/// Command codes
+pub mod command {
+    pub const SET_ENDPOINT_ID: u8 = 0x01;
+    pub const GET_ENDPOINT_ID: u8 = 0x02;
+    pub const GET_ENDPOINT_UUID: u8 = 0x03;
+    pub const GET_VERSION_SUPPORT: u8 = 0x04;
+    pub const GET_MESSAGE_TYPE_SUPPORT: u8 = 0x05;
+}
+
+/// Completion codes
+pub mod completion {
+    pub const SUCCESS: u8 = 0x00;
+    pub const ERROR: u8 = 0x01;
+    pub const INVALID_DATA: u8 = 0x02;
+    pub const INVALID_LENGTH: u8 = 0x03;
+    pub const NOT_READY: u8 = 0x04;
+    pub const UNSUPPORTED_CMD: u8 = 0x05;
+    /// Specific to Get MCTP Version Support
+    pub const MESSAGE_TYPE_NOT_SUPPORTED: u8 = 0x80;
+}
+
+/// Operations in Set Endpoint ID
+mod set_eid {
+    pub const SET: u8 = 0;
+    pub const FORCE: u8 = 1;
+    pub const RESET: u8 = 2;
+}
+
+/// Message type number that asks Get MCTP Version Support about the base
+/// specification
+const BASE_SPEC: u8 = 0xFF;
+
+/// The one version of the base specification and control protocol that we
+/// claim, 1.3.1, in the encoding of Get MCTP Version Support
+const VERSION: [u8; 4] = [0xF1, 0xF3, 0xF1, 0x00];
+
+const RQ: u8 = 1 << 7;
+const D: u8 = 1 << 6;
+const INSTANCE_ID: u8 = 0x1F;
+
+/// The state that control requests read and change
+#[derive(Copy, Clone, Debug)]
+pub struct Config {
+    /// Our EID, or `EID_NULL` until the bus owner assigns one
+    pub eid: u8,
+    /// The EID that we're configured with, if any
+    pub static_eid: Option<u8>,
+    /// Message types that we handle, other than control
+    pub types: &'static [u8],
+}
+
+/// Handles the control message `req` (without its message type byte),
+/// writing a response into `out` and returning its length, or `None` if
+/// there's nothing to send back: responses and datagrams aren't answered,
+/// and neither is anything that won't fit in `out`.
+pub fn respond(cfg: &mut Config, req: &[u8], out: &mut [u8]) -> Option<usize> {
+    let [hdr, cmd, data @ ..] = req else {
+        return None;
+    };
+    if hdr & RQ == 0 || hdr & D != 0 {
+        return None;
+    }
+
+    let mut rsp = [0u8; 8];
+    let body: &[u8] = match *cmd {
+        command::SET_ENDPOINT_ID => match *data {
+            [op, eid, ..] => {
+                let eid = match op & 0x3 {
+                    set_eid::SET | set_eid::FORCE => Some(eid)
+                        .filter(|&e| e != EID_NULL && e != EID_BROADCAST),
+                    set_eid::RESET => cfg.static_eid,
+                    _ => None,
+                };
+                match eid {
+                    Some(eid) => {
+                        cfg.eid = eid;
+                        // Accepted, and we have no pool of EIDs to hand out
+                        rsp[..4].copy_from_slice(&[
+                            completion::SUCCESS,
+                            0,
+                            eid,
+                            0,
+                        ]);
+                        &rsp[..4]
+                    }
+                    None => &[completion::INVALID_DATA],
+                }
+            }
+            _ => &[completion::INVALID_LENGTH],
+        },
+        command::GET_ENDPOINT_ID => {
+            // A simple endpoint, whose EID type depends on whether it has a
+            // static EID and whether it's still using it
+            let eid_type = match cfg.static_eid {
+                None => 0b00,
+                Some(s) if s == cfg.eid => 0b10,
+                Some(_) => 0b11,
+            };
+            rsp[..4].copy_from_slice(&[
+                completion::SUCCESS,
+                cfg.eid,
+                eid_type,
+                0,
+            ]);
+            &rsp[..4]
+        }
+        command::GET_VERSION_SUPPORT => match *data {
+            [BASE_SPEC | crate::msg_type::CONTROL, ..] => {
+                rsp[0] = completion::SUCCESS;
+                rsp[1] = 1;
+                rsp[2..6].copy_from_slice(&VERSION);
+                &rsp[..6]
+            }
+            [_, ..] => &[completion::MESSAGE_TYPE_NOT_SUPPORTED],
+            [] => &[completion::INVALID_LENGTH],
+        },
+        command::GET_MESSAGE_TYPE_SUPPORT => {
+            let n = cfg.types.len().min(rsp.len() - 2);
+            rsp[0] = completion::SUCCESS;
+            rsp[1] = n as u8;
+            rsp[2..2 + n].copy_from_slice(&cfg.types[..n]);
+            &rsp[..2 + n]
+        }
+        _ => &[completion::UNSUPPORTED_CMD],
+    };
+
+    let out = out.get_mut(..2 + body.len())?;
+    out[0] = hdr & INSTANCE_ID;
+    out[1] = *cmd;
+    out[2..].copy_from_slice(body);
+    Some(out.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::msg_type;
+
+    fn config() -> Config {
+        Config {
+            eid: EID_NULL,
+            static_eid: None,
+            types: &[msg_type::SPDM],
+        }
+    }
+
+    fn respond(cfg: &mut Config, req: &[u8]) -> Option<Vec<u8>> {
+        let mut out = [0; 64];
+        super::respond(cfg, req, &mut out).map(|n| out[..n].to_vec())
+    }
+
+    #[test]
+    fn set_endpoint_id() {
+        let mut cfg = config();
+        assert_eq!(
+            respond(&mut cfg, &[0x83, 0x01, 0x00, 0x1D]),
+            Some(vec![0x03, 0x01, 0x00, 0x00, 0x1D, 0x00])
+        );
+        assert_eq!(cfg.eid, 0x1D);
+
+        // Reserved EIDs are refused, as is going back to a static EID that
+        // we don't have.
+        for req in [[0x80, 0x01, 0x00, 0xFF], [0x80, 0x01, 0x02, 0x00]] {
+            assert_eq!(
+                respond(&mut cfg, &req),
+                Some(vec![0x00, 0x01, completion::INVALID_DATA])
+            );
+        }
+        assert_eq!(
+            respond(&mut cfg, &[0x80, 0x01, 0x00]),
+            Some(vec![0x00, 0x01, completion::INVALID_LENGTH])
+        );
+        assert_eq!(cfg.eid, 0x1D);
+
+        let mut cfg = Config {
+            static_eid: Some(0x20),
+            eid: 0x21,
+            ..config()
+        };
+        assert_eq!(
+            respond(&mut cfg, &[0x80, 0x01, 0x02, 0x00]),
+            Some(vec![0x00, 0x01, 0x00, 0x00, 0x20, 0x00])
+        );
+        assert_eq!(cfg.eid, 0x20);
+    }
+
+    #[test]
+    fn get_endpoint_id() {
+        let mut cfg = config();
+        assert_eq!(
+            respond(&mut cfg, &[0x81, 0x02]),
+            Some(vec![0x01, 0x02, 0x00, 0x00, 0x00, 0x00])
+        );
+
+        let mut cfg = Config {
+            static_eid: Some(0x20),
+            eid: 0x20,
+            ..config()
+        };
+        assert_eq!(
+            respond(&mut cfg, &[0x81, 0x02]),
+            Some(vec![0x01, 0x02, 0x00, 0x20, 0x02, 0x00])
+        );
+        cfg.eid = 0x30;
+        assert_eq!(
+            respond(&mut cfg, &[0x81, 0x02]),
+            Some(vec![0x01, 0x02, 0x00, 0x30, 0x03, 0x00])
+        );
+    }
+
+    #[test]
+    fn discovery() {
+        let mut cfg = config();
+        assert_eq!(
+            respond(&mut cfg, &[0x80, 0x04, 0xFF]),
+            Some(vec![0x00, 0x04, 0x00, 0x01, 0xF1, 0xF3, 0xF1, 0x00])
+        );
+        assert_eq!(
+            respond(&mut cfg, &[0x80, 0x04, 0x01]),
+            Some(vec![0x00, 0x04, 0x80])
+        );
+        assert_eq!(
+            respond(&mut cfg, &[0x80, 0x05]),
+            Some(vec![0x00, 0x05, 0x00, 0x01, 0x05])
+        );
+        assert_eq!(
+            respond(&mut cfg, &[0x80, 0x03]),
+            Some(vec![0x00, 0x03, completion::UNSUPPORTED_CMD])
+        );
+    }
+
+    #[test]
+    fn unanswered() {
+        let mut cfg = config();
+        // A response, a datagram, and a runt
+        assert_eq!(respond(&mut cfg, &[0x00, 0x02, 0x00]), None);
+        assert_eq!(respond(&mut cfg, &[0xC0, 0x01, 0x00, 0x1D]), None);
+        assert_eq!(respond(&mut cfg, &[0x80]), None);
+        assert_eq!(cfg.eid, EID_NULL);
+
+        let mut out = [0; 4];
+        assert_eq!(super::respond(&mut cfg, &[0x80, 0x02], &mut out), None);
+    }
+}
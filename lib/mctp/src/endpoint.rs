@@ -0,0 +1,384 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::control;
+use crate::{
+    msg_type, Error, Header, BASELINE_MTU, EID_BROADCAST, EID_NULL, HEADER_SIZE,
+};
+
+/// A message that's been reassembled by an [`Endpoint`]
+///
+/// Its body stays in the endpoint, and can be had with [`Endpoint::body`]
+/// until the next packet arrives.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Message {
+    /// The EID that sent it
+    pub src: u8,
+    pub tag: u8,
+    /// Message type, without the integrity check bit
+    pub msg_type: u8,
+    /// Whether the message ends with an integrity check
+    pub ic: bool,
+    len: usize,
+}
+
+/// The message being reassembled
+#[derive(Copy, Clone, Debug)]
+struct Partial {
+    src: u8,
+    tag: u8,
+    /// Sequence number of the next packet
+    seq: u8,
+    /// Payload size of the first packet, which all but the last must match
+    unit: usize,
+    len: usize,
+}
+
+/// An MCTP endpoint that answers requests, reassembling messages of up to `N`
+/// bytes (including the message type).
+///
+/// We only ever respond, so we never own a tag: every message that we take
+/// is a request, and every one that we send is a response, which goes back
+/// to the requester with the request's tag.
+pub struct Endpoint<const N: usize> {
+    control: control::Config,
+    partial: Option<Partial>,
+    buf: [u8; N],
+}
+
+impl<const N: usize> Endpoint<N> {
+    /// Makes an endpoint that handles messages of the given `types` besides
+    /// control messages, and starts out with `static_eid` if it has one.
+    pub const fn new(static_eid: Option<u8>, types: &'static [u8]) -> Self {
+        Self {
+            control: control::Config {
+                eid: match static_eid {
+                    Some(eid) => eid,
+                    None => EID_NULL,
+                },
+                static_eid,
+                types,
+            },
+            partial: None,
+            buf: [0; N],
+        }
+    }
+
+    /// Returns our EID, which is `EID_NULL` until one is assigned.
+    pub fn eid(&self) -> u8 {
+        self.control.eid
+    }
+
+    /// Takes in a packet, returning the message that it completes, if any.
+    ///
+    /// A packet that's in error is dropped, along with any message that it
+    /// was part of; the error says why.
+    pub fn recv(&mut self, packet: &[u8]) -> Result<Option<Message>, Error> {
+        let (hdr, payload) = Header::parse(packet)?;
+        if hdr.dest != self.control.eid
+            && hdr.dest != EID_NULL
+            && hdr.dest != EID_BROADCAST
+        {
+            return Err(Error::NotForUs);
+        }
+        if payload.len() > BASELINE_MTU {
+            return Err(Error::TooLarge);
+        }
+
+        let mut partial = if hdr.som {
+            // This starts a new message, and abandons any that we were in
+            // the middle of.
+            self.partial = None;
+            if !hdr.tag_owner {
+                return Err(Error::UnexpectedResponse);
+            }
+            if payload.is_empty() {
+                return Err(Error::Truncated);
+            }
+            Partial {
+                src: hdr.src,
+                tag: hdr.tag,
+                seq: hdr.seq,
+                unit: payload.len(),
+                len: 0,
+            }
+        } else {
+            match self.partial {
+                Some(p)
+                    if p.src == hdr.src
+                        && p.tag == hdr.tag
+                        && hdr.tag_owner =>
+                {
+                    p
+                }
+                _ => return Err(Error::Unexpected),
+            }
+        };
+
+        // Whatever happens next, this packet either completes its message,
+        // or gets it put back.
+        self.partial = None;
+        if hdr.seq != partial.seq {
+            return Err(Error::Sequence);
+        }
+        if payload.len() > partial.unit
+            || (!hdr.eom && payload.len() != partial.unit)
+        {
+            return Err(Error::BadLength);
+        }
+        let end = partial.len + payload.len();
+        self.buf
+            .get_mut(partial.len..end)
+            .ok_or(Error::TooLarge)?
+            .copy_from_slice(payload);
+        partial.len = end;
+        partial.seq = (partial.seq + 1) & 0x3;
+
+        if !hdr.eom {
+            self.partial = Some(partial);
+            return Ok(None);
+        }
+        Ok(Some(Message {
+            src: partial.src,
+            tag: partial.tag,
+            msg_type: self.buf[0] & !msg_type::IC,
+            ic: self.buf[0] & msg_type::IC != 0,
+            len: partial.len,
+        }))
+    }
+
+    /// Returns the body of `msg`, after its message type.
+    pub fn body(&self, msg: &Message) -> &[u8] {
+        &self.buf[1..msg.len]
+    }
+
+    /// Answers `msg`, if it's a control message, writing the response body
+    /// into `out` and returning its length; see [`control::respond`].
+    pub fn control(&mut self, msg: &Message, out: &mut [u8]) -> Option<usize> {
+        if msg.msg_type != msg_type::CONTROL {
+            return None;
+        }
+        control::respond(&mut self.control, &self.buf[1..msg.len], out)
+    }
+
+    /// Sends `body` back to the sender of `msg`, as a message of the same
+    /// type, handing each packet to `send` in turn.
+    pub fn reply<E>(
+        &self,
+        msg: &Message,
+        body: &[u8],
+        mut send: impl FnMut(&[u8]) -> Result<(), E>,
+    ) -> Result<(), E> {
+        let mut bytes =
+            core::iter::once(msg.msg_type).chain(body.iter().copied());
+        let mut remaining = body.len() + 1;
+        let mut packet = [0u8; HEADER_SIZE + BASELINE_MTU];
+        let mut hdr = Header {
+            dest: msg.src,
+            src: self.control.eid,
+            som: true,
+            eom: false,
+            seq: 0,
+            tag_owner: false,
+            tag: msg.tag,
+        };
+
+        loop {
+            let n = remaining.min(BASELINE_MTU);
+            remaining -= n;
+            hdr.eom = remaining == 0;
+            packet[..HEADER_SIZE].copy_from_slice(&hdr.encode());
+            for (b, v) in packet[HEADER_SIZE..][..n].iter_mut().zip(&mut bytes)
+            {
+                *b = v;
+            }
+            send(&packet[..HEADER_SIZE + n])?;
+
+            if hdr.eom {
+                return Ok(());
+            }
+            hdr.som = false;
+            hdr.seq = (hdr.seq + 1) & 0x3;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EID: u8 = 0x1D;
+    const REQUESTER: u8 = 0x08;
+
+    fn packet(som: bool, eom: bool, seq: u8, payload: &[u8]) -> Vec<u8> {
+        let hdr = Header {
+            dest: EID,
+            src: REQUESTER,
+            som,
+            eom,
+            seq,
+            tag_owner: true,
+            tag: 3,
+        };
+        let mut p = hdr.encode().to_vec();
+        p.extend_from_slice(payload);
+        p
+    }
+
+    fn endpoint() -> Endpoint<256> {
+        Endpoint::new(Some(EID), &[msg_type::SPDM])
+    }
+
+    #[test]
+    fn single_packet() {
+        let mut ep = endpoint();
+        let msg = ep
+            .recv(&packet(true, true, 0, &[0x05, 0x10, 0x84, 0x00, 0x00]))
+            .unwrap()
+            .unwrap();
+        assert_eq!(msg.src, REQUESTER);
+        assert_eq!(msg.tag, 3);
+        assert_eq!(msg.msg_type, msg_type::SPDM);
+        assert!(!msg.ic);
+        assert_eq!(ep.body(&msg), &[0x10, 0x84, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn reassembly() {
+        let mut ep = endpoint();
+        let data: Vec<u8> = (0..150).map(|i| i as u8).collect();
+        let mut msg = vec![0x85];
+        msg.extend_from_slice(&data);
+
+        // Sequence numbers wrap, so make sure we go past 3.
+        let chunks: Vec<_> = msg.chunks(BASELINE_MTU / 2).collect();
+        for (i, c) in chunks.iter().enumerate() {
+            let last = i == chunks.len() - 1;
+            let r = ep.recv(&packet(i == 0, last, i as u8 & 3, c)).unwrap();
+            assert_eq!(r.is_some(), last);
+            if let Some(m) = r {
+                assert_eq!(m.msg_type, msg_type::SPDM);
+                assert!(m.ic);
+                assert_eq!(ep.body(&m), &data[..]);
+            }
+        }
+    }
+
+    #[test]
+    fn bad_packets() {
+        let mut ep = endpoint();
+
+        // Middle packets without a start
+        assert_eq!(
+            ep.recv(&packet(false, true, 1, &[0; 4])),
+            Err(Error::Unexpected)
+        );
+
+        // Out of sequence, which drops the message
+        ep.recv(&packet(true, false, 0, &[0; 8])).unwrap();
+        assert_eq!(
+            ep.recv(&packet(false, false, 2, &[0; 8])),
+            Err(Error::Sequence)
+        );
+        assert_eq!(
+            ep.recv(&packet(false, true, 1, &[0; 4])),
+            Err(Error::Unexpected)
+        );
+
+        // A middle packet that's not the same size as the first
+        ep.recv(&packet(true, false, 0, &[0; 8])).unwrap();
+        assert_eq!(
+            ep.recv(&packet(false, false, 1, &[0; 4])),
+            Err(Error::BadLength)
+        );
+
+        // Too big for the MTU, and then for our buffer
+        assert_eq!(
+            ep.recv(&packet(true, true, 0, &[0; BASELINE_MTU + 1])),
+            Err(Error::TooLarge)
+        );
+        let mut small = Endpoint::<16>::new(Some(EID), &[]);
+        small.recv(&packet(true, false, 0, &[0; 12])).unwrap();
+        assert_eq!(
+            small.recv(&packet(false, true, 1, &[0; 12])),
+            Err(Error::TooLarge)
+        );
+
+        // Someone else's packet
+        let mut p = packet(true, true, 0, &[0x05]);
+        p[1] = EID + 1;
+        assert_eq!(ep.recv(&p), Err(Error::NotForUs));
+
+        // A response, when we never asked anything
+        let mut p = packet(true, true, 0, &[0x05]);
+        p[3] &= !(1 << 3);
+        assert_eq!(ep.recv(&p), Err(Error::UnexpectedResponse));
+    }
+
+    #[test]
+    fn reply() {
+        let ep = endpoint();
+        let msg = Message {
+            src: REQUESTER,
+            tag: 3,
+            msg_type: msg_type::SPDM,
+            ic: false,
+            len: 1,
+        };
+        let body: Vec<u8> = (0..100).collect();
+
+        let mut packets = vec![];
+        ep.reply(&msg, &body, |p| {
+            packets.push(p.to_vec());
+            Ok::<_, ()>(())
+        })
+        .unwrap();
+
+        assert_eq!(packets.len(), 2);
+        assert_eq!(packets[0].len(), HEADER_SIZE + BASELINE_MTU);
+        assert_eq!(packets[0][..4], [0x01, REQUESTER, EID, 0x83]);
+        assert_eq!(packets[0][4], msg_type::SPDM);
+        assert_eq!(packets[1][..4], [0x01, REQUESTER, EID, 0x53]);
+
+        // What we send, we can take back in.
+        let mut rx = endpoint();
+        rx.control.eid = REQUESTER;
+        for p in &mut packets {
+            p[3] |= 1 << 3;
+        }
+        assert_eq!(rx.recv(&packets[0]), Ok(None));
+        let m = rx.recv(&packets[1]).unwrap().unwrap();
+        assert_eq!(m.src, EID);
+        assert_eq!(rx.body(&m), &body[..]);
+    }
+
+    #[test]
+    fn control() {
+        let mut ep = Endpoint::<64>::new(None, &[msg_type::SPDM]);
+        assert_eq!(ep.eid(), EID_NULL);
+
+        // Set Endpoint ID, sent to the null EID
+        let mut p = packet(true, true, 0, &[0x00, 0x80, 0x01, 0x00, EID]);
+        p[1] = EID_NULL;
+        let msg = ep.recv(&p).unwrap().unwrap();
+        let mut out = [0; 16];
+        let n = ep.control(&msg, &mut out).unwrap();
+        assert_eq!(&out[..n], &[0x00, 0x01, 0x00, 0x00, EID, 0x00]);
+        assert_eq!(ep.eid(), EID);
+
+        let mut packets = vec![];
+        ep.reply(&msg, &out[..n], |p| {
+            packets.push(p.to_vec());
+            Ok::<_, ()>(())
+        })
+        .unwrap();
+        assert_eq!(
+            packets,
+            [[
+                0x01, REQUESTER, EID, 0xC3, 0x00, 0x00, 0x01, 0x00, 0x00, EID,
+                0x00
+            ]]
+        );
+    }
+}
@@ -0,0 +1,171 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! MCTP (DMTF DSP0236), as seen by a simple endpoint.
+//!
+//! An MCTP message is split into packets of at most a transmission unit each,
+//! every one of which carries a four-byte transport header: the destination
+//! and source endpoint IDs (EIDs), start- and end-of-message flags, a two-bit
+//! sequence number, and a message tag that ties requests to their responses.
+//! The first byte of every message gives its type (control, SPDM, and so on).
+//!
+//! [`Endpoint`] reassembles incoming messages, answers MCTP control requests
+//! (which is how the bus owner assigns our EID), and splits responses back
+//! into packets. It knows nothing about the medium: [`smbus`] implements the
+//! SMBus/I2C binding (DSP0237), which wraps each packet for the wire.
+
+#![cfg_attr(not(test), no_std)]
+
+pub mod control;
+mod endpoint;
+pub mod smbus;
+
+pub use endpoint::{Endpoint, Message};
+
+/// The header version that we speak (the only one there is)
+pub const HEADER_VERSION: u8 = 1;
+
+/// Size of the transport header at the start of every packet
+pub const HEADER_SIZE: usize = 4;
+
+/// The baseline transmission unit: the largest packet payload that every
+/// endpoint must accept, and the only one that we use.
+pub const BASELINE_MTU: usize = 64;
+
+/// The null EID, used before one is assigned and for physically addressed
+/// messages
+pub const EID_NULL: u8 = 0;
+
+/// The broadcast EID
+pub const EID_BROADCAST: u8 = 0xFF;
+
+/// Message types, from the first byte of every message (DSP0239)
+pub mod msg_type {
+    pub const CONTROL: u8 = 0x00;
+    pub const PLDM: u8 = 0x01;
+    pub const NCSI: u8 = 0x02;
+    pub const ETHERNET: u8 = 0x03;
+    pub const NVME: u8 = 0x04;
+    pub const SPDM: u8 = 0x05;
+    pub const SECURED: u8 = 0x06;
+    pub const VENDOR_PCI: u8 = 0x7E;
+    pub const VENDOR_IANA: u8 = 0x7F;
+
+    /// Integrity check bit, set when a message ends with a type-specific
+    /// integrity check
+    pub const IC: u8 = 0x80;
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// A packet or frame was too short for its headers
+    Truncated,
+    /// A packet carried a header version that we don't speak
+    BadVersion,
+    /// A packet was addressed to some other EID
+    NotForUs,
+    /// A packet arrived without a message to be part of
+    Unexpected,
+    /// A packet arrived out of sequence, and its message was dropped
+    Sequence,
+    /// A packet wasn't the same size as the one that started its message
+    BadLength,
+    /// A message didn't fit into our buffer, or a packet into the MTU
+    TooLarge,
+    /// A response arrived for a tag that we don't own
+    UnexpectedResponse,
+    /// A frame wasn't an MCTP frame for this binding
+    BadFrame,
+    /// A frame's packet error code didn't match its contents
+    BadPec,
+}
+
+/// The transport header at the start of every packet
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Header {
+    pub dest: u8,
+    pub src: u8,
+    /// Start of message
+    pub som: bool,
+    /// End of message
+    pub eom: bool,
+    /// Packet sequence number, modulo 4
+    pub seq: u8,
+    /// Tag owner: set on requests, and clear on their responses
+    pub tag_owner: bool,
+    pub tag: u8,
+}
+
+impl Header {
+    const SOM: u8 = 1 << 7;
+    const EOM: u8 = 1 << 6;
+    const TO: u8 = 1 << 3;
+
+    /// Splits `packet` into its header and payload.
+    pub fn parse(packet: &[u8]) -> Result<(Self, &[u8]), Error> {
+        let (hdr, payload) = packet
+            .split_first_chunk::<HEADER_SIZE>()
+            .ok_or(Error::Truncated)?;
+        if hdr[0] & 0xF != HEADER_VERSION {
+            return Err(Error::BadVersion);
+        }
+        let flags = hdr[3];
+        Ok((
+            Self {
+                dest: hdr[1],
+                src: hdr[2],
+                som: flags & Self::SOM != 0,
+                eom: flags & Self::EOM != 0,
+                seq: (flags >> 4) & 0x3,
+                tag_owner: flags & Self::TO != 0,
+                tag: flags & 0x7,
+            },
+            payload,
+        ))
+    }
+
+    pub fn encode(&self) -> [u8; HEADER_SIZE] {
+        let mut flags = ((self.seq & 0x3) << 4) | (self.tag & 0x7);
+        if self.som {
+            flags |= Self::SOM;
+        }
+        if self.eom {
+            flags |= Self::EOM;
+        }
+        if self.tag_owner {
+            flags |= Self::TO;
+        }
+        [HEADER_VERSION, self.dest, self.src, flags]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header() {
+        let hdr = Header {
+            dest: 0x1D,
+            src: 0x08,
+            som: true,
+            eom: false,
+            seq: 2,
+            tag_owner: true,
+            tag: 5,
+        };
+        let bytes = hdr.encode();
+        assert_eq!(bytes, [0x01, 0x1D, 0x08, 0xAD]);
+
+        let mut packet = bytes.to_vec();
+        packet.push(0x05);
+        assert_eq!(Header::parse(&packet), Ok((hdr, &[0x05][..])));
+
+        assert_eq!(Header::parse(&bytes[..3]), Err(Error::Truncated));
+        assert_eq!(
+            Header::parse(&[0x02, 0x1D, 0x08, 0xC8]),
+            Err(Error::BadVersion)
+        );
+    }
+}
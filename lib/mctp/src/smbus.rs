@@ -0,0 +1,362 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! The SMBus/I2C transport binding (DMTF DSP0237).
+//!
+//! Each packet travels as an SMBus block write from the sender, acting as
+//! controller, to the receiver, acting as target:
+//!
+//! | Byte  | Contents                                          |
+//! |-------|---------------------------------------------------|
+//! | 0     | Destination slave address, write bit (0)          |
+//! | 1     | Command code: `0x0F` for MCTP                     |
+//! | 2     | Byte count: everything after this, less the PEC   |
+//! | 3     | Source slave address, with bit 0 set              |
+//! | 4..8  | MCTP transport header                             |
+//! | 8..   | Payload                                           |
+//! | last  | PEC: CRC-8 over everything before it              |
+//!
+//! The controller sends the destination address as part of addressing the
+//! target, so the frames here start at the command code; that's what a
+//! target receives, and what a controller hands to an I2C write. The source
+//! address is how the receiver knows where to send responses.
+//!
+//! [`handle`] puts this together with an [`Endpoint`]: it takes in the frames
+//! written to a target, and writes the responses back to their senders.
+
+use crate::{msg_type, Endpoint, Error, Message, BASELINE_MTU, HEADER_SIZE};
+
+/// Command code for MCTP
+pub const COMMAND_CODE: u8 = 0x0F;
+
+/// Bytes of framing around a packet: command code, byte count, source
+/// address and PEC
+pub const FRAME_OVERHEAD: usize = 4;
+
+/// The largest frame that carries a baseline packet
+pub const MAX_FRAME_SIZE: usize = FRAME_OVERHEAD + HEADER_SIZE + BASELINE_MTU;
+
+const PEC: crc::Crc<u8> = crc::Crc::<u8>::new(&crc::CRC_8_SMBUS);
+
+/// Computes the PEC of a frame headed to the 7-bit `dest`, whose bytes
+/// (from the command code onwards) are `frame`.
+fn pec(dest: u8, frame: &[u8]) -> u8 {
+    let mut digest = PEC.digest();
+    digest.update(&[dest << 1]);
+    digest.update(frame);
+    digest.finalize()
+}
+
+/// Wraps `packet` in a frame from the 7-bit address `src` to `dest`, which
+/// is written into `out`; returns the length of the frame.
+pub fn encode(
+    dest: u8,
+    src: u8,
+    packet: &[u8],
+    out: &mut [u8],
+) -> Result<usize, Error> {
+    let len = packet.len() + FRAME_OVERHEAD;
+    if packet.len() > u8::MAX as usize - 1 {
+        return Err(Error::TooLarge);
+    }
+    let frame = out.get_mut(..len).ok_or(Error::TooLarge)?;
+    frame[0] = COMMAND_CODE;
+    frame[1] = (packet.len() + 1) as u8;
+    frame[2] = (src << 1) | 1;
+    frame[3..len - 1].copy_from_slice(packet);
+    frame[len - 1] = pec(dest, &frame[..len - 1]);
+    Ok(len)
+}
+
+/// Unwraps a frame that was received at the 7-bit address `dest`, returning
+/// the 7-bit address that sent it, and the packet that it carries.
+pub fn decode(dest: u8, frame: &[u8]) -> Result<(u8, &[u8]), Error> {
+    let [cmd, count, src, ..] = *frame else {
+        return Err(Error::Truncated);
+    };
+    if cmd != COMMAND_CODE || src & 1 == 0 {
+        return Err(Error::BadFrame);
+    }
+    let len = count as usize + 3;
+    if frame.len() < len {
+        return Err(Error::Truncated);
+    }
+    if pec(dest, &frame[..len - 1]) != frame[len - 1] {
+        return Err(Error::BadPec);
+    }
+    Ok((src >> 1, &frame[3..len - 1]))
+}
+
+/// A response that [`handle`] sent
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Sent {
+    /// The EID that it went to
+    pub dest: u8,
+    /// Size of its body, after the message type
+    pub len: usize,
+}
+
+/// Why [`handle`] didn't get to the end of a frame
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Failure<E> {
+    /// The frame was dropped, along with any message that it was part of
+    Dropped(Error),
+    /// The response couldn't be written
+    Write(E),
+}
+
+/// Takes in one `frame` received at the 7-bit `address`, and answers the
+/// message that it completes, if any.
+///
+/// Control messages are answered by `endpoint`, and everything else by
+/// `respond`, which is given the message and its body, writes the response
+/// body into the buffer that it's passed, and returns its length (or `None`
+/// to leave the message unanswered). The response goes back to the address
+/// that the frame came from, one frame at a time, through `write`.
+pub fn handle<const N: usize, E>(
+    address: u8,
+    endpoint: &mut Endpoint<N>,
+    frame: &[u8],
+    response: &mut [u8],
+    respond: impl FnOnce(&Message, &[u8], &mut [u8]) -> Option<usize>,
+    mut write: impl FnMut(u8, &[u8]) -> Result<(), E>,
+) -> Result<Option<Sent>, Failure<E>> {
+    let (peer, packet) = decode(address, frame).map_err(Failure::Dropped)?;
+    let Some(msg) = endpoint.recv(packet).map_err(Failure::Dropped)? else {
+        return Ok(None);
+    };
+
+    let len = match msg.msg_type {
+        msg_type::CONTROL => endpoint.control(&msg, response),
+        _ => respond(&msg, endpoint.body(&msg), response),
+    };
+    let Some(len) = len else {
+        return Ok(None);
+    };
+    let body = response
+        .get(..len)
+        .ok_or(Failure::Dropped(Error::TooLarge))?;
+
+    let mut out = [0u8; MAX_FRAME_SIZE];
+    endpoint.reply(&msg, body, |packet| {
+        let n = encode(peer, address, packet, &mut out)
+            .map_err(Failure::Dropped)?;
+        write(peer, &out[..n]).map_err(Failure::Write)
+    })?;
+    Ok(Some(Sent { dest: msg.src, len }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const US: u8 = 0x42;
+    const THEM: u8 = 0x10;
+
+    #[test]
+    fn round_trip() {
+        let packet = [0x01, 0x1D, 0x08, 0xC8, 0x05, 0x10, 0x84, 0x00, 0x00];
+        let mut frame = [0; MAX_FRAME_SIZE];
+        let n = encode(US, THEM, &packet, &mut frame).unwrap();
+        assert_eq!(n, packet.len() + FRAME_OVERHEAD);
+        assert_eq!(frame[..3], [COMMAND_CODE, 10, 0x21]);
+
+        // The PEC covers the destination address, which isn't in the frame.
+        let mut all = vec![US << 1];
+        all.extend_from_slice(&frame[..n]);
+        assert_eq!(PEC.checksum(&all), 0);
+
+        assert_eq!(decode(US, &frame[..n]), Ok((THEM, &packet[..])));
+        assert_eq!(decode(US + 1, &frame[..n]), Err(Error::BadPec));
+    }
+
+    #[test]
+    fn bad_frames() {
+        let mut frame = [0; MAX_FRAME_SIZE];
+        let n =
+            encode(US, THEM, &[0x01, 0x1D, 0x08, 0xC8], &mut frame).unwrap();
+
+        assert_eq!(decode(US, &frame[..n - 1]), Err(Error::Truncated));
+        assert_eq!(decode(US, &frame[..2]), Err(Error::Truncated));
+
+        let mut f = frame;
+        f[n - 2] ^= 1;
+        assert_eq!(decode(US, &f[..n]), Err(Error::BadPec));
+
+        let mut f = frame;
+        f[0] = 0x0E;
+        assert_eq!(decode(US, &f[..n]), Err(Error::BadFrame));
+
+        assert_eq!(
+            encode(US, THEM, &[0; 16], &mut [0; 16]),
+            Err(Error::TooLarge)
+        );
+    }
+
+    const EID: u8 = 0x1D;
+    const REQUESTER_EID: u8 = 0x08;
+
+    /// Frames a single-packet request from them to us, with tag 5.
+    fn request(payload: &[u8]) -> Vec<u8> {
+        let mut packet = vec![0x01, EID, REQUESTER_EID, 0xCD];
+        packet.extend_from_slice(payload);
+        let mut frame = [0; MAX_FRAME_SIZE];
+        let n = encode(US, THEM, &packet, &mut frame).unwrap();
+        frame[..n].to_vec()
+    }
+
+    type Handled = Result<Option<Sent>, Failure<()>>;
+
+    /// Handles `frame`, answering everything but control messages with
+    /// `reply`, and returns the packets written back to `THEM`.
+    fn handle_with(
+        endpoint: &mut Endpoint<256>,
+        frame: &[u8],
+        reply: &[u8],
+    ) -> (Handled, Vec<Vec<u8>>) {
+        let mut response = [0; 256];
+        let mut written = vec![];
+        let r = handle(
+            US,
+            endpoint,
+            frame,
+            &mut response,
+            |msg, body, out| {
+                assert_eq!(msg.msg_type, msg_type::SPDM);
+                assert_eq!(body, &[0x10, 0x84, 0x00, 0x00]);
+                out[..reply.len()].copy_from_slice(reply);
+                (!reply.is_empty()).then_some(reply.len())
+            },
+            |dest, frame| {
+                assert_eq!(dest, THEM);
+                let (src, packet) = decode(THEM, frame).unwrap();
+                assert_eq!(src, US);
+                written.push(packet.to_vec());
+                Ok(())
+            },
+        );
+        (r, written)
+    }
+
+    fn endpoint() -> Endpoint<256> {
+        Endpoint::new(Some(EID), &[msg_type::SPDM])
+    }
+
+    #[test]
+    fn handle_request() {
+        let mut ep = endpoint();
+        let frame = request(&[0x05, 0x10, 0x84, 0x00, 0x00]);
+        let (r, written) = handle_with(&mut ep, &frame, &[0x10, 0x04, 0, 0]);
+        assert_eq!(
+            r,
+            Ok(Some(Sent {
+                dest: REQUESTER_EID,
+                len: 4
+            }))
+        );
+        // SOM, EOM and the request's tag, without TO
+        assert_eq!(
+            written,
+            [[0x01, REQUESTER_EID, EID, 0xC5, 0x05, 0x10, 0x04, 0, 0]]
+        );
+    }
+
+    #[test]
+    fn handle_long_response() {
+        let mut ep = endpoint();
+        let frame = request(&[0x05, 0x10, 0x84, 0x00, 0x00]);
+        let reply: Vec<u8> = (0..100).collect();
+        let (r, written) = handle_with(&mut ep, &frame, &reply);
+        assert_eq!(
+            r,
+            Ok(Some(Sent {
+                dest: REQUESTER_EID,
+                len: 100
+            }))
+        );
+
+        // The message type and 63 bytes, then the other 37
+        assert_eq!(written.len(), 2);
+        assert_eq!(written[0][..5], [0x01, REQUESTER_EID, EID, 0x85, 0x05]);
+        assert_eq!(written[0][5..], reply[..63]);
+        assert_eq!(written[1][..4], [0x01, REQUESTER_EID, EID, 0x55]);
+        assert_eq!(written[1][4..], reply[63..]);
+    }
+
+    #[test]
+    fn handle_control() {
+        let mut ep = endpoint();
+
+        // Get Endpoint ID, which never reaches the SPDM side
+        let frame = request(&[0x00, 0x81, 0x02]);
+        let (r, written) = handle_with(&mut ep, &frame, &[]);
+        assert_eq!(
+            r,
+            Ok(Some(Sent {
+                dest: REQUESTER_EID,
+                len: 6
+            }))
+        );
+        assert_eq!(
+            written,
+            [[
+                0x01,
+                REQUESTER_EID,
+                EID,
+                0xC5,
+                0x00,
+                0x01,
+                0x02,
+                0x00,
+                EID,
+                0b10,
+                0
+            ]]
+        );
+    }
+
+    #[test]
+    fn handle_nothing() {
+        let mut ep = endpoint();
+        let frame = request(&[0x05, 0x10, 0x84, 0x00, 0x00]);
+
+        // No response to send
+        let (r, written) = handle_with(&mut ep, &frame, &[]);
+        assert_eq!(r, Ok(None));
+        assert!(written.is_empty());
+
+        // The first of two packets
+        let mut packet = vec![0x01, EID, REQUESTER_EID, 0x8D, 0x05];
+        packet.extend_from_slice(&[0; BASELINE_MTU - 1]);
+        let mut frame = [0; MAX_FRAME_SIZE];
+        let n = encode(US, THEM, &packet, &mut frame).unwrap();
+        let (r, written) = handle_with(&mut ep, &frame[..n], &[1]);
+        assert_eq!(r, Ok(None));
+        assert!(written.is_empty());
+    }
+
+    #[test]
+    fn handle_failures() {
+        let mut ep = endpoint();
+        let mut frame = request(&[0x05, 0x10, 0x84, 0x00, 0x00]);
+        let (r, _) = handle_with(&mut ep, &frame[..3], &[1]);
+        assert_eq!(r, Err(Failure::Dropped(Error::Truncated)));
+
+        *frame.last_mut().unwrap() ^= 1;
+        let (r, written) = handle_with(&mut ep, &frame, &[1]);
+        assert_eq!(r, Err(Failure::Dropped(Error::BadPec)));
+        assert!(written.is_empty());
+
+        let frame = request(&[0x05, 0x10, 0x84, 0x00, 0x00]);
+        let r = handle(
+            US,
+            &mut ep,
+            &frame,
+            &mut [0; 8],
+            |_, _, _| Some(2),
+            |_, _| Err("nak"),
+        );
+        assert_eq!(r, Err(Failure::Write("nak")));
+    }
+}
@@ -0,0 +1,204 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Algorithms negotiated by NEGOTIATE_ALGORITHMS.
+//!
+//! Each kind of algorithm is a bitfield, with one bit per algorithm; requests
+//! set every bit that the requester supports, and responses select at most
+//! one.
+
+/// Bits of `BaseHashAlgo` (and `BaseHashSel`)
+pub mod base_hash {
+    pub const SHA_256: u32 = 1 << 0;
+    pub const SHA_384: u32 = 1 << 1;
+    pub const SHA_512: u32 = 1 << 2;
+    pub const SHA3_256: u32 = 1 << 3;
+    pub const SHA3_384: u32 = 1 << 4;
+    pub const SHA3_512: u32 = 1 << 5;
+    pub const SM3_256: u32 = 1 << 6;
+}
+
+/// Bits of `BaseAsymAlgo` (and `BaseAsymSel`), which also serve for
+/// `ReqBaseAsymAlg`
+pub mod base_asym {
+    pub const RSASSA_2048: u32 = 1 << 0;
+    pub const RSAPSS_2048: u32 = 1 << 1;
+    pub const RSASSA_3072: u32 = 1 << 2;
+    pub const RSAPSS_3072: u32 = 1 << 3;
+    pub const ECDSA_P256: u32 = 1 << 4;
+    pub const RSASSA_4096: u32 = 1 << 5;
+    pub const RSAPSS_4096: u32 = 1 << 6;
+    pub const ECDSA_P384: u32 = 1 << 7;
+    pub const ECDSA_P521: u32 = 1 << 8;
+    pub const SM2_P256: u32 = 1 << 9;
+    pub const ED25519: u32 = 1 << 10;
+    pub const ED448: u32 = 1 << 11;
+}
+
+/// Bits of `MeasurementHashAlgo`
+pub mod measurement_hash {
+    pub const RAW_BIT_STREAM: u32 = 1 << 0;
+    pub const SHA_256: u32 = 1 << 1;
+    pub const SHA_384: u32 = 1 << 2;
+    pub const SHA_512: u32 = 1 << 3;
+    pub const SHA3_256: u32 = 1 << 4;
+    pub const SHA3_384: u32 = 1 << 5;
+    pub const SHA3_512: u32 = 1 << 6;
+    pub const SM3_256: u32 = 1 << 7;
+}
+
+/// Bits of the DHE algorithm structure
+pub mod dhe {
+    pub const FFDHE_2048: u16 = 1 << 0;
+    pub const FFDHE_3072: u16 = 1 << 1;
+    pub const FFDHE_4096: u16 = 1 << 2;
+    pub const SECP256R1: u16 = 1 << 3;
+    pub const SECP384R1: u16 = 1 << 4;
+    pub const SECP521R1: u16 = 1 << 5;
+    pub const SM2_P256: u16 = 1 << 6;
+}
+
+/// Bits of the AEAD cipher suite algorithm structure
+pub mod aead {
+    pub const AES_128_GCM: u16 = 1 << 0;
+    pub const AES_256_GCM: u16 = 1 << 1;
+    pub const CHACHA20_POLY1305: u16 = 1 << 2;
+    pub const SM4_GCM: u16 = 1 << 3;
+}
+
+/// Bits of the key schedule algorithm structure
+pub mod key_schedule {
+    pub const SPDM: u16 = 1 << 0;
+}
+
+/// Bits of `MeasurementSpecification`
+pub mod measurement_spec {
+    pub const DMTF: u8 = 1 << 0;
+}
+
+/// Bits of `OtherParamsSupport`
+pub mod other_params {
+    /// Opaque data is in the general format defined by DSP0274
+    pub const OPAQUE_DATA_FMT1: u8 = 1 << 1;
+}
+
+/// Types of the algorithm structures that follow the fixed fields of
+/// NEGOTIATE_ALGORITHMS and ALGORITHMS.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum AlgType {
+    Dhe = 2,
+    Aead = 3,
+    ReqBaseAsym = 4,
+    KeySchedule = 5,
+}
+
+impl AlgType {
+    pub const ALL: [Self; 4] =
+        [Self::Dhe, Self::Aead, Self::ReqBaseAsym, Self::KeySchedule];
+
+    /// This type's bit in a set of algorithm structure types
+    pub fn bit(self) -> u8 {
+        1 << (self as u8)
+    }
+}
+
+/// A set of algorithms, as selected by a responder.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Algorithms {
+    pub measurement_spec: u8,
+    pub other_params: u8,
+    pub measurement_hash: u32,
+    pub base_asym: u32,
+    pub base_hash: u32,
+    pub dhe: u16,
+    pub aead: u16,
+    pub req_base_asym: u16,
+    pub key_schedule: u16,
+}
+
+impl Algorithms {
+    /// Size of a digest using the selected base hash algorithm
+    pub fn hash_size(&self) -> usize {
+        hash_size(self.base_hash)
+    }
+
+    /// Size of a digest using the selected measurement hash algorithm, which
+    /// is zero for raw bit streams.
+    pub fn measurement_hash_size(&self) -> usize {
+        use measurement_hash::*;
+        match self.measurement_hash {
+            SHA_256 | SHA3_256 | SM3_256 => 32,
+            SHA_384 | SHA3_384 => 48,
+            SHA_512 | SHA3_512 => 64,
+            _ => 0,
+        }
+    }
+
+    /// Size of a signature using the selected base asymmetric algorithm
+    pub fn signature_size(&self) -> usize {
+        signature_size(self.base_asym)
+    }
+
+    /// Size of a signature from the requester, using the selected requester
+    /// asymmetric algorithm
+    pub fn req_signature_size(&self) -> usize {
+        signature_size(self.req_base_asym.into())
+    }
+
+    /// Size of a public key exchanged using the selected DHE group
+    pub fn dhe_size(&self) -> usize {
+        use dhe::*;
+        match self.dhe {
+            FFDHE_2048 => 256,
+            FFDHE_3072 => 384,
+            FFDHE_4096 => 512,
+            SECP256R1 | SM2_P256 => 64,
+            SECP384R1 => 96,
+            SECP521R1 => 132,
+            _ => 0,
+        }
+    }
+}
+
+/// Size of a digest using the given base hash algorithm (zero if there isn't
+/// exactly one)
+pub fn hash_size(base_hash: u32) -> usize {
+    use base_hash::*;
+    match base_hash {
+        SHA_256 | SHA3_256 | SM3_256 => 32,
+        SHA_384 | SHA3_384 => 48,
+        SHA_512 | SHA3_512 => 64,
+        _ => 0,
+    }
+}
+
+/// Size of a signature using the given base asymmetric algorithm (zero if
+/// there isn't exactly one)
+pub fn signature_size(base_asym: u32) -> usize {
+    use base_asym::*;
+    match base_asym {
+        RSASSA_2048 | RSAPSS_2048 => 256,
+        RSASSA_3072 | RSAPSS_3072 => 384,
+        RSASSA_4096 | RSAPSS_4096 => 512,
+        ECDSA_P256 | SM2_P256 | ED25519 => 64,
+        ECDSA_P384 => 96,
+        ECDSA_P521 => 132,
+        ED448 => 114,
+        _ => 0,
+    }
+}
+
+/// Picks the first of `preferred` that's also in `offered`, or returns zero if
+/// there's nothing in common.
+pub fn select<T>(offered: T, preferred: &[T]) -> T
+where
+    T: Copy + Default + PartialEq + core::ops::BitAnd<Output = T>,
+{
+    preferred
+        .iter()
+        .copied()
+        .find(|&p| offered & p != T::default())
+        .unwrap_or_default()
+}
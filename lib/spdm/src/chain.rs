@@ -0,0 +1,280 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Certificate chains, in the format that SPDM carries them.
+//!
+//! A chain, as returned by GET_CERTIFICATE and provisioned by
+//! SET_CERTIFICATE, is a four-byte header (the chain's total length, and two
+//! reserved bytes), the hash of the root certificate, and then the
+//! certificates themselves in DER, root-most first and leaf last. The digest
+//! of a slot, in DIGESTS and in session transcripts, is the hash of all of
+//! that.
+//!
+//! We take apart only as much DER as it takes to split a chain into its
+//! certificates and find the leaf's public key. Checking signatures is up to
+//! whoever has reason to trust the chain.
+
+use crate::{ErrorCode, Reader, Writer};
+
+/// Size of the header before the root hash
+pub const PREFIX_SIZE: usize = 4;
+
+/// DER tags that we look for
+const SEQUENCE: u8 = 0x30;
+const INTEGER: u8 = 0x02;
+/// The explicit `[0]` around a certificate's version
+const VERSION: u8 = 0xA0;
+
+/// A chain, taken apart.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Chain<'a> {
+    pub root_hash: &'a [u8],
+    /// The DER certificates, one after another
+    pub certs: &'a [u8],
+}
+
+impl<'a> Chain<'a> {
+    /// Parses `chain`, with a root hash of `hash_size` bytes, checking that
+    /// its length is right and that it has at least one certificate, all of
+    /// them well-formed.
+    pub fn parse(chain: &'a [u8], hash_size: usize) -> Result<Self, ErrorCode> {
+        let mut r = Reader::new(chain);
+        let length = r.u16()?;
+        let _reserved = r.u16()?;
+        if usize::from(length) != chain.len() {
+            return Err(ErrorCode::InvalidRequest);
+        }
+        let root_hash = r.bytes(hash_size)?;
+        let certs = r.bytes(r.remaining())?;
+        if certs.is_empty() {
+            return Err(ErrorCode::InvalidRequest);
+        }
+        for cert in certificates(certs) {
+            cert?;
+        }
+        Ok(Self { root_hash, certs })
+    }
+
+    /// Returns the first certificate, which is the root or was issued by it.
+    pub fn first(&self) -> &'a [u8] {
+        // `parse` checked that there's at least one, and all are good.
+        certificates(self.certs).next().unwrap().unwrap()
+    }
+
+    /// Returns the leaf certificate.
+    pub fn leaf(&self) -> &'a [u8] {
+        certificates(self.certs).last().unwrap().unwrap()
+    }
+}
+
+/// Encodes the header and root hash of a chain whose certificates take
+/// `certs_len` bytes.
+pub fn header(
+    w: &mut Writer<'_>,
+    root_hash: &[u8],
+    certs_len: usize,
+) -> Result<(), ErrorCode> {
+    let length = PREFIX_SIZE + root_hash.len() + certs_len;
+    w.u16(length.try_into().map_err(|_| ErrorCode::ResponseTooLarge)?)?;
+    w.u16(0)?;
+    w.bytes(root_hash)
+}
+
+/// Returns each of the DER certificates in `certs`, or an error (after which
+/// there's nothing more) if what's left isn't one.
+pub fn certificates(
+    certs: &[u8],
+) -> impl Iterator<Item = Result<&[u8], ErrorCode>> {
+    let mut rest = certs;
+    core::iter::from_fn(move || {
+        if rest.is_empty() {
+            return None;
+        }
+        let cert = subject_public_key_info(rest).and_then(|_| {
+            let (_, _, tail) = tlv(rest)?;
+            Ok(&rest[..rest.len() - tail.len()])
+        });
+        rest = match cert {
+            Ok(cert) => &rest[cert.len()..],
+            Err(_) => &[],
+        };
+        Some(cert)
+    })
+}
+
+/// Returns the `SubjectPublicKeyInfo` of the certificate at the start of
+/// `cert`, in DER, which is how the key would be compared against one that
+/// we know.
+pub fn subject_public_key_info(cert: &[u8]) -> Result<&[u8], ErrorCode> {
+    let (tag, cert, _) = tlv(cert)?;
+    expect(tag, SEQUENCE)?;
+    let (tag, tbs, _) = tlv(cert)?;
+    expect(tag, SEQUENCE)?;
+
+    let (mut tag, _, mut rest) = tlv(tbs)?;
+    if tag == VERSION {
+        (tag, _, rest) = tlv(rest)?;
+    }
+    expect(tag, INTEGER)?;
+    // Signature algorithm, issuer, validity, and subject come before the
+    // key.
+    for _ in 0..4 {
+        let (tag, _, tail) = tlv(rest)?;
+        expect(tag, SEQUENCE)?;
+        rest = tail;
+    }
+    let (tag, _, tail) = tlv(rest)?;
+    expect(tag, SEQUENCE)?;
+    Ok(&rest[..rest.len() - tail.len()])
+}
+
+fn expect(tag: u8, expected: u8) -> Result<(), ErrorCode> {
+    if tag == expected {
+        Ok(())
+    } else {
+        Err(ErrorCode::InvalidRequest)
+    }
+}
+
+/// Splits the DER element at the start of `der` into its tag and contents,
+/// and returns what comes after it.
+///
+/// Only low tag numbers, and lengths of up to two bytes, are accepted: nothing
+/// in an SPDM chain needs more, since the chain's own length is 16 bits.
+fn tlv(der: &[u8]) -> Result<(u8, &[u8], &[u8]), ErrorCode> {
+    let mut r = Reader::new(der);
+    let tag = r.u8()?;
+    if tag & 0x1F == 0x1F {
+        return Err(ErrorCode::InvalidRequest);
+    }
+    let len = match r.u8()? {
+        n @ 0..=0x7F => usize::from(n),
+        0x81 => usize::from(r.u8()?),
+        0x82 => usize::from(u16::from_be_bytes(*r.array()?)),
+        // Indefinite lengths aren't DER, and anything longer is too long.
+        _ => return Err(ErrorCode::InvalidRequest),
+    };
+    let contents = r.bytes(len)?;
+    Ok((tag, contents, r.bytes(r.remaining())?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn der(tag: u8, contents: &[u8]) -> Vec<u8> {
+        let mut out = vec![tag];
+        match contents.len() {
+            n @ 0..=0x7F => out.push(n as u8),
+            n @ 0x80..=0xFF => out.extend([0x81, n as u8]),
+            n => out.extend([0x82, (n >> 8) as u8, n as u8]),
+        }
+        out.extend(contents);
+        out
+    }
+
+    fn seq(parts: &[&[u8]]) -> Vec<u8> {
+        der(SEQUENCE, &parts.concat())
+    }
+
+    /// An Ed25519 key, as it appears in a certificate
+    fn spki(key: u8) -> Vec<u8> {
+        let alg = seq(&[&der(0x06, &[0x2B, 0x65, 0x70])]);
+        let mut bits = vec![0];
+        bits.extend([key; 32]);
+        seq(&[&alg, &der(0x03, &bits)])
+    }
+
+    /// A certificate, in shape if not in substance, with the given common
+    /// name and key
+    fn cert(name: &str, key: u8) -> Vec<u8> {
+        v1_cert(name, key, &der(VERSION, &der(INTEGER, &[2])))
+    }
+
+    /// A certificate that starts with `version`, which is empty for version
+    /// 1 certificates
+    fn v1_cert(name: &str, key: u8, version: &[u8]) -> Vec<u8> {
+        let alg = seq(&[&der(0x06, &[0x2B, 0x65, 0x70])]);
+        let name = seq(&[&der(0x31, &seq(&[&der(0x0C, name.as_bytes())]))]);
+        let tbs = seq(&[
+            version,
+            &der(INTEGER, &[0x01, 0x23]),
+            &alg,
+            &name,
+            &seq(&[&der(0x17, b"240101000000Z"), &der(0x18, b"99991231")]),
+            &name,
+            &spki(key),
+        ]);
+        seq(&[&tbs, &alg, &der(0x03, &[0; 65])])
+    }
+
+    fn chain(certs: &[Vec<u8>]) -> Vec<u8> {
+        let certs = certs.concat();
+        let mut buf = vec![0; 1024];
+        let mut w = Writer::new(&mut buf);
+        header(&mut w, &[0xAA; 32], certs.len()).unwrap();
+        let mut out = w.written().to_vec();
+        out.extend(certs);
+        out
+    }
+
+    #[test]
+    fn parse() {
+        let root = cert("root", 1);
+        // Long enough for a two-byte length
+        let leaf = cert(&"leaf".repeat(80), 2);
+        let bytes = chain(&[root.clone(), leaf.clone()]);
+        assert_eq!(
+            u16::from_le_bytes([bytes[0], bytes[1]]) as usize,
+            bytes.len()
+        );
+
+        let chain = Chain::parse(&bytes, 32).unwrap();
+        assert_eq!(chain.root_hash, &[0xAA; 32]);
+        assert_eq!(chain.first(), root);
+        assert_eq!(chain.leaf(), leaf);
+        assert_eq!(subject_public_key_info(chain.leaf()), Ok(&spki(2)[..]));
+        assert_eq!(certificates(chain.certs).count(), 2);
+    }
+
+    #[test]
+    fn no_version() {
+        // A version 1 certificate has no version field.
+        let cert = v1_cert("v1", 3, &[]);
+        assert_eq!(subject_public_key_info(&cert), Ok(&spki(3)[..]));
+    }
+
+    #[test]
+    fn malformed() {
+        let good = chain(&[cert("root", 1)]);
+        assert!(Chain::parse(&good, 32).is_ok());
+        // The root hash is the wrong size.
+        assert_eq!(Chain::parse(&good, 48), Err(ErrorCode::InvalidRequest));
+
+        // The length must match.
+        let mut bad = good.clone();
+        bad.push(0);
+        assert_eq!(Chain::parse(&bad, 32), Err(ErrorCode::InvalidRequest));
+
+        // So must the certificate's own.
+        let mut bad = good.clone();
+        bad.truncate(bad.len() - 1);
+        bad[0] -= 1;
+        assert_eq!(Chain::parse(&bad, 32), Err(ErrorCode::InvalidRequest));
+
+        // There must be a certificate.
+        let empty = chain(&[]);
+        assert_eq!(Chain::parse(&empty, 32), Err(ErrorCode::InvalidRequest));
+
+        // Anything after the last certificate must be another.
+        let bad = chain(&[cert("root", 1), vec![0x30, 0x00]]);
+        assert_eq!(Chain::parse(&bad, 32), Err(ErrorCode::InvalidRequest));
+
+        // Indefinite lengths aren't DER.
+        assert_eq!(
+            subject_public_key_info(&[0x30, 0x80, 0x00, 0x00]),
+            Err(ErrorCode::InvalidRequest)
+        );
+    }
+}
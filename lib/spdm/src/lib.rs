@@ -0,0 +1,190 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! SPDM 1.2 (DMTF DSP0274) messages, as seen by a responder.
+//!
+//! This crate decodes requests from their wire format, encodes responses into
+//! theirs, and keeps the transcripts that responses are signed over. Beyond
+//! hashing transcripts (with whichever `Digest` the caller provides), its only
+//! cryptography is that of secure sessions: the key schedule, and the AEAD
+//! that protects secured messages, in [`session`]. It holds no state between
+//! messages other than a session's keys; sequencing the protocol is up to the
+//! responder.
+//!
+//! Every failure is reported as the SPDM `ErrorCode` that the responder should
+//! send back, so that a caller can turn it straight into an ERROR response.
+
+#![cfg_attr(not(test), no_std)]
+
+pub mod algo;
+pub mod chain;
+pub mod measurement;
+pub mod opaque;
+pub mod request;
+pub mod response;
+pub mod session;
+pub mod transcript;
+mod wire;
+
+#[cfg(test)]
+mod replay;
+
+pub use request::Request;
+pub use wire::{Reader, Writer};
+
+/// Size of the header at the start of every message: version, request or
+/// response code, and two parameters.
+pub const HEADER_SIZE: usize = 4;
+
+/// Size of the nonces in CHALLENGE, GET_MEASUREMENTS and their responses.
+pub const NONCE_SIZE: usize = 32;
+
+/// Size of the random data in KEY_EXCHANGE and its response.
+pub const RANDOM_SIZE: usize = 32;
+
+/// Number of certificate slots a responder may have.
+pub const SLOT_COUNT: u8 = 8;
+
+/// An SPDM protocol version, as found in the first byte of every message
+/// (major version in the high nibble, minor version in the low).
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Version(pub u8);
+
+impl Version {
+    pub const V1_0: Self = Self(0x10);
+    pub const V1_1: Self = Self(0x11);
+    pub const V1_2: Self = Self(0x12);
+
+    /// Returns this version as a `VersionNumberEntry`, as listed in a VERSION
+    /// response (with update and alpha versions of zero).
+    pub fn number_entry(self) -> u16 {
+        u16::from(self.0) << 8
+    }
+}
+
+/// Request codes, from the second byte of a request.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum RequestCode {
+    GetDigests = 0x81,
+    GetCertificate = 0x82,
+    Challenge = 0x83,
+    GetVersion = 0x84,
+    GetMeasurements = 0xE0,
+    GetCapabilities = 0xE1,
+    NegotiateAlgorithms = 0xE3,
+    KeyExchange = 0xE4,
+    Finish = 0xE5,
+    PskExchange = 0xE6,
+    PskFinish = 0xE7,
+    KeyUpdate = 0xE9,
+    EndSession = 0xEC,
+    SetCertificate = 0xEE,
+}
+
+impl TryFrom<u8> for RequestCode {
+    type Error = ErrorCode;
+
+    fn try_from(code: u8) -> Result<Self, ErrorCode> {
+        Ok(match code {
+            0x81 => Self::GetDigests,
+            0x82 => Self::GetCertificate,
+            0x83 => Self::Challenge,
+            0x84 => Self::GetVersion,
+            0xE0 => Self::GetMeasurements,
+            0xE1 => Self::GetCapabilities,
+            0xE3 => Self::NegotiateAlgorithms,
+            0xE4 => Self::KeyExchange,
+            0xE5 => Self::Finish,
+            0xE6 => Self::PskExchange,
+            0xE7 => Self::PskFinish,
+            0xE9 => Self::KeyUpdate,
+            0xEC => Self::EndSession,
+            0xEE => Self::SetCertificate,
+            _ => return Err(ErrorCode::UnsupportedRequest),
+        })
+    }
+}
+
+/// Response codes, from the second byte of a response.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ResponseCode {
+    Digests = 0x01,
+    Certificate = 0x02,
+    ChallengeAuth = 0x03,
+    Version = 0x04,
+    Measurements = 0x60,
+    Capabilities = 0x61,
+    Algorithms = 0x63,
+    KeyExchangeRsp = 0x64,
+    FinishRsp = 0x65,
+    PskExchangeRsp = 0x66,
+    PskFinishRsp = 0x67,
+    KeyUpdateAck = 0x69,
+    EndSessionAck = 0x6C,
+    SetCertificateRsp = 0x6E,
+    Error = 0x7F,
+}
+
+/// Error codes, as sent in the first parameter of an ERROR response.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ErrorCode {
+    InvalidRequest = 0x01,
+    Busy = 0x03,
+    UnexpectedRequest = 0x04,
+    Unspecified = 0x05,
+    DecryptError = 0x06,
+    UnsupportedRequest = 0x07,
+    RequestInFlight = 0x08,
+    InvalidResponseCode = 0x09,
+    SessionLimitExceeded = 0x0A,
+    SessionRequired = 0x0B,
+    ResetRequired = 0x0C,
+    ResponseTooLarge = 0x0D,
+    RequestTooLarge = 0x0E,
+    LargeResponse = 0x0F,
+    MessageLost = 0x10,
+    VersionMismatch = 0x41,
+    ResponseNotReady = 0x42,
+    RequestResynch = 0x43,
+    OperationFailed = 0x44,
+}
+
+/// Flags in GET_CAPABILITIES and CAPABILITIES messages.
+pub mod capabilities {
+    pub const CACHE: u32 = 1 << 0;
+    pub const CERT: u32 = 1 << 1;
+    pub const CHAL: u32 = 1 << 2;
+    /// Measurements without signatures
+    pub const MEAS_NO_SIG: u32 = 0b01 << 3;
+    /// Measurements with (optional) signatures
+    pub const MEAS_SIG: u32 = 0b10 << 3;
+    pub const MEAS_FRESH: u32 = 1 << 5;
+    pub const ENCRYPT: u32 = 1 << 6;
+    pub const MAC: u32 = 1 << 7;
+    pub const MUT_AUTH: u32 = 1 << 8;
+    pub const KEY_EX: u32 = 1 << 9;
+    /// Pre-shared keys, without responder context
+    pub const PSK: u32 = 0b01 << 10;
+    /// Pre-shared keys, with responder context
+    pub const PSK_WITH_CONTEXT: u32 = 0b10 << 10;
+    pub const ENCAP: u32 = 1 << 12;
+    pub const HBEAT: u32 = 1 << 13;
+    pub const KEY_UPD: u32 = 1 << 14;
+    pub const HANDSHAKE_IN_THE_CLEAR: u32 = 1 << 15;
+    pub const PUB_KEY_ID: u32 = 1 << 16;
+    pub const CHUNK: u32 = 1 << 17;
+    pub const ALIAS_CERT: u32 = 1 << 18;
+    pub const SET_CERT: u32 = 1 << 19;
+}
+
+/// Which measurement summary hash a CHALLENGE or KEY_EXCHANGE asks for, from
+/// the request's first parameter.
+pub mod measurement_summary {
+    pub const NONE: u8 = 0x00;
+    pub const TCB: u8 = 0x01;
+    pub const ALL: u8 = 0xFF;
+}
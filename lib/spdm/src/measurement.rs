@@ -0,0 +1,152 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Measurement blocks, in the format of the DMTF measurement specification.
+//!
+//! The record in a MEASUREMENTS response is a series of blocks. Each is an
+//! index, the measurement specification, and the size of what follows: the
+//! DMTF measurement, which is a value type, the size of the value, and then
+//! the value, either a digest or a raw bit stream.
+
+use crate::algo::measurement_spec;
+use crate::{ErrorCode, Writer};
+
+/// Size of a block, less its value
+pub const BLOCK_HEADER_SIZE: usize = 7;
+
+/// Values of `DMTFSpecMeasurementValueType`
+pub mod value_type {
+    pub const IMMUTABLE_ROM: u8 = 0x00;
+    pub const MUTABLE_FIRMWARE: u8 = 0x01;
+    pub const HARDWARE_CONFIG: u8 = 0x02;
+    pub const FIRMWARE_CONFIG: u8 = 0x03;
+    pub const FREEFORM_MANIFEST: u8 = 0x04;
+    /// The structured debug and device mode, as `DeviceMode` encodes it
+    pub const DEVICE_MODE: u8 = 0x05;
+    pub const FIRMWARE_VERSION: u8 = 0x06;
+    pub const FIRMWARE_SVN: u8 = 0x07;
+    /// Set on any of the above if the value is a raw bit stream, rather than
+    /// a digest
+    pub const RAW_BIT_STREAM: u8 = 1 << 7;
+}
+
+/// Encodes a block with the measurement at `index`.
+pub fn block(
+    w: &mut Writer<'_>,
+    index: u8,
+    value_type: u8,
+    value: &[u8],
+) -> Result<(), ErrorCode> {
+    let value_size = u16::try_from(value.len())
+        .ok()
+        .filter(|&n| n <= u16::MAX - 3)
+        .ok_or(ErrorCode::ResponseTooLarge)?;
+    w.u8(index)?;
+    w.u8(measurement_spec::DMTF)?;
+    w.u16(value_size + 3)?;
+    w.u8(value_type)?;
+    w.u16(value_size)?;
+    w.bytes(value)
+}
+
+/// The structured debug and device mode, which is always a raw bit stream.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct DeviceMode {
+    /// Operational modes that the device can report; see `operational_mode`
+    pub operational_mode_capabilities: u32,
+    /// The operational mode that it's in
+    pub operational_mode_state: u32,
+    /// Debug modes that the device can report; see `device_mode`
+    pub device_mode_capabilities: u32,
+    /// The debug modes that are, or have been, active
+    pub device_mode_state: u32,
+}
+
+impl DeviceMode {
+    pub const SIZE: usize = 16;
+
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut out = [0; Self::SIZE];
+        for (chunk, v) in out.chunks_exact_mut(4).zip([
+            self.operational_mode_capabilities,
+            self.operational_mode_state,
+            self.device_mode_capabilities,
+            self.device_mode_state,
+        ]) {
+            chunk.copy_from_slice(&v.to_le_bytes());
+        }
+        out
+    }
+}
+
+/// Bits of `DeviceMode`'s operational mode fields
+pub mod operational_mode {
+    pub const MANUFACTURING: u32 = 1 << 0;
+    pub const VALIDATION: u32 = 1 << 1;
+    pub const NORMAL: u32 = 1 << 2;
+    pub const RECOVERY: u32 = 1 << 3;
+    pub const RMA: u32 = 1 << 4;
+    pub const DECOMMISSIONED: u32 = 1 << 5;
+}
+
+/// Bits of `DeviceMode`'s device mode fields
+pub mod device_mode {
+    pub const NON_INVASIVE_DEBUG_ACTIVE: u32 = 1 << 0;
+    pub const INVASIVE_DEBUG_ACTIVE: u32 = 1 << 1;
+    pub const NON_INVASIVE_DEBUG_THIS_RESET: u32 = 1 << 2;
+    pub const INVASIVE_DEBUG_THIS_RESET: u32 = 1 << 3;
+    pub const INVASIVE_DEBUG_SINCE_MANUFACTURING: u32 = 1 << 4;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn digest_block() {
+        let mut buf = [0; 64];
+        let mut w = Writer::new(&mut buf);
+        block(&mut w, 1, value_type::MUTABLE_FIRMWARE, &[0xAB; 32]).unwrap();
+        let out = w.written();
+        assert_eq!(out.len(), BLOCK_HEADER_SIZE + 32);
+        assert_eq!(out[..7], [0x01, 0x01, 0x23, 0x00, 0x01, 0x20, 0x00]);
+        assert_eq!(out[7..], [0xAB; 32]);
+    }
+
+    #[test]
+    fn raw_block() {
+        let mut buf = [0; 64];
+        let mut w = Writer::new(&mut buf);
+        let mode = DeviceMode {
+            operational_mode_capabilities: operational_mode::NORMAL
+                | operational_mode::VALIDATION,
+            operational_mode_state: operational_mode::VALIDATION,
+            ..Default::default()
+        };
+        block(
+            &mut w,
+            5,
+            value_type::DEVICE_MODE | value_type::RAW_BIT_STREAM,
+            &mode.to_bytes(),
+        )
+        .unwrap();
+        assert_eq!(
+            w.written(),
+            [
+                0x05, 0x01, 0x13, 0x00, // index, DMTF, size
+                0x85, 0x10, 0x00, // raw device mode, size
+                0x06, 0, 0, 0, 0x02, 0, 0, 0, // operational mode
+                0, 0, 0, 0, 0, 0, 0, 0, // device mode
+            ]
+        );
+
+        // It must fit.
+        let mut buf = [0; 8];
+        let mut w = Writer::new(&mut buf);
+        assert_eq!(
+            block(&mut w, 1, value_type::FIRMWARE_VERSION, b"1.0"),
+            Err(ErrorCode::ResponseTooLarge)
+        );
+    }
+}
@@ -0,0 +1,138 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Opaque data, in the general format of SPDM 1.2 (`OPAQUE_DATA_FMT1`).
+//!
+//! Opaque data is a list of elements, each tagged with the standards body or
+//! vendor that defines it, and padded to a multiple of four bytes. The only
+//! elements that we understand are those of DSP0277, with which a requester
+//! lists the versions of the secured message format that it speaks (in
+//! KEY_EXCHANGE or PSK_EXCHANGE), and the responder picks one.
+
+use crate::{ErrorCode, Reader, Version};
+
+/// Registry ID of elements defined by the DMTF
+const DMTF: u8 = 0;
+
+/// Version of the secured message element format
+const SM_DATA_VERSION: u8 = 1;
+
+/// Kinds of secured message element
+const VERSION_SELECTION: u8 = 0;
+const SUPPORTED_VERSION_LIST: u8 = 1;
+
+/// Size of opaque data holding a version selection, as encoded by
+/// [`version_selection`]
+pub const VERSION_SELECTION_SIZE: usize = 12;
+
+/// Returns the first of `ours` (in order of preference) that the secured
+/// message version list in `data` has.
+///
+/// It's an error for there to be no version list, or nothing in common.
+pub fn select_secured_version(
+    data: &[u8],
+    ours: &[Version],
+) -> Result<Version, ErrorCode> {
+    let mut r = Reader::new(data);
+    let count = r.u8()?;
+    r.bytes(3)?;
+
+    let mut found = None;
+    for _ in 0..count {
+        let id = r.u8()?;
+        let vendor_len = r.u8()?;
+        r.bytes(vendor_len.into())?;
+        let len = r.u16()?;
+        let element = r.bytes(len.into())?;
+        // Each element is padded out to a multiple of four bytes.
+        let size = 4 + usize::from(vendor_len) + usize::from(len);
+        r.bytes(size.next_multiple_of(4) - size)?;
+
+        if let (DMTF, [SM_DATA_VERSION, SUPPORTED_VERSION_LIST, n, list @ ..]) =
+            (id, element)
+        {
+            let list = list
+                .get(..2 * usize::from(*n))
+                .ok_or(ErrorCode::InvalidRequest)?;
+            found = Some(list);
+        }
+    }
+    let list = found.ok_or(ErrorCode::InvalidRequest)?;
+
+    ours.iter()
+        .copied()
+        .find(|v| {
+            list.chunks_exact(2)
+                .any(|e| u16::from_le_bytes([e[0], e[1]]) >> 8 == v.0.into())
+        })
+        .ok_or(ErrorCode::InvalidRequest)
+}
+
+/// Returns opaque data selecting the secured message `version`.
+pub fn version_selection(version: Version) -> [u8; VERSION_SELECTION_SIZE] {
+    let [lo, hi] = version.number_entry().to_le_bytes();
+    let (sm, kind) = (SM_DATA_VERSION, VERSION_SELECTION);
+    // One element, with no vendor ID, and four bytes of data
+    [1, 0, 0, 0, DMTF, 0, 4, 0, sm, kind, lo, hi]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const V1_0: Version = Version(0x10);
+    const V1_1: Version = Version(0x11);
+
+    // What a libspdm requester sends, offering secured message versions 1.0
+    // and 1.1
+    const SUPPORTED: [u8; 16] = [
+        0x01, 0x00, 0x00, 0x00, // TotalElements, reserved
+        0x00, 0x00, 0x07, 0x00, // ID, VendorLen, OpaqueElementDataLen
+        0x01, 0x01, 0x02, 0x00, 0x10, 0x00, 0x11, // version list
+        0x00, // padding
+    ];
+
+    #[test]
+    fn select() {
+        assert_eq!(select_secured_version(&SUPPORTED, &[V1_1, V1_0]), Ok(V1_1));
+        assert_eq!(select_secured_version(&SUPPORTED, &[V1_0, V1_1]), Ok(V1_0));
+        assert_eq!(
+            select_secured_version(&SUPPORTED, &[Version(0x12)]),
+            Err(ErrorCode::InvalidRequest)
+        );
+
+        // Other elements are skipped over, vendor-defined or otherwise.
+        let mut data = vec![0x03, 0x00, 0x00, 0x00];
+        data.extend([0x0A, 0x02, 0xAB, 0xCD, 0x01, 0x00, 0xEE, 0x00]);
+        data.extend([0x00, 0x00, 0x02, 0x00, 0x01, 0x00, 0x00, 0x11]);
+        data.extend(&SUPPORTED[4..]);
+        assert_eq!(select_secured_version(&data, &[V1_1]), Ok(V1_1));
+
+        assert_eq!(
+            select_secured_version(&[], &[V1_1]),
+            Err(ErrorCode::InvalidRequest)
+        );
+        assert_eq!(
+            select_secured_version(&SUPPORTED[..12], &[V1_1]),
+            Err(ErrorCode::InvalidRequest)
+        );
+    }
+
+    #[test]
+    fn selection() {
+        let data = version_selection(V1_1);
+        assert_eq!(
+            data,
+            [
+                0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x04, 0x00, 0x01, 0x00,
+                0x00, 0x11
+            ]
+        );
+        // A selection is not a list, so it doesn't select anything.
+        assert_eq!(
+            select_secured_version(&data, &[V1_1]),
+            Err(ErrorCode::InvalidRequest)
+        );
+    }
+}
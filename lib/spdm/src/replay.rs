@@ -0,0 +1,298 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Replays an exchange captured between the responder in
+//! `drv/spdm-responder-server` (with its mock keys) and an independent
+//! requester, written in Python with the `cryptography` package, which
+//! checked every signature and every verify data as it went.
+//!
+//! The exchange negotiates, retrieves part of a certificate chain, answers a
+//! CHALLENGE, reports signed measurements, and runs a session from
+//! KEY_EXCHANGE to END_SESSION. Replaying it checks that we decode the
+//! requester's messages, and hash transcripts and derive session keys, the
+//! same way that it did.
+//!
+//! That requester was written alongside the responder, so it can share our
+//! misreadings of the spec. We still need an exchange captured from libspdm's
+//! `spdm_requester_emu` covering the same ground (VCA, CHALLENGE, and
+//! KEY_EXCHANGE through FINISH), which we haven't been able to capture yet.
+//! To get one, run the emulator against the responder over MCTP with SPDM
+//! 1.2, SHA-256, Ed25519, secp256r1 and AES-256-GCM, and save the traffic with
+//! `--pcap`. Replaying the secured messages also needs the session's DHE
+//! secret, to stand in for `SHARED_SECRET`.
+
+use crate::algo::{self, Algorithms};
+use crate::request::{GetMeasurements, Request};
+use crate::session::{secured_session_id, session_id, Channel, Handshake};
+use crate::transcript::Transcript;
+use crate::{measurement_summary, Version};
+use sha2::{Digest, Sha256};
+
+/// Each request and its response, in order; FINISH and END_SESSION (and
+/// their responses) are secured messages.
+const EXCHANGE: [(&str, &str); 11] = [
+    // GET_VERSION
+    ("10840000", "1004000000010012"),
+    // GET_CAPABILITIES
+    (
+        "12e1000000140000c24600000004000000040000",
+        "1261000000140000d64a08000004000000040000",
+    ),
+    // NEGOTIATE_ALGORITHMS
+    (
+        "12e303002c000102100400000300000000000000000000000000000000000000\
+         022018000320030005200100",
+        "1263030030000102020000000004000001000000000000000000000000000000\
+         00000000022008000320020005200100",
+    ),
+    // GET_DIGESTS
+    (
+        "12810000",
+        "1201000178754ed72156abd2804e099f5c65aa36c87ba0b65b36863405ab0a46\
+         5b42c624",
+    ),
+    // GET_CERTIFICATE
+    (
+        "1282000000008000",
+        "120200008000f40174020000d0c2bda7ebb1622f27c2854005eefec4a0b62f1b\
+         c457916718acde96e6c447be308201233081d6a003020102020101300506032b\
+         65703028310d300b060355040a0c044d6f636b3117301506035504030c0e6d6f\
+         636b205350444d20726f6f743020170d3235303130313030303030305a180f39\
+         3939393132333132",
+    ),
+    // CHALLENGE
+    (
+        "128300ffb3caf776adec1b9f95147319bf646f2054c964b436ad8e4dcd2f2892\
+         d5772044",
+        "1203000178754ed72156abd2804e099f5c65aa36c87ba0b65b36863405ab0a46\
+         5b42c62476b8e0ada0f13d90405d6ae55386bd28bdd219b8a08ded1aa836efcc\
+         8b770dc78014bfe5b3fd34a5f263b8d28680aba7b84de9846b3806aa5bc7028c\
+         b34670f60000a0aedf49f9408eb7fa212a9b7be4767689453a14bb1c01bbb6ac\
+         81ceefe70d751f018a0916eed96dd31ad6eff5a41a20465747d8180b372286b9\
+         a24dbd22790c",
+    ),
+    // GET_MEASUREMENTS
+    (
+        "12e00000",
+        "1260030000000000da41597c5157488d7724e03fb8d84a376a43b8f41518a11c\
+         c387b669b2ee65860000",
+    ),
+    // GET_MEASUREMENTS
+    (
+        "12e001ffbf51a8b5e901d6fe5364a5802c7fcb8aea73f93e0cbf2a7f9fe6c53e\
+         1e60485f00",
+        "126000200375000003012300042000a85ba2b36261d0dca4b6cbbc840fa8a441\
+         ec95200abba5c5623e7ddadeff99e505012300052000fcd4732d31bc91222c09\
+         1057a1a61240a4a52fbe562012f46a58cafe8c29510506012300012000a8af09\
+         9bf2e878609558dbf69d8f88f4a31040a8cf84b549a0cfa912f12ffc3f9f07e7\
+         be5551387a98ba977c732d080dcb0f29a048e3656912c6533e32ee7aed00003e\
+         ca08c42366fcbf1195c3278fab47f198ef4662e71ce05092908a5b01f0c79704\
+         2c999da40b756e6d22af714e6a4dae95399118c7f21e0a669b02d3ef99b10f",
+    ),
+    // KEY_EXCHANGE
+    (
+        "12e40000feff0000686a2347302c22f07f3affb79ae1d3f843b103b6579cac2f\
+         54b1bd853db4c237f9a5660cdcf01b44e64086b5b13303549279fb4d85538dcc\
+         2302e29a30abc68f58343d8b204abce5c1912ba88ef02c77248916698fe176fb\
+         fc95cff63097161a100001000000000007000101020010001100",
+        "126400000100000029b721769ce64e43d57133b074d839d531ed1f28510afb45\
+         ace10a1f4b794d6fa8d7a6599e45b730815be36a120f533fdee7bfc48f48c95d\
+         bba4b7fb361b411363cdb546a93ee600cc991f08adb572971acc283f942dd092\
+         124c535ec6867c830c00010000000000040001000011fe7b3947613309955769\
+         a4eb4fab05b3eb91e80b5552100ce8bf5c63320b1f38e66b8f06d297d03114e5\
+         2f3a0872b4368f685f3a67ba3ab316b5b6ec53a9a2090839b776656c159b95c0\
+         14ffda7b4b42d82bfa3da554be4c1eac813ab815ced5",
+    ),
+    // FINISH
+    (
+        "feff010000003700bc15507c3f5440fcc2ddea5f079405019a63880d0edcf456\
+         fdfe831c7eca474422c37f8246780d1ac9c4ccfd9f1bb936963a35d16f6f59",
+        "feff0100000017002a518311a380dcf0080db7e19b60ef43807d0953feb25e",
+    ),
+    // END_SESSION
+    (
+        "feff01000000170090795ed70b6cccc5d43250d0b13a2f26e9e56878488a04",
+        "feff010000001700149eed48398859f627694a92743d43372800bd55b72fe2",
+    ),
+];
+
+/// The DHE shared secret that the requester arrived at
+const SHARED_SECRET: &str =
+    "77acfec86920e0ea5de864c13f7717b694d0d311c360601365cb6290f6b32c45";
+
+/// The transcript hashes that the requester checked the CHALLENGE_AUTH and
+/// MEASUREMENTS signatures over
+const M1: &str =
+    "bec56c13fe4b7e0cc6eb32232c9fd929f12f62c1af4688bef137ce0740304ba1";
+const L1: &str =
+    "e38e96814893c6f1d753cdc2d9a52e5d45c298987137c6056479f327ea52001e";
+
+/// Size of an Ed25519 signature
+const SIGNATURE_SIZE: usize = 64;
+
+/// Size of the sequence number in a secured message, in the MCTP binding
+const SEQ: usize = 2;
+
+fn hex(s: &str) -> Vec<u8> {
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+        .collect()
+}
+
+#[test]
+fn replay() {
+    let [(get_version, version), (get_capabilities, capabilities), (negotiate_algorithms, algorithms), (get_digests, digests), (get_certificate, certificate), (challenge, challenge_auth), (get_count, count), (get_measurements, measurements), (key_exchange, key_exchange_rsp), (mut finish, mut finish_rsp), (mut end_session, mut end_session_ack)] =
+        EXCHANGE.map(|(req, rsp)| (hex(req), hex(rsp)));
+
+    // Negotiation
+    let (v, req) = Request::parse(&get_version, None).unwrap();
+    assert_eq!((v, req), (Version::V1_0, Request::GetVersion));
+    let Ok((Version::V1_2, Request::GetCapabilities(caps))) =
+        Request::parse(&get_capabilities, None)
+    else {
+        panic!("not GET_CAPABILITIES");
+    };
+    assert_eq!(caps.data_transfer_size, 1024);
+    let Ok((_, Request::NegotiateAlgorithms(offered))) =
+        Request::parse(&negotiate_algorithms, None)
+    else {
+        panic!("not NEGOTIATE_ALGORITHMS");
+    };
+    assert_eq!(offered.base_asym, 0x410);
+    assert_eq!(offered.dhe, algo::dhe::SECP256R1 | algo::dhe::SECP384R1);
+    assert_eq!(offered.alg_structs, 0b10_1100);
+    let a = Algorithms {
+        measurement_spec: algo::measurement_spec::DMTF,
+        other_params: algo::other_params::OPAQUE_DATA_FMT1,
+        measurement_hash: algo::measurement_hash::SHA_256,
+        base_asym: algo::base_asym::ED25519,
+        base_hash: algo::base_hash::SHA_256,
+        dhe: algo::dhe::SECP256R1,
+        aead: algo::aead::AES_256_GCM,
+        req_base_asym: 0,
+        key_schedule: algo::key_schedule::SPDM,
+    };
+    assert_eq!(algorithms[6], a.measurement_spec);
+    assert_eq!(algorithms[12..20], [0, 4, 0, 0, 1, 0, 0, 0]);
+
+    let mut t = Transcript::<Sha256>::new();
+    for msg in [
+        &get_version,
+        &version,
+        &get_capabilities,
+        &capabilities,
+        &negotiate_algorithms,
+        &algorithms,
+    ] {
+        t.append_vca(msg);
+    }
+
+    // Certificate retrieval and CHALLENGE
+    assert_eq!(
+        Request::parse(&get_digests, Some(&a)),
+        Ok((Version::V1_2, Request::GetDigests))
+    );
+    let Ok((_, Request::GetCertificate(req))) =
+        Request::parse(&get_certificate, Some(&a))
+    else {
+        panic!("not GET_CERTIFICATE");
+    };
+    assert_eq!((req.slot, req.offset, req.length), (0, 0, 0x80));
+    let Ok((_, Request::Challenge(req))) = Request::parse(&challenge, Some(&a))
+    else {
+        panic!("not CHALLENGE");
+    };
+    assert_eq!(req.measurement_summary, measurement_summary::ALL);
+    assert_eq!(req.nonce[..], challenge[4..]);
+
+    let unsigned = challenge_auth.len() - SIGNATURE_SIZE;
+    for msg in [
+        &get_digests[..],
+        &digests,
+        &get_certificate,
+        &certificate,
+        &challenge,
+        &challenge_auth[..unsigned],
+    ] {
+        t.append_m1(msg);
+    }
+    assert_eq!(t.finish_m1().to_vec(), hex(M1));
+
+    // Signed measurements
+    let Ok((_, Request::GetMeasurements(req))) =
+        Request::parse(&get_measurements, Some(&a))
+    else {
+        panic!("not GET_MEASUREMENTS");
+    };
+    assert!(req.signature_requested());
+    assert_eq!(req.operation, GetMeasurements::MEASUREMENTS_ALL);
+
+    let unsigned = measurements.len() - SIGNATURE_SIZE;
+    for msg in [
+        &get_count[..],
+        &count,
+        &get_measurements,
+        &measurements[..unsigned],
+    ] {
+        t.append_l1(msg);
+    }
+    assert_eq!(t.finish_l1().to_vec(), hex(L1));
+
+    // A session
+    let Ok((_, Request::KeyExchange(req))) =
+        Request::parse(&key_exchange, Some(&a))
+    else {
+        panic!("not KEY_EXCHANGE");
+    };
+    assert_eq!(req.exchange_data.len(), a.dhe_size());
+    let id = session_id(
+        req.req_session_id,
+        u16::from_le_bytes([key_exchange_rsp[4], key_exchange_rsp[5]]),
+    );
+
+    let (signed, verify_data) =
+        key_exchange_rsp.split_at(key_exchange_rsp.len() - a.hash_size());
+    let mut th = t.vca();
+    th.update(&digests[4..36]);
+    th.update(&key_exchange);
+    th.update(signed);
+    let th1 = th.clone().finalize();
+    let hs = Handshake::<Sha256>::new(Version::V1_2, &hex(SHARED_SECRET), &th1);
+    assert_eq!(hs.responder_verify_data(&th1)[..], *verify_data);
+    th.update(verify_data);
+
+    assert_eq!(secured_session_id(&finish), Some(id));
+    let mut requests = Channel::<Sha256>::new(Version::V1_2, hs.requester);
+    let mut responses = Channel::<Sha256>::new(Version::V1_2, hs.responder);
+    let [5, msg @ ..] = requests.open(SEQ, &mut finish).unwrap() else {
+        panic!("not an SPDM message");
+    };
+    let Ok((_, Request::Finish(req))) = Request::parse(msg, Some(&a)) else {
+        panic!("not FINISH");
+    };
+    let head = &msg[..msg.len() - req.verify_data.len()];
+    let expected =
+        hs.requester_verify_data(&th.clone().chain_update(head).finalize());
+    assert_eq!(expected[..], *req.verify_data);
+    th.update(msg);
+    let rsp = responses.open(SEQ, &mut finish_rsp).unwrap();
+    assert_eq!(rsp, [5, 0x12, 0x65, 0, 0]);
+    th.update(&rsp[1..]);
+
+    let (req, rsp) = hs.data_secrets(&th.finalize());
+    let mut requests = Channel::<Sha256>::new(Version::V1_2, req);
+    let mut responses = Channel::<Sha256>::new(Version::V1_2, rsp);
+    let [5, msg @ ..] = requests.open(SEQ, &mut end_session).unwrap() else {
+        panic!("not an SPDM message");
+    };
+    assert!(matches!(
+        Request::parse(msg, Some(&a)),
+        Ok((_, Request::EndSession(_)))
+    ));
+    assert_eq!(
+        responses.open(SEQ, &mut end_session_ack).unwrap(),
+        [5, 0x12, 0x6C, 0, 0]
+    );
+}
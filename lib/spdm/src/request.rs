@@ -0,0 +1,820 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Decoding of requests.
+//!
+//! Requests borrow their variable-length fields from the message that they
+//! were decoded from. Decoding is strict: a request with trailing bytes, or
+//! with a parameter out of range, is rejected as `ErrorCode::InvalidRequest`.
+
+use crate::algo::{AlgType, Algorithms};
+use crate::{
+    measurement_summary, ErrorCode, Reader, RequestCode, Version, NONCE_SIZE,
+    RANDOM_SIZE, SLOT_COUNT,
+};
+
+/// Slot number that refers to a provisioned public key, rather than to a
+/// certificate slot, in CHALLENGE and KEY_EXCHANGE.
+pub const PUBLIC_KEY_SLOT: u8 = 0xFF;
+
+/// Smallest `DataTransferSize` that an SPDM 1.2 endpoint may have.
+pub const MIN_DATA_TRANSFER_SIZE: u32 = 42;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Request<'a> {
+    GetVersion,
+    GetCapabilities(GetCapabilities),
+    NegotiateAlgorithms(NegotiateAlgorithms),
+    GetDigests,
+    GetCertificate(GetCertificate),
+    Challenge(Challenge<'a>),
+    GetMeasurements(GetMeasurements<'a>),
+    KeyExchange(KeyExchange<'a>),
+    Finish(Finish<'a>),
+    PskExchange(PskExchange<'a>),
+    PskFinish(PskFinish<'a>),
+    KeyUpdate(KeyUpdate),
+    EndSession(EndSession),
+    SetCertificate(SetCertificate<'a>),
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct GetCapabilities {
+    pub ct_exponent: u8,
+    pub flags: u32,
+    /// Largest message that the requester can receive in one transfer (zero
+    /// before SPDM 1.2)
+    pub data_transfer_size: u32,
+    /// Largest message that the requester can receive at all (zero before
+    /// SPDM 1.2)
+    pub max_message_size: u32,
+}
+
+/// Algorithms offered by the requester, with every supported algorithm's bit
+/// set.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct NegotiateAlgorithms {
+    pub measurement_spec: u8,
+    pub other_params: u8,
+    pub base_asym: u32,
+    pub base_hash: u32,
+    pub dhe: u16,
+    pub aead: u16,
+    pub req_base_asym: u16,
+    pub key_schedule: u16,
+    /// Which algorithm structures were present, as `AlgType::bit`s; the
+    /// response must include the same ones.
+    pub alg_structs: u8,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct GetCertificate {
+    pub slot: u8,
+    pub offset: u16,
+    pub length: u16,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Challenge<'a> {
+    /// Certificate slot, or `PUBLIC_KEY_SLOT`
+    pub slot: u8,
+    /// One of `measurement_summary::*`
+    pub measurement_summary: u8,
+    pub nonce: &'a [u8; NONCE_SIZE],
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct GetMeasurements<'a> {
+    pub attributes: u8,
+    /// Zero to ask for the number of measurements, `MEASUREMENTS_ALL` for
+    /// all of them, or else the index of a single measurement
+    pub operation: u8,
+    /// Nonce to sign with, if a signature was requested
+    pub nonce: Option<&'a [u8; NONCE_SIZE]>,
+    /// Certificate slot to sign with (zero if no signature was requested)
+    pub slot: u8,
+}
+
+impl GetMeasurements<'_> {
+    /// Bit of `attributes` asking for a signed response
+    pub const SIGNATURE_REQUESTED: u8 = 1 << 0;
+    /// Bit of `attributes` asking for raw bit streams rather than digests
+    pub const RAW_BIT_STREAM_REQUESTED: u8 = 1 << 1;
+    /// `operation` asking for every measurement
+    pub const MEASUREMENTS_ALL: u8 = 0xFF;
+
+    pub fn signature_requested(&self) -> bool {
+        self.nonce.is_some()
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct KeyExchange<'a> {
+    /// One of `measurement_summary::*`
+    pub measurement_summary: u8,
+    /// Certificate slot, or `PUBLIC_KEY_SLOT`
+    pub slot: u8,
+    pub req_session_id: u16,
+    pub session_policy: u8,
+    pub random: &'a [u8; RANDOM_SIZE],
+    /// The requester's ephemeral public key, for the negotiated DHE group
+    pub exchange_data: &'a [u8],
+    pub opaque_data: &'a [u8],
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Finish<'a> {
+    /// Requester's signature, if it authenticated itself
+    pub signature: Option<&'a [u8]>,
+    /// Slot of the requester's certificate, if it included a signature
+    pub req_slot: u8,
+    /// HMAC of the transcript, using the requester's finished key
+    pub verify_data: &'a [u8],
+}
+
+impl Finish<'_> {
+    /// Bit of the first parameter indicating that a signature is included
+    pub const SIGNATURE_INCLUDED: u8 = 1 << 0;
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PskExchange<'a> {
+    /// One of `measurement_summary::*`
+    pub measurement_summary: u8,
+    pub session_policy: u8,
+    pub req_session_id: u16,
+    /// Identifies which pre-shared key to use
+    pub psk_hint: &'a [u8],
+    /// The requester's context, which is mixed into the transcript
+    pub context: &'a [u8],
+    pub opaque_data: &'a [u8],
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PskFinish<'a> {
+    /// HMAC of the transcript, using the requester's finished key
+    pub verify_data: &'a [u8],
+}
+
+/// What a KEY_UPDATE asks for, from its first parameter
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum KeyOperation {
+    /// Update the key for requests
+    UpdateKey = 1,
+    /// Update the keys for both requests and responses
+    UpdateAllKeys = 2,
+    /// Confirm that the requester has switched to the new keys
+    VerifyNewKey = 3,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct KeyUpdate {
+    pub operation: KeyOperation,
+    /// Echoed in the acknowledgement, to tell updates apart
+    pub tag: u8,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct EndSession {
+    /// Whether the responder should keep the negotiated state (algorithms
+    /// and the like) for the requester's next connection
+    pub preserve_state: bool,
+}
+
+impl EndSession {
+    /// Bit of the first parameter asking to preserve the negotiated state
+    pub const PRESERVE_STATE: u8 = 1 << 0;
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SetCertificate<'a> {
+    pub slot: u8,
+    /// The chain to provision, in SPDM format (see [`crate::chain`]), which
+    /// is everything after the header
+    pub cert_chain: &'a [u8],
+}
+
+/// Returns the version from a message's header, if it has one.
+pub fn version(msg: &[u8]) -> Option<Version> {
+    msg.first().map(|&v| Version(v))
+}
+
+impl<'a> Request<'a> {
+    /// Decodes `msg`, returning its version along with the request.
+    ///
+    /// KEY_EXCHANGE, FINISH and PSK_FINISH have fields whose sizes depend on
+    /// the negotiated algorithms, so decoding them fails (with
+    /// `ErrorCode::UnexpectedRequest`) if `algorithms` is `None`.
+    pub fn parse(
+        msg: &'a [u8],
+        algorithms: Option<&Algorithms>,
+    ) -> Result<(Version, Self), ErrorCode> {
+        let mut r = Reader::new(msg);
+        let version = Version(r.u8()?);
+        let code = RequestCode::try_from(r.u8()?)?;
+        let param1 = r.u8()?;
+        let param2 = r.u8()?;
+
+        let out = match code {
+            RequestCode::GetVersion => {
+                // This is always sent as version 1.0, since it's how the
+                // version is negotiated.
+                if version != Version::V1_0 {
+                    return Err(ErrorCode::VersionMismatch);
+                }
+                Self::GetVersion
+            }
+            RequestCode::GetCapabilities => {
+                Self::GetCapabilities(parse_get_capabilities(version, &mut r)?)
+            }
+            RequestCode::NegotiateAlgorithms => Self::NegotiateAlgorithms(
+                parse_negotiate_algorithms(msg, param1, &mut r)?,
+            ),
+            RequestCode::GetDigests => Self::GetDigests,
+            RequestCode::GetCertificate => {
+                let slot = param1 & 0xF;
+                if slot >= SLOT_COUNT {
+                    return Err(ErrorCode::InvalidRequest);
+                }
+                Self::GetCertificate(GetCertificate {
+                    slot,
+                    offset: r.u16()?,
+                    length: r.u16()?,
+                })
+            }
+            RequestCode::Challenge => Self::Challenge(Challenge {
+                slot: check_slot(param1)?,
+                measurement_summary: check_summary(param2)?,
+                nonce: r.array()?,
+            }),
+            RequestCode::GetMeasurements => {
+                let (nonce, slot) =
+                    if param1 & GetMeasurements::SIGNATURE_REQUESTED != 0 {
+                        let nonce = r.array()?;
+                        let slot = r.u8()? & 0xF;
+                        if slot >= SLOT_COUNT && slot != (PUBLIC_KEY_SLOT & 0xF)
+                        {
+                            return Err(ErrorCode::InvalidRequest);
+                        }
+                        (Some(nonce), slot)
+                    } else {
+                        (None, 0)
+                    };
+                Self::GetMeasurements(GetMeasurements {
+                    attributes: param1,
+                    operation: param2,
+                    nonce,
+                    slot,
+                })
+            }
+            RequestCode::KeyExchange => {
+                let algorithms =
+                    algorithms.ok_or(ErrorCode::UnexpectedRequest)?;
+                let measurement_summary = check_summary(param1)?;
+                let slot = check_slot(param2)?;
+                let req_session_id = r.u16()?;
+                let session_policy = r.u8()?;
+                let _reserved = r.u8()?;
+                let random = r.array()?;
+                let exchange_data = r.bytes(algorithms.dhe_size())?;
+                let opaque_len = r.u16()?;
+                let opaque_data = r.bytes(opaque_len.into())?;
+                Self::KeyExchange(KeyExchange {
+                    measurement_summary,
+                    slot,
+                    req_session_id,
+                    session_policy,
+                    random,
+                    exchange_data,
+                    opaque_data,
+                })
+            }
+            RequestCode::Finish => {
+                let algorithms =
+                    algorithms.ok_or(ErrorCode::UnexpectedRequest)?;
+                let signature = if param1 & Finish::SIGNATURE_INCLUDED != 0 {
+                    Some(r.bytes(algorithms.req_signature_size())?)
+                } else {
+                    None
+                };
+                Self::Finish(Finish {
+                    signature,
+                    req_slot: param2,
+                    verify_data: r.bytes(algorithms.hash_size())?,
+                })
+            }
+            RequestCode::PskExchange => {
+                let measurement_summary = check_summary(param1)?;
+                let req_session_id = r.u16()?;
+                let hint_len = r.u16()?;
+                let context_len = r.u16()?;
+                let opaque_len = r.u16()?;
+                Self::PskExchange(PskExchange {
+                    measurement_summary,
+                    session_policy: param2,
+                    req_session_id,
+                    psk_hint: r.bytes(hint_len.into())?,
+                    context: r.bytes(context_len.into())?,
+                    opaque_data: r.bytes(opaque_len.into())?,
+                })
+            }
+            RequestCode::PskFinish => {
+                let algorithms =
+                    algorithms.ok_or(ErrorCode::UnexpectedRequest)?;
+                Self::PskFinish(PskFinish {
+                    verify_data: r.bytes(algorithms.hash_size())?,
+                })
+            }
+            RequestCode::KeyUpdate => Self::KeyUpdate(KeyUpdate {
+                operation: match param1 {
+                    1 => KeyOperation::UpdateKey,
+                    2 => KeyOperation::UpdateAllKeys,
+                    3 => KeyOperation::VerifyNewKey,
+                    _ => return Err(ErrorCode::InvalidRequest),
+                },
+                tag: param2,
+            }),
+            RequestCode::EndSession => Self::EndSession(EndSession {
+                preserve_state: param1 & EndSession::PRESERVE_STATE != 0,
+            }),
+            RequestCode::SetCertificate => {
+                let slot = param1 & 0xF;
+                if slot >= SLOT_COUNT {
+                    return Err(ErrorCode::InvalidRequest);
+                }
+                Self::SetCertificate(SetCertificate {
+                    slot,
+                    cert_chain: r.bytes(r.remaining())?,
+                })
+            }
+        };
+        r.finish()?;
+        Ok((version, out))
+    }
+}
+
+fn parse_get_capabilities(
+    version: Version,
+    r: &mut Reader<'_>,
+) -> Result<GetCapabilities, ErrorCode> {
+    let mut out = GetCapabilities::default();
+    if version < Version::V1_1 {
+        return Ok(out);
+    }
+    let _reserved = r.u8()?;
+    out.ct_exponent = r.u8()?;
+    let _reserved = r.u16()?;
+    out.flags = r.u32()?;
+    if version >= Version::V1_2 {
+        out.data_transfer_size = r.u32()?;
+        out.max_message_size = r.u32()?;
+        if out.data_transfer_size < MIN_DATA_TRANSFER_SIZE
+            || out.max_message_size < out.data_transfer_size
+        {
+            return Err(ErrorCode::InvalidRequest);
+        }
+    }
+    Ok(out)
+}
+
+fn parse_negotiate_algorithms(
+    msg: &[u8],
+    alg_struct_count: u8,
+    r: &mut Reader<'_>,
+) -> Result<NegotiateAlgorithms, ErrorCode> {
+    let length = r.u16()?;
+    if usize::from(length) != msg.len() {
+        return Err(ErrorCode::InvalidRequest);
+    }
+    let mut out = NegotiateAlgorithms {
+        measurement_spec: r.u8()?,
+        other_params: r.u8()?,
+        base_asym: r.u32()?,
+        base_hash: r.u32()?,
+        ..Default::default()
+    };
+    r.bytes(12)?;
+    let ext_asym_count = r.u8()?;
+    let ext_hash_count = r.u8()?;
+    r.bytes(2)?;
+    // We don't support any extended (non-DMTF) algorithms, so skip them.
+    r.bytes(4 * usize::from(ext_asym_count))?;
+    r.bytes(4 * usize::from(ext_hash_count))?;
+
+    let mut last_type = 0;
+    for _ in 0..alg_struct_count {
+        let alg_type = r.u8()?;
+        let alg_count = r.u8()?;
+        // The high nibble is the size of the supported-algorithms field,
+        // which is always two bytes; the low nibble counts extended
+        // algorithms, which follow it.
+        if alg_count >> 4 != 2 {
+            return Err(ErrorCode::InvalidRequest);
+        }
+        let supported = r.u16()?;
+        r.bytes(4 * usize::from(alg_count & 0xF))?;
+
+        // Structures must be in order of type, each at most once.
+        if alg_type <= last_type {
+            return Err(ErrorCode::InvalidRequest);
+        }
+        last_type = alg_type;
+        let (alg_type, field) = match alg_type {
+            2 => (AlgType::Dhe, &mut out.dhe),
+            3 => (AlgType::Aead, &mut out.aead),
+            4 => (AlgType::ReqBaseAsym, &mut out.req_base_asym),
+            5 => (AlgType::KeySchedule, &mut out.key_schedule),
+            _ => return Err(ErrorCode::InvalidRequest),
+        };
+        *field = supported;
+        out.alg_structs |= alg_type.bit();
+    }
+    Ok(out)
+}
+
+fn check_slot(slot: u8) -> Result<u8, ErrorCode> {
+    if slot < SLOT_COUNT || slot == PUBLIC_KEY_SLOT {
+        Ok(slot)
+    } else {
+        Err(ErrorCode::InvalidRequest)
+    }
+}
+
+fn check_summary(summary: u8) -> Result<u8, ErrorCode> {
+    match summary {
+        measurement_summary::NONE
+        | measurement_summary::TCB
+        | measurement_summary::ALL => Ok(summary),
+        _ => Err(ErrorCode::InvalidRequest),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algo::*;
+    use crate::capabilities::*;
+
+    // These vectors are laid out as in DSP0274, with field values modelled on
+    // what a libspdm requester (e.g. spdm-emu's) sends.
+
+    const GET_VERSION: [u8; 4] = [0x10, 0x84, 0x00, 0x00];
+
+    const GET_CAPABILITIES: [u8; 20] = [
+        0x12, 0xE1, 0x00, 0x00, // header
+        0x00, // reserved
+        0x00, // CTExponent
+        0x00, 0x00, // reserved
+        0xC6, 0xF7, 0x00, 0x00, // flags
+        0x00, 0x12, 0x00, 0x00, // DataTransferSize
+        0x00, 0x12, 0x00, 0x00, // MaxSPDMmsgSize
+    ];
+
+    const NEGOTIATE_ALGORITHMS: [u8; 48] = [
+        0x12, 0xE3, 0x04, 0x00, // header, with 4 algorithm structures
+        0x30, 0x00, // length
+        0x01, // MeasurementSpecification
+        0x02, // OtherParamsSupport
+        0x90, 0x00, 0x00, 0x00, // BaseAsymAlgo
+        0x03, 0x00, 0x00, 0x00, // BaseHashAlgo
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, // reserved
+        0x00, 0x00, // ExtAsymCount, ExtHashCount
+        0x00, 0x00, // reserved
+        0x02, 0x20, 0x18, 0x00, // DHE
+        0x03, 0x20, 0x02, 0x00, // AEAD
+        0x04, 0x20, 0x90, 0x00, // ReqBaseAsymAlg
+        0x05, 0x20, 0x01, 0x00, // KeySchedule
+    ];
+
+    fn parse(msg: &[u8]) -> Result<Request<'_>, ErrorCode> {
+        Request::parse(msg, None).map(|(_, r)| r)
+    }
+
+    #[test]
+    fn get_version() {
+        assert_eq!(
+            Request::parse(&GET_VERSION, None),
+            Ok((Version::V1_0, Request::GetVersion))
+        );
+
+        // GET_VERSION is always sent as 1.0.
+        assert_eq!(
+            parse(&[0x12, 0x84, 0x00, 0x00]),
+            Err(ErrorCode::VersionMismatch)
+        );
+        assert_eq!(
+            parse(&[0x10, 0x84, 0x00, 0x00, 0x00]),
+            Err(ErrorCode::InvalidRequest)
+        );
+    }
+
+    #[test]
+    fn get_capabilities() {
+        let (version, req) = Request::parse(&GET_CAPABILITIES, None).unwrap();
+        assert_eq!(version, Version::V1_2);
+        assert_eq!(
+            req,
+            Request::GetCapabilities(GetCapabilities {
+                ct_exponent: 0,
+                flags: CERT
+                    | CHAL
+                    | ENCRYPT
+                    | MAC
+                    | MUT_AUTH
+                    | KEY_EX
+                    | PSK
+                    | ENCAP
+                    | HBEAT
+                    | KEY_UPD
+                    | HANDSHAKE_IN_THE_CLEAR,
+                data_transfer_size: 0x1200,
+                max_message_size: 0x1200,
+            })
+        );
+
+        // SPDM 1.1 doesn't have the transfer sizes
+        assert_eq!(
+            parse(&GET_CAPABILITIES[..12]),
+            Err(ErrorCode::InvalidRequest)
+        );
+        let mut v11 = GET_CAPABILITIES;
+        v11[0] = 0x11;
+        assert_eq!(
+            parse(&v11[..12]),
+            Ok(Request::GetCapabilities(GetCapabilities {
+                flags: 0xF7C6,
+                ..Default::default()
+            }))
+        );
+
+        // DataTransferSize must be at least 42 bytes
+        let mut small = GET_CAPABILITIES;
+        small[12..16].copy_from_slice(&41u32.to_le_bytes());
+        assert_eq!(parse(&small), Err(ErrorCode::InvalidRequest));
+    }
+
+    #[test]
+    fn negotiate_algorithms() {
+        assert_eq!(
+            parse(&NEGOTIATE_ALGORITHMS),
+            Ok(Request::NegotiateAlgorithms(NegotiateAlgorithms {
+                measurement_spec: measurement_spec::DMTF,
+                other_params: other_params::OPAQUE_DATA_FMT1,
+                base_asym: base_asym::ECDSA_P256 | base_asym::ECDSA_P384,
+                base_hash: base_hash::SHA_256 | base_hash::SHA_384,
+                dhe: dhe::SECP256R1 | dhe::SECP384R1,
+                aead: aead::AES_256_GCM,
+                req_base_asym: (base_asym::ECDSA_P256 | base_asym::ECDSA_P384)
+                    as u16,
+                key_schedule: key_schedule::SPDM,
+                alg_structs: 0b111100,
+            }))
+        );
+
+        // The length must match the message
+        assert_eq!(
+            parse(&NEGOTIATE_ALGORITHMS[..44]),
+            Err(ErrorCode::InvalidRequest)
+        );
+
+        // Algorithm structures must be in order
+        let mut swapped = NEGOTIATE_ALGORITHMS;
+        swapped[32] = 0x03;
+        swapped[36] = 0x02;
+        assert_eq!(parse(&swapped), Err(ErrorCode::InvalidRequest));
+
+        // Extended algorithms are skipped over
+        let mut ext = NEGOTIATE_ALGORITHMS.to_vec();
+        ext[4] = 0x38;
+        ext[28] = 0x01;
+        ext[29] = 0x01;
+        ext.splice(32..32, [0xAA; 8]);
+        assert_eq!(parse(&ext), parse(&NEGOTIATE_ALGORITHMS));
+    }
+
+    #[test]
+    fn get_certificate() {
+        assert_eq!(
+            parse(&[0x12, 0x82, 0x00, 0x00, 0x00, 0x00, 0x00, 0x04]),
+            Ok(Request::GetCertificate(GetCertificate {
+                slot: 0,
+                offset: 0,
+                length: 0x400,
+            }))
+        );
+        assert_eq!(
+            parse(&[0x12, 0x82, 0x08, 0x00, 0x00, 0x00, 0x00, 0x04]),
+            Err(ErrorCode::InvalidRequest)
+        );
+    }
+
+    #[test]
+    fn challenge() {
+        let mut msg = [0x5A; 36];
+        msg[..4].copy_from_slice(&[0x12, 0x83, 0x00, 0xFF]);
+        assert_eq!(
+            parse(&msg),
+            Ok(Request::Challenge(Challenge {
+                slot: 0,
+                measurement_summary: measurement_summary::ALL,
+                nonce: &[0x5A; 32],
+            }))
+        );
+
+        msg[3] = 0x02;
+        assert_eq!(parse(&msg), Err(ErrorCode::InvalidRequest));
+        assert_eq!(parse(&msg[..35]), Err(ErrorCode::InvalidRequest));
+    }
+
+    #[test]
+    fn get_measurements() {
+        assert_eq!(
+            parse(&[0x12, 0xE0, 0x00, 0x00]),
+            Ok(Request::GetMeasurements(GetMeasurements {
+                attributes: 0,
+                operation: 0,
+                nonce: None,
+                slot: 0,
+            }))
+        );
+
+        let mut msg = [0x33; 37];
+        msg[..4].copy_from_slice(&[0x12, 0xE0, 0x01, 0xFF]);
+        msg[36] = 0x01;
+        let Ok(Request::GetMeasurements(req)) = parse(&msg) else {
+            panic!("bad parse");
+        };
+        assert!(req.signature_requested());
+        assert_eq!(req.operation, GetMeasurements::MEASUREMENTS_ALL);
+        assert_eq!(req.nonce, Some(&[0x33; 32]));
+        assert_eq!(req.slot, 1);
+    }
+
+    #[test]
+    fn key_exchange() {
+        let algorithms = Algorithms {
+            dhe: dhe::SECP384R1,
+            ..Default::default()
+        };
+        let mut msg = vec![0x12, 0xE4, 0x00, 0x00];
+        msg.extend([0xFF, 0xFD]); // ReqSessionID
+        msg.extend([0x00, 0x00]); // SessionPolicy, reserved
+        msg.extend([0x11; 32]); // RandomData
+        msg.extend([0x22; 96]); // ExchangeData
+        msg.extend([0x04, 0x00, 0xAA, 0xBB, 0xCC, 0xDD]); // OpaqueData
+
+        assert_eq!(parse(&msg), Err(ErrorCode::UnexpectedRequest));
+        assert_eq!(
+            Request::parse(&msg, Some(&algorithms)),
+            Ok((
+                Version::V1_2,
+                Request::KeyExchange(KeyExchange {
+                    measurement_summary: measurement_summary::NONE,
+                    slot: 0,
+                    req_session_id: 0xFDFF,
+                    session_policy: 0,
+                    random: &[0x11; 32],
+                    exchange_data: &[0x22; 96],
+                    opaque_data: &[0xAA, 0xBB, 0xCC, 0xDD],
+                })
+            ))
+        );
+
+        // The opaque data length must match what's there
+        msg.push(0);
+        assert_eq!(
+            Request::parse(&msg, Some(&algorithms)),
+            Err(ErrorCode::InvalidRequest)
+        );
+    }
+
+    #[test]
+    fn finish() {
+        let algorithms = Algorithms {
+            base_hash: base_hash::SHA_384,
+            req_base_asym: base_asym::ECDSA_P384 as u16,
+            ..Default::default()
+        };
+        let mut msg = vec![0x12, 0xE5, 0x00, 0x00];
+        msg.extend([0x77; 48]);
+        assert_eq!(
+            Request::parse(&msg, Some(&algorithms)),
+            Ok((
+                Version::V1_2,
+                Request::Finish(Finish {
+                    signature: None,
+                    req_slot: 0,
+                    verify_data: &[0x77; 48],
+                })
+            ))
+        );
+
+        // With mutual authentication, the signature comes first
+        let mut msg = vec![0x12, 0xE5, 0x01, 0x00];
+        msg.extend([0x66; 96]);
+        msg.extend([0x77; 48]);
+        let Ok((_, Request::Finish(req))) =
+            Request::parse(&msg, Some(&algorithms))
+        else {
+            panic!("bad parse");
+        };
+        assert_eq!(req.signature, Some(&[0x66; 96][..]));
+        assert_eq!(req.verify_data, &[0x77; 48]);
+    }
+
+    #[test]
+    fn psk_exchange() {
+        let mut msg = vec![0x12, 0xE6, 0x00, 0x00];
+        msg.extend([0x01, 0xFF]); // ReqSessionID
+        msg.extend([0x03, 0x00]); // P
+        msg.extend([0x20, 0x00]); // R
+        msg.extend([0x02, 0x00]); // OpaqueDataLength
+        msg.extend(b"psk"); // PSKHint
+        msg.extend([0x11; 32]); // RequesterContext
+        msg.extend([0xAA, 0xBB]); // OpaqueData
+
+        assert_eq!(
+            parse(&msg),
+            Ok(Request::PskExchange(PskExchange {
+                measurement_summary: measurement_summary::NONE,
+                session_policy: 0,
+                req_session_id: 0xFF01,
+                psk_hint: b"psk",
+                context: &[0x11; 32],
+                opaque_data: &[0xAA, 0xBB],
+            }))
+        );
+        assert_eq!(
+            parse(&msg[..msg.len() - 1]),
+            Err(ErrorCode::InvalidRequest)
+        );
+    }
+
+    #[test]
+    fn session_requests() {
+        let algorithms = Algorithms {
+            base_hash: base_hash::SHA_256,
+            ..Default::default()
+        };
+        let mut msg = vec![0x12, 0xE7, 0x00, 0x00];
+        msg.extend([0x77; 32]);
+        assert_eq!(parse(&msg), Err(ErrorCode::UnexpectedRequest));
+        assert_eq!(
+            Request::parse(&msg, Some(&algorithms)),
+            Ok((
+                Version::V1_2,
+                Request::PskFinish(PskFinish {
+                    verify_data: &[0x77; 32]
+                })
+            ))
+        );
+
+        assert_eq!(
+            parse(&[0x12, 0xE9, 0x02, 0x5C]),
+            Ok(Request::KeyUpdate(KeyUpdate {
+                operation: KeyOperation::UpdateAllKeys,
+                tag: 0x5C,
+            }))
+        );
+        assert_eq!(
+            parse(&[0x12, 0xE9, 0x04, 0x5C]),
+            Err(ErrorCode::InvalidRequest)
+        );
+
+        assert_eq!(
+            parse(&[0x12, 0xEC, 0x01, 0x00]),
+            Ok(Request::EndSession(EndSession {
+                preserve_state: true
+            }))
+        );
+    }
+
+    #[test]
+    fn set_certificate() {
+        let mut msg = vec![0x12, 0xEE, 0x03, 0x00];
+        msg.extend([0x06, 0x00, 0x00, 0x00, 0xAB, 0xCD]);
+        assert_eq!(
+            parse(&msg),
+            Ok(Request::SetCertificate(SetCertificate {
+                slot: 3,
+                cert_chain: &[0x06, 0x00, 0x00, 0x00, 0xAB, 0xCD],
+            }))
+        );
+
+        msg[2] = 0x08;
+        assert_eq!(parse(&msg), Err(ErrorCode::InvalidRequest));
+    }
+
+    #[test]
+    fn unsupported() {
+        // RESPOND_IF_READY
+        assert_eq!(
+            parse(&[0x12, 0xFF, 0xE1, 0x01]),
+            Err(ErrorCode::UnsupportedRequest)
+        );
+        assert_eq!(parse(&[0x12]), Err(ErrorCode::InvalidRequest));
+    }
+}
@@ -0,0 +1,594 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Encoding of responses.
+//!
+//! Responses that end in a signature (CHALLENGE_AUTH, MEASUREMENTS and
+//! KEY_EXCHANGE_RSP) are encoded up to, but not including, the signature: the
+//! signature covers a transcript that includes the response itself, so the
+//! caller must add what's been written to the transcript, sign it, and then
+//! append the signature (and, for KEY_EXCHANGE_RSP, the verify data).
+//! PSK_EXCHANGE_RSP likewise stops short of its verify data.
+
+use crate::algo::{AlgType, Algorithms};
+use crate::request::KeyOperation;
+use crate::{
+    ErrorCode, ResponseCode, Version, Writer, HEADER_SIZE, NONCE_SIZE,
+    RANDOM_SIZE,
+};
+
+/// Size of a CERTIFICATE response, less the portion of the chain
+pub const CERTIFICATE_HEADER_SIZE: usize = HEADER_SIZE + 4;
+
+fn header(
+    w: &mut Writer<'_>,
+    version: Version,
+    code: ResponseCode,
+    param1: u8,
+    param2: u8,
+) -> Result<(), ErrorCode> {
+    w.bytes(&[version.0, code as u8, param1, param2])
+}
+
+/// Encodes a VERSION response listing `versions`. This is always sent as
+/// version 1.0.
+pub fn version(
+    w: &mut Writer<'_>,
+    versions: &[Version],
+) -> Result<(), ErrorCode> {
+    header(w, Version::V1_0, ResponseCode::Version, 0, 0)?;
+    w.u8(0)?;
+    w.u8(versions
+        .len()
+        .try_into()
+        .map_err(|_| ErrorCode::Unspecified)?)?;
+    for v in versions {
+        w.u16(v.number_entry())?;
+    }
+    Ok(())
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Capabilities {
+    pub ct_exponent: u8,
+    pub flags: u32,
+    pub data_transfer_size: u32,
+    pub max_message_size: u32,
+}
+
+impl Capabilities {
+    pub fn encode(
+        &self,
+        w: &mut Writer<'_>,
+        version: Version,
+    ) -> Result<(), ErrorCode> {
+        header(w, version, ResponseCode::Capabilities, 0, 0)?;
+        w.u8(0)?;
+        w.u8(self.ct_exponent)?;
+        w.u16(0)?;
+        w.u32(self.flags)?;
+        if version >= Version::V1_2 {
+            w.u32(self.data_transfer_size)?;
+            w.u32(self.max_message_size)?;
+        }
+        Ok(())
+    }
+}
+
+/// Encodes an ALGORITHMS response selecting `algorithms`, with an algorithm
+/// structure for each type in `alg_structs` (as `AlgType::bit`s), which
+/// should be those that the request had.
+pub fn algorithms(
+    w: &mut Writer<'_>,
+    version: Version,
+    algorithms: &Algorithms,
+    alg_structs: u8,
+) -> Result<(), ErrorCode> {
+    let structs = AlgType::ALL
+        .into_iter()
+        .filter(|t| alg_structs & t.bit() != 0)
+        .map(|t| {
+            let selected = match t {
+                AlgType::Dhe => algorithms.dhe,
+                AlgType::Aead => algorithms.aead,
+                AlgType::ReqBaseAsym => algorithms.req_base_asym,
+                AlgType::KeySchedule => algorithms.key_schedule,
+            };
+            (t, selected)
+        });
+    let count = structs.clone().count() as u8;
+
+    header(w, version, ResponseCode::Algorithms, count, 0)?;
+    w.u16(36 + 4 * u16::from(count))?;
+    w.u8(algorithms.measurement_spec)?;
+    w.u8(algorithms.other_params)?;
+    w.u32(algorithms.measurement_hash)?;
+    w.u32(algorithms.base_asym)?;
+    w.u32(algorithms.base_hash)?;
+    w.zeros(12)?;
+    // No extended algorithms are selected.
+    w.u8(0)?;
+    w.u8(0)?;
+    w.zeros(2)?;
+    for (t, selected) in structs {
+        w.u8(t as u8)?;
+        w.u8(0x20)?;
+        w.u16(selected)?;
+    }
+    Ok(())
+}
+
+/// Encodes a DIGESTS response, with a digest for each slot in `slot_mask`.
+pub fn digests(
+    w: &mut Writer<'_>,
+    version: Version,
+    slot_mask: u8,
+    digests: &[&[u8]],
+) -> Result<(), ErrorCode> {
+    header(w, version, ResponseCode::Digests, 0, slot_mask)?;
+    for d in digests {
+        w.bytes(d)?;
+    }
+    Ok(())
+}
+
+/// Encodes a CERTIFICATE response carrying `portion` bytes of the chain in
+/// `slot`, with `remainder` bytes of it left after them. Returns the space
+/// for the portion, which the caller must fill in.
+pub fn certificate<'w>(
+    w: &'w mut Writer<'_>,
+    version: Version,
+    slot: u8,
+    portion: u16,
+    remainder: u16,
+) -> Result<&'w mut [u8], ErrorCode> {
+    header(w, version, ResponseCode::Certificate, slot, 0)?;
+    w.u16(portion)?;
+    w.u16(remainder)?;
+    w.reserve(portion.into())
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct ChallengeAuth<'a> {
+    pub slot: u8,
+    /// Slots that have certificate chains
+    pub slot_mask: u8,
+    pub cert_chain_hash: &'a [u8],
+    pub nonce: &'a [u8; NONCE_SIZE],
+    /// Empty unless a summary was requested
+    pub measurement_summary_hash: &'a [u8],
+    pub opaque_data: &'a [u8],
+}
+
+impl ChallengeAuth<'_> {
+    /// Encodes everything but the signature.
+    pub fn encode(
+        &self,
+        w: &mut Writer<'_>,
+        version: Version,
+    ) -> Result<(), ErrorCode> {
+        header(
+            w,
+            version,
+            ResponseCode::ChallengeAuth,
+            self.slot & 0xF,
+            self.slot_mask,
+        )?;
+        w.bytes(self.cert_chain_hash)?;
+        w.bytes(self.nonce)?;
+        w.bytes(self.measurement_summary_hash)?;
+        opaque(w, self.opaque_data)
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Measurements<'a> {
+    /// Number of measurements the responder has, when that's what was asked
+    /// for (and zero otherwise)
+    pub total_count: u8,
+    /// Slot used for the signature, if one was requested
+    pub slot: u8,
+    /// Whether measurements changed since the last response; see
+    /// `CONTENT_CHANGED_*`
+    pub content_changed: u8,
+    /// Number of measurement blocks in `record`
+    pub block_count: u8,
+    pub record: &'a [u8],
+    pub nonce: &'a [u8; NONCE_SIZE],
+    pub opaque_data: &'a [u8],
+}
+
+impl Measurements<'_> {
+    /// The responder doesn't track whether measurements have changed
+    pub const CONTENT_CHANGED_UNSUPPORTED: u8 = 0b00;
+    pub const CONTENT_CHANGED: u8 = 0b01;
+    pub const CONTENT_UNCHANGED: u8 = 0b10;
+
+    /// Encodes everything but the signature, if there is one.
+    pub fn encode(
+        &self,
+        w: &mut Writer<'_>,
+        version: Version,
+    ) -> Result<(), ErrorCode> {
+        header(
+            w,
+            version,
+            ResponseCode::Measurements,
+            self.total_count,
+            (self.slot & 0xF) | (self.content_changed & 0b11) << 4,
+        )?;
+        w.u8(self.block_count)?;
+        w.u24(
+            self.record
+                .len()
+                .try_into()
+                .map_err(|_| ErrorCode::ResponseTooLarge)?,
+        )?;
+        w.bytes(self.record)?;
+        w.bytes(self.nonce)?;
+        opaque(w, self.opaque_data)
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct KeyExchangeRsp<'a> {
+    pub heartbeat_period: u8,
+    pub rsp_session_id: u16,
+    pub mut_auth_requested: u8,
+    pub req_slot: u8,
+    pub random: &'a [u8; RANDOM_SIZE],
+    /// The responder's ephemeral public key
+    pub exchange_data: &'a [u8],
+    /// Empty unless a summary was requested
+    pub measurement_summary_hash: &'a [u8],
+    pub opaque_data: &'a [u8],
+}
+
+impl KeyExchangeRsp<'_> {
+    /// Encodes everything but the signature and verify data.
+    pub fn encode(
+        &self,
+        w: &mut Writer<'_>,
+        version: Version,
+    ) -> Result<(), ErrorCode> {
+        header(
+            w,
+            version,
+            ResponseCode::KeyExchangeRsp,
+            self.heartbeat_period,
+            0,
+        )?;
+        w.u16(self.rsp_session_id)?;
+        w.u8(self.mut_auth_requested)?;
+        w.u8(self.req_slot)?;
+        w.bytes(self.random)?;
+        w.bytes(self.exchange_data)?;
+        w.bytes(self.measurement_summary_hash)?;
+        opaque(w, self.opaque_data)
+    }
+}
+
+/// Encodes the header of a FINISH_RSP; the caller appends the responder's
+/// verify data if the handshake was in the clear.
+pub fn finish_rsp(
+    w: &mut Writer<'_>,
+    version: Version,
+) -> Result<(), ErrorCode> {
+    header(w, version, ResponseCode::FinishRsp, 0, 0)
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct PskExchangeRsp<'a> {
+    pub heartbeat_period: u8,
+    pub rsp_session_id: u16,
+    /// Empty unless a summary was requested
+    pub measurement_summary_hash: &'a [u8],
+    /// The responder's context, which is mixed into the transcript
+    pub context: &'a [u8],
+    pub opaque_data: &'a [u8],
+}
+
+impl PskExchangeRsp<'_> {
+    /// Encodes everything but the verify data.
+    pub fn encode(
+        &self,
+        w: &mut Writer<'_>,
+        version: Version,
+    ) -> Result<(), ErrorCode> {
+        header(
+            w,
+            version,
+            ResponseCode::PskExchangeRsp,
+            self.heartbeat_period,
+            0,
+        )?;
+        w.u16(self.rsp_session_id)?;
+        w.u16(0)?;
+        w.u16(length(self.context)?)?;
+        w.u16(length(self.opaque_data)?)?;
+        w.bytes(self.measurement_summary_hash)?;
+        w.bytes(self.context)?;
+        w.bytes(self.opaque_data)
+    }
+}
+
+/// Encodes a PSK_FINISH_RSP.
+pub fn psk_finish_rsp(
+    w: &mut Writer<'_>,
+    version: Version,
+) -> Result<(), ErrorCode> {
+    header(w, version, ResponseCode::PskFinishRsp, 0, 0)
+}
+
+/// Encodes a KEY_UPDATE_ACK, which echoes the operation and tag of the
+/// KEY_UPDATE that it answers.
+pub fn key_update_ack(
+    w: &mut Writer<'_>,
+    version: Version,
+    operation: KeyOperation,
+    tag: u8,
+) -> Result<(), ErrorCode> {
+    header(w, version, ResponseCode::KeyUpdateAck, operation as u8, tag)
+}
+
+/// Encodes an END_SESSION_ACK.
+pub fn end_session_ack(
+    w: &mut Writer<'_>,
+    version: Version,
+) -> Result<(), ErrorCode> {
+    header(w, version, ResponseCode::EndSessionAck, 0, 0)
+}
+
+/// Encodes a SET_CERTIFICATE_RSP, for a chain provisioned into `slot`.
+pub fn set_certificate_rsp(
+    w: &mut Writer<'_>,
+    version: Version,
+    slot: u8,
+) -> Result<(), ErrorCode> {
+    header(w, version, ResponseCode::SetCertificateRsp, slot & 0xF, 0)
+}
+
+/// Encodes an ERROR response.
+pub fn error(
+    w: &mut Writer<'_>,
+    version: Version,
+    code: ErrorCode,
+    data: u8,
+) -> Result<(), ErrorCode> {
+    header(w, version, ResponseCode::Error, code as u8, data)
+}
+
+fn opaque(w: &mut Writer<'_>, data: &[u8]) -> Result<(), ErrorCode> {
+    w.u16(length(data)?)?;
+    w.bytes(data)
+}
+
+/// Returns the length of a variable-length field, for the field before it.
+fn length(data: &[u8]) -> Result<u16, ErrorCode> {
+    data.len()
+        .try_into()
+        .map_err(|_| ErrorCode::ResponseTooLarge)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algo::*;
+    use crate::capabilities::*;
+
+    fn encode(
+        f: impl FnOnce(&mut Writer<'_>) -> Result<(), ErrorCode>,
+    ) -> Vec<u8> {
+        let mut buf = [0; 512];
+        let mut w = Writer::new(&mut buf);
+        f(&mut w).unwrap();
+        w.written().to_vec()
+    }
+
+    #[test]
+    fn version_rsp() {
+        assert_eq!(
+            encode(|w| version(w, &[Version::V1_1, Version::V1_2])),
+            [0x10, 0x04, 0x00, 0x00, 0x00, 0x02, 0x00, 0x11, 0x00, 0x12]
+        );
+    }
+
+    #[test]
+    fn capabilities() {
+        let caps = Capabilities {
+            ct_exponent: 14,
+            flags: CERT | CHAL | MEAS_SIG | KEY_EX | HBEAT,
+            data_transfer_size: 1024,
+            max_message_size: 1024,
+        };
+        assert_eq!(
+            encode(|w| caps.encode(w, Version::V1_2)),
+            [
+                0x12, 0x61, 0x00, 0x00, // header
+                0x00, 0x0E, 0x00, 0x00, // reserved, CTExponent, reserved
+                0x16, 0x22, 0x00, 0x00, // flags
+                0x00, 0x04, 0x00, 0x00, // DataTransferSize
+                0x00, 0x04, 0x00, 0x00, // MaxSPDMmsgSize
+            ]
+        );
+        assert_eq!(encode(|w| caps.encode(w, Version::V1_1)).len(), 12);
+    }
+
+    #[test]
+    fn algorithms_rsp() {
+        let selected = Algorithms {
+            measurement_spec: measurement_spec::DMTF,
+            other_params: other_params::OPAQUE_DATA_FMT1,
+            measurement_hash: measurement_hash::SHA_384,
+            base_asym: base_asym::ECDSA_P384,
+            base_hash: base_hash::SHA_384,
+            dhe: dhe::SECP384R1,
+            aead: aead::AES_256_GCM,
+            req_base_asym: 0,
+            key_schedule: key_schedule::SPDM,
+        };
+        let all = AlgType::ALL.iter().fold(0, |m, t| m | t.bit());
+        assert_eq!(
+            encode(|w| algorithms(w, Version::V1_2, &selected, all)),
+            [
+                0x12, 0x63, 0x04, 0x00, // header
+                0x34, 0x00, // length
+                0x01, 0x02, // MeasurementSpecification, OtherParams
+                0x04, 0x00, 0x00, 0x00, // MeasurementHashAlgo
+                0x80, 0x00, 0x00, 0x00, // BaseAsymSel
+                0x02, 0x00, 0x00, 0x00, // BaseHashSel
+                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                0x00, 0x00, // reserved
+                0x00, 0x00, 0x00, 0x00, // ext counts, reserved
+                0x02, 0x20, 0x10, 0x00, // DHE
+                0x03, 0x20, 0x02, 0x00, // AEAD
+                0x04, 0x20, 0x00, 0x00, // ReqBaseAsymAlg
+                0x05, 0x20, 0x01, 0x00, // KeySchedule
+            ]
+        );
+
+        // Only the structures that were asked for are included
+        let rsp = encode(|w| {
+            algorithms(w, Version::V1_2, &selected, AlgType::Aead.bit())
+        });
+        assert_eq!(rsp.len(), 40);
+        assert_eq!(rsp[2], 1);
+        assert_eq!(rsp[4], 40);
+        assert_eq!(rsp[36..], [0x03, 0x20, 0x02, 0x00]);
+    }
+
+    #[test]
+    fn certificate_rsp() {
+        assert_eq!(
+            encode(|w| {
+                certificate(w, Version::V1_2, 1, 3, 0x100)
+                    .map(|p| p.copy_from_slice(&[1, 2, 3]))
+            }),
+            [0x12, 0x02, 0x01, 0x00, 0x03, 0x00, 0x00, 0x01, 1, 2, 3]
+        );
+        assert_eq!(
+            encode(|w| set_certificate_rsp(w, Version::V1_2, 5)),
+            [0x12, 0x6E, 0x05, 0x00]
+        );
+    }
+
+    #[test]
+    fn challenge_auth() {
+        let rsp = ChallengeAuth {
+            slot: 0,
+            slot_mask: 0b1,
+            cert_chain_hash: &[0xCC; 48],
+            nonce: &[0x11; 32],
+            measurement_summary_hash: &[],
+            opaque_data: &[],
+        };
+        let out = encode(|w| rsp.encode(w, Version::V1_2));
+        assert_eq!(out[..4], [0x12, 0x03, 0x00, 0x01]);
+        assert_eq!(out[4..52], [0xCC; 48]);
+        assert_eq!(out[52..84], [0x11; 32]);
+        assert_eq!(out[84..], [0x00, 0x00]);
+    }
+
+    #[test]
+    fn measurements() {
+        let rsp = Measurements {
+            total_count: 0,
+            slot: 2,
+            content_changed: Measurements::CONTENT_UNCHANGED,
+            block_count: 1,
+            record: &[0xAB; 7],
+            nonce: &[0x11; 32],
+            opaque_data: &[0xEE],
+        };
+        let out = encode(|w| rsp.encode(w, Version::V1_2));
+        assert_eq!(out[..8], [0x12, 0x60, 0x00, 0x22, 0x01, 0x07, 0x00, 0x00]);
+        assert_eq!(out[8..15], [0xAB; 7]);
+        assert_eq!(out[15..47], [0x11; 32]);
+        assert_eq!(out[47..], [0x01, 0x00, 0xEE]);
+    }
+
+    #[test]
+    fn key_exchange_rsp() {
+        let rsp = KeyExchangeRsp {
+            heartbeat_period: 0,
+            rsp_session_id: 0xFFFF,
+            mut_auth_requested: 0,
+            req_slot: 0,
+            random: &[0x22; 32],
+            exchange_data: &[0x33; 96],
+            measurement_summary_hash: &[],
+            opaque_data: &[0x44; 4],
+        };
+        let out = encode(|w| rsp.encode(w, Version::V1_2));
+        assert_eq!(out[..8], [0x12, 0x64, 0x00, 0x00, 0xFF, 0xFF, 0x00, 0x00]);
+        assert_eq!(out[8..40], [0x22; 32]);
+        assert_eq!(out[40..136], [0x33; 96]);
+        assert_eq!(out[136..], [0x04, 0x00, 0x44, 0x44, 0x44, 0x44]);
+    }
+
+    #[test]
+    fn psk_exchange_rsp() {
+        let rsp = PskExchangeRsp {
+            heartbeat_period: 0,
+            rsp_session_id: 0x0001,
+            measurement_summary_hash: &[],
+            context: &[0x55; 32],
+            opaque_data: &[0x66; 4],
+        };
+        let out = encode(|w| rsp.encode(w, Version::V1_2));
+        assert_eq!(
+            out[..12],
+            [
+                0x12, 0x66, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x20, 0x00,
+                0x04, 0x00
+            ]
+        );
+        assert_eq!(out[12..44], [0x55; 32]);
+        assert_eq!(out[44..], [0x66; 4]);
+    }
+
+    #[test]
+    fn session_acks() {
+        assert_eq!(
+            encode(|w| key_update_ack(
+                w,
+                Version::V1_2,
+                KeyOperation::VerifyNewKey,
+                0x5C
+            )),
+            [0x12, 0x69, 0x03, 0x5C]
+        );
+        assert_eq!(
+            encode(|w| end_session_ack(w, Version::V1_2)),
+            [0x12, 0x6C, 0x00, 0x00]
+        );
+        assert_eq!(
+            encode(|w| psk_finish_rsp(w, Version::V1_2)),
+            [0x12, 0x67, 0x00, 0x00]
+        );
+    }
+
+    #[test]
+    fn error_rsp() {
+        assert_eq!(
+            encode(|w| error(
+                w,
+                Version::V1_2,
+                ErrorCode::UnexpectedRequest,
+                0
+            )),
+            [0x12, 0x7F, 0x04, 0x00]
+        );
+    }
+
+    #[test]
+    fn too_large() {
+        let mut buf = [0; 9];
+        let mut w = Writer::new(&mut buf);
+        assert_eq!(
+            version(&mut w, &[Version::V1_0, Version::V1_1, Version::V1_2]),
+            Err(ErrorCode::ResponseTooLarge)
+        );
+    }
+}
@@ -0,0 +1,455 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Secure sessions: the key schedule of DSP0274, and the secured messages
+//! (DSP0277) that carry a session's traffic.
+//!
+//! A session starts with a handshake. KEY_EXCHANGE (or PSK_EXCHANGE) yields a
+//! shared secret, from ephemeral Diffie-Hellman (or a pre-shared key), which
+//! together with the transcript so far (TH1) yields the handshake secrets.
+//! FINISH (or PSK_FINISH) and its response travel under keys derived from
+//! those, and prove that both sides have them. The transcript through the
+//! handshake (TH2) then yields the data secrets, under which the rest of the
+//! session travels, and which KEY_UPDATE replaces with their successors.
+//!
+//! Every secret is the size of the negotiated hash, and every derivation is
+//! an HKDF-Expand, with an SPDM-specific label (DSP0274's `BinConcat`). The
+//! only AEAD that we support is AES-256-GCM.
+//!
+//! A secured message looks like this, where the transport binding decides
+//! how many bytes of the sequence number are sent:
+//!
+//! | Field             | Size     | Protection            |
+//! |-------------------|----------|-----------------------|
+//! | Session ID        | 4        | Authenticated         |
+//! | Sequence number   | 0-8      | Authenticated         |
+//! | Length            | 2        | Authenticated         |
+//! | Application data length | 2  | Encrypted             |
+//! | Application data  | variable | Encrypted             |
+//! | MAC               | 16       |                       |
+//!
+//! The length covers everything after it. Random padding may follow the
+//! application data, but we never send any.
+
+use aes_gcm::aead::AeadInPlace;
+use aes_gcm::{Aes256Gcm, KeyInit};
+use digest::core_api::BlockSizeUser;
+use digest::{Digest, Output};
+use hkdf::SimpleHkdf;
+use hmac::{Mac, SimpleHmac};
+
+use crate::{ErrorCode, Version};
+
+/// Size of an AES-256-GCM key
+pub const KEY_SIZE: usize = 32;
+
+/// Size of an AES-256-GCM IV
+pub const IV_SIZE: usize = 12;
+
+/// Size of an AES-256-GCM tag, which ends every secured message
+pub const TAG_SIZE: usize = 16;
+
+/// Returns the ID of a session from the halves contributed by the requester
+/// and the responder.
+pub fn session_id(req_session_id: u16, rsp_session_id: u16) -> u32 {
+    u32::from(req_session_id) | u32::from(rsp_session_id) << 16
+}
+
+/// Returns the ID of the session that a secured message belongs to.
+pub fn secured_session_id(msg: &[u8]) -> Option<u32> {
+    let (id, _) = msg.split_first_chunk()?;
+    Some(u32::from_le_bytes(*id))
+}
+
+/// Returns where the application data starts in a secured message, for a
+/// binding that sends `seq_size` bytes of sequence number.
+pub fn app_data_offset(seq_size: usize) -> usize {
+    4 + seq_size + 2 + 2
+}
+
+/// Returns the size of a secured message that carries `len` bytes of
+/// application data.
+pub fn secured_size(seq_size: usize, len: usize) -> usize {
+    app_data_offset(seq_size) + len + TAG_SIZE
+}
+
+/// Derives a secret of `out.len()` bytes from `secret`, with the HKDF-Expand
+/// label made of `label` and `context`.
+fn expand<D>(
+    version: Version,
+    secret: &Output<D>,
+    label: &[u8],
+    context: &[u8],
+    out: &mut [u8],
+) where
+    D: Digest + BlockSizeUser + Clone,
+{
+    // Secrets are always the size of a hash, which is all that this needs,
+    // and we never ask for more than 255 hashes' worth.
+    let hkdf = SimpleHkdf::<D>::from_prk(secret).unwrap();
+    let mut tag = *b"spdm1.2 ";
+    tag[4] = b'0' + (version.0 >> 4);
+    tag[6] = b'0' + (version.0 & 0xF);
+    let len = (out.len() as u16).to_le_bytes();
+    hkdf.expand_multi_info(&[&len, &tag, label, context], out)
+        .unwrap();
+}
+
+/// Derives a hash-sized secret from `secret`.
+fn derive<D>(
+    version: Version,
+    secret: &Output<D>,
+    label: &[u8],
+    context: &[u8],
+) -> Output<D>
+where
+    D: Digest + BlockSizeUser + Clone,
+{
+    let mut out = Output::<D>::default();
+    expand::<D>(version, secret, label, context, &mut out);
+    out
+}
+
+/// Returns the HMAC of the transcript hash `th` with the finished key that
+/// comes from a handshake secret.
+fn verify_data<D>(version: Version, secret: &Output<D>, th: &[u8]) -> Output<D>
+where
+    D: Digest + BlockSizeUser + Clone,
+{
+    let key = derive::<D>(version, secret, b"finished", &[]);
+    let mut mac = <SimpleHmac<D> as Mac>::new_from_slice(&key).unwrap();
+    mac.update(th);
+    mac.finalize().into_bytes()
+}
+
+/// The secrets of a session's handshake.
+#[derive(Clone)]
+pub struct Handshake<D: Digest> {
+    version: Version,
+    secret: Output<D>,
+    /// The requester's handshake secret, which protects FINISH
+    pub requester: Output<D>,
+    /// The responder's handshake secret, which protects FINISH_RSP
+    pub responder: Output<D>,
+}
+
+impl<D> Handshake<D>
+where
+    D: Digest + BlockSizeUser + Clone,
+{
+    /// Derives the handshake secrets from the DHE shared secret or the PSK,
+    /// and TH1: the hash of the transcript through KEY_EXCHANGE_RSP (or
+    /// PSK_EXCHANGE_RSP), less its verify data.
+    pub fn new(version: Version, shared_secret: &[u8], th1: &[u8]) -> Self {
+        let salt = Output::<D>::default();
+        let (secret, _) = SimpleHkdf::<D>::extract(Some(&salt), shared_secret);
+        Self {
+            version,
+            requester: derive::<D>(version, &secret, b"req hs data", th1),
+            responder: derive::<D>(version, &secret, b"rsp hs data", th1),
+            secret,
+        }
+    }
+
+    /// Returns the verify data that the requester must send in FINISH (or
+    /// PSK_FINISH), given the hash of the transcript up to it.
+    pub fn requester_verify_data(&self, th: &[u8]) -> Output<D> {
+        verify_data::<D>(self.version, &self.requester, th)
+    }
+
+    /// Returns the verify data that we send in KEY_EXCHANGE_RSP (or
+    /// PSK_EXCHANGE_RSP), given TH1.
+    pub fn responder_verify_data(&self, th: &[u8]) -> Output<D> {
+        verify_data::<D>(self.version, &self.responder, th)
+    }
+
+    /// Derives the requester's and responder's data secrets from TH2: the
+    /// hash of the transcript through FINISH_RSP (or PSK_FINISH_RSP).
+    pub fn data_secrets(&self, th2: &[u8]) -> (Output<D>, Output<D>) {
+        let salt = derive::<D>(self.version, &self.secret, b"derived", &[]);
+        let zeros = Output::<D>::default();
+        let (master, _) = SimpleHkdf::<D>::extract(Some(&salt), &zeros);
+        (
+            derive::<D>(self.version, &master, b"req app data", th2),
+            derive::<D>(self.version, &master, b"rsp app data", th2),
+        )
+    }
+}
+
+/// One direction of a session's traffic: the secret that protects it, the
+/// key and IV derived from that secret, and the sequence number of the next
+/// message.
+#[derive(Clone)]
+pub struct Channel<D: Digest> {
+    version: Version,
+    secret: Output<D>,
+    key: [u8; KEY_SIZE],
+    iv: [u8; IV_SIZE],
+    seq: u64,
+}
+
+impl<D> Channel<D>
+where
+    D: Digest + BlockSizeUser + Clone,
+{
+    pub fn new(version: Version, secret: Output<D>) -> Self {
+        let mut key = [0; KEY_SIZE];
+        let mut iv = [0; IV_SIZE];
+        expand::<D>(version, &secret, b"key", &[], &mut key);
+        expand::<D>(version, &secret, b"iv", &[], &mut iv);
+        Self {
+            version,
+            secret,
+            key,
+            iv,
+            seq: 0,
+        }
+    }
+
+    /// Replaces the secret with its successor, as KEY_UPDATE does, which
+    /// starts the sequence numbers over.
+    pub fn update(&mut self) {
+        let secret =
+            derive::<D>(self.version, &self.secret, b"traffic upd", &[]);
+        *self = Self::new(self.version, secret);
+    }
+
+    /// Returns the nonce for the next message: the IV, with the sequence
+    /// number mixed into its first eight bytes.
+    fn nonce(&self) -> [u8; IV_SIZE] {
+        let mut nonce = self.iv;
+        for (n, s) in nonce.iter_mut().zip(self.seq.to_le_bytes()) {
+            *n ^= s;
+        }
+        nonce
+    }
+
+    /// Seals the `len` bytes of application data that start at
+    /// `app_data_offset(seq_size)` in `buf` into a secured message for
+    /// `session_id`, returning its size.
+    pub fn seal(
+        &mut self,
+        session_id: u32,
+        seq_size: usize,
+        buf: &mut [u8],
+        len: usize,
+    ) -> Result<usize, ErrorCode> {
+        let size = secured_size(seq_size, len);
+        let buf = buf.get_mut(..size).ok_or(ErrorCode::ResponseTooLarge)?;
+        let length = u16::try_from(2 + len + TAG_SIZE)
+            .map_err(|_| ErrorCode::ResponseTooLarge)?;
+
+        let (aad, rest) = buf.split_at_mut(4 + seq_size + 2);
+        aad[..4].copy_from_slice(&session_id.to_le_bytes());
+        aad[4..4 + seq_size]
+            .copy_from_slice(&self.seq.to_le_bytes()[..seq_size]);
+        aad[4 + seq_size..].copy_from_slice(&length.to_le_bytes());
+        let (text, tag) = rest.split_at_mut(2 + len);
+        text[..2].copy_from_slice(&(len as u16).to_le_bytes());
+
+        let cipher = Aes256Gcm::new(&self.key.into());
+        let t = cipher
+            .encrypt_in_place_detached(&self.nonce().into(), aad, text)
+            .map_err(|_| ErrorCode::Unspecified)?;
+        tag.copy_from_slice(&t);
+        self.seq += 1;
+        Ok(size)
+    }
+
+    /// Opens a secured message (in place), returning the application data
+    /// that it carries.
+    ///
+    /// Messages must arrive in sequence; anything that's out of sequence,
+    /// malformed or not authentic is `ErrorCode::DecryptError`.
+    pub fn open<'a>(
+        &mut self,
+        seq_size: usize,
+        msg: &'a mut [u8],
+    ) -> Result<&'a [u8], ErrorCode> {
+        let aad_size = 4 + seq_size + 2;
+        if msg.len() < aad_size + 2 + TAG_SIZE {
+            return Err(ErrorCode::DecryptError);
+        }
+        let (aad, rest) = msg.split_at_mut(aad_size);
+        if aad[4..4 + seq_size] != self.seq.to_le_bytes()[..seq_size] {
+            return Err(ErrorCode::DecryptError);
+        }
+        let length = u16::from_le_bytes([aad[aad_size - 2], aad[aad_size - 1]]);
+        if usize::from(length) != rest.len() {
+            return Err(ErrorCode::DecryptError);
+        }
+        let (text, tag) = rest.split_at_mut(rest.len() - TAG_SIZE);
+
+        let cipher = Aes256Gcm::new(&self.key.into());
+        cipher
+            .decrypt_in_place_detached(
+                &self.nonce().into(),
+                aad,
+                text,
+                (&*tag).into(),
+            )
+            .map_err(|_| ErrorCode::DecryptError)?;
+        self.seq += 1;
+
+        let (len, data) = text.split_at(2);
+        let len = u16::from_le_bytes([len[0], len[1]]);
+        data.get(..len.into()).ok_or(ErrorCode::DecryptError)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sha2::Sha256;
+
+    const V: Version = Version::V1_2;
+    const SEQ: usize = 2;
+
+    fn pair() -> (Channel<Sha256>, Channel<Sha256>) {
+        let secret = Output::<Sha256>::from([0x42; 32]);
+        (Channel::new(V, secret), Channel::new(V, secret))
+    }
+
+    fn seal(ch: &mut Channel<Sha256>, id: u32, data: &[u8]) -> Vec<u8> {
+        let mut buf = [0; 128];
+        let off = app_data_offset(SEQ);
+        buf[off..off + data.len()].copy_from_slice(data);
+        let n = ch.seal(id, SEQ, &mut buf, data.len()).unwrap();
+        buf[..n].to_vec()
+    }
+
+    fn hex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    // Known answers, from an independent implementation of the key schedule
+    // and secured message format
+    #[test]
+    fn key_schedule() {
+        let shared: Vec<u8> = (0..32).collect();
+        let th1 = Sha256::digest(b"th1");
+        let th2 = Sha256::digest(b"th2");
+
+        let hs = Handshake::<Sha256>::new(V, &shared, &th1);
+        assert_eq!(
+            hs.responder_verify_data(&th1).to_vec(),
+            hex("ce92610425a069e51b4fdcaf23e6be4f\
+                 1f13347fb44149c47e33b2399e61558b")
+        );
+        assert_eq!(
+            hs.requester_verify_data(&th2).to_vec(),
+            hex("3c51741735c7412f344977a143e356b4\
+                 2452424f79d529d5942d31d8a1d48379")
+        );
+
+        let (req, rsp) = hs.data_secrets(&th2);
+        assert_eq!(
+            req.to_vec(),
+            hex("c4edbb153829b27e9008379b6fcab5d7\
+                 73c9c81548ba35028180c6e22f88acaf")
+        );
+        assert_eq!(
+            rsp.to_vec(),
+            hex("539e4a7b8c7170332d026d7a330eb48e\
+                 359b26dbe9a69dc4f8d9ccd33917a7b8")
+        );
+
+        let mut ch = Channel::<Sha256>::new(V, rsp);
+        assert_eq!(
+            seal(&mut ch, session_id(0xFFFE, 1), &[5, 0x12, 0x6C, 0, 0]),
+            hex("feff0100000017008d44e9ae0ee3090d\
+                 a88ce62832d2d4a22975bc6d23aedb")
+        );
+        ch.update();
+        assert_eq!(
+            ch.key.to_vec(),
+            hex("9eb1a74df7cf97ebbf2a05127cdfca52\
+                 d88a803528c3a061bb63bf542a9f6a37")
+        );
+    }
+
+    #[test]
+    fn ids() {
+        let id = session_id(0xFFFE, 0x0001);
+        assert_eq!(id.to_le_bytes(), [0xFE, 0xFF, 0x01, 0x00]);
+        assert_eq!(secured_session_id(&id.to_le_bytes()), Some(id));
+        assert_eq!(secured_session_id(&[0; 3]), None);
+    }
+
+    #[test]
+    fn round_trip() {
+        let (mut tx, mut rx) = pair();
+        let id = session_id(1, 2);
+        let data = [0x05, 0x12, 0xEC, 0x00, 0x00];
+
+        for seq in 0..3u8 {
+            let mut msg = seal(&mut tx, id, &data);
+            assert_eq!(msg.len(), secured_size(SEQ, data.len()));
+            assert_eq!(msg[..4], id.to_le_bytes());
+            assert_eq!(msg[4..6], [seq, 0]);
+            assert_eq!(msg[6..8], (2 + 5 + 16u16).to_le_bytes());
+            assert_ne!(msg[10..15], data);
+            assert_eq!(rx.open(SEQ, &mut msg), Ok(&data[..]));
+        }
+    }
+
+    #[test]
+    fn bad_messages() {
+        let (mut tx, mut rx) = pair();
+        let id = session_id(1, 2);
+        let msg = seal(&mut tx, id, &[1, 2, 3]);
+
+        // Anything tampered with, authenticated or encrypted
+        for i in [0, 4, 6, 9, msg.len() - 1] {
+            let mut m = msg.clone();
+            m[i] ^= 1;
+            assert_eq!(rx.open(SEQ, &mut m), Err(ErrorCode::DecryptError));
+        }
+        let mut m = msg[..msg.len() - 1].to_vec();
+        assert_eq!(rx.open(SEQ, &mut m), Err(ErrorCode::DecryptError));
+
+        // Replays, and skipped messages
+        assert!(rx.open(SEQ, &mut msg.clone()).is_ok());
+        assert_eq!(
+            rx.open(SEQ, &mut msg.clone()),
+            Err(ErrorCode::DecryptError)
+        );
+        seal(&mut tx, id, &[4]);
+        let mut m = seal(&mut tx, id, &[5]);
+        assert_eq!(rx.open(SEQ, &mut m), Err(ErrorCode::DecryptError));
+    }
+
+    #[test]
+    fn update() {
+        let (mut tx, mut rx) = pair();
+        let id = session_id(1, 2);
+        let mut msg = seal(&mut tx, id, &[1]);
+        rx.open(SEQ, &mut msg).unwrap();
+
+        // After an update, the old key no longer works, and sequence
+        // numbers start over.
+        tx.update();
+        let mut msg = seal(&mut tx, id, &[2]);
+        assert_eq!(msg[4..6], [0, 0]);
+        assert_eq!(
+            rx.clone().open(SEQ, &mut msg.clone()),
+            Err(ErrorCode::DecryptError)
+        );
+        rx.update();
+        assert_eq!(rx.open(SEQ, &mut msg), Ok(&[2][..]));
+    }
+
+    #[test]
+    fn too_large() {
+        let (mut tx, _) = pair();
+        let mut buf = [0; 32];
+        assert_eq!(
+            tx.seal(0, SEQ, &mut buf, 8),
+            Err(ErrorCode::ResponseTooLarge)
+        );
+    }
+}
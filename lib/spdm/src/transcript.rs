@@ -0,0 +1,182 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Transcripts of the messages that signatures cover.
+//!
+//! DSP0274 builds every signed transcript on the same prefix, message A: the
+//! GET_VERSION, GET_CAPABILITIES and NEGOTIATE_ALGORITHMS exchanges (the
+//! "VCA"). CHALLENGE_AUTH signs M1, which is A followed by the
+//! certificate-retrieval messages (B) and the challenge itself (C); signed
+//! MEASUREMENTS sign L1, which is A followed by the measurement requests and
+//! responses since the last signed one. Sessions start from A as well.
+//!
+//! Rather than keeping messages around, we keep a running hash of each
+//! transcript, and clone the hash of A whenever a transcript starts over.
+
+use digest::{Digest, Output};
+
+use crate::Version;
+
+#[derive(Clone)]
+pub struct Transcript<D> {
+    vca: D,
+    m1: D,
+    l1: D,
+}
+
+impl<D: Digest + Clone> Default for Transcript<D> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<D: Digest + Clone> Transcript<D> {
+    pub fn new() -> Self {
+        Self {
+            vca: D::new(),
+            m1: D::new(),
+            l1: D::new(),
+        }
+    }
+
+    /// Forgets everything, as when a new GET_VERSION arrives.
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    /// Appends a message that's part of version, capability or algorithm
+    /// negotiation (message A).
+    pub fn append_vca(&mut self, msg: &[u8]) {
+        self.vca.update(msg);
+        self.m1.update(msg);
+        self.l1.update(msg);
+    }
+
+    /// Appends a message from certificate retrieval or a challenge (messages
+    /// B and C).
+    pub fn append_m1(&mut self, msg: &[u8]) {
+        self.m1.update(msg);
+    }
+
+    /// Appends a measurement request or response (message L).
+    pub fn append_l1(&mut self, msg: &[u8]) {
+        self.l1.update(msg);
+    }
+
+    /// Returns the hash of M1, to be signed by CHALLENGE_AUTH, and starts M1
+    /// over.
+    pub fn finish_m1(&mut self) -> Output<D> {
+        core::mem::replace(&mut self.m1, self.vca.clone()).finalize()
+    }
+
+    /// Returns the hash of L1, to be signed by MEASUREMENTS, and starts L1
+    /// over.
+    pub fn finish_l1(&mut self) -> Output<D> {
+        core::mem::replace(&mut self.l1, self.vca.clone()).finalize()
+    }
+
+    /// Starts M1 over, discarding anything after message A.
+    pub fn reset_m1(&mut self) {
+        self.m1 = self.vca.clone();
+    }
+
+    /// Starts L1 over, discarding anything after message A; this happens
+    /// whenever a request other than GET_MEASUREMENTS arrives.
+    pub fn reset_l1(&mut self) {
+        self.l1 = self.vca.clone();
+    }
+
+    /// Returns a hasher that has seen message A, from which session
+    /// transcripts are built.
+    pub fn vca(&self) -> D {
+        self.vca.clone()
+    }
+}
+
+/// Signing context for CHALLENGE_AUTH
+pub const CHALLENGE_AUTH_CONTEXT: &str = "responder-challenge_auth signing";
+/// Signing context for MEASUREMENTS
+pub const MEASUREMENTS_CONTEXT: &str = "responder-measurements signing";
+/// Signing context for KEY_EXCHANGE_RSP
+pub const KEY_EXCHANGE_RSP_CONTEXT: &str = "responder-key_exchange_rsp signing";
+/// Signing context for FINISH, when the requester signs it
+pub const FINISH_CONTEXT: &str = "requester-finish signing";
+
+/// Size of the prefix that precedes a transcript hash when signing
+pub const SIGNING_PREFIX_SIZE: usize = 100;
+
+/// Returns the prefix that's signed along with a transcript hash in SPDM 1.2
+/// and later: "dmtf-spdm-v1.2.*" four times, then `context` right-aligned in
+/// 36 zero-padded bytes.
+///
+/// What's signed is this prefix followed by the transcript hash.
+pub fn signing_prefix(
+    version: Version,
+    context: &str,
+) -> [u8; SIGNING_PREFIX_SIZE] {
+    let mut out = [0; SIGNING_PREFIX_SIZE];
+    let mut tag = *b"dmtf-spdm-v1.2.*";
+    tag[11] = b'0' + (version.0 >> 4);
+    tag[13] = b'0' + (version.0 & 0xF);
+    for chunk in out[..64].chunks_exact_mut(16) {
+        chunk.copy_from_slice(&tag);
+    }
+    out[SIGNING_PREFIX_SIZE - context.len()..]
+        .copy_from_slice(context.as_bytes());
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sha2::Sha256;
+
+    const A: [&[u8]; 2] = [&[0x10, 0x84, 0x00, 0x00], &[0x10, 0x04, 0x00]];
+    const B: &[u8] = &[0x12, 0x81, 0x00, 0x00];
+    const L: &[u8] = &[0x12, 0xE0, 0x00, 0x00];
+
+    fn hash(parts: &[&[u8]]) -> Output<Sha256> {
+        let mut h = Sha256::new();
+        for p in parts {
+            h.update(p);
+        }
+        h.finalize()
+    }
+
+    #[test]
+    fn transcripts() {
+        let mut t = Transcript::<Sha256>::new();
+        for m in A {
+            t.append_vca(m);
+        }
+        t.append_m1(B);
+        t.append_l1(L);
+
+        assert_eq!(t.finish_m1(), hash(&[A[0], A[1], B]));
+        assert_eq!(t.finish_l1(), hash(&[A[0], A[1], L]));
+
+        // Both start over from message A
+        assert_eq!(t.finish_m1(), hash(&A));
+        t.append_l1(L);
+        t.reset_l1();
+        assert_eq!(t.finish_l1(), hash(&A));
+        assert_eq!(t.vca().finalize(), hash(&A));
+
+        t.reset();
+        assert_eq!(t.finish_m1(), hash(&[]));
+    }
+
+    #[test]
+    fn prefix() {
+        let p = signing_prefix(Version::V1_2, CHALLENGE_AUTH_CONTEXT);
+        assert_eq!(&p[..16], b"dmtf-spdm-v1.2.*");
+        assert_eq!(&p[48..64], b"dmtf-spdm-v1.2.*");
+        assert_eq!(p[64..68], [0; 4]);
+        assert_eq!(&p[68..], CHALLENGE_AUTH_CONTEXT.as_bytes());
+
+        let p = signing_prefix(Version(0x13), FINISH_CONTEXT);
+        assert_eq!(&p[..16], b"dmtf-spdm-v1.3.*");
+        assert_eq!(p[64..76], [0; 12]);
+    }
+}
@@ -0,0 +1,141 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Little-endian readers and writers for message fields.
+
+use crate::ErrorCode;
+
+/// Reads fields from the front of a request.
+///
+/// Running out of bytes means that the request was malformed, so every
+/// failure is `ErrorCode::InvalidRequest`.
+pub struct Reader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(buf: &'a [u8]) -> Self {
+        Self { buf }
+    }
+
+    pub fn bytes(&mut self, n: usize) -> Result<&'a [u8], ErrorCode> {
+        if n > self.buf.len() {
+            return Err(ErrorCode::InvalidRequest);
+        }
+        let (out, rest) = self.buf.split_at(n);
+        self.buf = rest;
+        Ok(out)
+    }
+
+    pub fn array<const N: usize>(&mut self) -> Result<&'a [u8; N], ErrorCode> {
+        Ok(self.bytes(N)?.try_into().unwrap())
+    }
+
+    pub fn u8(&mut self) -> Result<u8, ErrorCode> {
+        Ok(self.array::<1>()?[0])
+    }
+
+    pub fn u16(&mut self) -> Result<u16, ErrorCode> {
+        Ok(u16::from_le_bytes(*self.array()?))
+    }
+
+    pub fn u24(&mut self) -> Result<u32, ErrorCode> {
+        let &[a, b, c] = self.array()?;
+        Ok(u32::from_le_bytes([a, b, c, 0]))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, ErrorCode> {
+        Ok(u32::from_le_bytes(*self.array()?))
+    }
+
+    /// Number of bytes that haven't been read yet
+    pub fn remaining(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Checks that every byte has been read, since trailing bytes in a
+    /// fixed-size request mean that it's malformed.
+    pub fn finish(self) -> Result<(), ErrorCode> {
+        if self.buf.is_empty() {
+            Ok(())
+        } else {
+            Err(ErrorCode::InvalidRequest)
+        }
+    }
+}
+
+/// Writes fields into a response buffer.
+///
+/// Running out of space means that the response doesn't fit, so every failure
+/// is `ErrorCode::ResponseTooLarge`.
+pub struct Writer<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl<'a> Writer<'a> {
+    pub fn new(buf: &'a mut [u8]) -> Self {
+        Self { buf, len: 0 }
+    }
+
+    /// Reserves the next `n` bytes, returning them so that they can be filled
+    /// in.
+    pub fn reserve(&mut self, n: usize) -> Result<&mut [u8], ErrorCode> {
+        let out = self
+            .buf
+            .get_mut(self.len..self.len + n)
+            .ok_or(ErrorCode::ResponseTooLarge)?;
+        self.len += n;
+        Ok(out)
+    }
+
+    pub fn bytes(&mut self, data: &[u8]) -> Result<(), ErrorCode> {
+        self.reserve(data.len())?.copy_from_slice(data);
+        Ok(())
+    }
+
+    pub fn zeros(&mut self, n: usize) -> Result<(), ErrorCode> {
+        self.reserve(n)?.fill(0);
+        Ok(())
+    }
+
+    pub fn u8(&mut self, v: u8) -> Result<(), ErrorCode> {
+        self.bytes(&[v])
+    }
+
+    pub fn u16(&mut self, v: u16) -> Result<(), ErrorCode> {
+        self.bytes(&v.to_le_bytes())
+    }
+
+    /// Writes the low three bytes of `v`, which must fit in them.
+    pub fn u24(&mut self, v: u32) -> Result<(), ErrorCode> {
+        if v >= 1 << 24 {
+            return Err(ErrorCode::ResponseTooLarge);
+        }
+        self.bytes(&v.to_le_bytes()[..3])
+    }
+
+    pub fn u32(&mut self, v: u32) -> Result<(), ErrorCode> {
+        self.bytes(&v.to_le_bytes())
+    }
+
+    /// Number of bytes written so far
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Number of bytes that can still be written
+    pub fn remaining(&self) -> usize {
+        self.buf.len() - self.len
+    }
+
+    /// Returns everything written so far.
+    pub fn written(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+}
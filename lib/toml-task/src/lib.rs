@@ -21,6 +21,14 @@ pub struct Task<T = ordered_toml::Value> {
     pub stacksize: Option<u32>,
     #[serde(default)]
     pub start: bool,
+    /// Kernel ticks this task may run before the kernel rotates to another
+    /// runnable task of the same priority. If absent, the task keeps the CPU
+    /// until it blocks or a more important task is ready.
+    pub time_slice: Option<u32>,
+    /// Whether the task may read the image (the kernel's and every task's
+    /// flash) through the `read_image` kipc
+    #[serde(default)]
+    pub read_image: bool,
 
     #[serde(default)]
     pub uses: Vec<String>,
@@ -35,6 +43,12 @@ pub struct Task<T = ordered_toml::Value> {
     #[serde(default)]
     pub extern_regions: Vec<String>,
 
+    /// Idol interfaces served by this task. Clients that name the interface
+    /// they use through a task slot (`task_slot!(VAR, slot, ClientType)`) may
+    /// only be wired to a task that serves it.
+    #[serde(default)]
+    pub serves: Vec<String>,
+
     // Order matters here:
     // TOML serialization doesn't allow us to put a value type after any Table
     // type, so we put all of our `IndexMap` (and `config`, which often contains
@@ -195,6 +195,59 @@ pub struct ULease {
     pub length: u32,
 }
 
+impl ULease {
+    /// Bit position in `attributes` of the lender's `TaskId`, in a forwarded
+    /// lease.
+    const FORWARDED_LENDER_SHIFT: u32 = 16;
+    /// Bit position in `attributes` of the lender's lease number, in a
+    /// forwarded lease.
+    const FORWARDED_LEASE_SHIFT: u32 = 8;
+
+    /// Makes a lease that forwards `length` bytes, starting `offset` bytes
+    /// into lease number `lease_number` that `lender` has lent to the sender.
+    ///
+    /// `rights` gives the access (`READ` and/or `WRITE`) to pass on; the
+    /// borrower gets only the rights that both this and the original lease
+    /// grant.
+    pub const fn forwarded(
+        lender: TaskId,
+        lease_number: u8,
+        rights: LeaseAttributes,
+        offset: u32,
+        length: u32,
+    ) -> Self {
+        let rights = rights.bits()
+            & (LeaseAttributes::READ.bits() | LeaseAttributes::WRITE.bits());
+        Self {
+            attributes: LeaseAttributes::from_bits_retain(
+                rights
+                    | LeaseAttributes::FORWARDED.bits()
+                    | (lease_number as u32) << Self::FORWARDED_LEASE_SHIFT
+                    | (lender.0 as u32) << Self::FORWARDED_LENDER_SHIFT,
+            ),
+            base_address: offset,
+            length,
+        }
+    }
+
+    /// If this is a forwarded lease, returns the task that lent the original
+    /// lease and its lease number; otherwise `None`.
+    ///
+    /// For a forwarded lease, `base_address` is an offset into the original
+    /// lease rather than an address.
+    pub fn forwarded_from(&self) -> Option<(TaskId, usize)> {
+        if self.attributes.contains(LeaseAttributes::FORWARDED) {
+            let bits = self.attributes.bits();
+            Some((
+                TaskId((bits >> Self::FORWARDED_LENDER_SHIFT) as u16),
+                (bits >> Self::FORWARDED_LEASE_SHIFT) as u8 as usize,
+            ))
+        } else {
+            None
+        }
+    }
+}
+
 #[derive(
     Copy, Clone, Debug, FromBytes, Immutable, KnownLayout, PartialEq, Eq,
 )]
@@ -207,6 +260,9 @@ bitflags::bitflags! {
         const READ = 1 << 0;
         /// Allow the borrower to write this memory.
         const WRITE = 1 << 1;
+        /// This lease passes on part of a lease lent to the sender, rather
+        /// than lending the sender's own memory. See `ULease::forwarded`.
+        const FORWARDED = 1 << 2;
     }
 }
 
@@ -233,6 +289,13 @@ pub const fn extract_new_generation(code: u32) -> Option<Generation> {
 /// Response code returned by the kernel if a lender has defected.
 pub const DEFECT: u32 = 1;
 
+/// Response code returned by the kernel if a `SEND_TIMEOUT` reaches its
+/// deadline before the peer replies.
+///
+/// This sits just below the dead codes, so that it can't be confused with one,
+/// and is unlikely to collide with server-defined response codes.
+pub const TIMEOUT: u32 = FIRST_DEAD_CODE - 1;
+
 /// State used to make scheduling decisions.
 #[derive(Copy, Clone, Debug, Deserialize, Serialize)]
 pub enum TaskState {
@@ -306,6 +369,34 @@ impl From<SchedState> for TaskState {
     }
 }
 
+/// CPU time accounting for a task, as returned by the `read_task_runtime`
+/// kipc.
+///
+/// Times are measured with a free-running clock that runs at `tick_divisor`
+/// counts per kernel tick; on ARM, this is the SysTick input clock. These are
+/// accumulated across restarts of the task.
+#[derive(
+    Copy, Clone, Debug, Default, Eq, PartialEq, Deserialize, Serialize,
+)]
+pub struct TaskRuntime {
+    /// Time spent running the task, including syscalls it made.
+    pub run_time: u64,
+    /// Time spent in interrupt handlers for interrupts routed to the task.
+    pub isr_time: u64,
+    /// Number of times the task has been switched in.
+    pub context_switches: u32,
+}
+
+/// Stack usage of a task, as measured by the kernel.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct StackUsage {
+    /// Size of the task's stack, in bytes.
+    pub size: u32,
+    /// Deepest stack use observed since the task was last (re)started, in
+    /// bytes.
+    pub max_depth: u32,
+}
+
 /// A record describing a fault taken by a task.
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
 pub enum FaultInfo {
@@ -407,6 +498,9 @@ pub enum UsageError {
     /// A server is attempting to reply with a message that is too large for the
     /// client to handle.
     ReplyTooBig,
+    /// A program used a kernel facility that its app.toml entry doesn't grant
+    /// it.
+    NotGranted,
 }
 
 /// Origin of a fault.
@@ -449,6 +543,7 @@ pub enum ReplyFaultReason {
 }
 
 /// Enumeration of syscall numbers.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
 #[repr(u32)]
 pub enum Sysnum {
     Send = 0,
@@ -465,6 +560,7 @@ pub enum Sysnum {
     Post = 11,
     ReplyFault = 12,
     IrqStatus = 13,
+    SendTimeout = 14,
 }
 
 /// We're using an explicit `TryFrom` impl for `Sysnum` instead of
@@ -489,6 +585,7 @@ impl core::convert::TryFrom<u32> for Sysnum {
             11 => Ok(Self::Post),
             12 => Ok(Self::ReplyFault),
             13 => Ok(Self::IrqStatus),
+            14 => Ok(Self::SendTimeout),
             _ => Err(()),
         }
     }
@@ -501,6 +598,51 @@ pub struct TaskDumpRegion {
     pub size: u32,
 }
 
+/// One entry in a task's memory region table, as reported by the kernel.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct TaskRegion {
+    /// Address of the start of the region.
+    pub base: u32,
+    /// Size of the region, in bytes.
+    pub size: u32,
+    /// Bits from `RegionAttributes` describing what the task can do with the
+    /// region.
+    pub attributes: u32,
+}
+
+impl TaskRegion {
+    /// Returns the region's attributes.
+    pub fn attributes(&self) -> RegionAttributes {
+        RegionAttributes::from_bits_retain(self.attributes)
+    }
+
+    /// Tests whether the region contains all of `base..base + len`.
+    pub fn contains(&self, base: u32, len: u32) -> bool {
+        base >= self.base
+            && base
+                .checked_add(len)
+                .is_some_and(|end| end <= self.base.wrapping_add(self.size))
+    }
+}
+
+bitflags::bitflags! {
+    /// Attributes of a task memory region. These match the kernel's own
+    /// region descriptors, bit for bit.
+    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+    pub struct RegionAttributes: u32 {
+        /// Region can be read by the task.
+        const READ = 1 << 0;
+        /// Region can be written by the task.
+        const WRITE = 1 << 1;
+        /// Region can contain executable code for the task.
+        const EXECUTE = 1 << 2;
+        /// Region contains memory mapped registers.
+        const DEVICE = 1 << 3;
+        /// Region can be used for DMA or communication with other processors.
+        const DMA = 1 << 4;
+    }
+}
+
 /// Representation of kipc numbers
 pub enum Kipcnum {
     ReadTaskStatus = 1,
@@ -512,6 +654,13 @@ pub enum Kipcnum {
     ReadTaskDumpRegion = 7,
     SoftwareIrq = 8,
     FindFaultedTask = 9,
+    ReadTaskRuntime = 10,
+    ReadTaskStackUsage = 11,
+    ReadFaultLog = 12,
+    ClearFaultLog = 13,
+    InjectFault = 14,
+    ReadTaskRegion = 15,
+    ReadImage = 16,
 }
 
 impl core::convert::TryFrom<u16> for Kipcnum {
@@ -528,6 +677,13 @@ impl core::convert::TryFrom<u16> for Kipcnum {
             7 => Ok(Self::ReadTaskDumpRegion),
             8 => Ok(Self::SoftwareIrq),
             9 => Ok(Self::FindFaultedTask),
+            10 => Ok(Self::ReadTaskRuntime),
+            11 => Ok(Self::ReadTaskStackUsage),
+            12 => Ok(Self::ReadFaultLog),
+            13 => Ok(Self::ClearFaultLog),
+            14 => Ok(Self::InjectFault),
+            15 => Ok(Self::ReadTaskRegion),
+            16 => Ok(Self::ReadImage),
             _ => Err(()),
         }
     }
@@ -548,6 +704,195 @@ pub struct ImageHeader {
     pub epoch: u32,
 }
 
+/// Value of `TraceHeader::magic` once a kernel built with the `trace` feature
+/// has started recording.
+pub const TRACE_MAGIC: u32 = 0x7ACE_0002;
+
+/// Header of the kernel's event trace ring, `KERNEL_TRACE`, which is followed
+/// directly by `capacity` `TraceRecord`s.
+#[repr(C)]
+#[derive(
+    Copy, Clone, Debug, Default, IntoBytes, FromBytes, KnownLayout, Immutable,
+)]
+pub struct TraceHeader {
+    /// `TRACE_MAGIC` if the ring is valid.
+    pub magic: u32,
+    /// Number of records in the ring.
+    pub capacity: u32,
+    /// Number of timestamp counts per kernel tick.
+    pub clock_per_tick: u32,
+    /// Rate of the timestamp clock, in kHz.
+    pub clock_khz: u32,
+    /// Index of the record that will be written next.
+    pub next: u32,
+    /// Non-zero once the ring has filled up, meaning that the oldest record is
+    /// the one at `next` rather than the one at 0.
+    pub wrapped: u32,
+}
+
+/// A single event in the kernel's event trace ring.
+#[repr(C)]
+#[derive(
+    Copy, Clone, Debug, Default, IntoBytes, FromBytes, KnownLayout, Immutable,
+)]
+pub struct TraceRecord {
+    /// Bottom 32 bits of the timestamp.
+    pub time_lo: u32,
+    /// Top 32 bits of the timestamp.
+    pub time_hi: u32,
+    /// A `TraceEvent`.
+    pub event: u32,
+    /// Event-specific argument; see `TraceEvent`.
+    pub arg: u32,
+}
+
+impl TraceRecord {
+    /// Returns the full timestamp, in counts of the clock described by
+    /// `TraceHeader::clock_khz`.
+    pub fn time(&self) -> u64 {
+        u64::from(self.time_hi) << 32 | u64::from(self.time_lo)
+    }
+}
+
+/// Kinds of event recorded in the kernel's event trace ring.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[repr(u32)]
+pub enum TraceEvent {
+    /// A task made a syscall; `arg` is the syscall number.
+    SyscallEnter = 1,
+    /// The kernel returned from a syscall.
+    SyscallExit = 2,
+    /// The kernel entered its secondary syscall handler (used for context
+    /// switches).
+    SecondarySyscallEnter = 3,
+    /// The kernel left its secondary syscall handler.
+    SecondarySyscallExit = 4,
+    /// The kernel entered an interrupt handler.
+    IsrEnter = 5,
+    /// The kernel left an interrupt handler.
+    IsrExit = 6,
+    /// The interrupt being handled was identified; `arg` is its number.
+    Irq = 7,
+    /// The kernel entered its timer interrupt handler.
+    TimerIsrEnter = 8,
+    /// The kernel left its timer interrupt handler.
+    TimerIsrExit = 9,
+    /// The current task changed; `arg` is the new task's index.
+    ContextSwitch = 10,
+}
+
+impl core::convert::TryFrom<u32> for TraceEvent {
+    type Error = ();
+
+    fn try_from(x: u32) -> Result<Self, Self::Error> {
+        match x {
+            1 => Ok(Self::SyscallEnter),
+            2 => Ok(Self::SyscallExit),
+            3 => Ok(Self::SecondarySyscallEnter),
+            4 => Ok(Self::SecondarySyscallExit),
+            5 => Ok(Self::IsrEnter),
+            6 => Ok(Self::IsrExit),
+            7 => Ok(Self::Irq),
+            8 => Ok(Self::TimerIsrEnter),
+            9 => Ok(Self::TimerIsrExit),
+            10 => Ok(Self::ContextSwitch),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Value of `FaultLogHeader::magic` in a valid fault log.
+pub const FAULT_LOG_MAGIC: u32 = 0xFA17_0001;
+
+/// Number of bytes of event-specific data in a `FaultLogEntry`.
+pub const FAULT_LOG_DATA_LEN: usize = 108;
+
+/// Header of the kernel's retained fault log, which is followed directly by
+/// `capacity` `FaultLogEntry`s.
+#[repr(C)]
+#[derive(
+    Copy, Clone, Debug, Default, IntoBytes, FromBytes, KnownLayout, Immutable,
+)]
+pub struct FaultLogHeader {
+    /// `FAULT_LOG_MAGIC` if the log is valid.
+    pub magic: u32,
+    /// Number of entries in the log.
+    pub capacity: u32,
+    /// Index of the entry that will be written next.
+    pub next: u32,
+    /// Non-zero once the log has filled up, meaning that the oldest entry is
+    /// the one at `next` rather than the one at 0.
+    pub wrapped: u32,
+    /// Number of times the kernel has booted since the log was created.
+    pub boots: u32,
+}
+
+/// A single event in the kernel's retained fault log.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, IntoBytes, FromBytes, KnownLayout, Immutable)]
+pub struct FaultLogEntry {
+    /// A `FaultLogKind`.
+    pub kind: u32,
+    /// Value of `FaultLogHeader::boots` when this was recorded.
+    pub boot: u32,
+    /// Bottom 32 bits of the kernel timestamp, in ticks since that boot.
+    pub time_lo: u32,
+    /// Top 32 bits of the kernel timestamp.
+    pub time_hi: u32,
+    /// `TaskId` (including generation) of the task concerned, or
+    /// `TaskId::KERNEL` for kernel panics.
+    pub task: u32,
+    /// Event-specific data; see `FaultLogKind`.
+    pub data: [u8; FAULT_LOG_DATA_LEN],
+}
+
+impl FaultLogEntry {
+    /// Returns the full timestamp.
+    pub fn time(&self) -> u64 {
+        u64::from(self.time_hi) << 32 | u64::from(self.time_lo)
+    }
+
+    /// Returns the task concerned.
+    pub fn task(&self) -> TaskId {
+        TaskId(self.task as u16)
+    }
+
+    /// Returns the `data` of a `KernelPanic` entry as the (possibly
+    /// truncated) epitaph text, with trailing NULs removed.
+    pub fn epitaph(&self) -> &[u8] {
+        let len = self
+            .data
+            .iter()
+            .rposition(|&b| b != 0)
+            .map(|i| i + 1)
+            .unwrap_or(0);
+        &self.data[..len]
+    }
+}
+
+/// Kinds of event recorded in the kernel's retained fault log.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[repr(u32)]
+pub enum FaultLogKind {
+    /// The kernel panicked; `data` holds the start of the epitaph text.
+    KernelPanic = 1,
+    /// A task faulted; `data` holds its `FaultInfo`, serialized with
+    /// `ssmarshal`.
+    TaskFault = 2,
+}
+
+impl core::convert::TryFrom<u32> for FaultLogKind {
+    type Error = ();
+
+    fn try_from(x: u32) -> Result<Self, Self::Error> {
+        match x {
+            1 => Ok(Self::KernelPanic),
+            2 => Ok(Self::TaskFault),
+            _ => Err(()),
+        }
+    }
+}
+
 // Corresponds to the ARM vector table, limited to what we need
 // see ARMv8m B3.30 and B1.5.3 ARMv7m for the full description
 #[repr(C)]
@@ -33,6 +33,7 @@ struct Generated {
     tasks: Vec<TokenStream>,
     regions: Vec<TokenStream>,
     irq_code: TokenStream,
+    fault_log: Option<std::ops::Range<u32>>,
 }
 
 #[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
@@ -174,17 +175,22 @@ fn process_config() -> Result<Generated> {
 
         let index = u16::try_from(i).expect("over 2**16 tasks??");
         let priority = task.priority;
-        let flags = if task.start_at_boot {
-            quote::quote! { TaskFlags::START_AT_BOOT }
-        } else {
-            quote::quote! { TaskFlags::empty() }
-        };
+        let time_slice = task.time_slice.unwrap_or(0);
+        let mut flags = vec![];
+        if task.start_at_boot {
+            flags.push(quote::quote! { TaskFlags::START_AT_BOOT });
+        }
+        if task.read_image {
+            flags.push(quote::quote! { TaskFlags::READ_IMAGE });
+        }
+        let flags = quote::quote! { TaskFlags::empty()#(.union(#flags))* };
         task_descs.push(quote::quote! {
             TaskDesc {
                 regions: [#(&HUBRIS_REGION_DESCS[#regions]),*],
                 entry_point: #entry_point,
                 initial_stack: #initial_stack,
                 priority: #priority,
+                time_slice: #time_slice,
                 index: #index,
                 flags: #flags,
             }
@@ -226,7 +232,8 @@ fn process_config() -> Result<Generated> {
     let task_irq_map = per_task_irqs.into_iter().collect::<Vec<_>>();
 
     let target = build_util::target();
-    let irq_code = if target.starts_with("thumbv6m") {
+    let hosted = build_util::target_os() == "linux";
+    let irq_code = if target.starts_with("thumbv6m") || hosted {
         // On ARMv6-M we have no hardware division, which the perfect hash table
         // relies on (to get efficient integer remainder). Fall back to a good
         // old sorted list with binary search instead.
@@ -234,6 +241,9 @@ fn process_config() -> Result<Generated> {
         // This means our dispatch time for interrupts on ARMv6-M is O(log N)
         // instead of O(1), but these parts also tend to have few interrupts,
         // so, not the end of the world.
+        //
+        // The hosted simulation uses the same representation, since interrupt
+        // dispatch time is not something it tries to model.
 
         let task_irq_map = phash_gen::OwnedSortedList::build(task_irq_map)
             .context("building task-to-IRQ map")?;
@@ -327,10 +337,22 @@ fn process_config() -> Result<Generated> {
         panic!("Don't know the target {target}");
     };
 
+    if let Some(r) = &kconfig.fault_log {
+        let min_size = std::mem::size_of::<abi::FaultLogHeader>()
+            + std::mem::size_of::<abi::FaultLogEntry>();
+        if r.start % 4 != 0 || ((r.end - r.start) as usize) < min_size {
+            bail!(
+                "fault log region {r:x?} must be word-aligned and at least \
+                 {min_size} bytes"
+            );
+        }
+    }
+
     Ok(Generated {
         tasks: task_descs,
         regions: region_descs,
         irq_code,
+        fault_log: kconfig.fault_log,
     })
 }
 
@@ -421,7 +443,7 @@ fn generate_statics(gen: &Generated) -> Result<()> {
         file,
         "{}",
         quote::quote! {
-            const HUBRIS_TASK_COUNT: usize = #task_count;
+            pub(crate) const HUBRIS_TASK_COUNT: usize = #task_count;
             #[no_mangle]
             pub static HUBRIS_IMAGE_ID: u64 = #image_id;
 
@@ -461,6 +483,25 @@ fn generate_statics(gen: &Generated) -> Result<()> {
         },
     )?;
 
+    /////////////////////////////////////////////////////////
+    // Retained fault log
+
+    let fault_log = match &gen.fault_log {
+        Some(r) => {
+            let (start, end) = (r.start as usize, r.end as usize);
+            quote::quote! { Some(#start..#end) }
+        }
+        None => quote::quote! { None },
+    };
+    writeln!(
+        file,
+        "{}",
+        quote::quote! {
+            pub(crate) const HUBRIS_FAULT_LOG: Option<core::ops::Range<usize>> =
+                #fault_log;
+        },
+    )?;
+
     /////////////////////////////////////////////////////////
     // Interrupt table
 
@@ -19,8 +19,14 @@ cfg_if::cfg_if! {
     // Note: cfg_if! is slightly touchy about ordering and expression
     // complexity; this chain seems to be the best compromise.
 
-    if #[cfg(not(target_pointer_width = "32"))] {
-        compile_error!("non-32-bit targets not supported (even for simulation)");
+    if #[cfg(hubris_hosted)] {
+        // The hosted simulation keeps all task memory below 4 GiB, so it can
+        // run on 64-bit hosts despite the 32-bit syscall ABI.
+        #[macro_use]
+        pub mod hosted;
+        pub use hosted::*;
+    } else if #[cfg(not(target_pointer_width = "32"))] {
+        compile_error!("non-32-bit targets not supported (except hosted)");
     } else if #[cfg(target_arch = "arm")] {
         #[macro_use]
         pub mod arm_m;
@@ -252,6 +252,17 @@ cfg_if::cfg_if! {
     }
 }
 
+/// Reads the syscall arguments that a stub passed in r2 and r3, for syscalls
+/// that need more than the seven argument registers in `SavedState`. The
+/// hardware stacks these on the task's stack on entry to `SVCall`.
+pub fn syscall_stacked_args(task: &task::Task) -> Result<[u32; 2], FaultInfo> {
+    let frame =
+        USlice::<BaseExceptionFrame>::from_raw(task.save().psp as usize, 1)
+            .map_err(FaultInfo::SyscallUsage)?;
+    let frame = &task.try_read(&frame)?[0];
+    Ok([frame.r2, frame.r3])
+}
+
 /// Initially we just set the Thumb Mode bit, the minimum required.
 const INITIAL_PSR: u32 = 1 << 24;
 
@@ -322,7 +333,7 @@ pub fn reinitialize(task: &mut task::Task) {
             // us later.
             let zap = task.try_write(&mut uslice).unwrap_lite();
             for word in zap.iter_mut() {
-                *word = 0xbaddcafe;
+                *word = task::STACK_PAINT;
             }
         }
     }
@@ -1006,7 +1017,33 @@ cfg_if::cfg_if! {
 /// stored is actually in the task table, you'll be okay.
 pub unsafe fn set_current_task(task: &task::Task) {
     CURRENT_TASK_PTR.store(task as *const _ as *mut _, Ordering::Relaxed);
-    crate::profiling::event_context_switch(task as *const _ as usize);
+    crate::profiling::event_context_switch(task);
+}
+
+/// Reads a free-running clock for task CPU time accounting. This counts cycles
+/// of the SysTick input clock, so there are `tick_divisor` counts per tick.
+///
+/// This relies on the `SysTick` handler counting each tick before it does
+/// anything that might read the clock; see `kerncore::runtime_clock`.
+pub fn runtime_clock() -> u64 {
+    let divisor = CLOCK_FREQ_KHZ.load(Ordering::Relaxed);
+    let ticks = u64::from(now());
+    let mut current = cortex_m::peripheral::SYST::get_current();
+    // SysTick can't preempt the kernel, so if the counter has wrapped since
+    // the last tick, the handler won't have counted it yet. In that case our
+    // reading of the counter may be from either side of the wrap, so take it
+    // again to be sure it agrees.
+    let pending = cortex_m::peripheral::SCB::is_pendst_pending();
+    if pending {
+        current = cortex_m::peripheral::SYST::get_current();
+    }
+    kerncore::runtime_clock(ticks, pending, current, divisor)
+}
+
+/// Returns the rate of `runtime_clock`, in kHz. Ticks are taken to be 1 ms, so
+/// this is the tick divisor.
+pub fn runtime_clock_khz() -> u32 {
+    CLOCK_FREQ_KHZ.load(Ordering::Relaxed)
 }
 
 /// Reads the tick counter.
@@ -1038,34 +1075,57 @@ static TICKS: [AtomicU32; 2] = {
 #[allow(non_snake_case)]
 #[no_mangle]
 pub unsafe extern "C" fn SysTick() {
+    // Count the tick before anything else. By now the counter has reloaded
+    // and the tick is no longer pending, so until it's counted,
+    // `runtime_clock` would read a tick behind -- including for the profiling
+    // and trace events just below.
+
+    // Load the time before this tick event.
+    let t0 = TICKS[0].load(Ordering::Relaxed);
+    let t1 = TICKS[1].load(Ordering::Relaxed);
+
+    // Advance the kernel's notion of time by adding 1. Laboriously.
+    let (t0, t1) = if let Some(t0p) = t0.checked_add(1) {
+        // Incrementing t0 did not roll over, no need to update t1.
+        TICKS[0].store(t0p, Ordering::Relaxed);
+        (t0p, t1)
+    } else {
+        // Incrementing t0 overflowed. We need to also increment t1. We use
+        // normal checked addition for this, not wrapping, because this
+        // should not be able to overflow under normal operation, and would
+        // almost certainly indicate state corruption that we'd like to
+        // discover.
+        TICKS[0].store(0, Ordering::Relaxed);
+        TICKS[1].store(t1 + 1, Ordering::Relaxed);
+        (0, t1 + 1)
+    };
+    let now = Timestamp::from([t0, t1]);
+
     crate::profiling::event_timer_isr_enter();
-    with_task_table(|tasks| {
-        // Load the time before this tick event.
-        let t0 = TICKS[0].load(Ordering::Relaxed);
-        let t1 = TICKS[1].load(Ordering::Relaxed);
-
-        // Advance the kernel's notion of time by adding 1. Laboriously.
-        let (t0, t1) = if let Some(t0p) = t0.checked_add(1) {
-            // Incrementing t0 did not roll over, no need to update t1.
-            TICKS[0].store(t0p, Ordering::Relaxed);
-            (t0p, t1)
-        } else {
-            // Incrementing t0 overflowed. We need to also increment t1. We use
-            // normal checked addition for this, not wrapping, because this
-            // should not be able to overflow under normal operation, and would
-            // almost certainly indicate state corruption that we'd like to
-            // discover.
-            TICKS[0].store(0, Ordering::Relaxed);
-            TICKS[1].store(t1 + 1, Ordering::Relaxed);
-            (0, t1 + 1)
-        };
 
-        // Process any timers.
-        let now = Timestamp::from([t0, t1]);
-        let switch = task::process_timers(tasks, now);
+    let current = CURRENT_TASK_PTR.load(Ordering::Relaxed);
+    uassert!(!current.is_null()); // tick before kernel started?
+
+    // Safety: we're dereferencing the current task pointer, which we're
+    // trusting the rest of this module to maintain correctly.
+    let current = usize::from(unsafe { (*current).descriptor().index });
+
+    with_task_table(|tasks| {
+        // Process any timers, and charge the tick to the time slice of the
+        // task we interrupted.
+        let switch = task::process_timers(tasks, now)
+            .combine(task::charge_time_slice(tasks, current));
+
+        // Deliver any fault injected into the task we interrupted.
+        #[cfg(feature = "fault-injection")]
+        let switch = switch.combine(
+            task::deliver_injected_fault(tasks, current)
+                .unwrap_or(task::NextTask::Same),
+        );
 
-        // If any timers fired, we need to defer a context switch, because the entry
-        // sequence to this ISR doesn't save state correctly for efficiency.
+        // If any timers fired, or the current task's time slice is up, we
+        // need to defer a context switch, because the entry sequence to this
+        // ISR doesn't save state correctly for efficiency.
         if switch != task::NextTask::Same {
             pend_context_switch_from_isr();
         }
@@ -1181,7 +1241,9 @@ unsafe extern "C" fn pendsv_entry() {
     let current = usize::from(unsafe { (*current).descriptor().index });
 
     with_task_table(|tasks| {
-        let next = task::select(current, tasks);
+        let next = usize::from(task::select(current, tasks).descriptor().index);
+        task::account_switch(tasks, current, next);
+        let next = &tasks[next];
         apply_memory_protection(next);
         // Safety: next comes from the task table and we don't use it again
         // until next kernel entry, so we meet set_current_task's requirements.
@@ -1196,6 +1258,7 @@ unsafe extern "C" fn pendsv_entry() {
 #[no_mangle]
 pub unsafe extern "C" fn DefaultHandler() {
     crate::profiling::event_isr_enter();
+    let start = runtime_clock();
     // We can cheaply get the identity of the interrupt that called us from the
     // bottom 9 bits of IPSR.
     //
@@ -1228,10 +1291,18 @@ pub unsafe extern "C" fn DefaultHandler() {
         x if x >= 16 => {
             // Hardware interrupt
             let irq_num = exception_num - 16;
+            crate::trace::record(abi::TraceEvent::Irq, irq_num);
             let owner = crate::startup::HUBRIS_IRQ_TASK_LOOKUP
                 .get(abi::InterruptNum(irq_num))
                 .unwrap_or_else(|| panic!("unhandled IRQ {irq_num}"));
 
+            let current = CURRENT_TASK_PTR.load(Ordering::Relaxed);
+            uassert!(!current.is_null()); // irq before kernel started?
+
+            // Safety: we're dereferencing the current task pointer, which
+            // we're trusting the rest of this module to maintain correctly.
+            let current = usize::from(unsafe { (*current).descriptor().index });
+
             let switch = with_task_table(|tasks| {
                 // This can only fail if the IRQ number is out of range, which
                 // in this case would mean the hardware is conspiring against
@@ -1241,7 +1312,10 @@ pub unsafe extern "C" fn DefaultHandler() {
                 // Now, post the notification and return the
                 // scheduling hint.
                 let n = task::NotificationSet(owner.notification);
-                tasks[owner.task as usize].post(n)
+                let switch = tasks[owner.task as usize].post(n);
+
+                task::account_isr(tasks, current, owner.task as usize, start);
+                switch
             });
             if switch {
                 pend_context_switch_from_isr()
@@ -1620,6 +1694,26 @@ pub fn reset() -> ! {
     cortex_m::peripheral::SCB::sys_reset()
 }
 
+/// Returns the image: the kernel's and every task's flash, from the vector
+/// table for the length in the image header.
+pub fn image() -> &'static [u8] {
+    extern "C" {
+        // Exposed by the linker script.
+        static __start_vector: u8;
+    }
+
+    // Safety: the build system sets the header's length to cover the kernel
+    // and every task, all of which is flash that nothing writes.
+    unsafe {
+        let len = core::ptr::addr_of!(crate::header::HEADER.total_image_len)
+            .read_volatile();
+        core::slice::from_raw_parts(
+            core::ptr::addr_of!(__start_vector),
+            len as usize,
+        )
+    }
+}
+
 /// Common implementation of fault handling.
 ///
 /// # Safety
@@ -0,0 +1,1030 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Architecture support for running the kernel hosted on Linux.
+//!
+//! This is a simulation target, not a product target. It exists so that the
+//! portable parts of the kernel -- the scheduler, IPC, lease checking, kipc --
+//! can be exercised from `cargo test` on a workstation or CI machine, without
+//! silicon or an emulator.
+//!
+//! # Execution model
+//!
+//! The kernel runs as an event loop on the thread that calls `start_kernel`.
+//! Each task runs in its own child process, created with `clone(2)` so that it
+//! starts out running on its own stack, inside its own RAM region. Task RAM
+//! regions are mapped `MAP_SHARED` at their configured addresses before the
+//! task's process is created, so the kernel sees task memory at the same
+//! addresses the task does, and the `USlice`-based checks and copies in the
+//! rest of the kernel work unchanged.
+//!
+//! Only the current task's process is allowed to run. When the kernel switches
+//! tasks, it stops the outgoing process with `SIGSTOP` and continues the
+//! incoming one with `SIGCONT`. This gets us preemption that matches the
+//! hardware, including for tasks that spin without making syscalls (like
+//! `idle`).
+//!
+//! # Syscalls
+//!
+//! When built for the hosted target, the userlib syscall stubs call
+//! `hubris_hosted_syscall` instead of executing `SVC`. In the task process,
+//! that deposits the syscall number and argument registers in a mailbox page
+//! shared with the kernel, writes a byte to the task's trap pipe, and blocks
+//! reading its resume pipe. The kernel copies the mailbox into the task's
+//! `SavedState` and runs the normal `syscall_entry`. Whenever that task is next
+//! scheduled, the kernel copies `SavedState` back into the mailbox and writes
+//! the resume byte -- the moral equivalent of returning from `SVCall`.
+//!
+//! A task that dies on a host signal (e.g. `SIGSEGV`) is recorded as faulted,
+//! with the signal mapped onto the closest `FaultInfo`.
+//!
+//! # Timer and interrupts
+//!
+//! The kernel tick comes from a `timerfd`, and `tick_divisor` is interpreted as
+//! microseconds of host time per kernel tick. Interrupts are simulated with
+//! enable and pending bits shaped like the ARM NVIC. Test harnesses can assert
+//! an interrupt with `raise_irq`, from any thread.
+//!
+//! # Limitations
+//!
+//! - Task code is host code. It is linked into the harness binary rather than
+//!   loaded into the task's flash region, so each task's entry point must be
+//!   registered with `set_task_entry` before the kernel is started. Booting
+//!   an app's image (jefe, idle, helloworld and so on) isn't supported:
+//!   xtask only builds `no_main` task binaries for a target, which can't be
+//!   booted this way, and has no way to build an app for this backend.
+//!   `test/hosted-kernel` shows what a harness looks like instead, with
+//!   tasks written for it and a kconfig written by hand.
+//! - Task statics live in the (copy-on-write) host image rather than in the
+//!   task's RAM region, so they can't be lent to other tasks. Stack buffers
+//!   work as on hardware.
+//! - There is no memory protection beyond the process boundary;
+//!   `apply_memory_protection` does nothing.
+
+use core::sync::atomic::{
+    AtomicBool, AtomicI32, AtomicPtr, AtomicU32, Ordering,
+};
+use std::sync::{Mutex, OnceLock};
+
+use crate::atomic::AtomicExt;
+use crate::descs::RegionAttributes;
+use crate::startup::with_task_table;
+use crate::task;
+use crate::time::Timestamp;
+use crate::umem::USlice;
+use abi::{FaultInfo, FaultSource, InterruptNum, UsageError};
+use unwrap_lite::UnwrapLite;
+
+macro_rules! uassert {
+    ($cond : expr) => {
+        if !$cond {
+            panic!("Assertion failed!");
+        }
+    };
+}
+
+/// Pointer to the current task, maintained the same way as on ARM so that the
+/// rest of this module can find it.
+static CURRENT_TASK_PTR: AtomicPtr<task::Task> =
+    AtomicPtr::new(core::ptr::null_mut());
+
+/// Hosted "registers" that must be saved across context switches.
+///
+/// The syscall ABI is defined in terms of ARM registers r4-r11; we keep the
+/// same shape so the userlib stubs can share argument marshalling with the ARM
+/// versions.
+#[repr(C)]
+#[derive(Debug, Default)]
+pub struct SavedState {
+    /// Syscall argument and return registers, standing in for r4-r10.
+    regs: [u32; 7],
+    /// Syscall number, standing in for r11.
+    descriptor: u32,
+    /// Extra syscall arguments, standing in for the r2 and r3 values that ARM
+    /// stubs leave in the exception frame. See `syscall_stacked_args`.
+    stacked: [u32; 2],
+    /// Initial stack pointer. We can't observe the real stack pointer of a
+    /// task process, so this is only updated when the task is reinitialized.
+    sp: u32,
+}
+
+/// Map the saved registers to (architecture-independent) syscall argument and
+/// return slots.
+impl task::ArchState for SavedState {
+    fn stack_pointer(&self) -> u32 {
+        self.sp
+    }
+
+    /// Reads syscall argument register 0.
+    fn arg0(&self) -> u32 {
+        self.regs[0]
+    }
+    fn arg1(&self) -> u32 {
+        self.regs[1]
+    }
+    fn arg2(&self) -> u32 {
+        self.regs[2]
+    }
+    fn arg3(&self) -> u32 {
+        self.regs[3]
+    }
+    fn arg4(&self) -> u32 {
+        self.regs[4]
+    }
+    fn arg5(&self) -> u32 {
+        self.regs[5]
+    }
+    fn arg6(&self) -> u32 {
+        self.regs[6]
+    }
+
+    fn syscall_descriptor(&self) -> u32 {
+        self.descriptor
+    }
+
+    /// Writes syscall return argument 0.
+    fn ret0(&mut self, x: u32) {
+        self.regs[0] = x
+    }
+    fn ret1(&mut self, x: u32) {
+        self.regs[1] = x
+    }
+    fn ret2(&mut self, x: u32) {
+        self.regs[2] = x
+    }
+    fn ret3(&mut self, x: u32) {
+        self.regs[3] = x
+    }
+    fn ret4(&mut self, x: u32) {
+        self.regs[4] = x
+    }
+    fn ret5(&mut self, x: u32) {
+        self.regs[5] = x
+    }
+}
+
+/// Syscall registers exchanged between a task process and the kernel. One of
+/// these lives in a shared page per task process.
+#[repr(C)]
+struct Mailbox {
+    nr: u32,
+    regs: [u32; 7],
+    stacked: [u32; 2],
+}
+
+/// Kernel-side record of the host process running a task.
+struct Process {
+    pid: libc::pid_t,
+    /// Read end of the pipe the task writes to when it makes a syscall.
+    trap_rx: i32,
+    /// Write end of the pipe we write to when resuming the task.
+    resume_tx: i32,
+    /// Address of the task's `Mailbox`.
+    mailbox: usize,
+    /// The process is blocked waiting for a resume byte, and the contents of
+    /// its `SavedState` should be handed back to it when it next runs.
+    parked: bool,
+    /// The process has trapped, but we haven't processed the syscall yet,
+    /// because it wasn't the current task when the trap arrived. (This can
+    /// happen if it trapped just as we were stopping it.)
+    trapped: bool,
+    /// We have sent the process `SIGSTOP` and not yet continued it.
+    stopped: bool,
+}
+
+/// Task processes, indexed by task index. Only touched from the kernel thread.
+static PROCESSES: Mutex<Vec<Option<Process>>> = Mutex::new(Vec::new());
+
+/// Host function implementing a task. See `set_task_entry`.
+pub type TaskEntry = fn() -> !;
+
+/// Task entry points, indexed by task index.
+static TASK_ENTRIES: Mutex<Vec<Option<TaskEntry>>> = Mutex::new(Vec::new());
+
+/// Host pages backing task memory that we've already mapped. Regions can be
+/// shared between tasks, and small regions can share a page, so we track this
+/// at page granularity.
+static MAPPED_PAGES: Mutex<Vec<usize>> = Mutex::new(Vec::new());
+
+/// Inside a task process, the task's mailbox. Null in the kernel process.
+static TASK_MAILBOX: AtomicPtr<Mailbox> = AtomicPtr::new(core::ptr::null_mut());
+/// Inside a task process, the write end of the trap pipe.
+static TASK_TRAP_TX: AtomicI32 = AtomicI32::new(-1);
+/// Inside a task process, the read end of the resume pipe.
+static TASK_RESUME_RX: AtomicI32 = AtomicI32::new(-1);
+
+/// Registers the host function that implements task number `index`.
+///
+/// Since task code is linked into the same binary as the kernel when hosted,
+/// this takes the place of the `entry_point` address in the task descriptor.
+/// All tasks must be registered before calling `start_kernel`.
+pub fn set_task_entry(index: usize, entry: TaskEntry) {
+    let mut entries = TASK_ENTRIES.lock().unwrap();
+    if entries.len() <= index {
+        entries.resize(index + 1, None);
+    }
+    entries[index] = Some(entry);
+}
+
+pub unsafe fn set_clock_freq(_tick_divisor: u32) {
+    // Nothing to do: there's no debugger that needs to find the clock rate.
+}
+
+/// Reads the syscall arguments that the ARM stubs would pass in r2 and r3.
+/// Here, the userlib stubs hand them to `hubris_hosted_syscall` directly.
+pub fn syscall_stacked_args(task: &task::Task) -> Result<[u32; 2], FaultInfo> {
+    Ok(task.save().stacked)
+}
+
+pub fn reinitialize(task: &mut task::Task) {
+    *task.save_mut() = SavedState::default();
+    let index = usize::from(task.descriptor().index);
+    let initial_stack = task.descriptor().initial_stack as usize;
+
+    // Keep the same stack alignment requirement as hardware, so that images
+    // that run here will also run there.
+    uassert!(initial_stack & 0x7 == 0);
+
+    // If the task already has a process, it's stale now. Get rid of it before
+    // we scribble on its stack.
+    let old = PROCESSES
+        .lock()
+        .unwrap()
+        .get_mut(index)
+        .and_then(Option::take);
+    if let Some(old) = old {
+        destroy_process(old);
+    }
+
+    map_task_memory(task);
+
+    // Zap the task's stack with the same distinct (and storied) pattern we use
+    // on hardware. See the ARM version of this function for the details; the
+    // only difference is that there's no initial exception frame to leave
+    // room for.
+    if let Some(region) = task
+        .region_table()
+        .iter()
+        .find(|region| region.contains(initial_stack.saturating_sub(4)))
+    {
+        if let Ok(mut uslice) = USlice::<u32>::from_raw(
+            region.base as usize,
+            (initial_stack - region.base as usize) >> 2,
+        ) {
+            let zap = task.try_write(&mut uslice).unwrap_lite();
+            for word in zap.iter_mut() {
+                *word = task::STACK_PAINT;
+            }
+        }
+    }
+
+    task.save_mut().sp = initial_stack as u32;
+
+    let process = spawn_process(index, initial_stack);
+    let mut processes = PROCESSES.lock().unwrap();
+    if processes.len() <= index {
+        processes.resize_with(index + 1, || None);
+    }
+    processes[index] = Some(process);
+}
+
+/// Region data for the hosted target. There is no MPU to precompute anything
+/// for, so this is empty.
+#[derive(Copy, Clone, Debug)]
+pub struct RegionDescExt;
+
+pub const fn compute_region_extension_data(
+    _base: u32,
+    _size: u32,
+    _ratts: RegionAttributes,
+) -> RegionDescExt {
+    RegionDescExt
+}
+
+pub fn apply_memory_protection(_task: &task::Task) {
+    // Nothing to do; see the module docs.
+}
+
+/// Maps the RAM regions of `task` at their configured addresses, if they
+/// haven't been mapped already.
+///
+/// Device regions are skipped, as are regions without write access, since
+/// task code and constants live in the host image and nothing ever reads them
+/// at their configured addresses.
+fn map_task_memory(task: &task::Task) {
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+    let mut mapped = MAPPED_PAGES.lock().unwrap();
+
+    for region in task.region_table() {
+        if !region.attributes.contains(RegionAttributes::WRITE)
+            || region.attributes.contains(RegionAttributes::DEVICE)
+            || region.size == 0
+        {
+            continue;
+        }
+        let start = region.base as usize & !(page_size - 1);
+        let end = (region.end_addr() as usize).next_multiple_of(page_size);
+        for page in (start..end).step_by(page_size) {
+            if mapped.contains(&page) {
+                continue;
+            }
+            // Safety: MAP_FIXED_NOREPLACE will refuse to clobber anything the
+            // host has already put at this address, so this can't invalidate
+            // any existing memory.
+            let p = unsafe {
+                libc::mmap(
+                    page as *mut libc::c_void,
+                    page_size,
+                    libc::PROT_READ | libc::PROT_WRITE,
+                    libc::MAP_SHARED
+                        | libc::MAP_ANONYMOUS
+                        | libc::MAP_FIXED_NOREPLACE,
+                    -1,
+                    0,
+                )
+            };
+            if p as usize != page {
+                panic!("can't map task memory at {page:#x}");
+            }
+            mapped.push(page);
+        }
+    }
+}
+
+/// Everything a new task process needs to get going. This is passed by
+/// pointer across `clone`, which is fine, since the child gets a copy of our
+/// address space.
+struct TaskStart {
+    entry: TaskEntry,
+    mailbox: *mut Mailbox,
+    trap_tx: i32,
+    resume_rx: i32,
+}
+
+/// Creates the host process for task `index`, with its stack pointer at
+/// `initial_stack`.
+///
+/// The new process starts out parked, as though it were blocked in a syscall,
+/// and won't run task code until the kernel first resumes it.
+fn spawn_process(index: usize, initial_stack: usize) -> Process {
+    let entry = TASK_ENTRIES
+        .lock()
+        .unwrap()
+        .get(index)
+        .copied()
+        .flatten()
+        .unwrap_or_else(|| panic!("no entry point set for task {index}"));
+
+    // Safety: this is a fresh anonymous mapping, not aliasing anything.
+    let mailbox = unsafe {
+        libc::mmap(
+            core::ptr::null_mut(),
+            core::mem::size_of::<Mailbox>(),
+            libc::PROT_READ | libc::PROT_WRITE,
+            libc::MAP_SHARED | libc::MAP_ANONYMOUS,
+            -1,
+            0,
+        )
+    };
+    uassert!(mailbox != libc::MAP_FAILED);
+    let mailbox = mailbox as *mut Mailbox;
+
+    let (trap_rx, trap_tx) = pipe();
+    let (resume_rx, resume_tx) = pipe();
+
+    let start = TaskStart {
+        entry,
+        mailbox,
+        trap_tx,
+        resume_rx,
+    };
+    // Safety: the child runs on the task's stack, which we've mapped, and gets
+    // its own copy of `start` along with the rest of our address space. We
+    // don't pass CLONE_VM, so nothing else is shared with it.
+    let pid = unsafe {
+        libc::clone(
+            task_process_main,
+            initial_stack as *mut libc::c_void,
+            libc::SIGCHLD,
+            &start as *const TaskStart as *mut libc::c_void,
+        )
+    };
+    if pid < 0 {
+        panic!("can't create process for task {index}");
+    }
+
+    // The child's ends of the pipes belong to the child. Closing ours also
+    // means that the trap pipe reads as EOF if the child dies.
+    close(trap_tx);
+    close(resume_rx);
+
+    Process {
+        pid,
+        trap_rx,
+        resume_tx,
+        mailbox: mailbox as usize,
+        parked: true,
+        trapped: false,
+        stopped: false,
+    }
+}
+
+/// Entry point of a task process.
+extern "C" fn task_process_main(arg: *mut libc::c_void) -> libc::c_int {
+    // Safety: spawn_process passes a pointer to a TaskStart, and our copy of it
+    // won't go anywhere, since nothing in this process returns to it.
+    let start = unsafe { &*(arg as *const TaskStart) };
+    TASK_MAILBOX.store(start.mailbox, Ordering::Relaxed);
+    TASK_TRAP_TX.store(start.trap_tx, Ordering::Relaxed);
+    TASK_RESUME_RX.store(start.resume_rx, Ordering::Relaxed);
+
+    // Go down with the kernel, rather than lingering as an orphan.
+    unsafe {
+        libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGKILL);
+    }
+
+    // Wait to be scheduled for the first time.
+    if !recv_byte(start.resume_rx) {
+        unsafe { libc::_exit(0) }
+    }
+    (start.entry)()
+}
+
+/// Syscall entry point for tasks, used by userlib in place of `SVC`.
+///
+/// `regs` contains the syscall arguments on the way in, and the syscall
+/// results on the way out. `stacked` contains any arguments that the ARM stub
+/// would pass in r2 and r3.
+///
+/// # Safety
+///
+/// This must only be called from a task process, with a valid `regs`.
+#[no_mangle]
+pub unsafe extern "C" fn hubris_hosted_syscall(
+    nr: u32,
+    regs: *mut [u32; 7],
+    stacked0: u32,
+    stacked1: u32,
+) {
+    let mailbox = TASK_MAILBOX.load(Ordering::Relaxed);
+    uassert!(!mailbox.is_null()); // syscall from outside a task?
+
+    // Safety: the mailbox is set up for us by spawn_process, and the kernel
+    // doesn't touch it while we're running. Our caller is responsible for
+    // `regs`.
+    unsafe {
+        (*mailbox).nr = nr;
+        (*mailbox).regs = *regs;
+        (*mailbox).stacked = [stacked0, stacked1];
+    }
+
+    send_byte(TASK_TRAP_TX.load(Ordering::Relaxed));
+    if !recv_byte(TASK_RESUME_RX.load(Ordering::Relaxed)) {
+        // The kernel has gone away.
+        unsafe { libc::_exit(0) }
+    }
+
+    // Safety: as above.
+    unsafe {
+        *regs = (*mailbox).regs;
+    }
+}
+
+/// Kills a task process and releases everything associated with it.
+fn destroy_process(p: Process) {
+    // Safety: these are all resources we created in spawn_process.
+    unsafe {
+        libc::kill(p.pid, libc::SIGKILL);
+        libc::waitpid(p.pid, core::ptr::null_mut(), 0);
+        libc::munmap(
+            p.mailbox as *mut libc::c_void,
+            core::mem::size_of::<Mailbox>(),
+        );
+    }
+    close(p.trap_rx);
+    close(p.resume_tx);
+}
+
+fn pipe() -> (i32, i32) {
+    let mut fds = [0; 2];
+    // Safety: fds is big enough.
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+        panic!("can't create pipe");
+    }
+    (fds[0], fds[1])
+}
+
+fn close(fd: i32) {
+    // Safety: only used on descriptors we own.
+    unsafe {
+        libc::close(fd);
+    }
+}
+
+fn send_byte(fd: i32) {
+    loop {
+        // Safety: writing one byte from a valid buffer.
+        let n = unsafe { libc::write(fd, [0u8].as_ptr().cast(), 1) };
+        if n == 1 {
+            return;
+        }
+        if !interrupted() {
+            panic!("write to fd {fd} failed");
+        }
+    }
+}
+
+/// Reads a byte from `fd`, returning `false` at end of file.
+fn recv_byte(fd: i32) -> bool {
+    let mut byte = [0u8];
+    loop {
+        // Safety: reading one byte into a valid buffer.
+        let n = unsafe { libc::read(fd, byte.as_mut_ptr().cast(), 1) };
+        match n {
+            1 => return true,
+            0 => return false,
+            _ if interrupted() => continue,
+            _ => panic!("read from fd {fd} failed"),
+        }
+    }
+}
+
+fn interrupted() -> bool {
+    std::io::Error::last_os_error().kind() == std::io::ErrorKind::Interrupted
+}
+
+pub fn start_first_task(tick_divisor: u32, task: &task::Task) -> ! {
+    uassert!(tick_divisor != 0);
+    let period = libc::timespec {
+        tv_sec: (tick_divisor / 1_000_000) as _,
+        tv_nsec: ((tick_divisor % 1_000_000) * 1000) as _,
+    };
+    // Safety: we're creating and arming a timer we own.
+    let timer = unsafe {
+        let timer = libc::timerfd_create(libc::CLOCK_MONOTONIC, 0);
+        uassert!(timer >= 0);
+        let spec = libc::itimerspec {
+            it_interval: period,
+            it_value: period,
+        };
+        uassert!(
+            libc::timerfd_settime(timer, 0, &spec, core::ptr::null_mut()) == 0
+        );
+        timer
+    };
+
+    // Safety: task comes from the task table and we don't use it again.
+    unsafe {
+        set_current_task(task);
+    }
+
+    loop {
+        dispatch();
+        wait_for_events(timer);
+    }
+}
+
+fn current_index() -> usize {
+    let current = CURRENT_TASK_PTR.load(Ordering::Relaxed);
+    uassert!(!current.is_null());
+    // Safety: we're dereferencing the current task pointer, which we're
+    // trusting the rest of this module to maintain correctly, outside of any
+    // use of the task table.
+    usize::from(unsafe { (*current).descriptor().index })
+}
+
+/// Brings the host processes in line with the kernel's idea of the current
+/// task: stops everyone else, and gets the current task's process running.
+///
+/// Getting the current task running may mean processing a syscall it made
+/// while it wasn't current, which may in turn change the current task, so this
+/// loops until things settle.
+fn dispatch() {
+    loop {
+        deliver_pending_irqs();
+
+        let current = current_index();
+        let mut processes = PROCESSES.lock().unwrap();
+        for (i, p) in processes.iter_mut().enumerate() {
+            if let Some(p) = p {
+                if i != current && !p.stopped {
+                    unsafe {
+                        libc::kill(p.pid, libc::SIGSTOP);
+                    }
+                    p.stopped = true;
+                }
+            }
+        }
+
+        let p = processes[current]
+            .as_mut()
+            .unwrap_or_else(|| panic!("task {current} has no process"));
+        if p.stopped {
+            unsafe {
+                libc::kill(p.pid, libc::SIGCONT);
+            }
+            p.stopped = false;
+        }
+
+        if p.trapped {
+            p.trapped = false;
+            p.parked = true;
+            // Safety: the process is blocked waiting to be resumed, so it's
+            // not touching the mailbox.
+            let (nr, regs, stacked) = unsafe {
+                let mailbox = &*(p.mailbox as *const Mailbox);
+                (mailbox.nr, mailbox.regs, mailbox.stacked)
+            };
+            // Syscalls can reinitialize tasks, which needs the process table.
+            drop(processes);
+            enter_syscall(current, nr, regs, stacked);
+            continue;
+        }
+
+        if p.parked {
+            let regs = with_task_table(|tasks| tasks[current].save().regs);
+            // Safety: as above, the process isn't touching the mailbox.
+            unsafe {
+                (*(p.mailbox as *mut Mailbox)).regs = regs;
+            }
+            send_byte(p.resume_tx);
+            p.parked = false;
+        }
+        return;
+    }
+}
+
+/// Runs syscall `nr` on behalf of task `index`, which must be current.
+fn enter_syscall(index: usize, nr: u32, regs: [u32; 7], stacked: [u32; 2]) {
+    let task = with_task_table(|tasks| {
+        let task = &mut tasks[index];
+        task.save_mut().regs = regs;
+        task.save_mut().descriptor = nr;
+        task.save_mut().stacked = stacked;
+        task as *mut task::Task
+    });
+    // Safety: we're not using the task table, we're not being called
+    // reentrantly, and the task's registers have been stored in its
+    // SavedState, which is everything syscall_entry needs of an SVCall
+    // handler.
+    unsafe {
+        crate::syscalls::syscall_entry(nr, task);
+    }
+}
+
+/// Blocks until something happens that the kernel needs to deal with --
+/// timer ticks, interrupts, task syscalls or task deaths -- and deals with it.
+fn wait_for_events(timer: i32) {
+    let irq_rx = irq_pipe().0;
+    let mut fds = vec![
+        libc::pollfd {
+            fd: timer,
+            events: libc::POLLIN,
+            revents: 0,
+        },
+        libc::pollfd {
+            fd: irq_rx,
+            events: libc::POLLIN,
+            revents: 0,
+        },
+    ];
+    let mut owners = vec![];
+    for (i, p) in PROCESSES.lock().unwrap().iter().enumerate() {
+        if let Some(p) = p {
+            fds.push(libc::pollfd {
+                fd: p.trap_rx,
+                events: libc::POLLIN,
+                revents: 0,
+            });
+            owners.push(i);
+        }
+    }
+
+    // Safety: fds is a valid array of the length we're passing.
+    let n = unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as _, -1) };
+    if n < 0 {
+        uassert!(interrupted());
+        return;
+    }
+
+    if fds[0].revents != 0 {
+        let mut expirations = 0u64;
+        // Safety: reading a u64, as timerfd requires, into a u64.
+        let n = unsafe {
+            libc::read(timer, (&mut expirations as *mut u64).cast(), 8)
+        };
+        if n == 8 {
+            for _ in 0..expirations {
+                tick();
+            }
+        }
+    }
+
+    if fds[1].revents != 0 {
+        // These are just wakeups; the interesting state is in IRQ_PENDING,
+        // which dispatch looks at.
+        let mut buf = [0u8; 64];
+        unsafe {
+            libc::read(irq_rx, buf.as_mut_ptr().cast(), buf.len());
+        }
+    }
+
+    for (pfd, &index) in fds[2..].iter().zip(&owners) {
+        if pfd.revents == 0 {
+            continue;
+        }
+        if recv_byte(pfd.fd) {
+            let mut processes = PROCESSES.lock().unwrap();
+            let p = processes[index].as_mut().unwrap_lite();
+            p.trapped = true;
+        } else {
+            task_process_died(index);
+        }
+    }
+}
+
+/// Reaps the process for task `index`, which has exited, and faults the task.
+fn task_process_died(index: usize) {
+    let Some(p) = PROCESSES.lock().unwrap()[index].take() else {
+        return;
+    };
+    let mut status = 0;
+    // Safety: reaping a child of ours.
+    unsafe {
+        libc::waitpid(p.pid, &mut status, 0);
+        libc::munmap(
+            p.mailbox as *mut libc::c_void,
+            core::mem::size_of::<Mailbox>(),
+        );
+    }
+    close(p.trap_rx);
+    close(p.resume_tx);
+
+    let fault = if libc::WIFSIGNALED(status) {
+        match libc::WTERMSIG(status) {
+            libc::SIGSEGV => FaultInfo::MemoryAccess {
+                address: None,
+                source: FaultSource::User,
+            },
+            libc::SIGBUS => FaultInfo::BusError {
+                address: None,
+                source: FaultSource::User,
+            },
+            libc::SIGILL => FaultInfo::IllegalInstruction,
+            libc::SIGFPE => FaultInfo::DivideByZero,
+            libc::SIGABRT => FaultInfo::Panic,
+            sig => FaultInfo::InvalidOperation(sig as u32),
+        }
+    } else {
+        // Tasks aren't supposed to exit.
+        FaultInfo::InvalidOperation(libc::WEXITSTATUS(status) as u32)
+    };
+
+    let current = current_index();
+    with_task_table(|tasks| {
+        // force_fault's scheduling hint assumes that the faulting task is the
+        // current one, which it may not be here. Ask the scheduler instead.
+        let _ = task::force_fault(tasks, index, fault);
+        reschedule(current, tasks);
+    });
+}
+
+/// Picks a new task to run, as PendSV does on ARM. Since the kernel has its
+/// own thread here, there's no need to defer this.
+fn reschedule(current: usize, tasks: &mut [task::Task]) {
+    crate::profiling::event_secondary_syscall_enter();
+    let next = usize::from(task::select(current, tasks).descriptor().index);
+    task::account_switch(tasks, current, next);
+    let next = &tasks[next];
+    apply_memory_protection(next);
+    // Safety: next comes from the task table and we don't use it again
+    // until next kernel entry, so we meet set_current_task's requirements.
+    unsafe {
+        set_current_task(next);
+    }
+    crate::profiling::event_secondary_syscall_exit();
+}
+
+/// Records the address of `task` as the current user task.
+///
+/// # Safety
+///
+/// This records a pointer that aliases `task`. As long as you don't read that
+/// pointer while you have access to `task`, and as long as the `task` being
+/// stored is actually in the task table, you'll be okay.
+pub unsafe fn set_current_task(task: &task::Task) {
+    CURRENT_TASK_PTR.store(task as *const _ as *mut _, Ordering::Relaxed);
+    crate::profiling::event_context_switch(task);
+}
+
+/// Reads a free-running clock for task CPU time accounting. This counts
+/// microseconds, so that, as on ARM, there are `tick_divisor` counts per tick.
+pub fn runtime_clock() -> u64 {
+    static EPOCH: OnceLock<std::time::Instant> = OnceLock::new();
+    EPOCH
+        .get_or_init(std::time::Instant::now)
+        .elapsed()
+        .as_micros() as u64
+}
+
+/// Returns the rate of `runtime_clock`, in kHz.
+pub fn runtime_clock_khz() -> u32 {
+    1000
+}
+
+/// Reads the tick counter.
+pub fn now() -> Timestamp {
+    Timestamp::from([
+        TICKS[0].load(Ordering::Relaxed),
+        TICKS[1].load(Ordering::Relaxed),
+    ])
+}
+
+/// Kernel global for tracking the current timestamp, measured in ticks. This
+/// matches the ARM implementation; it's only touched from the kernel thread.
+static TICKS: [AtomicU32; 2] = {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const ZERO: AtomicU32 = AtomicU32::new(0);
+    [ZERO; 2]
+};
+
+/// Simulated timer interrupt.
+fn tick() {
+    crate::profiling::event_timer_isr_enter();
+    let current = current_index();
+    with_task_table(|tasks| {
+        let t0 = TICKS[0].load(Ordering::Relaxed);
+        let t1 = TICKS[1].load(Ordering::Relaxed);
+        let (t0, t1) = if let Some(t0p) = t0.checked_add(1) {
+            (t0p, t1)
+        } else {
+            (0, t1 + 1)
+        };
+        TICKS[0].store(t0, Ordering::Relaxed);
+        TICKS[1].store(t1, Ordering::Relaxed);
+
+        let now = Timestamp::from([t0, t1]);
+        let switch = task::process_timers(tasks, now)
+            .combine(task::charge_time_slice(tasks, current));
+        #[cfg(feature = "fault-injection")]
+        let switch = switch.combine(
+            task::deliver_injected_fault(tasks, current)
+                .unwrap_or(task::NextTask::Same),
+        );
+        if switch != task::NextTask::Same {
+            reschedule(current, tasks);
+        }
+    });
+    crate::profiling::event_timer_isr_exit();
+}
+
+/// Number of 32-bit words of interrupt state, matching the largest ARM NVIC
+/// (512 interrupts).
+const IRQ_WORDS: usize = 16;
+
+/// Simulated interrupt enable bits.
+static IRQ_ENABLED: [AtomicU32; IRQ_WORDS] = {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const ZERO: AtomicU32 = AtomicU32::new(0);
+    [ZERO; IRQ_WORDS]
+};
+
+/// Simulated interrupt pending bits. These can be set from any thread, by
+/// `raise_irq`.
+static IRQ_PENDING: [AtomicU32; IRQ_WORDS] = {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const ZERO: AtomicU32 = AtomicU32::new(0);
+    [ZERO; IRQ_WORDS]
+};
+
+/// Pipe used to wake the kernel when an interrupt is raised from another
+/// thread.
+fn irq_pipe() -> (i32, i32) {
+    static IRQ_PIPE: OnceLock<(i32, i32)> = OnceLock::new();
+    *IRQ_PIPE.get_or_init(pipe)
+}
+
+fn irq_word_and_mask(n: u32) -> Result<(usize, u32), UsageError> {
+    let reg_num = (n / 32) as usize;
+    if reg_num >= IRQ_WORDS {
+        return Err(UsageError::NoIrq);
+    }
+    Ok((reg_num, 1 << (n % 32)))
+}
+
+/// Asserts interrupt `n`, as a peripheral would on hardware. This can be
+/// called from any thread of the process running the kernel.
+pub fn raise_irq(n: u32) -> Result<(), UsageError> {
+    pend_software_irq(InterruptNum(n))?;
+    send_byte(irq_pipe().1);
+    Ok(())
+}
+
+/// Handles any interrupts that are both pending and enabled, just as the NVIC
+/// would on hardware.
+fn deliver_pending_irqs() {
+    for reg_num in 0..IRQ_WORDS {
+        loop {
+            let ready = IRQ_PENDING[reg_num].load(Ordering::Relaxed)
+                & IRQ_ENABLED[reg_num].load(Ordering::Relaxed);
+            if ready == 0 {
+                break;
+            }
+            let bit = ready.trailing_zeros();
+            IRQ_PENDING[reg_num].fetch_and(!(1 << bit), Ordering::Relaxed);
+            handle_irq(reg_num as u32 * 32 + bit);
+        }
+    }
+}
+
+/// Simulated interrupt handler, equivalent to `DefaultHandler` on ARM.
+fn handle_irq(irq_num: u32) {
+    crate::profiling::event_isr_enter();
+    crate::trace::record(abi::TraceEvent::Irq, irq_num);
+    let start = runtime_clock();
+    let owner = crate::startup::HUBRIS_IRQ_TASK_LOOKUP
+        .get(abi::InterruptNum(irq_num))
+        .unwrap_or_else(|| panic!("unhandled IRQ {irq_num}"));
+
+    let current = current_index();
+    with_task_table(|tasks| {
+        disable_irq(irq_num, false).ok();
+
+        let n = task::NotificationSet(owner.notification);
+        let switch = tasks[owner.task as usize].post(n);
+
+        task::account_isr(tasks, current, owner.task as usize, start);
+        if switch {
+            reschedule(current, tasks);
+        }
+    });
+    crate::profiling::event_isr_exit();
+}
+
+pub fn disable_irq(n: u32, also_clear_pending: bool) -> Result<(), UsageError> {
+    let (reg_num, bit_mask) = irq_word_and_mask(n)?;
+    IRQ_ENABLED[reg_num].fetch_and(!bit_mask, Ordering::Relaxed);
+    if also_clear_pending {
+        IRQ_PENDING[reg_num].fetch_and(!bit_mask, Ordering::Relaxed);
+    }
+    Ok(())
+}
+
+pub fn enable_irq(n: u32, also_clear_pending: bool) -> Result<(), UsageError> {
+    let (reg_num, bit_mask) = irq_word_and_mask(n)?;
+    if also_clear_pending {
+        IRQ_PENDING[reg_num].fetch_and(!bit_mask, Ordering::Relaxed);
+    }
+    IRQ_ENABLED[reg_num].fetch_or(bit_mask, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Returns a cross-platform representation of the simulated interrupt's
+/// status.
+pub fn irq_status(n: u32) -> Result<abi::IrqStatus, UsageError> {
+    let (reg_num, bit_mask) = irq_word_and_mask(n)?;
+    let mut status = abi::IrqStatus::empty();
+    status.set(
+        abi::IrqStatus::ENABLED,
+        IRQ_ENABLED[reg_num].load(Ordering::Relaxed) & bit_mask != 0,
+    );
+    status.set(
+        abi::IrqStatus::PENDING,
+        IRQ_PENDING[reg_num].load(Ordering::Relaxed) & bit_mask != 0,
+    );
+    Ok(status)
+}
+
+pub fn pend_software_irq(
+    InterruptNum(n): InterruptNum,
+) -> Result<(), UsageError> {
+    let (reg_num, bit_mask) = irq_word_and_mask(n)?;
+    IRQ_PENDING[reg_num].fetch_or(bit_mask, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Resets the "machine." The closest equivalent we have is ending the process;
+/// task processes will follow, since they're set to die with their parent.
+pub fn reset() -> ! {
+    std::process::exit(0)
+}
+
+/// Returns the image, of which there's none here: tasks are host code, not
+/// flash.
+pub fn image() -> &'static [u8] {
+    &[]
+}
+
+impl AtomicExt for AtomicBool {
+    type Primitive = bool;
+
+    #[inline(always)]
+    fn swap_polyfill(
+        &self,
+        value: Self::Primitive,
+        ordering: Ordering,
+    ) -> Self::Primitive {
+        self.swap(value, ordering)
+    }
+}
@@ -50,6 +50,10 @@ pub struct TaskDesc {
     pub initial_stack: u32,
     /// Initial priority of this task.
     pub priority: u8,
+    /// Number of kernel ticks this task may run before the kernel switches to
+    /// another runnable task of the same priority, or 0 to let it run until
+    /// it blocks or a more important task is ready.
+    pub time_slice: u32,
     /// Collection of boolean flags controlling task behavior.
     pub flags: TaskFlags,
     /// Index of this task within the task table.
@@ -71,7 +75,9 @@ bitflags::bitflags! {
     #[repr(transparent)]
     pub struct TaskFlags: u8 {
         const START_AT_BOOT = 1 << 0;
-        const RESERVED = !1;
+        /// The task may read the image with the `read_image` kipc.
+        const READ_IMAGE = 1 << 1;
+        const RESERVED = !0b11;
     }
 }
 
@@ -184,3 +190,18 @@ impl RegionAttributes {
         Self(bits)
     }
 }
+
+// Tasks read their regions' attributes (with `kipc::read_task_region`) as
+// `abi::RegionAttributes`, so the two sets of flags must agree bit for bit.
+const _: () = {
+    use abi::RegionAttributes as Abi;
+    assert!(RegionAttributes::READ.bits() == Abi::READ.bits());
+    assert!(RegionAttributes::WRITE.bits() == Abi::WRITE.bits());
+    assert!(RegionAttributes::EXECUTE.bits() == Abi::EXECUTE.bits());
+    assert!(RegionAttributes::DEVICE.bits() == Abi::DEVICE.bits());
+    assert!(RegionAttributes::DMA.bits() == Abi::DMA.bits());
+    assert!(
+        RegionAttributes::all().bits() & !RegionAttributes::RESERVED.bits()
+            == Abi::all().bits()
+    );
+};
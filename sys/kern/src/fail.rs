@@ -18,6 +18,10 @@
 //!   this buffer (as UTF-8) as possible, truncating if the buffer fills. The
 //!   number of bytes written isn't recorded anywhere; instead, for printing,
 //!   trim off any trailing NUL bytes.
+//!
+//! Both of these are lost on reset. If the application has configured a
+//! retained fault log, the start of the epitaph is also recorded there; see
+//! the `faultlog` module.
 
 #[cfg(not(feature = "nano"))]
 use core::{
@@ -75,8 +79,9 @@ pub fn die(msg: impl Display) -> ! {
 #[inline(never)]
 fn die_impl(msg: &dyn Display) -> ! {
     let buf = begin_epitaph();
-    let mut writer = Eulogist { dest: buf };
+    let mut writer = Eulogist { dest: &mut buf[..] };
     write!(writer, "{msg}").ok();
+    crate::faultlog::record_panic(buf);
 
     loop {
         // Platform-independent NOP
@@ -85,12 +90,12 @@ fn die_impl(msg: &dyn Display) -> ! {
 }
 
 #[cfg(not(feature = "nano"))]
-struct Eulogist {
-    dest: &'static mut [u8],
+struct Eulogist<'a> {
+    dest: &'a mut [u8],
 }
 
 #[cfg(not(feature = "nano"))]
-impl Write for Eulogist {
+impl Write for Eulogist<'_> {
     fn write_str(&mut self, s: &str) -> core::fmt::Result {
         let s = s.as_bytes();
         let n = s.len().min(self.dest.len());
@@ -104,13 +109,14 @@ impl Write for Eulogist {
     }
 }
 
-#[cfg(not(feature = "nano"))]
+// When hosted, we're built against `std`, which provides the panic handler.
+#[cfg(all(not(feature = "nano"), not(hubris_hosted)))]
 #[panic_handler]
 fn panic(info: &core::panic::PanicInfo<'_>) -> ! {
     die(info)
 }
 
-#[cfg(feature = "nano")]
+#[cfg(all(feature = "nano", not(hubris_hosted)))]
 #[panic_handler]
 fn panic(_info: &core::panic::PanicInfo<'_>) -> ! {
     unsafe {
@@ -0,0 +1,153 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Retained log of kernel panics and task faults.
+//!
+//! `KERNEL_EPITAPH` and the fault state of tasks are lost when the system
+//! resets or the supervisor restarts the task, respectively. If the
+//! application names a region of retained RAM as the kernel's `fault-log`, we
+//! also record each kernel panic and task fault in a ring in that region,
+//! where it survives a warm reset and can be read back (and cleared) by the
+//! supervisor after boot using kipc. If no region is configured, this all
+//! compiles to nothing.
+//!
+//! This module defines the following binary interface, which is also
+//! useful to debuggers:
+//!
+//! - The fault log region starts with an `abi::FaultLogHeader`, followed
+//!   directly by as many `abi::FaultLogEntry` as fit in the region. The header
+//!   is valid if its `magic` is `abi::FAULT_LOG_MAGIC` and its `capacity`
+//!   matches the region; if not, the kernel resets the log at boot. Entries
+//!   are written at `next`, which wraps back to 0 at `capacity`, setting
+//!   `wrapped`.
+//!
+//! The region should not be cached, or any record made just before a reset
+//! may be lost.
+
+use abi::{FaultInfo, FaultLogEntry, FaultLogHeader, FaultLogKind, TaskId};
+use core::mem::size_of;
+
+use crate::startup::HUBRIS_FAULT_LOG;
+
+/// Gets the header and entries of the fault log, if there is one.
+///
+/// # Safety
+///
+/// The result must not outlive the current kernel entry point, since the log
+/// is global. (Kernel entry points don't nest, so within one, there's only
+/// ever one of these around.)
+unsafe fn log(
+) -> Option<(&'static mut FaultLogHeader, &'static mut [FaultLogEntry])> {
+    let region = HUBRIS_FAULT_LOG?;
+    let capacity = (region.len() - size_of::<FaultLogHeader>())
+        / size_of::<FaultLogEntry>();
+    // Safety: the region is set aside for us by the application config, and
+    // our build script has checked that it's aligned and big enough for the
+    // header and `capacity` entries. Both types are valid for any bit pattern,
+    // which matters, since we don't know what was left in there.
+    unsafe {
+        let header = &mut *(region.start as *mut FaultLogHeader);
+        let entries = core::slice::from_raw_parts_mut(
+            (region.start + size_of::<FaultLogHeader>()) as *mut FaultLogEntry,
+            capacity,
+        );
+        Some((header, entries))
+    }
+}
+
+/// Checks the fault log left from before the reset (resetting it if it's
+/// not valid) and counts a boot. Called once from `start_kernel`.
+pub(crate) fn start() {
+    // Safety: we're in `start_kernel`, so nothing else is using the log.
+    let Some((header, entries)) = (unsafe { log() }) else {
+        return;
+    };
+    let capacity = entries.len() as u32;
+    if header.magic != abi::FAULT_LOG_MAGIC
+        || header.capacity != capacity
+        || header.next >= capacity
+    {
+        *header = FaultLogHeader {
+            magic: abi::FAULT_LOG_MAGIC,
+            capacity,
+            ..FaultLogHeader::default()
+        };
+    }
+    header.boots = header.boots.wrapping_add(1);
+}
+
+/// Records that `task` took `fault`.
+pub(crate) fn record_task_fault(task: TaskId, fault: &FaultInfo) {
+    let mut data = [0; abi::FAULT_LOG_DATA_LEN];
+    // FaultInfo is much smaller than our data field, so this can't fail.
+    ssmarshal::serialize(&mut data, fault).ok();
+    record(FaultLogKind::TaskFault, task, data);
+}
+
+/// Records a kernel panic, with (the start of) its epitaph.
+#[cfg(not(feature = "nano"))]
+pub(crate) fn record_panic(epitaph: &[u8]) {
+    let mut data = [0; abi::FAULT_LOG_DATA_LEN];
+    let n = epitaph.len().min(data.len());
+    data[..n].copy_from_slice(&epitaph[..n]);
+    record(FaultLogKind::KernelPanic, TaskId::KERNEL, data);
+}
+
+fn record(
+    kind: FaultLogKind,
+    task: TaskId,
+    data: [u8; abi::FAULT_LOG_DATA_LEN],
+) {
+    // Safety: we're being called from a kernel entry point, and don't hold
+    // onto this past it.
+    let Some((header, entries)) = (unsafe { log() }) else {
+        return;
+    };
+    if header.magic != abi::FAULT_LOG_MAGIC {
+        // Not started yet.
+        return;
+    }
+    let time = u64::from(crate::arch::now());
+    let next = header.next as usize;
+    entries[next] = FaultLogEntry {
+        kind: kind as u32,
+        boot: header.boots,
+        time_lo: time as u32,
+        time_hi: (time >> 32) as u32,
+        task: u32::from(task.0),
+        data,
+    };
+    if next + 1 == entries.len() {
+        header.next = 0;
+        header.wrapped = 1;
+    } else {
+        header.next = next as u32 + 1;
+    }
+}
+
+/// Returns the `index`th oldest entry in the fault log, if there is one.
+pub(crate) fn read(index: usize) -> Option<FaultLogEntry> {
+    // Safety: we're being called from a kernel entry point, and don't hold
+    // onto this past it.
+    let (header, entries) = unsafe { log() }?;
+    let (oldest, len) = if header.wrapped != 0 {
+        (header.next as usize, entries.len())
+    } else {
+        (0, header.next as usize)
+    };
+    if index >= len {
+        return None;
+    }
+    Some(entries[(oldest + index) % entries.len()])
+}
+
+/// Discards all entries in the fault log. The boot count is kept.
+pub(crate) fn clear() {
+    // Safety: we're being called from a kernel entry point, and don't hold
+    // onto this past it.
+    if let Some((header, _)) = unsafe { log() } {
+        header.next = 0;
+        header.wrapped = 0;
+    }
+}
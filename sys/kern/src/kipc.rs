@@ -7,8 +7,10 @@
 use abi::{FaultInfo, Kipcnum, SchedState, TaskState, UsageError};
 use core::mem::size_of;
 use unwrap_lite::UnwrapLite;
+use zerocopy::IntoBytes;
 
 use crate::arch;
+use crate::descs::TaskFlags;
 use crate::err::UserError;
 use crate::task::{current_id, ArchState, NextTask, Task};
 use crate::umem::USlice;
@@ -43,6 +45,24 @@ pub fn handle_kernel_message(
         Ok(Kipcnum::FindFaultedTask) => {
             find_faulted_task(tasks, caller, args.message?, args.response?)
         }
+        Ok(Kipcnum::ReadTaskRuntime) => {
+            read_task_runtime(tasks, caller, args.message?, args.response?)
+        }
+        Ok(Kipcnum::ReadTaskStackUsage) => {
+            read_task_stack_usage(tasks, caller, args.message?, args.response?)
+        }
+        Ok(Kipcnum::ReadFaultLog) => {
+            read_fault_log(tasks, caller, args.message?, args.response?)
+        }
+        Ok(Kipcnum::ClearFaultLog) => clear_fault_log(tasks, caller),
+        #[cfg(feature = "fault-injection")]
+        Ok(Kipcnum::InjectFault) => inject_fault(tasks, caller, args.message?),
+        Ok(Kipcnum::ReadTaskRegion) => {
+            read_task_region(tasks, caller, args.message?, args.response?)
+        }
+        Ok(Kipcnum::ReadImage) => {
+            read_image(tasks, caller, args.message?, args.response?)
+        }
 
         _ => {
             // Task has sent an unknown message to the kernel. That's bad.
@@ -137,6 +157,11 @@ fn reinit_task(
     // example. So, make a pass over the task table and unblock anyone who was
     // expecting useful work from the now-defunct task.
     for (i, task) in tasks.iter_mut().enumerate() {
+        // Any reply the old task owed for a SEND_TIMEOUT that expired is never
+        // coming. This applies to the caller too, since supervisors commonly
+        // restart a task right after giving up on it.
+        let _ = task.take_abandoned_reply(old_id);
+
         // Just to make this a little easier to think about, don't check either
         // of the tasks involved in the restart operation. Neither should be
         // affected anyway.
@@ -507,3 +532,197 @@ fn find_faulted_task(
         .set_send_response_and_length(0, response_len);
     Ok(NextTask::Same)
 }
+
+fn read_task_runtime(
+    tasks: &mut [Task],
+    caller: usize,
+    message: USlice<u8>,
+    response: USlice<u8>,
+) -> Result<NextTask, UserError> {
+    let index = deserialize_message::<u32>(&tasks[caller], message)? as usize;
+    if index >= tasks.len() {
+        return Err(UserError::Unrecoverable(FaultInfo::SyscallUsage(
+            UsageError::TaskOutOfRange,
+        )));
+    }
+    // The caller is the current task, so if it's asking about itself, include
+    // the time it's been running since it was last switched in.
+    let runtime = tasks[index].runtime(index == caller, arch::runtime_clock());
+
+    let response_len =
+        serialize_response(&mut tasks[caller], response, &runtime)?;
+    tasks[caller]
+        .save_mut()
+        .set_send_response_and_length(0, response_len);
+    Ok(NextTask::Same)
+}
+
+fn read_task_stack_usage(
+    tasks: &mut [Task],
+    caller: usize,
+    message: USlice<u8>,
+    response: USlice<u8>,
+) -> Result<NextTask, UserError> {
+    let index = deserialize_message::<u32>(&tasks[caller], message)? as usize;
+    if index >= tasks.len() {
+        return Err(UserError::Unrecoverable(FaultInfo::SyscallUsage(
+            UsageError::TaskOutOfRange,
+        )));
+    }
+    let usage = tasks[index].stack_usage();
+
+    let response_len =
+        serialize_response(&mut tasks[caller], response, &usage)?;
+    tasks[caller]
+        .save_mut()
+        .set_send_response_and_length(0, response_len);
+    Ok(NextTask::Same)
+}
+
+fn read_fault_log(
+    tasks: &mut [Task],
+    caller: usize,
+    message: USlice<u8>,
+    mut response: USlice<u8>,
+) -> Result<NextTask, UserError> {
+    if caller != 0 {
+        return Err(UserError::Unrecoverable(FaultInfo::SyscallUsage(
+            UsageError::NotSupervisor,
+        )));
+    }
+
+    let index = deserialize_message::<u32>(&tasks[caller], message)? as usize;
+
+    // Entries are sent back raw, rather than serialized, since they're
+    // already in a fixed binary format. An empty response means there's no
+    // such entry.
+    let response_len = match crate::faultlog::read(index) {
+        Some(entry) => {
+            let buf = tasks[caller].try_write(&mut response)?;
+            let bytes = entry.as_bytes();
+            // As in `serialize_response`, we tolerate a response buffer
+            // that's too small, and just report the size that would have
+            // worked.
+            if let Some(dest) = buf.get_mut(..bytes.len()) {
+                dest.copy_from_slice(bytes);
+            }
+            bytes.len()
+        }
+        None => 0,
+    };
+    tasks[caller]
+        .save_mut()
+        .set_send_response_and_length(0, response_len);
+    Ok(NextTask::Same)
+}
+
+fn clear_fault_log(
+    tasks: &mut [Task],
+    caller: usize,
+) -> Result<NextTask, UserError> {
+    if caller != 0 {
+        return Err(UserError::Unrecoverable(FaultInfo::SyscallUsage(
+            UsageError::NotSupervisor,
+        )));
+    }
+
+    crate::faultlog::clear();
+
+    tasks[caller].save_mut().set_send_response_and_length(0, 0);
+    Ok(NextTask::Same)
+}
+
+/// Arranges for a task to take a fault of the caller's choosing the next time
+/// it enters the kernel. This exists for testing recovery from faults, and is
+/// only available with the kernel's `fault-injection` feature.
+///
+/// As with `fault_task`, any task may use this, but not on the supervisor or
+/// on itself.
+#[cfg(feature = "fault-injection")]
+fn inject_fault(
+    tasks: &mut [Task],
+    caller: usize,
+    message: USlice<u8>,
+) -> Result<NextTask, UserError> {
+    let (index, fault): (u32, FaultInfo) =
+        deserialize_message(&tasks[caller], message)?;
+    let index = index as usize;
+
+    if index == 0 || index == caller {
+        return Err(UserError::Unrecoverable(FaultInfo::SyscallUsage(
+            UsageError::IllegalTask,
+        )));
+    }
+
+    if index >= tasks.len() {
+        return Err(UserError::Unrecoverable(FaultInfo::SyscallUsage(
+            UsageError::TaskOutOfRange,
+        )));
+    }
+
+    tasks[index].inject_fault(fault);
+    tasks[caller].save_mut().set_send_response_and_length(0, 0);
+
+    Ok(NextTask::Same)
+}
+
+fn read_task_region(
+    tasks: &mut [Task],
+    caller: usize,
+    message: USlice<u8>,
+    response: USlice<u8>,
+) -> Result<NextTask, UserError> {
+    let (index, rindex): (u32, u32) =
+        deserialize_message(&tasks[caller], message)?;
+    if index as usize >= tasks.len() {
+        return Err(UserError::Unrecoverable(FaultInfo::SyscallUsage(
+            UsageError::TaskOutOfRange,
+        )));
+    }
+
+    let rval = tasks[index as usize]
+        .region_table()
+        .get(rindex as usize)
+        .map(|r| abi::TaskRegion {
+            base: r.base,
+            size: r.size,
+            attributes: r.attributes.bits(),
+        });
+
+    let response_len = serialize_response(&mut tasks[caller], response, &rval)?;
+    tasks[caller]
+        .save_mut()
+        .set_send_response_and_length(0, response_len);
+    Ok(NextTask::Same)
+}
+
+fn read_image(
+    tasks: &mut [Task],
+    caller: usize,
+    message: USlice<u8>,
+    mut response: USlice<u8>,
+) -> Result<NextTask, UserError> {
+    // Reading the image crosses every task's isolation, so it's only for
+    // tasks granted it.
+    if !tasks[caller]
+        .descriptor()
+        .flags
+        .contains(TaskFlags::READ_IMAGE)
+    {
+        return Err(UserError::Unrecoverable(FaultInfo::SyscallUsage(
+            UsageError::NotGranted,
+        )));
+    }
+
+    let offset: u32 = deserialize_message(&tasks[caller], message)?;
+    let image = arch::image();
+    let src = image.get(offset as usize..).unwrap_or(&[]);
+
+    let dest = tasks[caller].try_write(&mut response)?;
+    let len = src.len().min(dest.len());
+    dest[..len].copy_from_slice(&src[..len]);
+    tasks[caller]
+        .save_mut()
+        .set_send_response_and_length(0, len);
+    Ok(NextTask::Same)
+}
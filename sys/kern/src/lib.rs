@@ -39,6 +39,7 @@ pub mod atomic;
 mod descs;
 pub mod err;
 pub mod fail;
+mod faultlog;
 pub mod header;
 pub mod kipc;
 pub mod profiling;
@@ -46,5 +47,6 @@ pub mod startup;
 pub mod syscalls;
 pub mod task;
 pub mod time;
+mod trace;
 pub mod umem;
 pub mod util;
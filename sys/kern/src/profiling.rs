@@ -7,6 +7,9 @@
 //! This is intended to help measure the timing of kernel events, duration of
 //! syscalls, and the like, as an aid to debugging or optimization work.
 //!
+//! Each event is also passed on to the `trace` module, which records it in RAM
+//! if the kernel was built with the `trace` feature.
+//!
 //! Because the kernel is SoC-independent, this module does not assume any
 //! particular way of getting profiling information out of the kernel. Instead,
 //! any target that wants to use profiling needs to populate an `EventsTable`
@@ -46,6 +49,11 @@
 
 use core::sync::atomic::{AtomicPtr, Ordering};
 
+use abi::TraceEvent;
+
+use crate::task::Task;
+use crate::trace;
+
 /// Hooks that must be provided by the board setup code if it wants to enable
 /// kernel profiling.
 ///
@@ -118,24 +126,28 @@ fn table() -> Option<&'static EventsTable> {
 }
 
 pub(crate) fn event_syscall_enter(nr: u32) {
+    trace::record(TraceEvent::SyscallEnter, nr);
     if let Some(t) = table() {
         (t.syscall_enter)(nr)
     }
 }
 
 pub(crate) fn event_syscall_exit() {
+    trace::record(TraceEvent::SyscallExit, 0);
     if let Some(t) = table() {
         (t.syscall_exit)()
     }
 }
 
 pub(crate) fn event_secondary_syscall_enter() {
+    trace::record(TraceEvent::SecondarySyscallEnter, 0);
     if let Some(t) = table() {
         (t.secondary_syscall_enter)()
     }
 }
 
 pub(crate) fn event_secondary_syscall_exit() {
+    trace::record(TraceEvent::SecondarySyscallExit, 0);
     if let Some(t) = table() {
         (t.secondary_syscall_exit)()
     }
@@ -144,6 +156,7 @@ pub(crate) fn event_secondary_syscall_exit() {
 /// Signals entry to an ISR. This is `pub` in case you write your own
 /// non-kernel-managed ISR but you'd like to include it in ISR statistics.
 pub fn event_isr_enter() {
+    trace::record(TraceEvent::IsrEnter, 0);
     if let Some(t) = table() {
         (t.isr_enter)()
     }
@@ -152,25 +165,32 @@ pub fn event_isr_enter() {
 /// Signals exit from an ISR. This is `pub` in case you write your own
 /// non-kernel-managed ISR but you'd like to include it in ISR statistics.
 pub fn event_isr_exit() {
+    trace::record(TraceEvent::IsrExit, 0);
     if let Some(t) = table() {
         (t.isr_exit)()
     }
 }
 
 pub(crate) fn event_timer_isr_enter() {
+    trace::record(TraceEvent::TimerIsrEnter, 0);
     if let Some(t) = table() {
         (t.timer_isr_enter)()
     }
 }
 
 pub(crate) fn event_timer_isr_exit() {
+    trace::record(TraceEvent::TimerIsrExit, 0);
     if let Some(t) = table() {
         (t.timer_isr_exit)()
     }
 }
 
-pub(crate) fn event_context_switch(tcb: usize) {
+pub(crate) fn event_context_switch(task: &Task) {
+    trace::record(
+        TraceEvent::ContextSwitch,
+        u32::from(task.descriptor().index),
+    );
     if let Some(t) = table() {
-        (t.context_switch)(tcb)
+        (t.context_switch)(task as *const _ as usize)
     }
 }
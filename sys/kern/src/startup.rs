@@ -46,6 +46,8 @@ pub unsafe fn start_kernel(tick_divisor: u32) -> ! {
     unsafe {
         crate::arch::set_clock_freq(tick_divisor);
     }
+    crate::trace::start(tick_divisor);
+    crate::faultlog::start();
 
     // Grab references to all our statics.
     let task_descs = &HUBRIS_TASK_DESCS;
@@ -41,7 +41,7 @@ use crate::err::{InteractFault, UserError};
 use crate::startup::with_task_table;
 use crate::task::{self, current_id, ArchState, NextTask, Task};
 use crate::time::Timestamp;
-use crate::umem::{safe_copy, USlice};
+use crate::umem::{self, safe_copy, USlice};
 
 #[cfg(hubris_phantom_svc_mitigation)]
 pub(crate) static EXPECT_PHANTOM_SYSCALL: AtomicBool = AtomicBool::new(false);
@@ -97,16 +97,19 @@ pub unsafe extern "C" fn syscall_entry(nr: u32, task: *mut Task) {
             NextTask::Same => (),
 
             NextTask::Specific(i) => {
+                task::account_switch(tasks, idx, i);
                 // Safety: this is a valid task from the tasks table, meeting
                 // switch_to's requirements.
                 unsafe { switch_to(&tasks[i]) }
             }
 
             NextTask::Other => {
-                let next = task::select(idx, tasks);
+                let next = task::select(idx, tasks).descriptor().index;
+                let next = usize::from(next);
+                task::account_switch(tasks, idx, next);
                 // Safety: this is a valid task from the tasks table, meeting
                 // switch_to's requirements.
-                unsafe { switch_to(next) }
+                unsafe { switch_to(&tasks[next]) }
             }
         }
     });
@@ -117,8 +120,14 @@ pub unsafe extern "C" fn syscall_entry(nr: u32, task: *mut Task) {
 /// Factored out of `syscall_entry` to encapsulate the bits that don't need
 /// unsafe.
 fn safe_syscall_entry(nr: u32, current: usize, tasks: &mut [Task]) -> NextTask {
+    // An injected fault takes the place of the syscall.
+    #[cfg(feature = "fault-injection")]
+    if let Some(hint) = task::deliver_injected_fault(tasks, current) {
+        return hint;
+    }
+
     let res = match Sysnum::try_from(nr) {
-        Ok(Sysnum::Send) => send(tasks, current),
+        Ok(Sysnum::Send) => send(tasks, current, None),
         Ok(Sysnum::Recv) => recv(tasks, current),
         Ok(Sysnum::Reply) => reply(tasks, current).map_err(UserError::from),
         Ok(Sysnum::SetTimer) => Ok(set_timer(&mut tasks[current], arch::now())),
@@ -134,6 +143,7 @@ fn safe_syscall_entry(nr: u32, current: usize, tasks: &mut [Task]) -> NextTask {
             reply_fault(tasks, current).map_err(UserError::from)
         }
         Ok(Sysnum::IrqStatus) => irq_status(tasks, current),
+        Ok(Sysnum::SendTimeout) => send_timeout(tasks, current, arch::now()),
         Err(_) => {
             // Bogus syscall number! That's a fault.
             Err(FaultInfo::SyscallUsage(UsageError::BadSyscallNumber).into())
@@ -155,10 +165,22 @@ fn safe_syscall_entry(nr: u32, current: usize, tasks: &mut [Task]) -> NextTask {
 ///
 /// `caller` is a valid task index (i.e. not directly from user code).
 ///
+/// If `deadline` is provided, the caller will be unblocked with
+/// `abi::TIMEOUT` if it's still waiting to send or waiting for a reply at that
+/// time.
+///
 /// # Panics
 ///
 /// If `caller` is out of range for `tasks`.
-fn send(tasks: &mut [Task], caller: usize) -> Result<NextTask, UserError> {
+fn send(
+    tasks: &mut [Task],
+    caller: usize,
+    deadline: Option<Timestamp>,
+) -> Result<NextTask, UserError> {
+    // Any deadline left over from a previous SEND_TIMEOUT must not apply to
+    // this one.
+    tasks[caller].set_send_deadline(deadline);
+
     // Extract callee.
     let callee_id = tasks[caller].save().as_send_args().callee;
 
@@ -177,7 +199,12 @@ fn send(tasks: &mut [Task], caller: usize) -> Result<NextTask, UserError> {
     // Check for ready peer.
     let mut next_task = NextTask::Same;
     let caller_id = current_id(tasks, caller);
-    if tasks[callee].state().can_accept_message_from(caller_id) {
+    // If the callee still owes us a reply to a message we gave up on, hold
+    // this one back until it's been dealt with, or the reply would look like
+    // it was for this message.
+    if tasks[callee].state().can_accept_message_from(caller_id)
+        && !tasks[caller].is_abandoned_by(callee_id)
+    {
         // Callee is waiting in receive -- either an open receive, or a
         // closed receive from just us. Either way, we can directly deliver the
         // message and switch tasks...unless either task was naughty, in which
@@ -208,6 +235,29 @@ fn send(tasks: &mut [Task], caller: usize) -> Result<NextTask, UserError> {
     Ok(NextTask::Other.combine(next_task))
 }
 
+/// Implementation of the SEND_TIMEOUT IPC primitive.
+///
+/// This takes the same arguments as SEND, plus a deadline in kernel time,
+/// which the syscall stub leaves where `arch::syscall_stacked_args` can find
+/// it. If the deadline has already passed, the message isn't sent at all.
+///
+/// `caller` is a valid task index (i.e. not directly from user code).
+///
+/// # Panics
+///
+/// If `caller` is out of range for `tasks`.
+fn send_timeout(
+    tasks: &mut [Task],
+    caller: usize,
+    now: Timestamp,
+) -> Result<NextTask, UserError> {
+    let deadline = Timestamp::from(arch::syscall_stacked_args(&tasks[caller])?);
+    if deadline <= now {
+        return Err(UserError::Recoverable(abi::TIMEOUT, NextTask::Same));
+    }
+    send(tasks, caller, Some(deadline))
+}
+
 /// Implementation of the RECV IPC primitive.
 ///
 /// `caller` is a valid task index (i.e. not directly from user code).
@@ -277,7 +327,7 @@ fn recv(tasks: &mut [Task], caller: usize) -> Result<NextTask, UserError> {
         let sender_idx = task::check_task_id_against_table(tasks, sender_id)?;
 
         // Second possibility: task has a message for us.
-        if tasks[sender_idx].state().is_sending_to(caller_id) {
+        if tasks[sender_idx].is_sending_to(caller_id) {
             // Oh hello sender!
             match deliver(tasks, sender_idx, caller) {
                 Ok(_) => {
@@ -322,9 +372,9 @@ fn recv(tasks: &mut [Task], caller: usize) -> Result<NextTask, UserError> {
         let mut last = caller; // keep track of scan position.
 
         // Is anyone blocked waiting to send to us?
-        while let Some((sender, _)) = task::priority_scan(last, tasks, |t| {
-            t.state().is_sending_to(caller_id)
-        }) {
+        while let Some((sender, _)) =
+            task::priority_scan(last, tasks, |t| t.is_sending_to(caller_id))
+        {
             // Oh hello sender!
             match deliver(tasks, sender, caller) {
                 Ok(()) => {
@@ -378,6 +428,12 @@ fn reply(tasks: &mut [Task], caller: usize) -> Result<NextTask, FaultInfo> {
         Ok(x) => x,
     };
 
+    if tasks[callee].take_abandoned_reply(caller_id) {
+        // The target's SEND_TIMEOUT expired before we got here. It may be
+        // sending us something new by now, which this isn't a reply to.
+        return Ok(NextTask::Same);
+    }
+
     match tasks[callee].state() {
         TaskState::Healthy(SchedState::InReply(x)) if *x == caller_id => (),
         _ => {
@@ -491,7 +547,7 @@ fn borrow_read(
 
     let lender = task::check_task_id_against_table(tasks, args.lender)?;
 
-    let lease =
+    let (lender, lease) =
         borrow_lease(tasks, caller, lender, args.lease_number, args.offset)?;
 
     // Does the lease grant us the ability to read from the memory?
@@ -534,7 +590,7 @@ fn borrow_write(
 
     let lender = task::check_task_id_against_table(tasks, args.lender)?;
 
-    let lease =
+    let (lender, lease) =
         borrow_lease(tasks, caller, lender, args.lease_number, args.offset)?;
 
     // Does the lease grant us the ability to write to the memory?
@@ -576,7 +632,7 @@ fn borrow_info(
 
     let lender = task::check_task_id_against_table(tasks, args.lender)?;
 
-    let lease = borrow_lease(tasks, caller, lender, args.lease_number, 0)?;
+    let (_, lease) = borrow_lease(tasks, caller, lender, args.lease_number, 0)?;
 
     tasks[caller]
         .save_mut()
@@ -584,18 +640,100 @@ fn borrow_info(
     Ok(NextTask::Same)
 }
 
+/// Finds lease number `lease_number` that `lender` has lent to `caller`,
+/// offset by `offset` bytes.
+///
+/// If the lease was forwarded to `caller` (i.e. `lender` is passing on part of
+/// a lease it was lent by some other task), this follows it back to the task
+/// whose memory is actually being lent, and returns that task's index along
+/// with the lease narrowed to the forwarded part. Otherwise, the index
+/// returned is just `lender`.
+///
+/// Forwarded leases need no explicit revocation: each step is only honored
+/// while the original lender is still waiting for a reply from the task that
+/// forwarded its lease, so replying to it, or its being restarted, cuts off
+/// access.
 fn borrow_lease(
     tasks: &mut [Task],
     caller: usize,
     lender: usize,
     lease_number: usize,
     offset: usize,
-) -> Result<ULease, UserError> {
-    let caller_id = current_id(tasks, caller);
+) -> Result<(usize, ULease), UserError> {
+    let Some(mut lease) = read_lease(tasks, caller, lender, lease_number)?
+    else {
+        // Borrower provided an invalid lease number. Borrower was told the
+        // number of leases on successful RECV and should respect that. (Note:
+        // if the lender's lease table changed shape, this will fault the
+        // borrower, which might be bad.)
+        return Err(FaultInfo::SyscallUsage(UsageError::LeaseOutOfRange).into());
+    };
+
+    // Attempt to offset the lease. Handle cases where the offset is bogus.
+    // First, we must convert to u32, which _should be_ a no-op but we'll do it
+    // the careful way:
+    let offset = u32::try_from(offset).unwrap_lite();
+    // Now, proceed only if both neither the length nor address computation
+    // wrap. (For a forwarded lease, the "address" is an offset into the
+    // original lease, but the same arithmetic applies.)
+    if let (Some(off_len), Some(off_addr)) = (
+        lease.length.checked_sub(offset),
+        lease.base_address.checked_add(offset),
+    ) {
+        lease.base_address = off_addr;
+        lease.length = off_len;
+    } else {
+        return Err(
+            FaultInfo::SyscallUsage(UsageError::OffsetOutOfRange).into()
+        );
+    }
+
+    // Follow any forwarding back to the original lender. Each task in the
+    // chain is waiting for a reply from the next, so the chain can't be longer
+    // than the task table -- but we bound the loop anyway rather than trust
+    // that.
+    let mut lender = lender;
+    for _ in 0..tasks.len() {
+        let Some((origin_id, origin_lease)) = lease.forwarded_from() else {
+            return Ok((lender, lease));
+        };
+        // Any problem from here on is the forwarding task's doing (or just
+        // means the lease has been revoked), so it's reported as a defecting
+        // lender rather than faulting the borrower.
+        let Ok(origin) = task::check_task_id_against_table(tasks, origin_id)
+        else {
+            return Err(UserError::Recoverable(abi::DEFECT, NextTask::Same));
+        };
+        let Some(parent) = read_lease(tasks, lender, origin, origin_lease)?
+        else {
+            return Err(UserError::Recoverable(abi::DEFECT, NextTask::Same));
+        };
+        let Some(narrowed) = umem::narrow_forwarded_lease(&parent, &lease)
+        else {
+            return Err(UserError::Recoverable(abi::DEFECT, NextTask::Same));
+        };
+        lender = origin;
+        lease = narrowed;
+    }
+    Err(UserError::Recoverable(abi::DEFECT, NextTask::Same))
+}
+
+/// Reads lease number `lease_number` from the lease table of `lender`, which
+/// must be waiting for a reply from `borrower`.
+///
+/// Returns `Ok(None)` if `lease_number` is out of range for the table. Faults
+/// `lender` if its lease table can't be read.
+fn read_lease(
+    tasks: &mut [Task],
+    borrower: usize,
+    lender: usize,
+    lease_number: usize,
+) -> Result<Option<ULease>, UserError> {
+    let borrower_id = current_id(tasks, borrower);
 
     // Check state of lender and range of lease table.
     match tasks[lender].state() {
-        TaskState::Healthy(SchedState::InReply(x)) if *x == caller_id => (),
+        TaskState::Healthy(SchedState::InReply(x)) if *x == borrower_id => (),
         _ => {
             // The alleged lender isn't lending anything at all.
             // Let's assume this is a defecting lender.
@@ -628,32 +766,7 @@ fn borrow_lease(
     // Try reading the lease. This is unsafe in the general case, but since
     // we've just convinced ourselves that the lease table is in task memory,
     // we can do this safely.
-    let lease = leases.get(lease_number).cloned();
-    // Is the lease number provided by the borrower legitimate?
-    if let Some(mut lease) = lease {
-        // Attempt to offset the lease. Handle cases where the offset is bogus.
-        // First, we must convert to u32, which _should be_ a no-op but we'll do
-        // it the careful way:
-        let offset = u32::try_from(offset).unwrap_lite();
-        // Now, proceed only if both neither the length nor address computation
-        // wrap.
-        if let (Some(off_len), Some(off_addr)) = (
-            lease.length.checked_sub(offset),
-            lease.base_address.checked_add(offset),
-        ) {
-            lease.base_address = off_addr;
-            lease.length = off_len;
-            Ok(lease)
-        } else {
-            Err(FaultInfo::SyscallUsage(UsageError::OffsetOutOfRange).into())
-        }
-    } else {
-        // Borrower provided an invalid lease number. Borrower was told the
-        // number of leases on successful RECV and should respect that. (Note:
-        // if the lender's lease table changed shape, this will fault the
-        // borrower, which might be bad.)
-        Err(FaultInfo::SyscallUsage(UsageError::LeaseOutOfRange).into())
-    }
+    Ok(leases.get(lease_number).cloned())
 }
 
 /// Performs the architecture-specific bookkeeping to activate `task` on next
@@ -871,6 +984,12 @@ fn reply_fault(
         Ok(x) => x,
     };
 
+    if tasks[callee].take_abandoned_reply(caller_id) {
+        // As in `reply`, the target gave up on us, so it's too late to blame
+        // it for anything.
+        return Ok(NextTask::Same);
+    }
+
     match tasks[callee].state() {
         TaskState::Healthy(SchedState::InReply(x)) if *x == caller_id => (),
         _ => {
@@ -7,8 +7,8 @@
 use core::ops::Range;
 
 use abi::{
-    FaultInfo, FaultSource, Generation, ReplyFaultReason, SchedState, TaskId,
-    TaskState, ULease, UsageError,
+    FaultInfo, FaultSource, Generation, ReplyFaultReason, SchedState,
+    StackUsage, TaskId, TaskRuntime, TaskState, ULease, UsageError,
 };
 use zerocopy::{FromBytes, Immutable, KnownLayout};
 
@@ -17,10 +17,48 @@ use crate::descs::{
     REGIONS_PER_TASK,
 };
 use crate::err::UserError;
-use crate::startup::HUBRIS_FAULT_NOTIFICATION;
+use crate::startup::{HUBRIS_FAULT_NOTIFICATION, HUBRIS_TASK_COUNT};
 use crate::time::Timestamp;
 use crate::umem::USlice;
 
+/// Value that `arch::reinitialize` paints over the unused part of a task's
+/// stack, so that the deepest stack use can be found later by looking for the
+/// lowest word that no longer holds it.
+pub const STACK_PAINT: u32 = 0xbaddcafe;
+
+/// A set of tasks, by index.
+#[derive(Copy, Clone, Debug)]
+struct TaskSet([u32; TaskSet::WORDS]);
+
+impl TaskSet {
+    const WORDS: usize = HUBRIS_TASK_COUNT.div_ceil(32);
+    const EMPTY: Self = Self([0; Self::WORDS]);
+
+    fn word_and_bit(index: usize) -> (usize, u32) {
+        (index / 32, 1 << (index % 32))
+    }
+
+    fn contains(&self, index: usize) -> bool {
+        let (word, bit) = Self::word_and_bit(index);
+        self.0.get(word).is_some_and(|w| w & bit != 0)
+    }
+
+    fn insert(&mut self, index: usize) {
+        let (word, bit) = Self::word_and_bit(index);
+        self.0[word] |= bit;
+    }
+
+    /// Removes `index`, returning `true` if it was there.
+    fn remove(&mut self, index: usize) -> bool {
+        let present = self.contains(index);
+        if present {
+            let (word, bit) = Self::word_and_bit(index);
+            self.0[word] &= !bit;
+        }
+        present
+    }
+}
+
 /// Internal representation of a task.
 ///
 /// The fields of this struct are private to this module so that we can maintain
@@ -39,6 +77,20 @@ pub struct Task {
     state: TaskState,
     /// State for tracking the task's timer.
     timer: TimerState,
+    /// Deadline for the task's current `SEND_TIMEOUT`, if any. This is only
+    /// meaningful while the task is blocked in `InSend` or `InReply`, and is
+    /// reset by every SEND.
+    send_deadline: Option<Timestamp>,
+    /// Peers that received a message from this task, but hadn't replied by
+    /// the deadline of the `SEND_TIMEOUT` that sent it. Those replies, if they
+    /// ever come, must be discarded rather than delivered. A task can only
+    /// have one message outstanding with each peer, so this is tracked by
+    /// peer index.
+    abandoned_by: TaskSet,
+    /// Synthetic fault injected by `kipc::inject_fault`, to be taken the next
+    /// time this task enters the kernel.
+    #[cfg(feature = "fault-injection")]
+    injected_fault: Option<FaultInfo>,
     /// Restart count for this task. We increment this whenever we reinitialize
     /// the task. The low bits of this become the task's generation number.
     generation: u32,
@@ -46,6 +98,18 @@ pub struct Task {
     /// Notification status.
     notifications: u32,
 
+    /// CPU time accounting. Unlike most of our state, this is preserved
+    /// across restarts.
+    runtime: TaskRuntime,
+    /// Value of `arch::runtime_clock` when this task was last switched in,
+    /// pushed forward by any time spent in interrupt handlers since. This is
+    /// only meaningful while the task is current.
+    switched_in_at: u64,
+    /// Number of kernel ticks that this task has been current for since it
+    /// was last switched in, for time slicing. This is only maintained for
+    /// tasks with a `time_slice` in their descriptor.
+    slice_ticks: u32,
+
     /// Pointer to the ROM descriptor used to create this task, so it can be
     /// restarted.
     descriptor: &'static TaskDesc,
@@ -69,6 +133,13 @@ impl Task {
             notifications: 0,
             save: crate::arch::SavedState::default(),
             timer: crate::task::TimerState::default(),
+            send_deadline: None,
+            abandoned_by: TaskSet::EMPTY,
+            #[cfg(feature = "fault-injection")]
+            injected_fault: None,
+            runtime: TaskRuntime::default(),
+            switched_in_at: 0,
+            slice_ticks: 0,
         }
     }
 
@@ -305,6 +376,59 @@ impl Task {
         (self.timer.deadline, self.timer.to_post)
     }
 
+    /// Sets the deadline for the SEND this task is about to perform. `None`
+    /// means the SEND may block indefinitely.
+    pub fn set_send_deadline(&mut self, deadline: Option<Timestamp>) {
+        self.send_deadline = deadline;
+    }
+
+    /// Checks if this task is blocked trying to deliver a message to `target`,
+    /// and that message can be delivered now.
+    ///
+    /// A task that gave up waiting for `target` to reply can't send it
+    /// anything new until the abandoned reply has been consumed, so that the
+    /// late reply can't be mistaken for a response to the new message.
+    pub fn is_sending_to(&self, target: TaskId) -> bool {
+        self.state.is_sending_to(target) && !self.is_abandoned_by(target)
+    }
+
+    /// Checks if `peer` still owes this task a reply to a message whose
+    /// `SEND_TIMEOUT` has expired.
+    pub fn is_abandoned_by(&self, peer: TaskId) -> bool {
+        self.abandoned_by.contains(peer.index())
+    }
+
+    /// Records that the reply owed by `peer` has arrived (or that `peer` has
+    /// restarted and never will reply), if it was one we abandoned.
+    ///
+    /// Returns `true` if the reply was abandoned, in which case it should be
+    /// discarded.
+    pub fn take_abandoned_reply(&mut self, peer: TaskId) -> bool {
+        self.abandoned_by.remove(peer.index())
+    }
+
+    /// Unblocks this task from an expired `SEND_TIMEOUT`, if it's still
+    /// waiting on one. Returns `true` if the task was unblocked.
+    fn time_out_send(&mut self) -> bool {
+        match self.state {
+            TaskState::Healthy(SchedState::InSend(_)) => (),
+            TaskState::Healthy(SchedState::InReply(peer)) => {
+                // The peer has our message and may still reply to it, or
+                // borrow from our leases. The borrows will fail once we're
+                // no longer in reply, but the reply needs to be remembered so
+                // it can be thrown away. This is kept for each peer, since we
+                // may go on to abandon others before this one replies.
+                self.abandoned_by.insert(peer.index());
+            }
+            // Faulted tasks keep their state for the supervisor to inspect,
+            // and anything else means the send completed in time.
+            _ => return false,
+        }
+        self.save.set_error_response(abi::TIMEOUT);
+        self.state = TaskState::Healthy(SchedState::Runnable);
+        true
+    }
+
     /// Rewrites this task's state back to its initial form, to effect a task
     /// reboot.
     ///
@@ -318,6 +442,12 @@ impl Task {
     pub fn reinitialize(&mut self) {
         self.generation = self.generation.wrapping_add(1);
         self.timer = TimerState::default();
+        self.send_deadline = None;
+        self.abandoned_by = TaskSet::EMPTY;
+        #[cfg(feature = "fault-injection")]
+        {
+            self.injected_fault = None;
+        }
         self.notifications = 0;
         self.state = TaskState::default();
 
@@ -370,6 +500,53 @@ impl Task {
         }
     }
 
+    /// Arranges for this task to take `fault` the next time it enters the
+    /// kernel, replacing any fault injected earlier that it hasn't yet taken.
+    #[cfg(feature = "fault-injection")]
+    pub fn inject_fault(&mut self, fault: FaultInfo) {
+        self.injected_fault = Some(fault);
+    }
+
+    /// Returns this task's CPU time accounting, as of the last time it was
+    /// switched out. If the task is current, `now` (from
+    /// `arch::runtime_clock`) is used to include the time it has been running
+    /// since it was switched in.
+    pub fn runtime(&self, current: bool, now: u64) -> TaskRuntime {
+        let mut runtime = self.runtime;
+        if current {
+            runtime.run_time += now.saturating_sub(self.switched_in_at);
+        }
+        runtime
+    }
+
+    /// Measures this task's stack use since it was last (re)initialized, by
+    /// scanning up from the bottom of its stack for the first word that no
+    /// longer holds `STACK_PAINT`.
+    ///
+    /// Returns `None` if the task's initial stack pointer doesn't fall in one
+    /// of its regions, in which case the stack wasn't painted either.
+    pub fn stack_usage(&self) -> Option<StackUsage> {
+        let initial_stack = self.descriptor.initial_stack as usize;
+        let region = self
+            .region_table()
+            .iter()
+            .find(|region| region.contains(initial_stack.saturating_sub(4)))?;
+        let base = region.base as usize;
+        let size = initial_stack - base;
+
+        let stack = USlice::<u32>::from_raw(base, size >> 2).ok()?;
+        let words = self.try_read(&stack).ok()?;
+        let untouched = words
+            .iter()
+            .take_while(|&&word| word == STACK_PAINT)
+            .count();
+
+        Some(StackUsage {
+            size: size as u32,
+            max_depth: (size - untouched * 4) as u32,
+        })
+    }
+
     /// Returns a reference to the saved machine state for the task.
     pub fn save(&self) -> &crate::arch::SavedState {
         &self.save
@@ -773,6 +950,8 @@ impl NextTask {
 
 /// Processes all enabled timers in the task table, posting notifications for
 /// any that have expired by `current_time` (and disabling them atomically).
+///
+/// This also times out any `SEND_TIMEOUT` whose deadline has passed.
 pub fn process_timers(tasks: &mut [Task], current_time: Timestamp) -> NextTask {
     let mut sched_hint = NextTask::Same;
     for (index, task) in tasks.iter_mut().enumerate() {
@@ -787,10 +966,103 @@ pub fn process_timers(tasks: &mut [Task], current_time: Timestamp) -> NextTask {
                 sched_hint = sched_hint.combine(task_hint)
             }
         }
+        if let Some(deadline) = task.send_deadline {
+            if deadline <= current_time {
+                task.send_deadline = None;
+                if task.time_out_send() {
+                    sched_hint = sched_hint.combine(NextTask::Specific(index));
+                }
+            }
+        }
     }
     sched_hint
 }
 
+/// Delivers any fault injected into `tasks[current]`, the task that has just
+/// entered the kernel, by faulting it. Returns the scheduling hint from
+/// `force_fault` if it did so, or `None` if there was nothing to deliver.
+///
+/// This is called from the syscall and timer tick entry points, so an
+/// injected fault is taken at the task's next syscall, or at the first tick
+/// while it's running.
+#[cfg(feature = "fault-injection")]
+pub fn deliver_injected_fault(
+    tasks: &mut [Task],
+    current: usize,
+) -> Option<NextTask> {
+    let fault = tasks[current].injected_fault.take()?;
+    Some(force_fault(tasks, current, fault))
+}
+
+/// Updates CPU time accounting for a switch from `tasks[from]` to `tasks[to]`,
+/// and starts a new time slice for `tasks[to]`. This should be called whenever
+/// the scheduler picks a new task, before `arch::set_current_task`. If `from`
+/// and `to` are the same, nothing happens.
+pub fn account_switch(tasks: &mut [Task], from: usize, to: usize) {
+    if from == to {
+        return;
+    }
+    let now = crate::arch::runtime_clock();
+
+    let prev = &mut tasks[from];
+    prev.runtime.run_time += now.saturating_sub(prev.switched_in_at);
+
+    let next = &mut tasks[to];
+    next.runtime.context_switches =
+        next.runtime.context_switches.wrapping_add(1);
+    next.switched_in_at = now;
+    next.slice_ticks = 0;
+}
+
+/// Charges a kernel tick to the time slice of `tasks[current]`, the task that
+/// was running when the tick arrived.
+///
+/// If the task has a time slice configured and has now used all of it, this
+/// returns `NextTask::Other` if there's another runnable task at the same
+/// priority, so that the caller can rotate to it. (The scheduler scans from
+/// the task after `current`, so peers take turns.) Otherwise, and always for
+/// tasks without a time slice, returns `NextTask::Same`, preserving strict
+/// priority scheduling.
+pub fn charge_time_slice(tasks: &mut [Task], current: usize) -> NextTask {
+    let task = &mut tasks[current];
+    let time_slice = task.descriptor.time_slice;
+    if time_slice == 0 {
+        return NextTask::Same;
+    }
+    task.slice_ticks = task.slice_ticks.saturating_add(1);
+    if task.slice_ticks < time_slice {
+        return NextTask::Same;
+    }
+    // Start a new slice, whether or not we switch; if there's nobody to share
+    // with, the task may as well keep running.
+    task.slice_ticks = 0;
+    let priority = task.priority;
+
+    let peer_waiting = tasks.iter().enumerate().any(|(i, t)| {
+        i != current && t.priority == priority && t.is_runnable()
+    });
+    if peer_waiting {
+        NextTask::Other
+    } else {
+        NextTask::Same
+    }
+}
+
+/// Updates CPU time accounting for an interrupt handler that began at `start`
+/// (per `arch::runtime_clock`) and is finishing now. The time is charged to
+/// `tasks[owner]`, the task the interrupt is routed to, rather than to
+/// `tasks[current]`, the task it interrupted.
+pub fn account_isr(
+    tasks: &mut [Task],
+    current: usize,
+    owner: usize,
+    start: u64,
+) {
+    let elapsed = crate::arch::runtime_clock().saturating_sub(start);
+    tasks[owner].runtime.isr_time += elapsed;
+    tasks[current].switched_in_at += elapsed;
+}
+
 /// Checks a user-provided `TaskId` for validity against `table`.
 ///
 /// On success, returns an index that can be used to dereference `table` without
@@ -893,6 +1165,7 @@ pub fn force_fault(
     index: usize,
     fault: FaultInfo,
 ) -> NextTask {
+    crate::faultlog::record_task_fault(current_id(tasks, index), &fault);
     let task = &mut tasks[index];
     task.state = match task.state {
         TaskState::Healthy(sched) => TaskState::Faulted {
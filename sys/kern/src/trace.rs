@@ -0,0 +1,118 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Kernel event tracing.
+//!
+//! When the kernel is built with the `trace` feature, it records syscall entry
+//! and exit, interrupts, timer ticks, and context switches into a fixed-size
+//! ring in RAM, each stamped with `arch::runtime_clock`. Unlike the hooks in
+//! `profiling`, this needs no support from the board, and the result can be
+//! read out of a RAM dump after the fact (see `cargo xtask trace`). Without
+//! the feature, recording compiles to nothing.
+//!
+//! This module defines the following binary interface to debuggers:
+//!
+//! - `KERNEL_TRACE` (unmangled) is an `abi::TraceHeader` followed directly by
+//!   an array of `abi::TraceRecord`, with `capacity` entries. Its `magic` field
+//!   is set to `abi::TRACE_MAGIC` when the kernel starts; until then, the ring
+//!   should be ignored. Records are written at `next`, which wraps back to 0 at
+//!   `capacity`, setting `wrapped`. The header also gives the rate of the
+//!   timestamp clock, and its counts per tick, so that the ring can be decoded
+//!   without knowing how the board was set up.
+//!
+//! The ring is written without synchronization, on the basis that kernel entry
+//! points don't nest. A debugger that halts the processor in the middle of a
+//! kernel entry may see the most recent record half-written.
+
+use abi::TraceEvent;
+
+/// Number of records in the trace ring. Each is 16 bytes.
+#[cfg(feature = "trace")]
+const TRACE_RECORDS: usize = 256;
+
+#[cfg(feature = "trace")]
+#[repr(C)]
+struct TraceRing {
+    header: abi::TraceHeader,
+    records: [abi::TraceRecord; TRACE_RECORDS],
+}
+
+#[cfg(feature = "trace")]
+#[no_mangle]
+#[used]
+static mut KERNEL_TRACE: TraceRing = TraceRing {
+    header: abi::TraceHeader {
+        magic: 0,
+        capacity: TRACE_RECORDS as u32,
+        clock_per_tick: 0,
+        clock_khz: 0,
+        next: 0,
+        wrapped: 0,
+    },
+    records: [abi::TraceRecord {
+        time_lo: 0,
+        time_hi: 0,
+        event: 0,
+        arg: 0,
+    }; TRACE_RECORDS],
+};
+
+/// Marks the trace ring as valid and starts recording. Called once from
+/// `start_kernel`, after the clock used for timestamps has been set up.
+pub(crate) fn start(tick_divisor: u32) {
+    cfg_if::cfg_if! {
+        if #[cfg(feature = "trace")] {
+            // Safety: this is only called from `start_kernel`, before any
+            // other kernel entry point can run.
+            let header = unsafe {
+                &mut *core::ptr::addr_of_mut!(KERNEL_TRACE.header)
+            };
+            header.clock_per_tick = tick_divisor;
+            header.clock_khz = crate::arch::runtime_clock_khz();
+            header.next = 0;
+            header.wrapped = 0;
+            header.magic = abi::TRACE_MAGIC;
+        } else {
+            let _ = tick_divisor;
+        }
+    }
+}
+
+/// Records `event` in the trace ring, if tracing is enabled and has started.
+#[inline(always)]
+pub(crate) fn record(event: TraceEvent, arg: u32) {
+    cfg_if::cfg_if! {
+        if #[cfg(feature = "trace")] {
+            record_impl(event, arg);
+        } else {
+            let _ = (event, arg);
+        }
+    }
+}
+
+#[cfg(feature = "trace")]
+#[inline(never)]
+fn record_impl(event: TraceEvent, arg: u32) {
+    // Safety: kernel entry points don't nest, so there's only ever one of us
+    // running, and nothing else in the kernel touches the ring.
+    let ring = unsafe { &mut *core::ptr::addr_of_mut!(KERNEL_TRACE) };
+    if ring.header.magic != abi::TRACE_MAGIC {
+        // Not started yet, so the clock may not be either.
+        return;
+    }
+    let time = crate::arch::runtime_clock();
+    let next = ring.header.next as usize;
+    ring.records[next] = abi::TraceRecord {
+        time_lo: time as u32,
+        time_hi: (time >> 32) as u32,
+        event: event as u32,
+        arg,
+    };
+    if next + 1 == TRACE_RECORDS {
+        ring.header.next = 0;
+        ring.header.wrapped = 1;
+    } else {
+        ring.header.next = next as u32 + 1;
+    }
+}
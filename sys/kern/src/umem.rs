@@ -11,7 +11,7 @@ use zerocopy::{FromBytes, Immutable, KnownLayout};
 use crate::err::InteractFault;
 use crate::task::Task;
 use crate::util::index2_distinct;
-use abi::{FaultInfo, FaultSource, UsageError};
+use abi::{FaultInfo, FaultSource, LeaseAttributes, ULease, UsageError};
 
 /// A (user, untrusted, unprivileged) slice.
 ///
@@ -321,6 +321,44 @@ impl<T> kerncore::UserSlice for USlice<T> {
     }
 }
 
+/// Resolves one step of a forwarded lease: narrows `parent`, the lease it was
+/// forwarded out of, to the part named by `lease`, and drops any access rights
+/// that `lease` doesn't pass on.
+///
+/// If `parent` was itself forwarded, the result is still a forwarded lease,
+/// naming a narrower part of the same original lease.
+///
+/// Returns `None` if `lease` doesn't fall entirely within `parent`.
+pub fn narrow_forwarded_lease(
+    parent: &ULease,
+    lease: &ULease,
+) -> Option<ULease> {
+    // Go through `from_raw` rather than `From<&ULease>` so that a parent that
+    // wraps the address space is rejected rather than trusted.
+    let parent_area = USlice::<u8>::from_raw(
+        parent.base_address as usize,
+        parent.length as usize,
+    )
+    .ok()?;
+    let range = kerncore::forwarded_range(
+        parent_area,
+        lease.base_address as usize,
+        lease.length as usize,
+    )?;
+
+    let mut attributes = parent.attributes;
+    for right in [LeaseAttributes::READ, LeaseAttributes::WRITE] {
+        if !lease.attributes.contains(right) {
+            attributes.remove(right);
+        }
+    }
+    Some(ULease {
+        attributes,
+        base_address: range.start as u32,
+        length: range.len() as u32,
+    })
+}
+
 /// Copies bytes from `tasks[from_index]` in region `from_slice` into
 /// `tasks[to_index]` at region `to_slice`, checking memory access before doing
 /// so.
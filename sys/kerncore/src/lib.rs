@@ -10,6 +10,7 @@
 #![forbid(clippy::wildcard_imports)]
 
 use core::cmp::Ordering;
+use core::ops::Range;
 
 /// Describes types that act as "slices" (in the very abstract sense) referenced
 /// by tasks in syscalls.
@@ -230,10 +231,110 @@ where
     false
 }
 
+/// Computes the addresses covered by a lease forwarded out of `parent`.
+///
+/// A forwarded lease names part of a lease that its sender was itself lent, as
+/// an `offset` into that lease and a `length`. This returns the corresponding
+/// range of addresses within `parent`.
+///
+/// This does not check that anyone can access `parent` -- the resulting range
+/// needs the same checks (e.g. [`can_access`]) as `parent` itself would.
+///
+/// # Returns
+///
+/// `Some(range)` if `length` bytes starting `offset` bytes into `parent` fall
+/// entirely within `parent`. An empty range is allowed anywhere up to and
+/// including the end of `parent`.
+///
+/// `None` otherwise, including if `offset + length` would overflow.
+#[must_use]
+pub fn forwarded_range<S>(
+    parent: S,
+    offset: usize,
+    length: usize,
+) -> Option<Range<usize>>
+where
+    S: UserSlice,
+{
+    let parent_len = parent.end_addr() - parent.base_addr();
+    let end = offset.checked_add(length)?;
+    if end > parent_len {
+        return None;
+    }
+    // These can't overflow, because `parent` doesn't, and we're inside it.
+    let base = parent.base_addr() + offset;
+    Some(base..base + length)
+}
+
+/// Computes the kernel's runtime clock from a reading of the tick timer.
+///
+/// The clock counts cycles of the timer's input, of which there are `divisor`
+/// per tick. `ticks` is the kernel's tick count, and `current` the timer's
+/// counter, which runs down from `divisor - 1` and then wraps, pending a tick.
+/// `tick_pending` indicates that the timer has wrapped since `ticks` was last
+/// advanced, in which case `current` must have been read after the wrap.
+///
+/// This is monotonic only if `ticks` is advanced as soon as the pending tick
+/// is taken, before anything else can read the clock; otherwise it reads a
+/// whole tick behind until then.
+#[must_use]
+pub fn runtime_clock(
+    ticks: u64,
+    tick_pending: bool,
+    current: u32,
+    divisor: u32,
+) -> u64 {
+    let ticks = ticks + u64::from(tick_pending);
+    let divisor = u64::from(divisor);
+    ticks * divisor + divisor.saturating_sub(1 + u64::from(current))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn runtime_clock_never_decreases_across_ticks() {
+        const DIVISOR: u32 = 8;
+        let mut ticks = 0;
+        let mut pending = false;
+        let mut current = DIVISOR - 1;
+        let mut last = runtime_clock(ticks, pending, current, DIVISOR);
+        for cycle in 1..5 * u64::from(DIVISOR) {
+            // The counter runs down, and on wrapping, pends a tick...
+            if current == 0 {
+                current = DIVISOR - 1;
+                pending = true;
+            } else {
+                current -= 1;
+            }
+            // ...which the kernel takes a few cycles later, counting it before
+            // anything reads the clock.
+            if pending && current == DIVISOR - 4 {
+                ticks += 1;
+                pending = false;
+            }
+            let t = runtime_clock(ticks, pending, current, DIVISOR);
+            assert_eq!(
+                t,
+                last + 1,
+                "clock went from {last} to {t} at cycle {cycle} \
+                 (tick {ticks}, pending {pending}, counter {current})",
+            );
+            last = t;
+        }
+    }
+
+    #[test]
+    fn runtime_clock_counts_cycles() {
+        assert_eq!(runtime_clock(0, false, 9, 10), 0);
+        assert_eq!(runtime_clock(0, false, 0, 10), 9);
+        assert_eq!(runtime_clock(0, true, 9, 10), 10);
+        assert_eq!(runtime_clock(3, false, 4, 10), 35);
+        // Before the timer is set up, it's all zeros.
+        assert_eq!(runtime_clock(0, false, 0, 0), 0);
+    }
+
     struct TestSlice {
         base: usize,
         size: usize,
@@ -491,4 +592,69 @@ mod tests {
              good ranges but passes through uncontained memory, but can",
         );
     }
+
+    #[test]
+    fn forwarded_range_within_parent() {
+        let parent = TestSlice {
+            base: 0x2000_0000,
+            size: 0x100,
+        };
+        assert_eq!(
+            forwarded_range(&parent, 0x10, 0x20),
+            Some(0x2000_0010..0x2000_0030),
+        );
+        assert_eq!(
+            forwarded_range(&parent, 0, 0x100),
+            Some(0x2000_0000..0x2000_0100),
+            "should be able to forward an entire lease",
+        );
+        assert_eq!(
+            forwarded_range(&parent, 0x100, 0),
+            Some(0x2000_0100..0x2000_0100),
+            "should be able to forward an empty range at the end of a lease",
+        );
+    }
+
+    #[test]
+    fn forwarded_range_outside_parent() {
+        let parent = TestSlice {
+            base: 0x2000_0000,
+            size: 0x100,
+        };
+        assert_eq!(
+            forwarded_range(&parent, 0x101, 0),
+            None,
+            "should NOT be able to forward a range past the end of a lease",
+        );
+        assert_eq!(
+            forwarded_range(&parent, 0xF0, 0x11),
+            None,
+            "should NOT be able to forward a range overlapping the end of a \
+             lease",
+        );
+        assert_eq!(
+            forwarded_range(&parent, usize::MAX, 2),
+            None,
+            "should NOT be able to forward a range whose end overflows",
+        );
+        assert_eq!(
+            forwarded_range(&parent, 1, usize::MAX),
+            None,
+            "should NOT be able to forward a range whose end overflows",
+        );
+    }
+
+    #[test]
+    fn forwarded_range_of_empty_parent() {
+        let parent = TestSlice {
+            base: 0x2000_0000,
+            size: 0,
+        };
+        assert_eq!(
+            forwarded_range(&parent, 0, 0),
+            Some(0x2000_0000..0x2000_0000),
+        );
+        assert_eq!(forwarded_range(&parent, 0, 1), None);
+        assert_eq!(forwarded_range(&parent, 1, 0), None);
+    }
 }
@@ -5,8 +5,10 @@
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     build_util::expose_m_profile()?;
 
-    // Do an architecture check.
-    if build_util::target_os() != "none" {
+    // Do an architecture check. Linux is allowed, since that's the hosted
+    // simulation target.
+    let os = build_util::target_os();
+    if os != "none" && os != "linux" {
         eprintln!("***********************************************");
         eprintln!("Hi!");
         eprintln!("You appear to be building this natively,");
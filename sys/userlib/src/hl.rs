@@ -7,14 +7,14 @@
 //! This is intended to provide a more ergonomic interface than the raw
 //! syscalls.
 
-use abi::{Generation, TaskId};
+use abi::{Generation, LeaseAttributes, TaskId};
 use core::marker::PhantomData;
 use zerocopy::{FromBytes, FromZeros, Immutable, IntoBytes, KnownLayout};
 
 use crate::{
     sys_borrow_info, sys_borrow_read, sys_borrow_write, sys_get_timer,
     sys_recv, sys_recv_closed, sys_recv_open, sys_reply, sys_reply_fault,
-    sys_set_timer, BorrowInfo, ClosedRecvError, FromPrimitive,
+    sys_set_timer, BorrowInfo, ClosedRecvError, FromPrimitive, Lease,
 };
 
 const INTERNAL_TIMER_NOTIFICATION: u32 = 1 << 31;
@@ -415,6 +415,26 @@ impl Borrow<'_> {
             Some(())
         }
     }
+
+    /// Makes a lease passing on `length` bytes of this borrow, starting at
+    /// `offset`, for use in a `sys_send` to another task. The receiver gets
+    /// at most the access given by `rights`, and no more than the caller
+    /// granted.
+    ///
+    /// This isn't checked until the receiver uses the lease: if the range is
+    /// out of bounds, or the caller has gone away, the receiver sees us as a
+    /// defecting lender. The lease stops working once we reply to the caller.
+    ///
+    /// Returns `None` if `offset` or `length` is too big for any borrow, or
+    /// this borrow's lease index is over 255, which no caller can lend.
+    pub fn forward(
+        &self,
+        offset: usize,
+        length: usize,
+        rights: LeaseAttributes,
+    ) -> Option<Lease<'_>> {
+        Lease::forwarded(self.id, self.index, offset, length, rights)
+    }
 }
 
 /// Suspends the calling task until the kernel time is `>= time`.
@@ -0,0 +1,246 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Syscall stubs for the hosted (Linux user-space) target.
+//!
+//! These have the same signatures as the ARM stubs in the crate root, but
+//! instead of loading registers and executing `SVC`, they pass the same
+//! register values to the kernel's `hubris_hosted_syscall` entry point, which
+//! is linked into the same binary. See the kernel's `arch::hosted` module for
+//! how that gets to the scheduler.
+//!
+//! Argument `i` here corresponds to register `r(4+i)` in the ARM stubs, and
+//! the same goes for results.
+
+use super::{
+    BorrowReadArgs, BorrowWriteArgs, RawBorrowInfo, RawRecvMessage,
+    RawTimerState, RcLen, SendArgs, Sysnum,
+};
+
+extern "C" {
+    fn hubris_hosted_syscall(
+        nr: u32,
+        regs: *mut [u32; 7],
+        stacked0: u32,
+        stacked1: u32,
+    );
+}
+
+/// Makes syscall `nr` with the given argument registers, returning the result
+/// registers.
+fn syscall(nr: Sysnum, regs: [u32; 7]) -> [u32; 7] {
+    syscall_stacked(nr, regs, [0; 2])
+}
+
+/// Makes syscall `nr` with the given argument registers, plus the extra
+/// arguments that the ARM stub would pass in r2 and r3.
+fn syscall_stacked(
+    nr: Sysnum,
+    mut regs: [u32; 7],
+    stacked: [u32; 2],
+) -> [u32; 7] {
+    // Safety: the kernel only reads and writes the array we pass.
+    unsafe {
+        hubris_hosted_syscall(nr as u32, &mut regs, stacked[0], stacked[1]);
+    }
+    regs
+}
+
+fn rc_len(regs: [u32; 7]) -> RcLen {
+    RcLen(u64::from(regs[0]) | u64::from(regs[1]) << 32)
+}
+
+pub(crate) unsafe extern "C" fn sys_send_stub(
+    args: &mut SendArgs<'_>,
+) -> RcLen {
+    rc_len(syscall(
+        Sysnum::Send,
+        [
+            args.packed_target_operation,
+            args.outgoing_ptr as u32,
+            args.outgoing_len as u32,
+            args.incoming_ptr as u32,
+            args.incoming_len as u32,
+            args.lease_ptr as u32,
+            args.lease_len as u32,
+        ],
+    ))
+}
+
+pub(crate) unsafe extern "C" fn sys_send_timeout_stub(
+    args: &mut SendArgs<'_>,
+    deadline: u64,
+) -> RcLen {
+    rc_len(syscall_stacked(
+        Sysnum::SendTimeout,
+        [
+            args.packed_target_operation,
+            args.outgoing_ptr as u32,
+            args.outgoing_len as u32,
+            args.incoming_ptr as u32,
+            args.incoming_len as u32,
+            args.lease_ptr as u32,
+            args.lease_len as u32,
+        ],
+        [deadline as u32, (deadline >> 32) as u32],
+    ))
+}
+
+pub(crate) unsafe extern "C" fn sys_recv_stub(
+    buffer_ptr: *mut u8,
+    buffer_len: usize,
+    notification_mask: u32,
+    specific_sender: u32,
+    out: *mut RawRecvMessage,
+) -> u32 {
+    let regs = syscall(
+        Sysnum::Recv,
+        [
+            buffer_ptr as u32,
+            buffer_len as u32,
+            notification_mask,
+            specific_sender,
+            0,
+            0,
+            0,
+        ],
+    );
+    // Safety: our caller passes a valid output pointer.
+    unsafe {
+        out.write(RawRecvMessage {
+            sender: regs[1],
+            operation: regs[2],
+            message_len: regs[3] as usize,
+            response_capacity: regs[4] as usize,
+            lease_count: regs[5] as usize,
+        });
+    }
+    regs[0]
+}
+
+pub(crate) unsafe extern "C" fn sys_reply_stub(
+    peer: u32,
+    code: u32,
+    message_ptr: *const u8,
+    message_len: usize,
+) {
+    syscall(
+        Sysnum::Reply,
+        [peer, code, message_ptr as u32, message_len as u32, 0, 0, 0],
+    );
+}
+
+pub(crate) unsafe extern "C" fn sys_set_timer_stub(
+    set_timer: u32,
+    deadline_lo: u32,
+    deadline_hi: u32,
+    notification: u32,
+) {
+    syscall(
+        Sysnum::SetTimer,
+        [set_timer, deadline_lo, deadline_hi, notification, 0, 0, 0],
+    );
+}
+
+pub(crate) unsafe extern "C" fn sys_borrow_read_stub(
+    args: *mut BorrowReadArgs,
+) -> RcLen {
+    // Safety: our caller passes a valid argument pointer.
+    let args = unsafe { &*args };
+    rc_len(syscall(
+        Sysnum::BorrowRead,
+        [
+            args.lender,
+            args.index as u32,
+            args.offset as u32,
+            args.dest as u32,
+            args.dest_len as u32,
+            0,
+            0,
+        ],
+    ))
+}
+
+pub(crate) unsafe extern "C" fn sys_borrow_write_stub(
+    args: *mut BorrowWriteArgs,
+) -> RcLen {
+    // Safety: our caller passes a valid argument pointer.
+    let args = unsafe { &*args };
+    rc_len(syscall(
+        Sysnum::BorrowWrite,
+        [
+            args.lender,
+            args.index as u32,
+            args.offset as u32,
+            args.src as u32,
+            args.src_len as u32,
+            0,
+            0,
+        ],
+    ))
+}
+
+pub(crate) unsafe extern "C" fn sys_borrow_info_stub(
+    lender: u32,
+    index: usize,
+    out: *mut RawBorrowInfo,
+) {
+    let regs =
+        syscall(Sysnum::BorrowInfo, [lender, index as u32, 0, 0, 0, 0, 0]);
+    // Safety: our caller passes a valid output pointer.
+    unsafe {
+        out.write(RawBorrowInfo {
+            rc: regs[0],
+            atts: regs[1],
+            length: regs[2] as usize,
+        });
+    }
+}
+
+pub(crate) unsafe extern "C" fn sys_irq_control_stub(mask: u32, enable: u32) {
+    syscall(Sysnum::IrqControl, [mask, enable, 0, 0, 0, 0, 0]);
+}
+
+pub(crate) unsafe extern "C" fn sys_panic_stub(
+    msg: *const u8,
+    len: usize,
+) -> ! {
+    syscall(Sysnum::Panic, [msg as u32, len as u32, 0, 0, 0, 0, 0]);
+    // The kernel doesn't resume tasks that have panicked, so we shouldn't get
+    // here -- but if we do, we mustn't return.
+    loop {
+        core::hint::spin_loop();
+    }
+}
+
+pub(crate) unsafe extern "C" fn sys_get_timer_stub(out: *mut RawTimerState) {
+    let regs = syscall(Sysnum::GetTimer, [0; 7]);
+    // Safety: our caller passes a valid output pointer.
+    unsafe {
+        out.write(RawTimerState {
+            now_lo: regs[0],
+            now_hi: regs[1],
+            set: regs[2],
+            dl_lo: regs[3],
+            dl_hi: regs[4],
+            on_dl: regs[5],
+        });
+    }
+}
+
+pub(crate) unsafe extern "C" fn sys_refresh_task_id_stub(tid: u32) -> u32 {
+    syscall(Sysnum::RefreshTaskId, [tid, 0, 0, 0, 0, 0, 0])[0]
+}
+
+pub(crate) unsafe extern "C" fn sys_post_stub(tid: u32, mask: u32) -> u32 {
+    syscall(Sysnum::Post, [tid, mask, 0, 0, 0, 0, 0])[0]
+}
+
+pub(crate) unsafe extern "C" fn sys_reply_fault_stub(tid: u32, reason: u32) {
+    syscall(Sysnum::ReplyFault, [tid, reason, 0, 0, 0, 0, 0]);
+}
+
+pub(crate) unsafe extern "C" fn sys_irq_status_stub(mask: u32) -> u32 {
+    syscall(Sysnum::IrqStatus, [mask, 0, 0, 0, 0, 0, 0])[0]
+}
@@ -17,7 +17,7 @@
 use core::num::NonZeroUsize;
 
 use abi::{Kipcnum, TaskId};
-use zerocopy::IntoBytes;
+use zerocopy::{FromZeros, IntoBytes};
 
 use crate::{sys_send, UnwrapLite};
 
@@ -126,6 +126,24 @@ pub fn fault_task(task: usize) {
     );
 }
 
+/// Makes `task` take `fault` the next time it enters the kernel (at its next
+/// syscall, or kernel tick while it's running). This is for testing how the
+/// system recovers from faults, and only works if the kernel was built with
+/// its `fault-injection` feature; otherwise, the kernel faults the caller.
+pub fn inject_fault(task: usize, fault: abi::FaultInfo) {
+    // Coerce `task` to a known size (Rust doesn't assume that usize == u32)
+    let msg = (task as u32, fault);
+    let mut buf = [0; core::mem::size_of::<(u32, abi::FaultInfo)>()];
+    ssmarshal::serialize(&mut buf, &msg).unwrap_lite();
+    let (_rc, _len) = sys_send(
+        TaskId::KERNEL,
+        Kipcnum::InjectFault as u16,
+        &buf,
+        &mut [],
+        &[],
+    );
+}
+
 pub fn system_restart() -> ! {
     let _ = sys_send(TaskId::KERNEL, Kipcnum::Reset as u16, &[], &mut [], &[]);
     loop {
@@ -162,3 +180,119 @@ pub fn software_irq(task: usize, mask: u32) {
         &[],
     );
 }
+
+/// Reads the CPU time accounting for the task at index `task`. See
+/// `abi::TaskRuntime` for the units.
+pub fn read_task_runtime(task: usize) -> abi::TaskRuntime {
+    // Coerce `task` to a known size (Rust doesn't assume that usize == u32)
+    let task = task as u32;
+    let mut response = [0; core::mem::size_of::<abi::TaskRuntime>()];
+    let (_rc, len) = sys_send(
+        TaskId::KERNEL,
+        Kipcnum::ReadTaskRuntime as u16,
+        task.as_bytes(),
+        &mut response,
+        &[],
+    );
+    ssmarshal::deserialize(&response[..len]).unwrap_lite().0
+}
+
+/// Reads the stack usage of the task at index `task`, or `None` if the kernel
+/// couldn't find its stack.
+pub fn read_task_stack_usage(task: usize) -> Option<abi::StackUsage> {
+    // Coerce `task` to a known size (Rust doesn't assume that usize == u32)
+    let task = task as u32;
+    let mut response = [0; core::mem::size_of::<Option<abi::StackUsage>>()];
+    let (_rc, len) = sys_send(
+        TaskId::KERNEL,
+        Kipcnum::ReadTaskStackUsage as u16,
+        task.as_bytes(),
+        &mut response,
+        &[],
+    );
+    ssmarshal::deserialize(&response[..len]).unwrap_lite().0
+}
+
+/// Reads entry `region` of `task`'s memory region table, or `None` if
+/// `region` is past the end of the table.
+///
+/// Every task has the same number of entries; entries a task doesn't need
+/// refer to a region with no attributes, which can be ignored.
+pub fn read_task_region(task: usize, region: usize) -> Option<abi::TaskRegion> {
+    let msg = (task as u32, region as u32);
+    let mut buf = [0; core::mem::size_of::<(u32, u32)>()];
+    ssmarshal::serialize(&mut buf, &msg).unwrap_lite();
+
+    let mut response = [0; core::mem::size_of::<Option<abi::TaskRegion>>()];
+    let (_rc, len) = sys_send(
+        TaskId::KERNEL,
+        Kipcnum::ReadTaskRegion as u16,
+        &buf,
+        &mut response,
+        &[],
+    );
+    ssmarshal::deserialize(&response[..len]).unwrap_lite().0
+}
+
+/// Reads the image, from `offset` into it, into `buf`, returning how many
+/// bytes there were: fewer than `buf.len()` (and zero, once `offset` is past
+/// it) at the end of the image.
+///
+/// The image is the kernel's and every task's flash, as the build system
+/// wrote it, without any signature. Only a task with `read-image = true` in
+/// its `app.toml` entry may read it; anyone else is faulted.
+pub fn read_image(offset: usize, buf: &mut [u8]) -> usize {
+    let msg = offset as u32;
+    let mut msg_buf = [0; core::mem::size_of::<u32>()];
+    ssmarshal::serialize(&mut msg_buf, &msg).unwrap_lite();
+
+    let (_rc, len) = sys_send(
+        TaskId::KERNEL,
+        Kipcnum::ReadImage as u16,
+        &msg_buf,
+        buf,
+        &[],
+    );
+    len
+}
+
+/// Reads the `index`th oldest entry in the kernel's retained fault log, or
+/// `None` if there's no such entry (including if the application has no fault
+/// log).
+pub fn read_fault_log(index: usize) -> Option<abi::FaultLogEntry> {
+    // Coerce `index` to a known size (Rust doesn't assume that usize == u32)
+    let index = index as u32;
+    let mut entry = abi::FaultLogEntry::new_zeroed();
+    let (_rc, len) = sys_send(
+        TaskId::KERNEL,
+        Kipcnum::ReadFaultLog as u16,
+        index.as_bytes(),
+        entry.as_mut_bytes(),
+        &[],
+    );
+    (len != 0).then_some(entry)
+}
+
+/// Decodes the fault recorded in a `TaskFault` fault log entry, or returns
+/// `None` if `entry` records something else.
+pub fn fault_log_fault_info(
+    entry: &abi::FaultLogEntry,
+) -> Option<abi::FaultInfo> {
+    if entry.kind != abi::FaultLogKind::TaskFault as u32 {
+        return None;
+    }
+    ssmarshal::deserialize(&entry.data)
+        .ok()
+        .map(|(fault, _)| fault)
+}
+
+/// Discards all entries in the kernel's retained fault log.
+pub fn clear_fault_log() {
+    let (_rc, _len) = sys_send(
+        TaskId::KERNEL,
+        Kipcnum::ClearFaultLog as u16,
+        &[],
+        &mut [],
+        &[],
+    );
+}
@@ -35,6 +35,7 @@ pub use num_derive::{FromPrimitive, ToPrimitive};
 pub use num_traits::{FromPrimitive, ToPrimitive};
 pub use unwrap_lite::UnwrapLite;
 
+#[cfg(not(hubris_hosted))]
 use core::arch;
 use core::marker::PhantomData;
 
@@ -46,6 +47,17 @@ pub mod units;
 #[cfg(feature = "critical-section")]
 pub mod critical_section;
 
+#[cfg(hubris_hosted)]
+mod hosted;
+#[cfg(hubris_hosted)]
+use hosted::{
+    sys_borrow_info_stub, sys_borrow_read_stub, sys_borrow_write_stub,
+    sys_get_timer_stub, sys_irq_control_stub, sys_irq_status_stub,
+    sys_panic_stub, sys_post_stub, sys_recv_stub, sys_refresh_task_id_stub,
+    sys_reply_fault_stub, sys_reply_stub, sys_send_stub, sys_send_timeout_stub,
+    sys_set_timer_stub,
+};
+
 #[derive(Debug)]
 #[repr(transparent)]
 pub struct Lease<'a> {
@@ -86,6 +98,36 @@ impl<'a> Lease<'a> {
             _marker: PhantomData,
         }
     }
+
+    /// Passes on `length` bytes, starting `offset` bytes into lease number
+    /// `index` that `lender` has lent to us, with (at most) the access given
+    /// by `rights`.
+    ///
+    /// The receiver can access the memory directly, without it being copied
+    /// through us, until we reply to `lender`. See `hl::Borrow::forward` for a
+    /// more convenient way to make one of these.
+    ///
+    /// Returns `None` if `index` doesn't fit in the 8 bits a forwarded lease
+    /// has for it, or `offset` or `length` doesn't fit in 32 bits; no lease
+    /// that `lender` could have lent us is that big.
+    pub fn forwarded(
+        lender: TaskId,
+        index: usize,
+        offset: usize,
+        length: usize,
+        rights: LeaseAttributes,
+    ) -> Option<Self> {
+        Some(Self {
+            _kern_rep: abi::ULease::forwarded(
+                lender,
+                u8::try_from(index).ok()?,
+                rights,
+                u32::try_from(offset).ok()?,
+                u32::try_from(length).ok()?,
+            ),
+            _marker: PhantomData,
+        })
+    }
 }
 
 impl<'a> From<&'a [u8]> for Lease<'a> {
@@ -152,6 +194,7 @@ struct SendArgs<'a> {
 /// Core implementation of the SEND syscall.
 ///
 /// See the note on syscall stubs at the top of this module for rationale.
+#[cfg(not(hubris_hosted))]
 #[unsafe(naked)]
 unsafe extern "C" fn sys_send_stub(_args: &mut SendArgs<'_>) -> RcLen {
     cfg_if::cfg_if! {
@@ -219,6 +262,116 @@ unsafe extern "C" fn sys_send_stub(_args: &mut SendArgs<'_>) -> RcLen {
     }
 }
 
+/// Sends a message like `sys_send`, but gives up waiting if the peer hasn't
+/// replied by `deadline` (in ticks since boot, as for `sys_set_timer`).
+///
+/// If the deadline passes first, the response code is `TIMEOUT` and the
+/// length is zero. The message may or may not have been received by the
+/// peer; if it was, the kernel will discard the peer's eventual reply, and
+/// won't deliver any further messages from this task to that peer until it
+/// has replied (or been restarted).
+///
+/// If the deadline has already passed when this is called, the message is not
+/// sent.
+#[inline(always)]
+pub fn sys_send_timeout(
+    target: TaskId,
+    operation: u16,
+    outgoing: &[u8],
+    incoming: &mut [u8],
+    leases: &[Lease<'_>],
+    deadline: u64,
+) -> (u32, usize) {
+    let mut args = SendArgs {
+        packed_target_operation: u32::from(target.0) << 16
+            | u32::from(operation),
+        outgoing_ptr: outgoing.as_ptr(),
+        outgoing_len: outgoing.len(),
+        incoming_ptr: incoming.as_mut_ptr(),
+        incoming_len: incoming.len(),
+        lease_ptr: leases.as_ptr(),
+        lease_len: leases.len(),
+    };
+    unsafe { sys_send_timeout_stub(&mut args, deadline).into() }
+}
+
+/// Core implementation of the SEND_TIMEOUT syscall.
+///
+/// This is the same as `sys_send_stub`, except that the deadline arrives in r2
+/// and r3 (per the C ABI for a `u64` second argument), which must be left
+/// alone so the kernel can read them from the exception frame.
+#[cfg(not(hubris_hosted))]
+#[unsafe(naked)]
+unsafe extern "C" fn sys_send_timeout_stub(
+    _args: &mut SendArgs<'_>,
+    _deadline: u64,
+) -> RcLen {
+    cfg_if::cfg_if! {
+        if #[cfg(armv6m)] {
+            arch::naked_asm!("
+                @ Spill the registers we're about to use to pass stuff.
+                push {{r4-r7, lr}}
+                mov r4, r8
+                mov r5, r9
+                mov r6, r10
+                mov r7, r11
+                push {{r4-r7}}
+                @ Load the constant syscall number.
+                eors r4, r4
+                adds r4, #{sysnum}
+                mov r11, r4
+                @ Load in args from the struct, without touching r2-r3.
+                ldm r0!, {{r4-r7}}
+                ldr r1, [r0, #8]
+                mov r10, r1
+                ldm r0, {{r0-r1}}
+                mov r8, r0
+                mov r9, r1
+
+                @ To the kernel!
+                svc #0
+
+                @ Move the two results back into their return positions.
+                mov r0, r4
+                mov r1, r5
+                @ Restore the registers we used.
+                pop {{r4-r7}}
+                mov r8, r4
+                mov r9, r5
+                mov r10, r6
+                mov r11, r7
+                pop {{r4-r7, pc}}
+                ",
+                sysnum = const Sysnum::SendTimeout as u32,
+            )
+        } else if #[cfg(any(armv7m, armv8m))] {
+            arch::naked_asm!("
+                @ Spill the registers we're about to use to pass stuff.
+                push {{r4-r11}}
+                @ Load in args from the struct.
+                ldm r0, {{r4-r10}}
+                @ Load the constant syscall number.
+                mov r11, {sysnum}
+
+                @ To the kernel!
+                svc #0
+
+                @ Move the two results back into their return positions.
+                mov r0, r4
+                mov r1, r5
+                @ Restore the registers we used.
+                pop {{r4-r11}}
+                @ Fin.
+                bx lr
+                ",
+                sysnum = const Sysnum::SendTimeout as u32,
+            )
+        } else {
+            compile_error!("missing sys_send_timeout_stub for ARM profile");
+        }
+    }
+}
+
 /// Performs an "open" RECV that will accept messages from any task or
 /// notifications from the kernel.
 ///
@@ -416,6 +569,7 @@ pub struct RecvMessage {
 /// Core implementation of the RECV syscall.
 ///
 /// See the note on syscall stubs at the top of this module for rationale.
+#[cfg(not(hubris_hosted))]
 #[unsafe(naked)]
 #[must_use]
 unsafe extern "C" fn sys_recv_stub(
@@ -531,6 +685,7 @@ pub fn sys_reply(peer: TaskId, code: u32, message: &[u8]) {
 /// Core implementation of the REPLY syscall.
 ///
 /// See the note on syscall stubs at the top of this module for rationale.
+#[cfg(not(hubris_hosted))]
 #[unsafe(naked)]
 unsafe extern "C" fn sys_reply_stub(
     _peer: u32,
@@ -645,6 +800,7 @@ pub fn set_timer_relative(interval: u32, notifications: u32) -> u64 {
 /// Core implementation of the SET_TIMER syscall.
 ///
 /// See the note on syscall stubs at the top of this module for rationale.
+#[cfg(not(hubris_hosted))]
 #[unsafe(naked)]
 unsafe extern "C" fn sys_set_timer_stub(
     _set_timer: u32,
@@ -731,6 +887,7 @@ pub fn sys_borrow_read(
 /// Core implementation of the BORROW_READ syscall.
 ///
 /// See the note on syscall stubs at the top of this module for rationale.
+#[cfg(not(hubris_hosted))]
 #[unsafe(naked)]
 unsafe extern "C" fn sys_borrow_read_stub(_args: *mut BorrowReadArgs) -> RcLen {
     cfg_if::cfg_if! {
@@ -824,6 +981,7 @@ pub fn sys_borrow_write(
 /// Core implementation of the BORROW_WRITE syscall.
 ///
 /// See the note on syscall stubs at the top of this module for rationale.
+#[cfg(not(hubris_hosted))]
 #[unsafe(naked)]
 unsafe extern "C" fn sys_borrow_write_stub(
     _args: *mut BorrowWriteArgs,
@@ -939,6 +1097,7 @@ pub struct BorrowInfo {
 /// Core implementation of the BORROW_INFO syscall.
 ///
 /// See the note on syscall stubs at the top of this module for rationale.
+#[cfg(not(hubris_hosted))]
 #[unsafe(naked)]
 unsafe extern "C" fn sys_borrow_info_stub(
     _lender: u32,
@@ -1035,6 +1194,7 @@ pub fn sys_irq_control_clear_pending(mask: u32, enable: bool) {
 /// Core implementation of the IRQ_CONTROL syscall.
 ///
 /// See the note on syscall stubs at the top of this module for rationale.
+#[cfg(not(hubris_hosted))]
 #[unsafe(naked)]
 unsafe extern "C" fn sys_irq_control_stub(_mask: u32, _enable: u32) {
     cfg_if::cfg_if! {
@@ -1100,6 +1260,7 @@ pub fn sys_panic(msg: &[u8]) -> ! {
 /// Core implementation of the PANIC syscall.
 ///
 /// See the note on syscall stubs at the top of this module for rationale.
+#[cfg(not(hubris_hosted))]
 #[unsafe(naked)]
 unsafe extern "C" fn sys_panic_stub(_msg: *const u8, _len: usize) -> ! {
     cfg_if::cfg_if! {
@@ -1208,6 +1369,7 @@ struct RawTimerState {
 /// Core implementation of the GET_TIMER syscall.
 ///
 /// See the note on syscall stubs at the top of this module for rationale.
+#[cfg(not(hubris_hosted))]
 #[unsafe(naked)]
 unsafe extern "C" fn sys_get_timer_stub(_out: *mut RawTimerState) {
     cfg_if::cfg_if! {
@@ -1273,6 +1435,7 @@ unsafe extern "C" fn sys_get_timer_stub(_out: *mut RawTimerState) {
 #[doc(hidden)]
 #[no_mangle]
 #[link_section = ".text.start"]
+#[cfg(not(hubris_hosted))]
 #[unsafe(naked)]
 pub unsafe extern "C" fn _start() -> ! {
     // Provided by the user program:
@@ -1410,7 +1573,11 @@ compile_error!(
 /// task, to ensure that memory is available for the panic message, even if the
 /// resources have been trimmed aggressively using `xtask sizes` and `humility
 /// stackmargin`.
-#[cfg(all(not(feature = "no-panic"), feature = "panic-messages"))]
+#[cfg(all(
+    not(feature = "no-panic"),
+    feature = "panic-messages",
+    not(hubris_hosted)
+))]
 #[panic_handler]
 fn panic(info: &core::panic::PanicInfo<'_>) -> ! {
     // Implementation Note
@@ -1557,7 +1724,11 @@ fn panic(info: &core::panic::PanicInfo<'_>) -> ! {
 /// Panic handler for tasks without the `panic-messages` feature enabled. This
 /// kills the task with a fixed message, `"PANIC"`. While this is less helpful
 /// than a proper panic message, the stack trace can still be informative.
-#[cfg(all(not(feature = "no-panic"), not(feature = "panic-messages")))]
+#[cfg(all(
+    not(feature = "no-panic"),
+    not(feature = "panic-messages"),
+    not(hubris_hosted)
+))]
 #[panic_handler]
 fn panic(_: &core::panic::PanicInfo<'_>) -> ! {
     sys_panic(b"PANIC")
@@ -1565,7 +1736,7 @@ fn panic(_: &core::panic::PanicInfo<'_>) -> ! {
 
 /// Panic handler for when panics are not permitted in a task. This is enabled
 /// by the `no-panic` feature and causes a link error if a panic is introduced.
-#[cfg(feature = "no-panic")]
+#[cfg(all(feature = "no-panic", not(hubris_hosted)))]
 #[panic_handler]
 fn panic(_: &core::panic::PanicInfo<'_>) -> ! {
     extern "C" {
@@ -1586,6 +1757,7 @@ pub fn sys_refresh_task_id(task_id: TaskId) -> TaskId {
 /// Core implementation of the REFRESH_TASK_ID syscall.
 ///
 /// See the note on syscall stubs at the top of this module for rationale.
+#[cfg(not(hubris_hosted))]
 #[unsafe(naked)]
 unsafe extern "C" fn sys_refresh_task_id_stub(_tid: u32) -> u32 {
     cfg_if::cfg_if! {
@@ -1653,6 +1825,7 @@ pub fn sys_post(task_id: TaskId, bits: u32) -> u32 {
 /// Core implementation of the POST syscall.
 ///
 /// See the note on syscall stubs at the top of this module for rationale.
+#[cfg(not(hubris_hosted))]
 #[unsafe(naked)]
 unsafe extern "C" fn sys_post_stub(_tid: u32, _mask: u32) -> u32 {
     cfg_if::cfg_if! {
@@ -1721,6 +1894,7 @@ pub fn sys_reply_fault(task_id: TaskId, reason: ReplyFaultReason) {
 /// Core implementation of the REPLY_FAULT syscall.
 ///
 /// See the note on syscall stubs at the top of this module for rationale.
+#[cfg(not(hubris_hosted))]
 #[unsafe(naked)]
 unsafe extern "C" fn sys_reply_fault_stub(_tid: u32, _reason: u32) {
     cfg_if::cfg_if! {
@@ -1803,6 +1977,7 @@ pub fn sys_irq_status(mask: u32) -> abi::IrqStatus {
 /// Core implementation of the IRQ_STATUS syscall.
 ///
 /// See the note on syscall stubs at the top of this module for rationale.
+#[cfg(not(hubris_hosted))]
 #[unsafe(naked)]
 unsafe extern "C" fn sys_irq_status_stub(_mask: u32) -> u32 {
     cfg_if::cfg_if! {
@@ -23,4 +23,23 @@ macro_rules! task_slot {
             );
         }
     };
+    // Also records that the slot is used to reach the Idol interface
+    // `$interface` (a client type, which implements `task_slot::Interface`),
+    // so that `xtask dist` can check that the task it's bound to serves it.
+    ($vis:vis $var:ident, $task_name:ident, $interface:ty) => {
+        $crate::task_slot!($vis $var, $task_name);
+
+        $crate::macros::paste::paste! {
+            #[used]
+            #[link_section = ".task_slot_interfaces"]
+            static [< _TASK_SLOT_INTERFACE_ $var >]: [u8; {
+                stringify!($task_name).len()
+                    + <$interface as $crate::task_slot::Interface>::NAME.len()
+                    + 2
+            }] = $crate::task_slot::interface_entry(
+                stringify!($task_name),
+                <$interface as $crate::task_slot::Interface>::NAME,
+            );
+        }
+    };
 }
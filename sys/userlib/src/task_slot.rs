@@ -93,3 +93,43 @@ impl<const N: usize> TaskSlotTableEntry<N> {
 // the process space.  As such, instances of TaskSlotTableEntry will never exist
 // at runtime.
 unsafe impl<const N: usize> Sync for TaskSlotTableEntry<N> {}
+
+/// An Idol interface, as seen by its clients.
+///
+/// Idol client types implement this (see
+/// `build_util::build_client_interface`) so that `task_slot!` can record
+/// which interface a task uses through each of its task slots. The build
+/// system checks these records against the interfaces that each task
+/// `serves`, as declared in the app.toml.
+pub trait Interface {
+    /// Name of the interface, as given in its `.idol` file
+    const NAME: &'static str;
+}
+
+/// Builds an entry in the `.task_slot_interfaces` ELF section, which is the
+/// name of a task slot and the name of the interface used through it, each
+/// NUL-terminated.  `N` must be the length of both names plus two.
+///
+/// Like the `.task_slot_table`, this section isn't loaded; it's only read by
+/// the build system.
+pub const fn interface_entry<const N: usize>(
+    slot_name: &str,
+    interface: &str,
+) -> [u8; N] {
+    let slot_name = slot_name.as_bytes();
+    let interface = interface.as_bytes();
+    assert!(slot_name.len() + interface.len() + 2 == N);
+
+    let mut out = [0; N];
+    let mut i = 0;
+    while i < slot_name.len() {
+        out[i] = slot_name[i];
+        i += 1;
+    }
+    let mut i = 0;
+    while i < interface.len() {
+        out[slot_name.len() + 1 + i] = interface[i];
+        i += 1;
+    }
+    out
+}
@@ -6,5 +6,6 @@ use std::error::Error;
 
 fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
     idol::client::build_client_stub("../../idl/attest.idol", "client_stub.rs")?;
+    build_util::build_client_interface("../../idl/attest.idol")?;
     Ok(())
 }
@@ -10,7 +10,7 @@ use gateway_messages::{
 use userlib::{task_slot, UnwrapLite};
 use zerocopy::{FromBytes, FromZeros, IntoBytes};
 
-task_slot!(DUMP_AGENT, dump_agent);
+task_slot!(DUMP_AGENT, dump_agent, DumpAgent);
 
 const DUMP_TASK_SIZE: u32 = size_of::<humpty::DumpTask>() as u32;
 const HEADER_SIZE: u32 = size_of::<humpty::DumpAreaHeader>() as u32;
@@ -15,7 +15,7 @@ use task_validate_api::{Sensor, DEVICES as VALIDATE_DEVICES};
 use task_validate_api::{Validate, ValidateError, ValidateOk};
 use userlib::UnwrapLite;
 
-userlib::task_slot!(VALIDATE, validate);
+userlib::task_slot!(VALIDATE, validate, Validate);
 userlib::task_slot!(SENSOR, sensor);
 
 pub(crate) struct Inventory {
@@ -44,7 +44,7 @@ mod mgs_handler;
 use self::mgs_handler::MgsHandler;
 
 task_slot!(JEFE, jefe);
-task_slot!(NET, net);
+task_slot!(NET, net, Net);
 task_slot!(SYS, sys);
 
 #[allow(dead_code)] // Not all cases are used by all variants
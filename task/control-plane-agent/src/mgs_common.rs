@@ -45,10 +45,10 @@ use task_packrat_api::Packrat;
 use task_sensor_api::{Sensor, SensorId};
 use userlib::{kipc, sys_get_timer, task_slot};
 
-task_slot!(SENSOR, sensor);
-task_slot!(pub PACKRAT, packrat);
-task_slot!(pub SPROT, sprot);
-task_slot!(pub UPDATE_SERVER, update_server);
+task_slot!(SENSOR, sensor, Sensor);
+task_slot!(pub PACKRAT, packrat, Packrat);
+task_slot!(pub SPROT, sprot, SpRot);
+task_slot!(pub UPDATE_SERVER, update_server, Update);
 
 /// Provider of MGS handler logic common to all targets (gimlet, sidecar, psc).
 pub(crate) struct MgsCommon {
@@ -519,7 +519,7 @@ impl MgsCommon {
         buf: &mut [u8],
     ) -> Result<usize, GwSpError> {
         use task_vpd_api::{Vpd, VpdError};
-        task_slot!(VPD, vpd);
+        task_slot!(VPD, vpd, Vpd);
 
         ringbuf_entry!(Log::MgsMessage(MgsMessage::VpdLockStatus));
         let vpd = Vpd::from(VPD.get_task_id());
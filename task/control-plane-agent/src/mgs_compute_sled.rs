@@ -87,8 +87,8 @@ static_assertions::const_assert!(
 const SERIAL_CONSOLE_FLUSH_TIMEOUT_MILLIS: u64 = 500;
 
 userlib::task_slot!(HOST_FLASH, hf);
-userlib::task_slot!(CPU_SEQ, cpu_seq);
-userlib::task_slot!(USER_LEDS, user_leds);
+userlib::task_slot!(CPU_SEQ, cpu_seq, Sequencer);
+userlib::task_slot!(USER_LEDS, user_leds, UserLeds);
 
 type InstallinatorImageIdBuf = Vec<u8, MAX_INSTALLINATOR_IMAGE_ID_LEN>;
 
@@ -30,7 +30,7 @@ use userlib::sys_get_timer;
 const UPDATE_BUFFER_SIZE: usize =
     usize_max(SpUpdate::BLOCK_SIZE, RotUpdate::BLOCK_SIZE);
 
-userlib::task_slot!(USER_LEDS, user_leds);
+userlib::task_slot!(USER_LEDS, user_leds, UserLeds);
 
 // Create type aliases that include our `UpdateBuffer` size (i.e., the size of
 // the largest update chunk of all the components we update).
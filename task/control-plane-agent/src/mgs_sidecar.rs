@@ -42,10 +42,10 @@ mod monorail_port_status;
 
 use ignition_handler::IgnitionController;
 
-userlib::task_slot!(SIDECAR_SEQ, sequencer);
-userlib::task_slot!(MONORAIL, monorail);
-userlib::task_slot!(TRANSCEIVERS, transceivers);
-userlib::task_slot!(RNG, rng_driver);
+userlib::task_slot!(SIDECAR_SEQ, sequencer, Sequencer);
+userlib::task_slot!(MONORAIL, monorail, Monorail);
+userlib::task_slot!(TRANSCEIVERS, transceivers, Transceivers);
+userlib::task_slot!(RNG, rng_driver, drv_rng_api::Rng);
 
 #[allow(dead_code)] // Not all cases are used by all variants
 #[derive(Clone, Copy, PartialEq, ringbuf::Count)]
@@ -15,7 +15,7 @@ use gateway_messages::{
     UpdatePreparationStatus, UpdateStatus,
 };
 
-userlib::task_slot!(HOST_FLASH, hf);
+userlib::task_slot!(HOST_FLASH, hf, HostFlash);
 
 pub(crate) struct HostFlashUpdate {
     task: HostFlash,
@@ -73,7 +73,7 @@ cfg_if! {
 
         mod auxflash;
 
-        userlib::task_slot!(AUX_FLASH_SERVER, auxflash);
+        userlib::task_slot!(AUX_FLASH_SERVER, auxflash, AuxFlash);
     } else {
         mod stub_auxflash;
         use stub_auxflash as auxflash;
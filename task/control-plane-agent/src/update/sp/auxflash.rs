@@ -5,8 +5,8 @@
 use crate::mgs_handler::{BorrowedUpdateBuffer, UpdateBuffer};
 use core::ops::Range;
 use drv_auxflash_api::{
-    AuxFlash, AuxFlashChecksum, AuxFlashError, PAGE_SIZE_BYTES,
-    SECTOR_SIZE_BYTES, SLOT_COUNT, SLOT_SIZE,
+    AuxFlash, AuxFlashChecksum, AuxFlashError, DATA_SLOT_COUNT,
+    PAGE_SIZE_BYTES, SECTOR_SIZE_BYTES, SLOT_SIZE,
 };
 use gateway_messages::{
     SpComponent, SpError, UpdateId, UpdateInProgressStatus,
@@ -78,7 +78,7 @@ impl State {
                     // wrapped around and is now less than `active_slot`, we've
                     // already scanned `active_slot..total_slots` and
                     // `0..index`.
-                    (SLOT_COUNT - active_slot) + scan.index
+                    (DATA_SLOT_COUNT - active_slot) + scan.index
                 } else {
                     // Otherwise, we've only scanned
                     // `active_slot..index`.
@@ -89,7 +89,7 @@ impl State {
                     id,
                     progress: Some(UpdatePreparationProgress {
                         current: slots_scanned,
-                        total: SLOT_COUNT + SECTORS_PER_SLOT as u32,
+                        total: DATA_SLOT_COUNT + SECTORS_PER_SLOT as u32,
                     }),
                 })
             }
@@ -97,8 +97,8 @@ impl State {
                 UpdateStatus::Preparing(UpdatePreparationStatus {
                     id,
                     progress: Some(UpdatePreparationProgress {
-                        current: SLOT_COUNT + erase.sectors_to_erase.start,
-                        total: SLOT_COUNT + SECTORS_PER_SLOT as u32,
+                        current: DATA_SLOT_COUNT + erase.sectors_to_erase.start,
+                        total: DATA_SLOT_COUNT + SECTORS_PER_SLOT as u32,
                     }),
                 })
             }
@@ -139,7 +139,8 @@ pub(super) struct ScanningForChck {
     // (i.e., this is `None`), it is treated as 0.
     active_slot: Option<u32>,
     // Index of the slot to scan next. `active_slot - 1` (after wrapping
-    // around at SLOT_COUNT) is the final slot to scan.
+    // around at DATA_SLOT_COUNT, since we leave reserved slots alone) is the
+    // final slot to scan.
     index: u32,
     // While we're scanning, we record the first empty, even-numbered slot
     // we see. If we don't find a CHCK match, we'll pick this slot to
@@ -194,7 +195,7 @@ impl ScanningForChck {
         // The chck at index didn't match; advance to either the next
         // slot or the next state (if we've scanned all slots).
         self.index += 1;
-        if self.index == SLOT_COUNT {
+        if self.index == DATA_SLOT_COUNT {
             self.index = 0;
         }
 
@@ -207,7 +208,7 @@ impl ScanningForChck {
                 // Round up to next even number...
                 let next_even = (self.index + 2) & !1;
                 // and wrap back around to 0 if needed.
-                next_even % SLOT_COUNT
+                next_even % DATA_SLOT_COUNT
             });
             ChckScanResult::NewState(State::ErasingSlot(ErasingSlot {
                 buffer: self.buffer,
@@ -20,10 +20,10 @@ use userlib::{
 };
 use zerocopy::IntoBytes;
 
-task_slot!(JEFE, jefe);
-task_slot!(PACKRAT, packrat);
-task_slot!(LOADER, spartan7_loader);
-task_slot!(SENSOR, sensor);
+task_slot!(JEFE, jefe, Jefe);
+task_slot!(PACKRAT, packrat, Packrat);
+task_slot!(LOADER, spartan7_loader, Spartan7Loader);
+task_slot!(SENSOR, sensor, Sensor);
 
 #[derive(counters::Count, Copy, Clone, PartialEq)]
 enum Trace {
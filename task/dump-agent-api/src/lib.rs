@@ -109,3 +109,4 @@ pub const DUMP_AGENT_TASKS: u8 = 0x12_u8;
 pub const DUMP_AGENT_SYSTEM: u8 = 0x13_u8;
 
 include!(concat!(env!("OUT_DIR"), "/client_stub.rs"));
+include!(concat!(env!("OUT_DIR"), "/client_interface.rs"));
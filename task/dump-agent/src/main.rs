@@ -43,9 +43,9 @@ enum Trace {
 ringbuf!(Trace, 4, Trace::None);
 
 #[cfg(not(feature = "no-rot"))]
-task_slot!(SPROT, sprot);
+task_slot!(SPROT, sprot, drv_sprot_api::SpRot);
 
-task_slot!(JEFE, jefe);
+task_slot!(JEFE, jefe, Jefe);
 
 impl ServerImpl {
     fn initialize(&self) -> Result<(), DumpAgentError> {
@@ -302,7 +302,7 @@ fn main() -> ! {
 
     #[cfg(feature = "net")]
     {
-        task_slot!(NET, net);
+        task_slot!(NET, net, task_net_api::Net);
         let (rx_data_buf, tx_data_buf) = udp::claim_statics();
         let mut server = ServerImpl {
             jefe: Jefe::from(JEFE.get_task_id()),
@@ -36,7 +36,7 @@ enum Trace {
     ReinitResumed,
 }
 
-task_slot!(SP_CTRL, swd);
+task_slot!(SP_CTRL, swd, SpCtrl);
 
 const READ_SIZE: usize = 256;
 
@@ -50,7 +50,7 @@ use ringbuf::{counted_ringbuf, ringbuf_entry};
 use task_packrat_api::Packrat;
 use userlib::{task_slot, RecvMessage, UnwrapLite};
 
-task_slot!(PACKRAT, packrat);
+task_slot!(PACKRAT, packrat, Packrat);
 
 #[derive(Copy, Clone, Eq, PartialEq, counters::Count)]
 enum Trace {
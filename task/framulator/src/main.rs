@@ -11,7 +11,7 @@ use drv_spi_api::SpiServer;
 use ringbuf::{ringbuf, ringbuf_entry};
 use userlib::UnwrapLite;
 
-userlib::task_slot!(SPI, spi_driver);
+userlib::task_slot!(SPI, spi_driver, drv_spi_api::Spi);
 
 const TEXT: &[u8] = b"system working?\n";
 
@@ -19,8 +19,8 @@ use hubpack::SerializedSize;
 use task_net_api::*;
 use userlib::*;
 
-task_slot!(NET, net);
-task_slot!(SEQ, seq);
+task_slot!(NET, net, Net);
+task_slot!(SEQ, seq, Sequencer);
 
 #[derive(Count, Copy, Clone)]
 enum Event {
@@ -33,9 +33,9 @@ use userlib::{
     sys_irq_control, sys_recv_notification, task_slot, FromPrimitive,
 };
 
-task_slot!(SYS, sys);
-task_slot!(PACKRAT, packrat);
-task_slot!(JEFE, jefe);
+task_slot!(SYS, sys, Sys);
+task_slot!(PACKRAT, packrat, Packrat);
+task_slot!(JEFE, jefe, Jefe);
 
 mod ltc4306;
 
@@ -12,10 +12,10 @@
 use userlib::*;
 
 // Test SPDM API import
-use drv_spdm_responder_api::{SpdmResponder, SpdmError, SpdmVersion};
+use drv_spdm_responder_api::{SpdmError, SpdmResponder, SpdmVersion};
 
 task_slot!(UART, uart_driver);
-task_slot!(SPDM_RESPONDER, spdm_responder);
+task_slot!(SPDM_RESPONDER, spdm_responder, SpdmResponder);
 
 #[export_name = "main"]
 fn main() -> ! {
@@ -68,7 +68,8 @@ fn uart_read(text: &mut [u8]) -> bool {
         sys_send(peer, OP_READ, &[], text, &mut []);
     */
     let mut response = [0u8; 4];
-    let (code, _) = sys_send(peer, OP_READ, &[], &mut response, &mut [Lease::from(text)]);
+    let (code, _) =
+        sys_send(peer, OP_READ, &[], &mut response, &mut [Lease::from(text)]);
 
     code == 0
 }
@@ -669,7 +669,7 @@ pub(crate) fn spi_write(
 }
 
 #[cfg(feature = "qspi")]
-task_slot!(HF, hf);
+task_slot!(HF, hf, drv_hf_api::HostFlash);
 
 #[cfg(feature = "qspi")]
 pub(crate) fn qspi_read_id(
@@ -880,7 +880,7 @@ pub(crate) fn qspi_sector0_erase(
 }
 
 #[cfg(feature = "hash")]
-task_slot!(HASH, hash_driver);
+task_slot!(HASH, hash_driver, hash::Hash);
 
 // TODO: port this
 #[cfg(all(feature = "qspi", feature = "hash"))]
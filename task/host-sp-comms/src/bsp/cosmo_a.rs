@@ -18,8 +18,8 @@ use host_sp_messages::{InventoryData, InventoryDataResult};
 pub(crate) use self::i2c_config::MAX_COMPONENT_ID_LEN;
 
 userlib::task_slot!(I2C, i2c_driver);
-userlib::task_slot!(SPI, spi_driver);
-userlib::task_slot!(AUXFLASH, auxflash);
+userlib::task_slot!(SPI, spi_driver, drv_spi_api::Spi);
+userlib::task_slot!(AUXFLASH, auxflash, drv_auxflash_api::AuxFlash);
 
 // SP_TO_SP5_CPU_INT_L
 pub(crate) const SP_TO_HOST_CPU_INT_L: drv_stm32xx_sys_api::PinSet =
@@ -17,7 +17,7 @@ use host_sp_messages::{InventoryData, InventoryDataResult};
 pub(crate) use self::i2c_config::MAX_COMPONENT_ID_LEN;
 
 userlib::task_slot!(I2C, i2c_driver);
-userlib::task_slot!(SPI, spi_driver);
+userlib::task_slot!(SPI, spi_driver, drv_spi_api::Spi);
 
 // This net is named SP_TO_SP3_INT_L in the schematic
 pub(crate) const SP_TO_HOST_CPU_INT_L: drv_stm32xx_sys_api::PinSet =
@@ -11,8 +11,8 @@ use drv_spi_api::SpiServer;
 
 use host_sp_messages::{InventoryData, InventoryDataResult};
 
-userlib::task_slot!(SPI, spi_driver);
-userlib::task_slot!(AUXFLASH, auxflash);
+userlib::task_slot!(SPI, spi_driver, drv_spi_api::Spi);
+userlib::task_slot!(AUXFLASH, auxflash, drv_auxflash_api::AuxFlash);
 
 // the CPU interrupt is not connected on grapefruit, so pick an
 // unconnected GPIO
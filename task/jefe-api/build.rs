@@ -4,6 +4,7 @@
 
 fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     idol::client::build_client_stub("../../idl/jefe.idol", "client_stub.rs")?;
+    build_util::build_client_interface("../../idl/jefe.idol")?;
 
     Ok(())
 }
@@ -38,6 +38,16 @@ pub enum DumpAreaError {
     AlreadyInUse,
 }
 
+/// Errors from reading the kernel's retained fault log.
+#[derive(
+    Copy, Clone, Debug, FromPrimitive, Eq, PartialEq, IdolError, counters::Count,
+)]
+#[repr(C)]
+pub enum FaultLogError {
+    /// There's no entry at that index, or the application has no fault log.
+    NoSuchEntry = 1,
+}
+
 impl Jefe {
     /// Asks the supervisor to restart the current task without recording a
     /// fault.
@@ -48,3 +58,4 @@ impl Jefe {
 }
 
 include!(concat!(env!("OUT_DIR"), "/client_stub.rs"));
+include!(concat!(env!("OUT_DIR"), "/client_interface.rs"));
@@ -37,8 +37,8 @@ use core::convert::Infallible;
 use hubris_num_tasks::NUM_TASKS;
 use humpty::DumpArea;
 use idol_runtime::RequestError;
-use task_jefe_api::{DumpAgentError, ResetReason};
-use userlib::{kipc, Generation, TaskId};
+use task_jefe_api::{DumpAgentError, FaultLogError, ResetReason};
+use userlib::{kipc, FaultLogEntry, Generation, TaskId};
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Default)]
 pub enum Disposition {
@@ -164,6 +164,23 @@ impl idl::InOrderJefeImpl for ServerImpl<'_> {
         Ok(())
     }
 
+    fn read_fault_log(
+        &mut self,
+        _msg: &userlib::RecvMessage,
+        index: u32,
+    ) -> Result<FaultLogEntry, RequestError<FaultLogError>> {
+        kipc::read_fault_log(index as usize)
+            .ok_or(FaultLogError::NoSuchEntry.into())
+    }
+
+    fn clear_fault_log(
+        &mut self,
+        _msg: &userlib::RecvMessage,
+    ) -> Result<(), RequestError<Infallible>> {
+        kipc::clear_fault_log();
+        Ok(())
+    }
+
     fn restart_me_raw(
         &mut self,
         msg: &userlib::RecvMessage,
@@ -458,6 +475,7 @@ include!(concat!(env!("OUT_DIR"), "/notifications.rs"));
 
 // And the Idol bits
 mod idl {
-    use task_jefe_api::{DumpAgentError, ResetReason};
+    use task_jefe_api::{DumpAgentError, FaultLogError, ResetReason};
+    use userlib::FaultLogEntry;
     include!(concat!(env!("OUT_DIR"), "/server_stub.rs"));
 }
@@ -0,0 +1,257 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! MCTP transport over SMBus/I2C
+//!
+//! This task is an MCTP endpoint (DSP0236) on an I2C bus, using the SMBus
+//! binding (DSP0237) on top of the I2C server's target mode. It polls the
+//! server for frames written to our target address, reassembles them into
+//! messages, and passes SPDM messages (and secured messages, which carry SPDM
+//! sessions) to the SPDM responder; MCTP control messages, through which the
+//! bus owner assigns our EID, it answers itself.
+//! Responses go back to whoever sent the request, as I2C writes with us
+//! acting as the controller.
+//!
+//! We only ever respond, one message at a time.
+//!
+//! The SPDM responder keeps the state of a single connection -- what was
+//! negotiated, the transcript, and any open sessions -- and a GET_VERSION
+//! from anyone starts it over. So we talk to one requester at a time: once a
+//! requester has sent us a message, we answer SPDM messages from any
+//! other EID with a Busy error (and drop their secured messages) until it's
+//! been quiet for `IDLE_TIMEOUT`. After that, another requester can take
+//! over, which ends the first one's sessions.
+
+#![no_std]
+#![no_main]
+
+use drv_i2c_api::{Controller, I2cDevice, PortIndex, ResponseCode};
+use drv_spdm_responder_api::{SpdmError, SpdmResponder, MAX_MESSAGE_SIZE};
+use mctp::{msg_type, smbus, Endpoint, Message};
+use ringbuf::{ringbuf, ringbuf_entry};
+use static_cell::ClaimOnceCell;
+use userlib::*;
+
+task_slot!(I2C, i2c);
+task_slot!(SPDM_RESPONDER, spdm_responder, SpdmResponder);
+
+task_config::task_config! {
+    /// I2C controller that the bus is on
+    controller: Controller,
+    /// Port of that controller that the bus is on
+    port: u8,
+    /// Our 7-bit target address
+    address: u8,
+    /// Our EID, or 0 to wait for the bus owner to assign one
+    eid: u8,
+}
+
+/// How often to poll the I2C server for frames, in milliseconds
+const POLL_INTERVAL: u64 = 10;
+
+/// How long the requester that we're talking to can be quiet before another
+/// can take over, in milliseconds
+const IDLE_TIMEOUT: u64 = 10_000;
+
+/// SPDM ERROR response code, and its Busy error code (DSP0274)
+const SPDM_ERROR: u8 = 0x7F;
+const SPDM_BUSY: u8 = 0x03;
+
+/// Message types that we handle, besides control
+const TYPES: [u8; 2] = [msg_type::SPDM, msg_type::SECURED];
+
+/// Size of the buffer that we poll for frames with: room for two of the
+/// largest messages that the I2C server queues, each preceded by its source
+/// address and length
+const POLL_SIZE: usize = 2 * (2 + 255);
+
+/// Largest message that we take in, including its message type
+const MESSAGE_SIZE: usize = MAX_MESSAGE_SIZE + 1;
+
+#[derive(Copy, Clone, PartialEq)]
+enum Trace {
+    None,
+    Listening { address: u8, eid: u8 },
+    I2cError(ResponseCode),
+    Dropped(mctp::Error),
+    Received { src: u8, msg_type: u8, len: usize },
+    Unsupported(u8),
+    Busy(u8),
+    SpdmError(SpdmError),
+    Sent { dest: u8, len: usize },
+    Eid(u8),
+}
+
+ringbuf!(Trace, 16, Trace::None);
+
+/// The requester that we're talking to
+#[derive(Copy, Clone)]
+struct Peer {
+    eid: u8,
+    /// When we last heard from it
+    last: u64,
+}
+
+struct Bufs {
+    endpoint: Endpoint<MESSAGE_SIZE>,
+    poll: [u8; POLL_SIZE],
+    response: [u8; MAX_MESSAGE_SIZE],
+}
+
+#[export_name = "main"]
+fn main() -> ! {
+    let i2c = I2C.get_task_id();
+    let spdm = SpdmResponder::from(SPDM_RESPONDER.get_task_id());
+    let port = PortIndex(TASK_CONFIG.port);
+    let target = I2cDevice::new(i2c, TASK_CONFIG.controller, port, None, 0);
+
+    // There's nothing else for us to do until we're on the bus.
+    while let Err(e) = target
+        .configure_slave_address(TASK_CONFIG.address)
+        .and_then(|()| target.enable_slave_receive())
+    {
+        ringbuf_entry!(Trace::I2cError(e));
+        hl::sleep_for(POLL_INTERVAL);
+    }
+
+    let Bufs {
+        ref mut endpoint,
+        ref mut poll,
+        ref mut response,
+    } = {
+        const STATIC_EID: Option<u8> = match TASK_CONFIG.eid {
+            mctp::EID_NULL => None,
+            eid => Some(eid),
+        };
+        static BUFS: ClaimOnceCell<Bufs> = ClaimOnceCell::new(Bufs {
+            endpoint: Endpoint::new(STATIC_EID, &TYPES),
+            poll: [0; POLL_SIZE],
+            response: [0; MAX_MESSAGE_SIZE],
+        });
+        BUFS.claim()
+    };
+    ringbuf_entry!(Trace::Listening {
+        address: TASK_CONFIG.address,
+        eid: endpoint.eid(),
+    });
+
+    let mut peer = None;
+    loop {
+        let len = match target.check_slave_buffer(poll) {
+            Ok(len) => len.min(POLL_SIZE),
+            Err(e) => {
+                ringbuf_entry!(Trace::I2cError(e));
+                0
+            }
+        };
+
+        // Each frame is preceded by the address that it came from, which we
+        // ignore in favor of the one in the frame, and its length.
+        let mut frames = &poll[..len];
+        while let [_, n, rest @ ..] = frames {
+            let Some((frame, next)) = rest.split_at_checked(*n as usize) else {
+                break;
+            };
+            frames = next;
+
+            let eid = endpoint.eid();
+            let r = smbus::handle(
+                TASK_CONFIG.address,
+                endpoint,
+                frame,
+                response,
+                |msg, body, response| {
+                    respond(&spdm, &mut peer, msg, body, response)
+                },
+                |peer, frame| {
+                    I2cDevice {
+                        address: peer,
+                        ..target
+                    }
+                    .write(frame)
+                },
+            );
+            match r {
+                Ok(Some(smbus::Sent { dest, len })) => {
+                    ringbuf_entry!(Trace::Sent { dest, len })
+                }
+                Ok(None) => (),
+                Err(smbus::Failure::Dropped(e)) => {
+                    ringbuf_entry!(Trace::Dropped(e))
+                }
+                Err(smbus::Failure::Write(e)) => {
+                    ringbuf_entry!(Trace::I2cError(e))
+                }
+            }
+            if endpoint.eid() != eid {
+                ringbuf_entry!(Trace::Eid(endpoint.eid()));
+            }
+        }
+
+        if len == 0 {
+            hl::sleep_for(POLL_INTERVAL);
+        }
+    }
+}
+
+/// Answers an SPDM or secured message through the SPDM responder, returning
+/// the length of its reply, if it has one.
+///
+/// If the message isn't from `peer`, and `peer` is still active, we turn it
+/// away instead; otherwise, its sender becomes `peer`.
+fn respond(
+    spdm: &SpdmResponder,
+    peer: &mut Option<Peer>,
+    msg: &Message,
+    body: &[u8],
+    response: &mut [u8],
+) -> Option<usize> {
+    ringbuf_entry!(Trace::Received {
+        src: msg.src,
+        msg_type: msg.msg_type,
+        len: body.len(),
+    });
+    let now = sys_get_timer().now;
+    match *peer {
+        Some(p) if p.eid != msg.src && now < p.last + IDLE_TIMEOUT => {
+            ringbuf_entry!(Trace::Busy(msg.src));
+            return busy(msg, body, response);
+        }
+        _ => {
+            *peer = Some(Peer {
+                eid: msg.src,
+                last: now,
+            })
+        }
+    }
+
+    let r = match msg.msg_type {
+        msg_type::SPDM => spdm.respond(body, response),
+        msg_type::SECURED => spdm.respond_secured(body, response),
+        t => {
+            ringbuf_entry!(Trace::Unsupported(t));
+            return None;
+        }
+    };
+    r.map(|len| len as usize)
+        .inspect_err(|&e| ringbuf_entry!(Trace::SpdmError(e)))
+        .ok()
+}
+
+/// Turns away a message from a requester other than the one we're talking to,
+/// returning the length of our reply, if we have one.
+///
+/// An SPDM request gets a Busy error, in the version it was sent in, so that
+/// its sender will try again later. We can't answer a secured message, which
+/// belongs to a session that we don't have.
+fn busy(msg: &Message, body: &[u8], response: &mut [u8]) -> Option<usize> {
+    match (msg.msg_type, body) {
+        (msg_type::SPDM, [version, ..]) => {
+            let rsp = [*version, SPDM_ERROR, SPDM_BUSY, 0];
+            response.get_mut(..rsp.len())?.copy_from_slice(&rsp);
+            Some(rsp.len())
+        }
+        _ => None,
+    }
+}
@@ -14,7 +14,7 @@ use vsc7448::{
 use vsc7448_pac::{DEVCPU_GCB, HSIO, VAUI0, VAUI1};
 use vsc85xx::{vsc8504::Vsc8504, vsc8562::Vsc8562Phy, PhyRw};
 
-task_slot!(SEQ, seq);
+task_slot!(SEQ, seq, Sequencer);
 task_slot!(FRONT_IO, ecp5_front_io);
 
 /// Interval at which `Bsp::wake()` is called by the main loop
@@ -39,13 +39,13 @@ cfg_if::cfg_if! {
         }
     } else {
         pub fn claim_spi(_sys: &Sys) -> drv_spi_api::Spi {
-            task_slot!(SPI, spi_driver);
+            task_slot!(SPI, spi_driver, drv_spi_api::Spi);
             drv_spi_api::Spi::from(SPI.get_task_id())
         }
     }
 }
 
-task_slot!(SYS, sys);
+task_slot!(SYS, sys, Sys);
 
 #[derive(Copy, Clone, PartialEq, counters::Count)]
 enum Trace {
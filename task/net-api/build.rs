@@ -4,6 +4,7 @@
 
 fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     idol::client::build_client_stub("../../idl/net.idol", "client_stub.rs")?;
+    build_util::build_client_interface("../../idl/net.idol")?;
 
     let out_dir = build_util::out_dir();
     let dest_path = out_dir.join("net_config.rs");
@@ -311,4 +311,5 @@ pub struct VLanConfig {
 }
 
 include!(concat!(env!("OUT_DIR"), "/client_stub.rs"));
+include!(concat!(env!("OUT_DIR"), "/client_interface.rs"));
 include!(concat!(env!("OUT_DIR"), "/net_config.rs"));
@@ -27,7 +27,7 @@ use userlib::task_slot;
 use vsc7448_pac::{phy, types::PhyRegisterAddress};
 use vsc85xx::VscError;
 
-task_slot!(USER_LEDS, user_leds);
+task_slot!(USER_LEDS, user_leds, drv_user_leds_api::UserLeds);
 
 #[derive(Copy, Clone, Eq, PartialEq, counters::Count)]
 enum Trace {
@@ -26,7 +26,7 @@ use task_net_api::{
 use userlib::{hl::sleep_for, task_slot, UnwrapLite};
 use vsc7448_pac::types::PhyRegisterAddress;
 
-task_slot!(SEQ, seq);
+task_slot!(SEQ, seq, Sequencer);
 
 ////////////////////////////////////////////////////////////////////////////////
 
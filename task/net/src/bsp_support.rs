@@ -39,7 +39,7 @@ cfg_if::cfg_if! {
         }
     } else if #[cfg(all(feature = "ksz8463", not(feature = "use-spi-core")))] {
         // The SPI peripheral is owned by a separate `stm32h7-spi-server` task
-        userlib::task_slot!(SPI, spi_driver);
+        userlib::task_slot!(SPI, spi_driver, drv_spi_api::Spi);
         pub type Ksz8463 = ksz8463::Ksz8463<drv_spi_api::Spi>;
 
         /// Claims the SPI handle
@@ -88,7 +88,7 @@ task_slot!(SYS, sys);
 task_slot!(JEFE, jefe);
 
 #[cfg(feature = "vpd-mac")]
-task_slot!(PACKRAT, packrat);
+task_slot!(PACKRAT, packrat, Packrat);
 
 /////////////////////////////////////////////////////////////////////////////
 // Configuration things!
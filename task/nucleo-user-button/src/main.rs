@@ -23,8 +23,8 @@ compile_error!(
     "the `nucleo-user-button` task is only supported on the Nucleo H753ZI and H743ZI2 boards"
 );
 
-task_slot!(USER_LEDS, user_leds);
-task_slot!(SYS, sys);
+task_slot!(USER_LEDS, user_leds, drv_user_leds_api::UserLeds);
+task_slot!(SYS, sys, drv_stm32xx_sys_api::Sys);
 
 task_config::optional_task_config! {
     /// The index of the user LED to toggle
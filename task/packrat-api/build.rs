@@ -5,5 +5,6 @@
 fn main() -> anyhow::Result<()> {
     idol::client::build_client_stub("../../idl/packrat.idol", "client_stub.rs")
         .map_err(|e| anyhow::anyhow!("{e}"))?;
+    build_util::build_client_interface("../../idl/packrat.idol")?;
     Ok(())
 }
@@ -7,7 +7,7 @@
 
 use userlib::*;
 
-task_slot!(USER_LEDS, user_leds);
+task_slot!(USER_LEDS, user_leds, drv_user_leds_api::UserLeds);
 
 #[export_name = "main"]
 pub fn main() -> ! {
@@ -81,7 +81,7 @@ pub(crate) static CONTROLLER_CONFIG: [PowerControllerConfig;
 ];
 
 pub(crate) fn get_state() -> PowerState {
-    userlib::task_slot!(SEQUENCER, cosmo_seq);
+    userlib::task_slot!(SEQUENCER, cosmo_seq, seq_api::Sequencer);
 
     use drv_cpu_seq_api as seq_api;
 
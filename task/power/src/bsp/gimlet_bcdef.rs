@@ -56,7 +56,7 @@ pub(crate) static CONTROLLER_CONFIG: [PowerControllerConfig;
 ];
 
 pub(crate) fn get_state() -> PowerState {
-    userlib::task_slot!(SEQUENCER, gimlet_seq);
+    userlib::task_slot!(SEQUENCER, gimlet_seq, seq_api::Sequencer);
 
     use drv_cpu_seq_api as seq_api;
 
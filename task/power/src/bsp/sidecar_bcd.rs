@@ -29,7 +29,7 @@ pub(crate) static CONTROLLER_CONFIG: [PowerControllerConfig;
 ];
 
 pub(crate) fn get_state() -> PowerState {
-    userlib::task_slot!(SEQUENCER, sequencer);
+    userlib::task_slot!(SEQUENCER, sequencer, seq_api::Sequencer);
 
     use drv_sidecar_seq_api as seq_api;
 
@@ -57,7 +57,7 @@ enum PowerState {
 const TIMER_INTERVAL: u32 = 1000;
 
 task_slot!(I2C, i2c_driver);
-task_slot!(SENSOR, sensor);
+task_slot!(SENSOR, sensor, sensor_api::Sensor);
 
 include!(concat!(env!("OUT_DIR"), "/i2c_config.rs"));
 
@@ -234,4 +234,5 @@ impl Sensor {
 }
 
 include!(concat!(env!("OUT_DIR"), "/client_stub.rs"));
+include!(concat!(env!("OUT_DIR"), "/client_interface.rs"));
 include!(concat!(env!("OUT_DIR"), "/sensor_config.rs"));
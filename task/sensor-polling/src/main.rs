@@ -13,7 +13,7 @@ use task_sensor_api::{Sensor, SensorId};
 use userlib::*;
 
 task_slot!(I2C, i2c_driver);
-task_slot!(SENSOR, sensor);
+task_slot!(SENSOR, sensor, Sensor);
 
 /// Type containing all of our temperature sensor types, so we can store them
 /// generically in an array.  Right now, we only support the MWOCP68.
@@ -45,8 +45,8 @@ use task_packrat_api::Packrat;
 use userlib::{sys_recv_notification, task_slot};
 use zerocopy::TryFromBytes;
 
-task_slot!(NET, net);
-task_slot!(PACKRAT, packrat);
+task_slot!(NET, net, Net);
+task_slot!(PACKRAT, packrat, Packrat);
 
 #[derive(Count, Copy, Clone)]
 enum Event {
@@ -14,7 +14,7 @@ const READ_SIZE: usize = 256;
 
 const TRANSACTION_SIZE: u32 = 1024;
 
-task_slot!(SP_CTRL, swd);
+task_slot!(SP_CTRL, swd, SpCtrl);
 
 #[derive(Copy, Clone, PartialEq)]
 enum Trace {
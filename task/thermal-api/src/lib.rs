@@ -230,3 +230,4 @@ impl From<drv_i2c_devices::pct2075::Error> for SensorReadError {
 }
 
 include!(concat!(env!("OUT_DIR"), "/client_stub.rs"));
+include!(concat!(env!("OUT_DIR"), "/client_interface.rs"));
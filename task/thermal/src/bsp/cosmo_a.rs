@@ -17,7 +17,7 @@ use task_sensor_api::SensorId;
 use task_thermal_api::ThermalProperties;
 use userlib::{task_slot, units::Celsius, TaskId, UnwrapLite};
 
-task_slot!(SEQ, cosmo_seq);
+task_slot!(SEQ, cosmo_seq, Sequencer);
 
 // We monitor the TMP117 air temperature sensors, but don't use them as part of
 // the control loop.
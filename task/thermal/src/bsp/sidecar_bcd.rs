@@ -19,7 +19,7 @@ include!(concat!(env!("OUT_DIR"), "/i2c_config.rs"));
 use i2c_config::devices;
 use i2c_config::sensors;
 
-task_slot!(SEQUENCER, sequencer);
+task_slot!(SEQUENCER, sequencer, Sequencer);
 
 ////////////////////////////////////////////////////////////////////////////////
 // Constants!
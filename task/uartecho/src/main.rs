@@ -13,7 +13,7 @@ use heapless::Deque;
 use ringbuf::*;
 use userlib::*;
 
-task_slot!(SYS, sys);
+task_slot!(SYS, sys, Sys);
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 enum UartLog {
@@ -11,6 +11,7 @@ fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         "../../idl/validate.idol",
         "client_stub.rs",
     )?;
+    build_util::build_client_interface("../../idl/validate.idol")?;
     Ok(())
 }
 
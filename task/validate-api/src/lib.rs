@@ -50,6 +50,7 @@ pub enum ValidateOk {
 }
 
 include!(concat!(env!("OUT_DIR"), "/client_stub.rs"));
+include!(concat!(env!("OUT_DIR"), "/client_interface.rs"));
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum Sensor {
@@ -48,3 +48,4 @@ impl From<ResponseCode> for VpdError {
 }
 
 include!(concat!(env!("OUT_DIR"), "/client_stub.rs"));
+include!(concat!(env!("OUT_DIR"), "/client_interface.rs"));
@@ -0,0 +1,353 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Boots the image in `kconfig.ron` on the hosted backend and checks that its
+//! tasks can talk to each other.
+//!
+//! The kernel never returns, and takes the process with it when it resets, so
+//! the test runs it in a child process: the test binary runs itself with
+//! `KERNEL_VAR` set, and checks what the child's tasks print. The client
+//! prints `PASS` once every check has passed, and restarts the system to end
+//! the run. If any task faults instead (say, on a failed assertion), the
+//! supervisor prints `FAIL` and does the same.
+//!
+//! The tasks are written here, rather than taken from an app, because the
+//! hosted backend can't boot an app's image; see `kern::arch::hosted`.
+//!
+//! This needs the kconfig at build time, as any kernel does, so CI leaves it
+//! out of `cargo test --workspace` (with `--exclude hosted-kernel`), and runs
+//! it on its own:
+//!
+//! ```text
+//! HUBRIS_KCONFIG="$(cat test/hosted-kernel/kconfig.ron)" HUBRIS_IMAGE_ID=0 \
+//!     cargo test -p hosted-kernel
+//! ```
+
+use std::io::Read;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+use userlib::*;
+
+/// Set in the child that runs the kernel
+const KERNEL_VAR: &str = "HOSTED_KERNEL_TEST_BOOT";
+
+/// How long the image gets to finish
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Kernel tick, in microseconds
+const TICK_DIVISOR: u32 = 1000;
+
+const SUPERVISOR: usize = 0;
+const SERVER: usize = 1;
+const CLIENT: usize = 2;
+const IDLE: usize = 3;
+const SERVER2: usize = 4;
+const SERVER3: usize = 5;
+
+/// Server operations
+const OP_INCREMENT: u16 = 1;
+const OP_SUM_LEASE: u16 = 2;
+const OP_FILL_LEASE: u16 = 3;
+/// Holds the message, and replies to it (with `STALE`) only once the next
+/// message arrives, as a server that defers replies might
+const OP_DEFER: u16 = 4;
+/// Sums lease 0, like `OP_SUM_LEASE`, but first forwards it (less a byte at
+/// each end) to the next server that many times
+const OP_FORWARD_SUM: u16 = 5;
+/// Forwards lease 0 to the next server with `OP_READ_LATE`
+const OP_FORWARD_LATE: u16 = 6;
+/// Waits for `LATE_TICKS`, then checks that lease 0 can no longer be read
+const OP_READ_LATE: u16 = 7;
+
+/// How long `OP_READ_LATE` waits; longer than the client waits for a reply
+const LATE_TICKS: u64 = 20;
+
+const STALE: u32 = 0xDEAD;
+
+/// Writes a line to stdout. Tasks are processes of their own, which don't
+/// share std's buffering, so this goes straight to the file descriptor.
+fn say(line: &str) {
+    let line = format!("{line}\n");
+    // Safety: writing a buffer that we own.
+    unsafe {
+        libc::write(1, line.as_ptr().cast(), line.len());
+    }
+}
+
+fn supervisor() -> ! {
+    loop {
+        sys_recv_notification(kern::startup::HUBRIS_FAULT_NOTIFICATION);
+        if let Some(task) = kipc::find_faulted_task(0) {
+            let status = kipc::read_task_status(task.get());
+            say(&format!("FAIL: task {} faulted: {status:?}", task.get()));
+            kipc::system_restart();
+        }
+    }
+}
+
+/// Runs a server, which forwards leases to `next`.
+fn server(next: usize) -> ! {
+    let next = TaskId::for_index_and_gen(next, Generation::default());
+    let mut buf = [0u8; 4];
+    let mut deferred = None;
+    loop {
+        let msg = sys_recv_open(&mut buf, 0);
+        let arg = u32::from_le_bytes(buf);
+        if let Some(sender) = deferred.take() {
+            sys_reply(sender, 0, &STALE.to_le_bytes());
+        }
+        match msg.operation as u16 {
+            OP_DEFER => deferred = Some(msg.sender),
+            OP_INCREMENT => {
+                sys_reply(msg.sender, 0, &(arg + 1).to_le_bytes());
+            }
+            OP_SUM_LEASE => {
+                let mut lent = [0u8; 8];
+                let (rc, n) = sys_borrow_read(msg.sender, 0, 0, &mut lent);
+                let sum: u32 = lent[..n].iter().map(|&b| u32::from(b)).sum();
+                sys_reply(msg.sender, rc, &sum.to_le_bytes());
+            }
+            OP_FILL_LEASE => {
+                let fill = [arg as u8; 4];
+                let (rc, _) = sys_borrow_write(msg.sender, 0, 0, &fill);
+                sys_reply(msg.sender, rc, &[]);
+            }
+            OP_FORWARD_SUM if arg == 0 => {
+                let mut lent = [0u8; 8];
+                let (rc, n) = sys_borrow_read(msg.sender, 0, 0, &mut lent);
+                let sum: u32 = lent[..n].iter().map(|&b| u32::from(b)).sum();
+                sys_reply(msg.sender, rc, &sum.to_le_bytes());
+            }
+            OP_FORWARD_SUM => {
+                let len = sys_borrow_info(msg.sender, 0).unwrap().len;
+                let lease = Lease::forwarded(
+                    msg.sender,
+                    0,
+                    1,
+                    len - 2,
+                    LeaseAttributes::READ,
+                )
+                .unwrap();
+                let mut sum = [0u8; 4];
+                let (rc, _) = sys_send(
+                    next,
+                    OP_FORWARD_SUM,
+                    &(arg - 1).to_le_bytes(),
+                    &mut sum,
+                    &[lease],
+                );
+                sys_reply(msg.sender, rc, &sum);
+            }
+            OP_FORWARD_LATE => {
+                let len = sys_borrow_info(msg.sender, 0).unwrap().len;
+                let lease = Lease::forwarded(
+                    msg.sender,
+                    0,
+                    0,
+                    len,
+                    LeaseAttributes::READ,
+                )
+                .unwrap();
+                let (rc, _) =
+                    sys_send(next, OP_READ_LATE, &[], &mut [], &[lease]);
+                sys_reply(msg.sender, rc, &[]);
+            }
+            OP_READ_LATE => {
+                // By now, the task that lent the lease we were forwarded has
+                // given up on its send, so the lease must be gone, even though
+                // the task that forwarded it to us is still waiting on us.
+                hl::sleep_for(LATE_TICKS);
+                let mut lent = [0u8; 1];
+                let (rc, _) = sys_borrow_read(msg.sender, 0, 0, &mut lent);
+                assert_eq!(
+                    rc,
+                    abi::DEFECT,
+                    "forwarded lease outlived its lender"
+                );
+                sys_reply(msg.sender, 0, &[]);
+            }
+            _ => sys_reply(msg.sender, 1, &[]),
+        }
+    }
+}
+
+fn client() -> ! {
+    let server = sys_refresh_task_id(TaskId::for_index_and_gen(
+        SERVER,
+        Generation::default(),
+    ));
+
+    for i in 0..3u32 {
+        let mut out = [0u8; 4];
+        let (rc, len) =
+            sys_send(server, OP_INCREMENT, &i.to_le_bytes(), &mut out, &[]);
+        assert_eq!((rc, len), (0, 4));
+        assert_eq!(u32::from_le_bytes(out), i + 1);
+    }
+    say("ok send/recv/reply");
+
+    let lent = [1u8, 2, 3, 4, 5];
+    let mut out = [0u8; 4];
+    let (rc, _) = sys_send(
+        server,
+        OP_SUM_LEASE,
+        &[],
+        &mut out,
+        &[Lease::from(&lent[..])],
+    );
+    assert_eq!(rc, 0);
+    assert_eq!(u32::from_le_bytes(out), 15);
+    say("ok read lease");
+
+    let mut lent = [0u8; 4];
+    let (rc, _) = sys_send(
+        server,
+        OP_FILL_LEASE,
+        &7u32.to_le_bytes(),
+        &mut [],
+        &[Lease::from(&mut lent[..])],
+    );
+    assert_eq!(rc, 0);
+    assert_eq!(lent, [7; 4]);
+    say("ok write lease");
+
+    let (rc, _) = sys_send(server, 99, &[], &mut [], &[]);
+    assert_eq!(rc, 1);
+    say("ok error reply");
+
+    // A send to a task that isn't listening blocks until the deadline.
+    let idle = TaskId::for_index_and_gen(IDLE, Generation::default());
+    let now = sys_get_timer().now;
+    let r = sys_send_timeout(idle, 0, &[], &mut [], &[], now + 10);
+    assert_eq!(r, (abi::TIMEOUT, 0));
+    assert!(sys_get_timer().now >= now + 10);
+    say("ok send timeout");
+
+    // The server passes on the middle of our lease to a second server, which
+    // reads it straight out of our memory.
+    let lent = [1u8, 2, 3, 4, 5, 6, 7];
+    let mut out = [0u8; 4];
+    let (rc, _) = sys_send(
+        server,
+        OP_FORWARD_SUM,
+        &1u32.to_le_bytes(),
+        &mut out,
+        &[Lease::from(&lent[..])],
+    );
+    assert_eq!(rc, 0);
+    assert_eq!(u32::from_le_bytes(out), 2 + 3 + 4 + 5 + 6);
+    say("ok forwarded lease");
+
+    // ...and that server passes on the middle of that to a third.
+    let (rc, _) = sys_send(
+        server,
+        OP_FORWARD_SUM,
+        &2u32.to_le_bytes(),
+        &mut out,
+        &[Lease::from(&lent[..])],
+    );
+    assert_eq!(rc, 0);
+    assert_eq!(u32::from_le_bytes(out), 3 + 4 + 5);
+    say("ok nested forwarded lease");
+
+    // If we stop waiting for the server, the lease it forwarded stops
+    // working, even though it's still waiting for the task it forwarded it
+    // to. That task checks this (and faults if it can still read the lease)
+    // before the server is free to take our next message.
+    let now = sys_get_timer().now;
+    let r = sys_send_timeout(
+        server,
+        OP_FORWARD_LATE,
+        &[],
+        &mut [],
+        &[Lease::from(&lent[..])],
+        now + 5,
+    );
+    assert_eq!(r, (abi::TIMEOUT, 0));
+    let (rc, len) =
+        sys_send(server, OP_INCREMENT, &1u32.to_le_bytes(), &mut out, &[]);
+    assert_eq!((rc, len), (0, 4));
+    say("ok revoked forwarded lease");
+
+    // Give up on a reply from each of two servers. The first mustn't get
+    // another message until it's replied to the one we gave up on, or that
+    // reply would be taken for the reply to the new one.
+    let server2 = TaskId::for_index_and_gen(SERVER2, Generation::default());
+    for peer in [server, server2] {
+        let mut out = [0u8; 4];
+        let now = sys_get_timer().now;
+        let r = sys_send_timeout(peer, OP_DEFER, &[], &mut out, &[], now + 5);
+        assert_eq!(r, (abi::TIMEOUT, 0));
+    }
+    let mut out = [0u8; 4];
+    let now = sys_get_timer().now;
+    let r = sys_send_timeout(
+        server,
+        OP_INCREMENT,
+        &1u32.to_le_bytes(),
+        &mut out,
+        &[],
+        now + 5,
+    );
+    assert_eq!(r, (abi::TIMEOUT, 0));
+    assert_ne!(u32::from_le_bytes(out), STALE);
+    say("ok abandoned replies");
+
+    say("PASS");
+    kipc::system_restart();
+}
+
+fn idle() -> ! {
+    loop {
+        core::hint::spin_loop();
+    }
+}
+
+fn boot() -> ! {
+    kern::arch::set_task_entry(SUPERVISOR, supervisor);
+    kern::arch::set_task_entry(SERVER, || server(SERVER2));
+    kern::arch::set_task_entry(CLIENT, client);
+    kern::arch::set_task_entry(IDLE, idle);
+    kern::arch::set_task_entry(SERVER2, || server(SERVER3));
+    kern::arch::set_task_entry(SERVER3, || server(SERVER));
+    // Safety: this is the only call, and it's on the main thread.
+    unsafe { kern::startup::start_kernel(TICK_DIVISOR) }
+}
+
+fn main() {
+    if std::env::var_os(KERNEL_VAR).is_some() {
+        boot();
+    }
+
+    let mut child = Command::new(std::env::current_exe().unwrap())
+        .env(KERNEL_VAR, "1")
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("can't start the kernel");
+    let start = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait().unwrap() {
+            break status;
+        }
+        if start.elapsed() > TIMEOUT {
+            child.kill().unwrap();
+            panic!("image didn't finish within {TIMEOUT:?}");
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    };
+
+    let mut output = String::new();
+    child
+        .stdout
+        .take()
+        .unwrap()
+        .read_to_string(&mut output)
+        .unwrap();
+    print!("{output}");
+    assert!(status.success(), "kernel exited with {status}");
+    assert!(
+        output.lines().any(|l| l == "PASS"),
+        "image didn't pass:\n{output}"
+    );
+}
@@ -6,5 +6,6 @@ fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     idol::Generator::new()
         .with_counters(idol::CounterSettings::default())
         .build_client_stub("api.idol", "client_stub.rs")?;
+    build_util::build_client_interface("api.idol")?;
     Ok(())
 }